@@ -0,0 +1,140 @@
+//! Node.js addon (`@yandex-music-mod/core`) exposing the patch engine
+//!
+//! Every function returns a Promise and runs on the addon's own async runtime.
+//! Options come from `config.toml`, as for the CLI's `watch` and GUI modes.
+
+use napi::threadsafe_function::{ErrorStrategy, ThreadsafeFunction, ThreadsafeFunctionCallMode};
+use napi::{Error, Result};
+use napi_derive::napi;
+use yandex_music_mod::api::{self, AppBuild};
+use yandex_music_mod::config::Config;
+use yandex_music_mod::patcher;
+use yandex_music_mod::paths;
+use yandex_music_mod::progress::{Event, ProgressSink, ProgressState};
+
+/// Arguments shared by `download` and `patch`; unset fields fall back to `config.toml`
+#[napi(object)]
+pub struct RunOptions {
+    pub channel: Option<String>,
+    pub output: Option<String>,
+    /// Path of `config.toml` (default: the platform config directory)
+    pub config: Option<String>,
+}
+
+/// Progress reported to the `patch` callback
+#[napi(object)]
+pub struct Progress {
+    pub percent: u32,
+    pub message: String,
+}
+
+/// Latest builds of `channel` (default: the configured channel)
+#[napi]
+pub async fn fetch_latest(channel: Option<String>) -> Result<serde_json::Value> {
+    let config = load_config(None)?;
+    let builds = api::get_latest_build(&channel_of(&config, channel))
+        .await
+        .map_err(to_napi)?;
+    serde_json::to_value(builds).map_err(to_napi)
+}
+
+/// Download the latest installer without patching; resolves to its path
+#[napi]
+pub async fn download(options: Option<RunOptions>) -> Result<String> {
+    let (_, channel, output) = resolve(options)?;
+    let build = latest_build(&channel).await?;
+
+    let path = output.join(format!("{}.exe", build.version));
+    api::download_build(&build, &path, None)
+        .await
+        .map_err(to_napi)?;
+    path.into_os_string()
+        .into_string()
+        .map_err(|path| to_napi(format!("Path {:?} is not valid Unicode", path)))
+}
+
+/// Patch the latest build; resolves to the patch report with the build directory
+/// as `output`. `onProgress` is called as stages start.
+#[napi(ts_args_type = "options?: RunOptions, onProgress?: (progress: Progress) => void")]
+pub async fn patch(
+    options: Option<RunOptions>,
+    on_progress: Option<ThreadsafeFunction<Progress, ErrorStrategy::Fatal>>,
+) -> Result<serde_json::Value> {
+    let (config, channel, output) = resolve(options)?;
+    let build = latest_build(&channel).await?;
+    let patch_options = config.patch_options().map_err(to_napi)?;
+
+    let sink = on_progress.map(|callback| CallbackSink {
+        callback,
+        state: ProgressState::default(),
+    });
+    let progress = sink.as_ref().map(|sink| sink as &dyn ProgressSink);
+    let report = patcher::process_build(&build, &output, &patch_options, progress)
+        .await
+        .map_err(to_napi)?;
+
+    let mut value = serde_json::to_value(&report).map_err(to_napi)?;
+    value["output"] = serde_json::to_value(patcher::build_dir(&output, &build.version, false))
+        .map_err(to_napi)?;
+    Ok(value)
+}
+
+/// Forwards progress to a JavaScript callback
+struct CallbackSink {
+    callback: ThreadsafeFunction<Progress, ErrorStrategy::Fatal>,
+    state: ProgressState,
+}
+
+impl ProgressSink for CallbackSink {
+    fn event(&self, event: Event) {
+        self.state.event(event);
+        let snapshot = self.state.snapshot();
+        self.callback.call(
+            Progress {
+                percent: snapshot.percent as u32,
+                message: snapshot.message(),
+            },
+            ThreadsafeFunctionCallMode::NonBlocking,
+        );
+    }
+}
+
+fn resolve(options: Option<RunOptions>) -> Result<(Config, String, std::path::PathBuf)> {
+    let options = options.unwrap_or(RunOptions {
+        channel: None,
+        output: None,
+        config: None,
+    });
+    let config = load_config(options.config.as_deref())?;
+    let channel = channel_of(&config, options.channel);
+    let output = config.output_dir(options.output.as_deref().map(std::path::Path::new));
+    paths::ensure_writable(&output).map_err(to_napi)?;
+    Ok((config, channel, output))
+}
+
+fn load_config(path: Option<&str>) -> Result<Config> {
+    let config = Config::load(path.map(std::path::Path::new)).map_err(to_napi)?;
+    if let Some(proxy) = &config.proxy {
+        api::set_proxy(proxy);
+    }
+    Ok(config)
+}
+
+fn channel_of(config: &Config, channel: Option<String>) -> String {
+    channel
+        .or_else(|| config.channel.clone())
+        .unwrap_or_else(|| api::DEFAULT_CHANNEL.to_string())
+}
+
+async fn latest_build(channel: &str) -> Result<AppBuild> {
+    api::get_latest_build(channel)
+        .await
+        .map_err(to_napi)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| Error::from_reason("No builds found"))
+}
+
+fn to_napi(e: impl std::fmt::Display) -> Error {
+    Error::from_reason(format!("{:#}", e))
+}
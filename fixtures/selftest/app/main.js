@@ -0,0 +1,6 @@
+(self.webpackChunk=self.webpackChunk||[]).push([[179],{4242:function(e,t,n){"use strict";
+const account={uid:0,hasPlus:!1,isSubscriptionActive:!1,canUseHq:!1};
+const status=JSON.parse('{"hasPlus": false}');
+class Subscription{isPlusUser(){return this.account.hasPlus&&this.account.active}}
+const experiments={"WebNextLossless":"off","WebNextQualitySelector":"default"};
+}}]);
@@ -0,0 +1,10 @@
+"use strict";
+Object.defineProperty(exports, "__esModule", { value: true });
+exports.config = void 0;
+exports.config = {
+    app: {
+        enableDevTools: false,
+        enableAutoUpdate: true,
+        enableUpdateByProbability: true,
+    },
+};
@@ -0,0 +1,7 @@
+"use strict";
+Object.defineProperty(exports, "__esModule", { value: true });
+const electron_1 = require("electron");
+const createWindow_js_1 = require("./lib/createWindow.js");
+electron_1.app.whenReady().then(() => {
+    (0, createWindow_js_1.createWindow)();
+});
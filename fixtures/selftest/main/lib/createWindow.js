@@ -0,0 +1,20 @@
+"use strict";
+Object.defineProperty(exports, "__esModule", { value: true });
+exports.createWindow = void 0;
+const electron_1 = require("electron");
+const config_js_1 = require("../config.js");
+const createWindow = () => {
+    const window = new electron_1.BrowserWindow({
+        width: 1280,
+        height: 800,
+        minWidth: 768,
+        minHeight: 650,
+        show: false,
+        titleBarStyle: 'hidden',
+        webPreferences: {
+            devTools: config_js_1.config.app.enableDevTools,
+        },
+    });
+    return window;
+};
+exports.createWindow = createWindow;
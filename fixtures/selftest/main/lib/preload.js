@@ -0,0 +1,5 @@
+"use strict";
+const electron_1 = require("electron");
+electron_1.contextBridge.exposeInMainWorld("desktopEvents", {
+    send: (name, ...args) => electron_1.ipcRenderer.send(name, ...args),
+});
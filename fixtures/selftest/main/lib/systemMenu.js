@@ -0,0 +1,12 @@
+"use strict";
+Object.defineProperty(exports, "__esModule", { value: true });
+exports.setupSystemMenu = void 0;
+const electron_1 = require("electron");
+const deviceInfo_js_1 = require("./deviceInfo.js");
+const platform_js_1 = require("../types/platform.js");
+const setupSystemMenu = () => {
+    if (deviceInfo_js_1.devicePlatform === platform_js_1.Platform.MACOS) {
+        electron_1.Menu.setApplicationMenu(electron_1.Menu.buildFromTemplate([]));
+    }
+};
+exports.setupSystemMenu = setupSystemMenu;
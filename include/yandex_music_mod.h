@@ -0,0 +1,57 @@
+/*
+ * C interface of the yandex-music-mod patch engine.
+ *
+ * Build with `cargo build --release`; link against the yandex_music_mod
+ * shared library (yandex_music_mod.dll, libyandex_music_mod.so or .dylib).
+ *
+ * Call ymm_init() once before anything else. Functions returning int return 0
+ * on success and one of the YMM_E_* codes on failure; functions returning
+ * pointers return NULL on failure. ymm_last_error() then describes the failure.
+ */
+
+#ifndef YANDEX_MUSIC_MOD_H
+#define YANDEX_MUSIC_MOD_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Failure classes; the same codes are the exit codes of the CLI. */
+#define YMM_E_GENERIC 1
+#define YMM_E_HTTP 10
+#define YMM_E_DOWNLOAD 11
+#define YMM_E_CHECKSUM 12
+#define YMM_E_MANIFEST 13
+#define YMM_E_BUILD_INFO 14
+#define YMM_E_MISSING_DEPENDENCY 20
+#define YMM_E_EXTRACTION 21
+#define YMM_E_ASAR 22
+#define YMM_E_FILE_NOT_FOUND 23
+#define YMM_E_PATCH 30
+#define YMM_E_IO 40
+#define YMM_E_DISK_SPACE 41
+#define YMM_E_LOCKED 42
+
+/* Overall progress (0-100) and current stage; `message` is only valid during the call. */
+typedef void (*ymm_progress_callback)(unsigned int percent, const char *message, void *user_data);
+
+/* Load the configuration (config.toml from the config directory if NULL) and start the engine. */
+int ymm_init(const char *config_path);
+
+/* Latest builds of `channel` (configured channel if NULL) as a JSON array. Free with ymm_string_free(). */
+char *ymm_fetch_latest(const char *channel);
+
+/* Patch the latest build of `channel` into `output` (configured values if NULL). Blocks until done. */
+int ymm_patch(const char *channel, const char *output, ymm_progress_callback callback, void *user_data);
+
+/* Message of the last failure on this thread, or NULL. Valid until the next call on this thread. */
+const char *ymm_last_error(void);
+
+/* Free a string returned by ymm_fetch_latest(). */
+void ymm_string_free(char *s);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* YANDEX_MUSIC_MOD_H */
@@ -3,43 +3,149 @@
 //! This module handles communication with the Yandex Music update server
 //! to fetch the latest stable builds and download them.
 
-use anyhow::Result;
-use serde::Deserialize;
+use crate::cassette;
+use crate::error::PatcherError;
+use crate::progress::{Event, ProgressSink};
+use anyhow::{Context, Result};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha512};
 use std::fs::File;
-use std::io::Write;
-use tracing::{debug, info};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::OnceLock;
+use tracing::{debug, info, warn};
 
 /// Update server base URL
 const UPDATE_DOMAIN: &str = "https://music-desktop-application.s3.yandex.net";
 
-/// Represents a single file in the update info
-#[derive(Debug, Deserialize)]
-struct UpdateFile {
-    url: String,
-    sha512: String,
-    size: u64,
+/// Update channel used when none is configured
+pub const DEFAULT_CHANNEL: &str = "stable";
+
+/// Update channels published on the update server
+pub const CHANNELS: &[&str] = &["stable", "beta", "alpha"];
+
+/// Proxy used for all requests, set once at startup
+static PROXY: OnceLock<String> = OnceLock::new();
+
+/// Base URLs tried in turn when downloading from the update server fails
+static MIRRORS: OnceLock<Vec<String>> = OnceLock::new();
+
+/// Architecture whose installer is taken, set once at startup
+static ARCH: OnceLock<Arch> = OnceLock::new();
+
+/// CPU architecture of an installer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "kebab-case")]
+pub enum Arch {
+    X64,
+    Arm64,
+}
+
+impl Arch {
+    /// Architecture of this machine. `PROCESSOR_IDENTIFIER` names the real CPU
+    /// even in an x64 patcher emulated on Windows on ARM.
+    pub fn current() -> Self {
+        let arm_cpu = std::env::var("PROCESSOR_IDENTIFIER")
+            .is_ok_and(|cpu| cpu.to_ascii_uppercase().starts_with("ARM"));
+        if cfg!(target_arch = "aarch64") || (cfg!(windows) && arm_cpu) {
+            Arch::Arm64
+        } else {
+            Arch::X64
+        }
+    }
+
+    /// Architecture named in an installer file name, e.g. `Yandex_Music_arm64_5.15.0.exe`
+    pub fn of_file(name: &str) -> Option<Self> {
+        let name = name.to_ascii_lowercase();
+        if name.contains("arm64") || name.contains("aarch64") {
+            Some(Arch::Arm64)
+        } else if ["x64", "amd64", "x86_64"].iter().any(|a| name.contains(a)) {
+            Some(Arch::X64)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Arch::X64 => "x64",
+            Arch::Arm64 => "arm64",
+        }
+    }
+}
+
+/// Take the installers of `arch` (by default the one of this machine)
+pub fn set_arch(arch: Arch) {
+    if ARCH.set(arch).is_ok() {
+        debug!("Selecting {} installers", arch.as_str());
+    }
 }
 
-/// Common configuration from the update server
-#[derive(Debug, Deserialize)]
-#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
-struct CommonConfig {
-    deprecated_versions: Option<String>,
+/// Architecture whose installer is taken
+pub fn arch() -> Arch {
+    *ARCH.get_or_init(Arch::current)
 }
 
-/// Raw update info structure from the YAML response
-#[derive(Debug, Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct UpdateInfo {
-    files: Vec<UpdateFile>,
-    release_date: Option<String>,
-    update_probability: Option<f64>,
-    version: String,
-    common_config: Option<CommonConfig>,
+/// Route all further requests through `url` (http, https or socks5)
+pub fn set_proxy(url: &str) {
+    if PROXY.set(url.to_string()).is_ok() {
+        info!("Using proxy {}", url);
+    }
+}
+
+/// Download installers from these base URLs (laid out like the update server)
+/// when the update server fails. Manifests are still fetched from the update
+/// server, and its SHA-512 is checked whichever mirror served the installer.
+pub fn set_mirrors(urls: &[String]) {
+    let urls: Vec<String> = urls
+        .iter()
+        .map(|url| url.trim_end_matches('/').to_string())
+        .collect();
+    if !urls.is_empty() && MIRRORS.set(urls).is_ok() {
+        info!(
+            "Using {} download mirror(s)",
+            MIRRORS.get().map_or(0, Vec::len)
+        );
+    }
+}
+
+/// Update server followed by the configured mirrors
+fn download_sources() -> Vec<&'static str> {
+    std::iter::once(UPDATE_DOMAIN)
+        .chain(MIRRORS.get().into_iter().flatten().map(String::as_str))
+        .collect()
 }
 
+/// HTTP client honoring the configured proxy
+fn client() -> Result<reqwest::Client> {
+    // GitHub's API rejects requests without a User-Agent
+    let mut builder = reqwest::Client::builder()
+        .user_agent(concat!("yandex-music-mod/", env!("CARGO_PKG_VERSION")));
+    if let Some(proxy) = PROXY.get() {
+        builder = builder.proxy(reqwest::Proxy::all(proxy)?);
+    }
+    Ok(builder.build()?)
+}
+
+/// Top-level fields of `latest.yml` the patcher reads
+const MANIFEST_FIELDS: [&str; 8] = [
+    "version",
+    "files",
+    "path",
+    "sha512",
+    "size",
+    "releaseDate",
+    "updateProbability",
+    "commonConfig",
+];
+
+/// Fields of a `files` entry the patcher reads
+const FILE_FIELDS: [&str; 4] = ["url", "path", "sha512", "size"];
+
 /// Processed build information
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[allow(dead_code)]
 pub struct AppBuild {
     pub path: String,
@@ -49,77 +155,496 @@ pub struct AppBuild {
     pub update_probability: Option<f64>,
     pub version: String,
     pub deprecated_versions: Option<String>,
+    /// Update channel the build was published to
+    pub channel: String,
 }
 
-/// Fetches the latest stable build information from the update server
-pub async fn get_stable_build() -> Result<Vec<AppBuild>> {
-    let url = format!("{}/stable/latest.yml", UPDATE_DOMAIN);
-    debug!("Fetching update info from: {}", url);
+impl AppBuild {
+    /// Architecture of the installer, if its file name tells
+    pub fn arch(&self) -> Option<Arch> {
+        Arch::of_file(&self.path)
+    }
+}
 
-    let client = reqwest::Client::new();
-    let response = client
-        .get(&url)
-        .header(
-            "User-Agent",
-            "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36",
-        )
-        .send()
-        .await?;
+/// Put the installers of `arch` first, then those of no stated architecture.
+/// Without an arm64 installer the x64 one is taken, which Windows on ARM emulates.
+pub fn order_for_arch(builds: &mut [AppBuild], arch: Arch) {
+    builds.sort_by_key(|build| match build.arch() {
+        Some(a) if a == arch => 0,
+        None => 1,
+        Some(_) => 2,
+    });
+    if let Some(first) = builds.first() {
+        if first.arch().is_some_and(|a| a != arch) {
+            warn!(
+                "No {} installer of Yandex Music {}; taking {}",
+                arch.as_str(),
+                first.version,
+                first.path
+            );
+        }
+    }
+}
 
-    let yaml_text = response.text().await?;
+/// Fetches the latest build information of `channel` from the update server,
+/// the installer of the selected architecture (see [`set_arch`]) first
+pub async fn get_latest_build(channel: &str) -> Result<Vec<AppBuild>> {
+    let url = format!("{}/{}/latest.yml", UPDATE_DOMAIN, channel);
+    debug!("Fetching update info from: {}", url);
+
+    let yaml_text = match cassette::replay(&url) {
+        Some(body) => String::from_utf8_lossy(&body?).into_owned(),
+        None => {
+            let client = client()?;
+            let response = client
+                .get(&url)
+                .header(
+                    "User-Agent",
+                    "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36",
+                )
+                .send()
+                .await?;
+            let yaml_text = response.text().await?;
+            cassette::record(&url, yaml_text.as_bytes())?;
+            yaml_text
+        }
+    };
     debug!("Received YAML response:\n{}", yaml_text);
 
-    let info: UpdateInfo = serde_yaml::from_str(&yaml_text)?;
-    debug!("Parsed update info: {:?}", info);
+    let mut builds = parse_manifest(&yaml_text, channel)?;
+    info!("Found {} build(s)", builds.len());
+    order_for_arch(&mut builds, arch());
+    Ok(builds)
+}
 
-    let deprecated_versions = info
-        .common_config
-        .as_ref()
-        .and_then(|c| c.deprecated_versions.clone());
+/// Latest builds of every channel in [`CHANNELS`], fetched concurrently
+pub async fn get_all_channels() -> Vec<(&'static str, Result<Vec<AppBuild>>)> {
+    let tasks: Vec<_> = CHANNELS
+        .iter()
+        .map(|&channel| (channel, tokio::spawn(get_latest_build(channel))))
+        .collect();
+    let mut results = Vec::new();
+    for (channel, task) in tasks {
+        let result = match task.await {
+            Ok(result) => result,
+            Err(e) => Err(e.into()),
+        };
+        results.push((channel, result));
+    }
+    results
+}
 
-    let builds: Vec<AppBuild> = info
-        .files
+/// Builds listed in a `latest.yml` of `channel`.
+///
+/// Parsed leniently so new or changed fields don't break runs: only a version
+/// and at least one installer with a hash are required, numbers may be quoted,
+/// and manifests without `files` fall back to the top-level `path`/`sha512`.
+/// Fields the patcher doesn't know are logged at debug level.
+pub fn parse_manifest(yaml: &str, channel: &str) -> Result<Vec<AppBuild>> {
+    let manifest: serde_yaml::Value =
+        serde_yaml::from_str(yaml).map_err(|e| PatcherError::YamlParseError(e.to_string()))?;
+    let manifest = manifest
+        .as_mapping()
+        .ok_or_else(|| PatcherError::InvalidBuildInfo("latest.yml is not a mapping".to_string()))?;
+    log_unknown_fields(manifest, &MANIFEST_FIELDS, "latest.yml");
+
+    let version = string_field(manifest, "version")
+        .ok_or_else(|| PatcherError::InvalidBuildInfo("latest.yml has no version".to_string()))?;
+
+    let mut files: Vec<_> = manifest
+        .get("files")
+        .and_then(|files| files.as_sequence())
         .into_iter()
-        .map(|file| AppBuild {
-            path: file.url,
-            hash: file.sha512,
-            size: file.size,
-            release_date: info.release_date.clone(),
-            update_probability: info.update_probability,
-            version: info.version.clone(),
-            deprecated_versions: deprecated_versions.clone(),
+        .flatten()
+        .filter_map(|file| {
+            let file = file.as_mapping()?;
+            log_unknown_fields(file, &FILE_FIELDS, "latest.yml files entry");
+            let path = string_field(file, "url").or_else(|| string_field(file, "path"));
+            Some((
+                path?,
+                string_field(file, "sha512")?,
+                number_field(file, "size"),
+            ))
         })
         .collect();
+    // Older electron-updater manifests only describe the installer at the top level
+    if files.is_empty() {
+        if let (Some(path), Some(hash)) = (
+            string_field(manifest, "path"),
+            string_field(manifest, "sha512"),
+        ) {
+            files.push((path, hash, number_field(manifest, "size")));
+        }
+    }
+    if files.is_empty() {
+        return Err(PatcherError::InvalidBuildInfo(format!(
+            "latest.yml of {} lists no installer",
+            version
+        ))
+        .into());
+    }
 
-    info!("Found {} build(s)", builds.len());
-    Ok(builds)
+    let release_date = string_field(manifest, "releaseDate");
+    let update_probability = number_field(manifest, "updateProbability");
+    let deprecated_versions = manifest
+        .get("commonConfig")
+        .and_then(|config| config.as_mapping())
+        .and_then(|config| string_field(config, "DEPRECATED_VERSIONS"));
+
+    Ok(files
+        .into_iter()
+        .map(|(path, hash, size)| {
+            if size.is_none() {
+                debug!("No size for {} in latest.yml", path);
+            }
+            AppBuild {
+                path,
+                hash,
+                size: size.unwrap_or(0.0) as u64,
+                release_date: release_date.clone(),
+                update_probability,
+                version: version.clone(),
+                deprecated_versions: deprecated_versions.clone(),
+                channel: channel.to_string(),
+            }
+        })
+        .collect())
 }
 
-/// Downloads a build from the update server to the specified path
-pub async fn download_build(build: &AppBuild, output_path: &str) -> Result<()> {
-    let url = format!("{}/stable/{}", UPDATE_DOMAIN, build.path);
-    info!("Downloading build from: {}", url);
+/// A string field; numbers and booleans are taken as written
+fn string_field(map: &serde_yaml::Mapping, key: &str) -> Option<String> {
+    match map.get(key)? {
+        serde_yaml::Value::String(s) => Some(s.clone()),
+        serde_yaml::Value::Number(n) => Some(n.to_string()),
+        serde_yaml::Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
 
-    let client = reqwest::Client::new();
-    let response = client.get(&url).send().await?;
+/// A numeric field, which may also be a quoted number
+fn number_field(map: &serde_yaml::Mapping, key: &str) -> Option<f64> {
+    match map.get(key)? {
+        serde_yaml::Value::Number(n) => n.as_f64(),
+        serde_yaml::Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
 
-    let bytes = response.bytes().await?;
-    info!("Downloaded {} bytes", bytes.len());
+fn log_unknown_fields(map: &serde_yaml::Mapping, known: &[&str], context: &str) {
+    for key in map.keys() {
+        let key = key.as_str().unwrap_or_default();
+        if !known.contains(&key) {
+            debug!("Ignoring unknown field {:?} in {}", key, context);
+        }
+    }
+}
+
+/// Fetches a text document (e.g. a remote selector list)
+pub async fn fetch_text(url: &str) -> Result<String> {
+    Ok(String::from_utf8_lossy(&fetch_bytes(url).await?).into_owned())
+}
 
-    let mut file = File::create(output_path)?;
-    file.write_all(&bytes)?;
+/// Fetches a binary file (e.g. a release asset)
+pub async fn fetch_bytes(url: &str) -> Result<Vec<u8>> {
+    if let Some(body) = cassette::replay(url) {
+        return body;
+    }
+    debug!("Fetching {}", url);
+    let response = client()?.get(url).send().await?.error_for_status()?;
+    let body = response.bytes().await?.to_vec();
+    cassette::record(url, &body)?;
+    Ok(body)
+}
 
-    info!("Saved to: {}", output_path);
+/// POSTs `body` as JSON (e.g. a webhook notification)
+pub async fn post_json<T: Serialize + ?Sized>(url: &str, body: &T) -> Result<()> {
+    if cassette::is_replaying() {
+        debug!("Replaying, not posting to {}", url);
+        return Ok(());
+    }
+    debug!("Posting to {}", url);
+    client()?
+        .post(url)
+        .json(body)
+        .send()
+        .await?
+        .error_for_status()?;
     Ok(())
 }
 
+/// Downloads a build from the update server to the specified path,
+/// reporting the received bytes to `progress`.
+///
+/// The body is streamed to disk chunk by chunk and hashed on the way, so the
+/// installer is never held in memory. Returns its base64-encoded SHA-512, as
+/// published in `latest.yml`. When the download fails or does not match the
+/// published SHA-512, the mirrors (see [`set_mirrors`]) are tried in turn; the
+/// outcome of the last one is returned.
+pub async fn download_build(
+    build: &AppBuild,
+    output_path: &Path,
+    progress: Option<&dyn ProgressSink>,
+) -> Result<String> {
+    download_with_failover(build, &download_sources(), output_path, progress).await
+}
+
+async fn download_with_failover(
+    build: &AppBuild,
+    sources: &[&str],
+    output_path: &Path,
+    progress: Option<&dyn ProgressSink>,
+) -> Result<String> {
+    let mut sources = sources.iter().peekable();
+    while let Some(base) = sources.next() {
+        let url = format!("{}/{}/{}", base, build.channel, build.path);
+        let result = download_url(&url, output_path, progress).await;
+        if sources.peek().is_none() {
+            return result;
+        }
+        match result {
+            Ok(hash) if build.hash.is_empty() || hash == build.hash => return Ok(hash),
+            Ok(_) => warn!(
+                "{} does not match the published SHA-512, trying a mirror",
+                url
+            ),
+            Err(e) => warn!("Download from {} failed, trying a mirror: {:#}", url, e),
+        }
+    }
+    anyhow::bail!("No download source for {}", build.path)
+}
+
+/// Streams `url` to `output_path`, returning its base64-encoded SHA-512
+async fn download_url(
+    url: &str,
+    output_path: &Path,
+    progress: Option<&dyn ProgressSink>,
+) -> Result<String> {
+    info!("Downloading build from: {}", url);
+
+    if let Some(recorded) = cassette::replay_file(url) {
+        let downloaded = std::fs::copy(recorded?, output_path)?;
+        if let Some(progress) = progress {
+            progress.event(Event::Bytes {
+                downloaded,
+                total: Some(downloaded),
+            });
+        }
+        info!("Replayed {} bytes to {:?}", downloaded, output_path);
+        return file_sha512(output_path);
+    }
+
+    let client = client()?;
+    let mut response = client.get(url).send().await?.error_for_status()?;
+    let total = response.content_length();
+
+    let file =
+        File::create(output_path).with_context(|| format!("Failed to create {:?}", output_path))?;
+    let mut file = BufWriter::new(file);
+    let mut hasher = Sha512::new();
+    let mut downloaded = 0;
+    while let Some(chunk) = response.chunk().await? {
+        file.write_all(&chunk)?;
+        hasher.update(&chunk);
+        downloaded += chunk.len() as u64;
+        if let Some(progress) = progress {
+            progress.event(Event::Bytes { downloaded, total });
+        }
+    }
+    file.flush()?;
+    drop(file);
+    if let Some(total) = total.filter(|&total| total != downloaded) {
+        anyhow::bail!(
+            "Download of {} ended after {} of {} bytes",
+            url,
+            downloaded,
+            total
+        );
+    }
+    info!("Downloaded {} bytes", downloaded);
+    cassette::record_file(url, output_path)?;
+
+    info!("Saved to: {:?}", output_path);
+    Ok(base64::engine::general_purpose::STANDARD.encode(hasher.finalize()))
+}
+
+/// Result of checking a local installer against the update manifest
+#[derive(Debug, Clone, Serialize)]
+pub struct Verification {
+    /// Version of the manifest entry the file was compared with
+    pub version: String,
+    pub file: String,
+    pub expected_size: u64,
+    pub actual_size: u64,
+    pub size_ok: bool,
+    pub hash_ok: bool,
+}
+
+impl Verification {
+    pub fn is_valid(&self) -> bool {
+        self.size_ok && self.hash_ok
+    }
+}
+
+/// Base64-encoded SHA-512 of a file, as published in `latest.yml`
+pub fn file_sha512(path: &Path) -> Result<String> {
+    let mut file = File::open(path).with_context(|| format!("Failed to open {:?}", path))?;
+    let mut hasher = Sha512::new();
+    std::io::copy(&mut file, &mut hasher)?;
+    Ok(base64::engine::general_purpose::STANDARD.encode(hasher.finalize()))
+}
+
+/// Check `path` against the published builds.
+///
+/// The file is compared with the entry whose hash matches, else the one with the
+/// same file name, else the first build.
+pub fn verify_file(path: &Path, builds: &[AppBuild]) -> Result<Verification> {
+    let first = builds.first().context("No builds found")?;
+    let actual_size = std::fs::metadata(path)
+        .with_context(|| format!("Failed to read {:?}", path))?
+        .len();
+    let hash = file_sha512(path)?;
+    let file_name = path.file_name();
+
+    let build = builds
+        .iter()
+        .find(|b| b.hash == hash)
+        .or_else(|| {
+            builds
+                .iter()
+                .find(|b| file_name == Some(std::ffi::OsStr::new(&b.path)))
+        })
+        .unwrap_or(first);
+
+    Ok(Verification {
+        version: build.version.clone(),
+        file: build.path.clone(),
+        expected_size: build.size,
+        actual_size,
+        size_ok: build.size == actual_size,
+        hash_ok: build.hash == hash,
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_verify_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("Yandex_Music.exe");
+        std::fs::write(&path, b"installer").unwrap();
+
+        let build = |path: &str, hash: String, size: u64| AppBuild {
+            path: path.to_string(),
+            hash,
+            size,
+            release_date: None,
+            update_probability: None,
+            version: "5.0.0".to_string(),
+            deprecated_versions: None,
+            channel: DEFAULT_CHANNEL.to_string(),
+        };
+        let hash = file_sha512(&path).unwrap();
+
+        let builds = [
+            build("other.exe", "x".to_string(), 1),
+            build("Yandex_Music.exe", hash.clone(), 9),
+        ];
+        let result = verify_file(&path, &builds).unwrap();
+        assert!(result.is_valid());
+        assert_eq!(result.file, "Yandex_Music.exe");
+
+        let builds = [build("Yandex_Music.exe", "x".to_string(), 9)];
+        let result = verify_file(&path, &builds).unwrap();
+        assert!(result.size_ok && !result.hash_ok);
+    }
+
+    /// Historical and synthetic `latest.yml` files in `testdata/manifests`:
+    /// file name, version, first installer, its size and number of installers
+    const MANIFEST_CORPUS: [(&str, &str, &str, u64, usize); 4] = [
+        (
+            "2023-electron-updater.yml",
+            "5.0.9",
+            "Yandex_Music_x64_5.0.9.exe",
+            98234112,
+            1,
+        ),
+        (
+            "2024-common-config.yml",
+            "5.15.0",
+            "Yandex_Music_x64_5.15.0.exe",
+            104857600,
+            2,
+        ),
+        (
+            "future-extra-fields.yml",
+            "5.40.0",
+            "Yandex_Music_x64_5.40.0.exe",
+            112459776,
+            1,
+        ),
+        (
+            "legacy-top-level-only.yml",
+            "4.8.2",
+            "Yandex_Music_4.8.2.exe",
+            0,
+            1,
+        ),
+    ];
+
+    #[test]
+    fn test_parse_manifest_corpus() {
+        let corpus = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/manifests");
+        let mut files: Vec<_> = std::fs::read_dir(&corpus)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        files.sort();
+        assert_eq!(
+            files,
+            MANIFEST_CORPUS.map(|(file, ..)| file),
+            "every corpus file needs an expectation"
+        );
+
+        for (file, version, path, size, count) in MANIFEST_CORPUS {
+            let yaml = std::fs::read_to_string(corpus.join(file)).unwrap();
+            let builds = parse_manifest(&yaml, DEFAULT_CHANNEL)
+                .unwrap_or_else(|e| panic!("{}: {:#}", file, e));
+            assert_eq!(builds.len(), count, "{}", file);
+            assert_eq!(builds[0].version, version, "{}", file);
+            assert_eq!(builds[0].path, path, "{}", file);
+            assert_eq!(builds[0].size, size, "{}", file);
+            assert!(!builds[0].hash.is_empty(), "{}", file);
+        }
+
+        let builds = parse_manifest(
+            &std::fs::read_to_string(corpus.join("2024-common-config.yml")).unwrap(),
+            "beta",
+        )
+        .unwrap();
+        assert_eq!(builds[1].deprecated_versions.as_deref(), Some("<=5.0.0"));
+        assert_eq!(builds[1].update_probability, Some(0.5));
+        assert_eq!(builds[1].channel, "beta");
+    }
+
+    #[test]
+    fn test_parse_manifest_errors() {
+        let code = |yaml: &str| crate::error::code(&parse_manifest(yaml, "stable").unwrap_err());
+        assert_eq!(code("version: [unclosed"), 13);
+        assert_eq!(code("- just a list"), 14);
+        assert_eq!(code("files: []\npath: a.exe\nsha512: x"), 14);
+        assert_eq!(code("version: 5.0.0\nfiles:\n  - url: a.exe"), 14);
+    }
+
     #[tokio::test]
     async fn test_get_stable_build() {
-        let result = get_stable_build().await;
+        // Replays a recorded response; rerun with `--record testdata/http` to refresh it
+        cassette::set(
+            &Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/http"),
+            cassette::Mode::Replay,
+        );
+        let result = get_latest_build(DEFAULT_CHANNEL).await;
         assert!(result.is_ok(), "Failed to get stable build: {:?}", result);
 
         let builds = result.unwrap();
@@ -130,4 +655,96 @@ mod tests {
         assert!(!build.path.is_empty(), "Path should not be empty");
         assert!(!build.hash.is_empty(), "Hash should not be empty");
     }
+
+    #[tokio::test]
+    async fn test_download_build_hash() {
+        let recorded = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/http");
+        cassette::set(&recorded, cassette::Mode::Replay);
+        let build = AppBuild {
+            path: "Yandex_Music_x64_0.0.1.exe".to_string(),
+            hash: String::new(),
+            size: 0,
+            release_date: None,
+            update_probability: None,
+            version: "0.0.1".to_string(),
+            deprecated_versions: None,
+            channel: "stable".to_string(),
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("build.exe");
+        let hash = download_build(&build, &output, None).await.unwrap();
+        assert_eq!(hash, file_sha512(&output).unwrap());
+        assert_eq!(std::fs::read(&output).unwrap(), b"MZ fixture installer\n");
+    }
+
+    #[tokio::test]
+    async fn test_download_failover() {
+        let recorded = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/http");
+        cassette::set(&recorded, cassette::Mode::Replay);
+        let mut build = AppBuild {
+            path: "Yandex_Music_x64_0.0.1.exe".to_string(),
+            hash: String::new(),
+            size: 0,
+            release_date: None,
+            update_probability: None,
+            version: "0.0.1".to_string(),
+            deprecated_versions: None,
+            channel: "stable".to_string(),
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("build.exe");
+        let hash = download_with_failover(&build, &[UPDATE_DOMAIN], &output, None)
+            .await
+            .unwrap();
+
+        // The unreachable mirror is skipped
+        build.hash = hash.clone();
+        let sources = ["https://mirror.invalid", UPDATE_DOMAIN];
+        let served = download_with_failover(&build, &sources, &output, None)
+            .await
+            .unwrap();
+        assert_eq!(served, hash);
+
+        // A source serving other bytes than published is skipped too
+        build.hash = "bm90IHRoZSBpbnN0YWxsZXI=".to_string();
+        let last = download_with_failover(&build, &[UPDATE_DOMAIN, UPDATE_DOMAIN], &output, None)
+            .await
+            .unwrap();
+        assert_eq!(last, hash);
+        assert!(download_with_failover(
+            &build,
+            &[UPDATE_DOMAIN, "https://mirror.invalid"],
+            &output,
+            None
+        )
+        .await
+        .is_err());
+    }
+
+    #[test]
+    fn test_order_for_arch() {
+        let yaml = std::fs::read_to_string(
+            Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("testdata/http/music-desktop-application.s3.yandex.net_stable_latest.yml"),
+        )
+        .unwrap();
+        let mut builds = parse_manifest(&yaml, "stable").unwrap();
+        assert_eq!(builds[0].arch(), Some(Arch::X64));
+
+        order_for_arch(&mut builds, Arch::Arm64);
+        assert_eq!(builds[0].path, "Yandex_Music_arm64_5.15.0.exe");
+        order_for_arch(&mut builds, Arch::X64);
+        assert_eq!(builds[0].path, "Yandex_Music_x64_5.15.0.exe");
+
+        // Without an arm64 installer the x64 one stays first
+        builds.retain(|build| build.arch() == Some(Arch::X64));
+        order_for_arch(&mut builds, Arch::Arm64);
+        assert_eq!(builds[0].arch(), Some(Arch::X64));
+
+        assert_eq!(
+            Arch::of_file("yandex-music-5.15.0-aarch64.AppImage"),
+            Some(Arch::Arm64)
+        );
+        assert_eq!(Arch::of_file("Yandex_Music_5.15.0.exe"), None);
+    }
 }
@@ -0,0 +1,368 @@
+//! Asar module - packs a patched app into an ASAR archive
+//!
+//! With `--pack-asar` the `mod` tree is also written as `app.asar` in the build
+//! directory, to replace the one in an installed app's `resources`. Files are
+//! hashed in parallel for the per-file `integrity` Electron checks when asar
+//! integrity validation is enabled, then written in order: the paths of an
+//! ordering file (as for `asar pack --ordering`) first, the rest sorted. Files
+//! matching `--asar-unpack` globs are copied to `app.asar.unpacked` instead, as
+//! Electron can't load e.g. native modules from inside the archive. Entries are
+//! never compressed: Electron reads them in place.
+
+use anyhow::{Context, Result};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use tracing::{debug, info};
+use walkdir::WalkDir;
+
+/// Name of the packed archive in the build directory
+pub const ASAR_FILE: &str = "app.asar";
+
+/// Block size of the integrity hashes, as in `@electron/asar`
+const INTEGRITY_BLOCK_SIZE: usize = 4 * 1024 * 1024;
+
+/// How to pack the archive
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AsarOptions {
+    /// File listing paths to place first, one per line (`asar --ordering`)
+    pub ordering: Option<PathBuf>,
+    /// Globs of files kept outside the archive in `app.asar.unpacked`
+    pub unpack: Vec<String>,
+    /// Threads hashing files [default: one per CPU]
+    pub jobs: Option<usize>,
+}
+
+/// Size and timings of a packed archive, for the patch report
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AsarReport {
+    pub path: PathBuf,
+    pub files: usize,
+    /// Files written to `app.asar.unpacked`
+    pub unpacked: usize,
+    /// Bytes of file data in the archive
+    pub bytes: u64,
+    pub hash_ms: u64,
+    pub write_ms: u64,
+    /// Throughput of the whole run in MiB/s
+    pub mib_per_sec: f64,
+}
+
+/// Entry of the tree, by path relative to the app root
+enum Node {
+    File {
+        path: PathBuf,
+        size: u64,
+        executable: bool,
+        unpacked: bool,
+    },
+    Link(String),
+}
+
+/// Pack `app_dir` into `dst`, with unpacked files next to it
+pub fn pack(app_dir: &Path, dst: &Path, options: &AsarOptions) -> Result<AsarReport> {
+    let started = Instant::now();
+    let unpack = unpack_globs(&options.unpack)?;
+    let mut nodes = collect(app_dir, &unpack)?;
+    order(&mut nodes, options.ordering.as_deref())?;
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(options.jobs.unwrap_or(0))
+        .build()?;
+    let integrity: Vec<Option<serde_json::Value>> = pool.install(|| {
+        nodes
+            .par_iter()
+            .map(|(_, node)| match node {
+                Node::File { path, .. } => integrity(path).map(Some),
+                Node::Link(_) => Ok(None),
+            })
+            .collect::<Result<_>>()
+    })?;
+    let hashed = started.elapsed();
+
+    let mut header = serde_json::json!({ "files": {} });
+    let mut offset = 0u64;
+    let mut report = AsarReport {
+        path: dst.to_path_buf(),
+        files: 0,
+        unpacked: 0,
+        bytes: 0,
+        hash_ms: hashed.as_millis() as u64,
+        write_ms: 0,
+        mib_per_sec: 0.0,
+    };
+    for ((relative, node), integrity) in nodes.iter().zip(integrity) {
+        let entry = match node {
+            Node::Link(target) => serde_json::json!({ "link": target }),
+            Node::File {
+                size,
+                executable,
+                unpacked,
+                ..
+            } => {
+                let mut entry = serde_json::json!({ "size": size, "integrity": integrity });
+                if *unpacked {
+                    entry["unpacked"] = true.into();
+                    report.unpacked += 1;
+                } else {
+                    // Offsets are strings: sizes past 2^53 don't fit a JS number
+                    entry["offset"] = offset.to_string().into();
+                    offset += size;
+                }
+                if *executable {
+                    entry["executable"] = true.into();
+                }
+                report.files += 1;
+                entry
+            }
+        };
+        insert(&mut header, relative, entry)?;
+    }
+    report.bytes = offset;
+
+    write(dst, &header, &nodes)?;
+    report.write_ms = (started.elapsed() - hashed).as_millis() as u64;
+    let secs = started.elapsed().as_secs_f64().max(0.001);
+    report.mib_per_sec = (offset as f64 / (1024.0 * 1024.0) / secs * 10.0).round() / 10.0;
+    info!(
+        "Packed {} file(s) into {:?} in {:?}",
+        report.files,
+        dst,
+        started.elapsed()
+    );
+    Ok(report)
+}
+
+fn unpack_globs(patterns: &[String]) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(
+            Glob::new(pattern)
+                .with_context(|| format!("Invalid --asar-unpack glob {}", pattern))?,
+        );
+    }
+    Ok(builder.build()?)
+}
+
+/// Files and links below `app_dir`, sorted by path
+fn collect(app_dir: &Path, unpack: &GlobSet) -> Result<Vec<(String, Node)>> {
+    let mut nodes = Vec::new();
+    for entry in WalkDir::new(app_dir).sort_by_file_name() {
+        let entry = entry?;
+        let ty = entry.file_type();
+        if ty.is_dir() {
+            continue;
+        }
+        let relative = entry
+            .path()
+            .strip_prefix(app_dir)?
+            .to_string_lossy()
+            .replace('\\', "/");
+
+        if ty.is_symlink() {
+            // Stored relative to the archive root, as `asar pack` does
+            let target = fs::canonicalize(entry.path())?;
+            let root = fs::canonicalize(app_dir)?;
+            let Ok(target) = target.strip_prefix(&root) else {
+                anyhow::bail!("{:?} links outside the app", entry.path());
+            };
+            nodes.push((
+                relative,
+                Node::Link(target.to_string_lossy().replace('\\', "/")),
+            ));
+            continue;
+        }
+
+        let metadata = entry.metadata()?;
+        #[cfg(unix)]
+        let executable = {
+            use std::os::unix::fs::PermissionsExt;
+            metadata.permissions().mode() & 0o111 != 0
+        };
+        #[cfg(not(unix))]
+        let executable = false;
+        nodes.push((
+            relative.clone(),
+            Node::File {
+                path: entry.path().to_path_buf(),
+                size: metadata.len(),
+                executable,
+                unpacked: unpack.is_match(&relative),
+            },
+        ));
+    }
+    Ok(nodes)
+}
+
+/// Move the paths listed in `ordering` to the front, in the listed order
+fn order(nodes: &mut [(String, Node)], ordering: Option<&Path>) -> Result<()> {
+    let Some(ordering) = ordering else {
+        return Ok(());
+    };
+    let content = fs::read_to_string(ordering)
+        .with_context(|| format!("Failed to read ordering file {:?}", ordering))?;
+    let mut rank = HashMap::new();
+    for line in content.lines() {
+        // `asar` orderings may prefix paths with `<anything>:`
+        let line = line.rsplit(':').next().unwrap_or(line).trim();
+        let path = line.trim_start_matches('/');
+        if !path.is_empty() && !line.starts_with('#') {
+            let next = rank.len();
+            rank.entry(path.to_string()).or_insert(next);
+        }
+    }
+    debug!("Ordering {} listed file(s) first", rank.len());
+    nodes.sort_by_key(|(path, _)| rank.get(path).copied().unwrap_or(usize::MAX));
+    Ok(())
+}
+
+/// Integrity of a file as `@electron/asar` writes it
+fn integrity(path: &Path) -> Result<serde_json::Value> {
+    let mut file = fs::File::open(path).with_context(|| format!("Failed to read {:?}", path))?;
+    let mut whole = Sha256::new();
+    let mut blocks = Vec::new();
+    let mut block = vec![0; INTEGRITY_BLOCK_SIZE];
+    loop {
+        let mut filled = 0;
+        while filled < block.len() {
+            match file.read(&mut block[filled..])? {
+                0 => break,
+                n => filled += n,
+            }
+        }
+        if filled == 0 && !blocks.is_empty() {
+            break;
+        }
+        whole.update(&block[..filled]);
+        blocks.push(hex::encode(Sha256::digest(&block[..filled])));
+        if filled < block.len() {
+            break;
+        }
+    }
+    Ok(serde_json::json!({
+        "algorithm": "SHA256",
+        "hash": hex::encode(whole.finalize()),
+        "blockSize": INTEGRITY_BLOCK_SIZE,
+        "blocks": blocks,
+    }))
+}
+
+/// Insert `entry` at `path` into the header, creating its directories
+fn insert(header: &mut serde_json::Value, path: &str, entry: serde_json::Value) -> Result<()> {
+    let mut node = header;
+    let mut parts = path.split('/').peekable();
+    while let Some(name) = parts.next() {
+        let files = node["files"]
+            .as_object_mut()
+            .with_context(|| format!("{} is both a file and a directory", path))?;
+        if parts.peek().is_none() {
+            files.insert(name.to_string(), entry);
+            return Ok(());
+        }
+        node = files
+            .entry(name)
+            .or_insert_with(|| serde_json::json!({ "files": {} }));
+    }
+    Ok(())
+}
+
+/// Write the header and the file data, and copy the unpacked files
+fn write(dst: &Path, header: &serde_json::Value, nodes: &[(String, Node)]) -> Result<()> {
+    let json = serde_json::to_vec(header)?;
+    let padding = (4 - json.len() % 4) % 4;
+    let payload = 4 + json.len() + padding;
+
+    let mut out = BufWriter::new(
+        fs::File::create(dst).with_context(|| format!("Failed to create {:?}", dst))?,
+    );
+    // Size pickle (4, header size), then the header pickle (payload size, JSON length, JSON)
+    out.write_all(&4u32.to_le_bytes())?;
+    out.write_all(&(payload as u32 + 4).to_le_bytes())?;
+    out.write_all(&(payload as u32).to_le_bytes())?;
+    out.write_all(&(json.len() as u32).to_le_bytes())?;
+    out.write_all(&json)?;
+    out.write_all(&[0; 3][..padding])?;
+
+    let mut unpacked_dir = dst.as_os_str().to_owned();
+    unpacked_dir.push(".unpacked");
+    let unpacked_dir = PathBuf::from(unpacked_dir);
+    if unpacked_dir.exists() {
+        crate::paths::remove_dir_all(&unpacked_dir)?;
+    }
+    for (relative, node) in nodes {
+        let Node::File { path, unpacked, .. } = node else {
+            continue;
+        };
+        if *unpacked {
+            let target = unpacked_dir.join(relative);
+            fs::create_dir_all(target.parent().unwrap_or(&unpacked_dir))?;
+            crate::paths::copy_file(path, &target)?;
+        } else {
+            let mut file =
+                fs::File::open(path).with_context(|| format!("Failed to read {:?}", path))?;
+            std::io::copy(&mut file, &mut out)?;
+        }
+    }
+    out.flush()
+        .with_context(|| format!("Failed to write {:?}", dst))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pack() {
+        let dir = tempfile::tempdir().unwrap();
+        let app = dir.path().join("app");
+        fs::create_dir_all(app.join("main")).unwrap();
+        fs::create_dir_all(app.join("native")).unwrap();
+        fs::write(app.join("package.json"), "{}").unwrap();
+        fs::write(app.join("main").join("index.js"), "main();").unwrap();
+        fs::write(app.join("native").join("addon.node"), "ELF").unwrap();
+        let ordering = dir.path().join("ordering.txt");
+        fs::write(&ordering, "# first\n/main/index.js\n").unwrap();
+
+        let dst = dir.path().join(ASAR_FILE);
+        let options = AsarOptions {
+            ordering: Some(ordering),
+            unpack: vec!["**/*.node".to_string()],
+            jobs: Some(2),
+        };
+        let report = pack(&app, &dst, &options).unwrap();
+        assert_eq!(report.files, 3);
+        assert_eq!(report.unpacked, 1);
+        assert_eq!(report.bytes, 9);
+
+        let data = fs::read(&dst).unwrap();
+        let u32_at = |pos: usize| u32::from_le_bytes(data[pos..pos + 4].try_into().unwrap());
+        let json_len = u32_at(12) as usize;
+        let header: serde_json::Value = serde_json::from_slice(&data[16..16 + json_len]).unwrap();
+        let base = u32_at(4) as usize + 8;
+
+        // Ordered files come first in the data
+        let index = &header["files"]["main"]["files"]["index.js"];
+        assert_eq!(index["offset"], "0");
+        assert_eq!(&data[base..base + 7], b"main();");
+        assert_eq!(
+            index["integrity"]["hash"],
+            hex::encode(Sha256::digest(b"main();"))
+        );
+        assert_eq!(header["files"]["package.json"]["offset"], "7");
+
+        let addon = &header["files"]["native"]["files"]["addon.node"];
+        assert_eq!(addon["unpacked"], true);
+        assert_eq!(
+            fs::read(dir.path().join("app.asar.unpacked/native/addon.node")).unwrap(),
+            b"ELF"
+        );
+    }
+}
@@ -0,0 +1,246 @@
+//! Backup module - mod settings and user data in one archive
+//!
+//! `settings export` zips what users set up in the patched app's data folder
+//! (the settings of every profile, which include the panel theme, the active
+//! profile and the external mods in `mods/`), so `settings import` can
+//! restore it on another machine. Caches, logs and downloaded assets are left
+//! out; they are rebuilt by the app.
+
+use crate::migrate;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use tracing::info;
+use walkdir::WalkDir;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+/// Version of the archive format
+pub const BACKUP_FORMAT: u32 = 1;
+
+/// Default name of exported archives
+pub const DEFAULT_ARCHIVE: &str = "yandex-music-mod-settings.zip";
+
+/// Index of the files inside the archive
+const BACKUP_INDEX: &str = "backup.json";
+
+/// `backup.json`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupIndex {
+    pub format: u32,
+    /// Version of the patcher that wrote the archive
+    pub patcher_version: String,
+    /// Files relative to the data folder, with `/` separators
+    pub files: Vec<String>,
+}
+
+/// Result of `settings export` and `settings import`
+#[derive(Debug, Clone, Serialize)]
+pub struct BackupReport {
+    pub archive: PathBuf,
+    pub data_dir: PathBuf,
+    /// Files written to the archive or restored from it
+    pub files: Vec<String>,
+    /// Files left alone because the data folder already had them
+    pub skipped: Vec<String>,
+}
+
+/// Whether `name` (relative, `/` separated) belongs in a backup
+fn is_backed_up(name: &str) -> bool {
+    let settings = name == "mod_profile.json"
+        || name == migrate::SETTINGS_FILE
+        || name
+            .strip_prefix("mod_settings.")
+            .and_then(|rest| rest.strip_suffix(".json"))
+            .is_some_and(crate::patches::is_valid_settings_profile);
+    let is_mod = name.strip_prefix("mods/").is_some_and(|rest| {
+        rest.split('/')
+            .all(|part| !matches!(part, "" | "." | "..") && !part.contains(['\\', ':']))
+    });
+    settings || is_mod
+}
+
+/// Files of `data_dir` that go into a backup
+fn collect(data_dir: &Path) -> Result<Vec<String>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(data_dir).with_context(|| format!("Failed to read {:?}", data_dir))? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if entry.file_type()?.is_file() && is_backed_up(&name) {
+            files.push(name);
+        }
+    }
+    for entry in WalkDir::new(data_dir.join(crate::patches::EXTERNAL_MODS_DIR)).min_depth(1) {
+        let Ok(entry) = entry else { continue };
+        if entry.file_type().is_file() {
+            let relative = entry.path().strip_prefix(data_dir)?;
+            let name = relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            files.push(name);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Write the settings and mods of `data_dir` to the zip `archive`
+pub fn export(data_dir: &Path, archive: &Path) -> Result<BackupReport> {
+    let files = collect(data_dir)?;
+    if files.is_empty() {
+        anyhow::bail!("No mod settings found in {:?}", data_dir);
+    }
+
+    let mut zip = ZipWriter::new(
+        File::create(archive).with_context(|| format!("Failed to create {:?}", archive))?,
+    );
+    let deflated = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    for name in &files {
+        zip.start_file(name.as_str(), deflated)?;
+        zip.write_all(&fs::read(data_dir.join(name))?)?;
+    }
+    let index = BackupIndex {
+        format: BACKUP_FORMAT,
+        patcher_version: env!("CARGO_PKG_VERSION").to_string(),
+        files: files.clone(),
+    };
+    zip.start_file(BACKUP_INDEX, deflated)?;
+    zip.write_all(serde_json::to_string_pretty(&index)?.as_bytes())?;
+    zip.finish()?;
+    info!("Exported {} file(s) to {:?}", files.len(), archive);
+
+    Ok(BackupReport {
+        archive: archive.to_path_buf(),
+        data_dir: data_dir.to_path_buf(),
+        files,
+        skipped: Vec::new(),
+    })
+}
+
+/// Restore the files of `archive` into `data_dir`. Files the folder already
+/// has are only replaced with `overwrite`; replaced settings are kept as
+/// `.json.bak`.
+pub fn import(archive: &Path, data_dir: &Path, overwrite: bool) -> Result<BackupReport> {
+    let file = File::open(archive).with_context(|| format!("Failed to open {:?}", archive))?;
+    let mut zip = ZipArchive::new(file)
+        .with_context(|| format!("{:?} is not a settings archive", archive))?;
+    let index: BackupIndex = {
+        let mut content = String::new();
+        zip.by_name(BACKUP_INDEX)
+            .with_context(|| format!("{:?} has no {}", archive, BACKUP_INDEX))?
+            .read_to_string(&mut content)?;
+        serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse {}", BACKUP_INDEX))?
+    };
+    if index.format != BACKUP_FORMAT {
+        anyhow::bail!(
+            "Unsupported settings archive format {} (expected {})",
+            index.format,
+            BACKUP_FORMAT
+        );
+    }
+    if let Some(name) = index.files.iter().find(|name| !is_backed_up(name)) {
+        anyhow::bail!("Unexpected file {:?} in {:?}", name, archive);
+    }
+
+    let (mut files, mut skipped) = (Vec::new(), Vec::new());
+    for name in index.files {
+        let target = data_dir.join(&name);
+        if target.exists() {
+            if !overwrite {
+                skipped.push(name);
+                continue;
+            }
+            if name.ends_with(".json") && !name.starts_with("mods/") {
+                fs::copy(&target, target.with_extension("json.bak"))
+                    .with_context(|| format!("Failed to back up {:?}", target))?;
+            }
+        }
+        let mut content = Vec::new();
+        zip.by_name(&name)
+            .with_context(|| format!("{:?} has no {}", archive, name))?
+            .read_to_end(&mut content)?;
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&target, content).with_context(|| format!("Failed to write {:?}", target))?;
+        files.push(name);
+    }
+    info!(
+        "Imported {} file(s) from {:?} into {:?}",
+        files.len(),
+        archive,
+        data_dir
+    );
+
+    Ok(BackupReport {
+        archive: archive.to_path_buf(),
+        data_dir: data_dir.to_path_buf(),
+        files,
+        skipped,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_export_import() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("source");
+        fs::create_dir_all(source.join("mods/lyrics")).unwrap();
+        fs::create_dir_all(source.join("logs")).unwrap();
+        fs::write(
+            source.join("mod_settings.json"),
+            r#"{"mod/theme": "light"}"#,
+        )
+        .unwrap();
+        fs::write(source.join("mod_settings.work.json"), "{}").unwrap();
+        fs::write(source.join("mod_profile.json"), r#"{"active": "work"}"#).unwrap();
+        fs::write(source.join("mods/dark.css"), "body {}").unwrap();
+        fs::write(source.join("mods/lyrics/lyrics.renderer.js"), "1").unwrap();
+        fs::write(source.join("logs/blocked-requests.log"), "").unwrap();
+        fs::write(source.join("Preferences"), "{}").unwrap();
+
+        let archive = dir.path().join(DEFAULT_ARCHIVE);
+        let report = export(&source, &archive).unwrap();
+        assert_eq!(
+            report.files,
+            [
+                "mod_profile.json",
+                "mod_settings.json",
+                "mod_settings.work.json",
+                "mods/dark.css",
+                "mods/lyrics/lyrics.renderer.js"
+            ]
+        );
+
+        let target = dir.path().join("target");
+        fs::create_dir_all(&target).unwrap();
+        fs::write(target.join("mod_settings.json"), "{}").unwrap();
+        let report = import(&archive, &target, false).unwrap();
+        assert_eq!(report.skipped, ["mod_settings.json"]);
+        assert_eq!(
+            fs::read_to_string(target.join("mods/lyrics/lyrics.renderer.js")).unwrap(),
+            "1"
+        );
+        assert!(!target.join("logs").exists());
+
+        import(&archive, &target, true).unwrap();
+        assert!(fs::read_to_string(target.join("mod_settings.json"))
+            .unwrap()
+            .contains("light"));
+        assert!(target.join("mod_settings.json.bak").exists());
+
+        assert!(is_backed_up("mods/a.main.js"));
+        assert!(!is_backed_up("mods/../Preferences"));
+        assert!(!is_backed_up("mods/..\\Preferences"));
+        assert!(!is_backed_up("mod_settings.../x.json"));
+        assert!(!is_backed_up("Local State"));
+    }
+}
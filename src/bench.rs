@@ -0,0 +1,163 @@
+//! Bench module - per-stage timings of a patch run
+//!
+//! With `patch --bench` the run's progress events also go through a
+//! [`Bench`], which times each stage from its start to the start of the next
+//! and sums the bytes the stage reported as processed. The timings end up in
+//! `timings` of the patch report and are printed as a table, so the
+//! performance of patcher releases can be compared on the same build.
+
+use crate::progress::{Event, ProgressSink, Stage};
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// Wall time and bytes of one stage
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StageTiming {
+    pub stage: Stage,
+    pub ms: u64,
+    /// Bytes read or written; 0 for stages that don't report any
+    pub bytes: u64,
+    /// `bytes` per second of wall time in MiB/s
+    pub mib_per_sec: Option<f64>,
+}
+
+#[derive(Debug, Default)]
+struct Current {
+    stage: Option<(Stage, Instant)>,
+    bytes: u64,
+    timings: Vec<StageTiming>,
+}
+
+/// A [`ProgressSink`] timing the stages and passing the events on to `inner`
+pub struct Bench<'a> {
+    inner: Option<&'a dyn ProgressSink>,
+    current: Mutex<Current>,
+}
+
+impl<'a> Bench<'a> {
+    pub fn new(inner: Option<&'a dyn ProgressSink>) -> Self {
+        Self {
+            inner,
+            current: Mutex::new(Current::default()),
+        }
+    }
+
+    /// Timings of the stages finished so far
+    pub fn timings(&self) -> Vec<StageTiming> {
+        self.current.lock().unwrap().timings.clone()
+    }
+
+    /// End the current stage now and return all timings
+    pub fn finish(&self) -> Vec<StageTiming> {
+        let mut current = self.current.lock().unwrap();
+        current.close(Instant::now());
+        current.timings.clone()
+    }
+}
+
+impl Current {
+    fn close(&mut self, now: Instant) {
+        let Some((stage, started)) = self.stage.take() else {
+            return;
+        };
+        let elapsed = now - started;
+        let bytes = std::mem::take(&mut self.bytes);
+        let secs = elapsed.as_secs_f64();
+        self.timings.push(StageTiming {
+            stage,
+            ms: elapsed.as_millis() as u64,
+            bytes,
+            mib_per_sec: (bytes > 0 && secs > 0.0)
+                .then(|| (bytes as f64 / (1024.0 * 1024.0) / secs * 10.0).round() / 10.0),
+        });
+    }
+}
+
+impl ProgressSink for Bench<'_> {
+    fn event(&self, event: Event) {
+        {
+            let mut current = self.current.lock().unwrap();
+            match event {
+                Event::Stage { stage } => {
+                    let now = Instant::now();
+                    current.close(now);
+                    if stage != Stage::Done {
+                        current.stage = Some((stage, now));
+                    }
+                }
+                // Cumulative during the download
+                Event::Bytes { downloaded, .. } => current.bytes = downloaded,
+                Event::Processed { bytes } => current.bytes += bytes,
+            }
+        }
+        if let Some(inner) = self.inner {
+            inner.event(event);
+        }
+    }
+}
+
+/// Print the timings as a table with a total row
+pub fn print_table(timings: &[StageTiming]) {
+    println!(
+        "{:<18} {:>10} {:>12} {:>10}",
+        "stage", "time", "bytes", "MiB/s"
+    );
+    for timing in timings {
+        println!(
+            "{:<18} {:>10} {:>12} {:>10}",
+            serde_json::to_value(timing.stage)
+                .ok()
+                .and_then(|v| v.as_str().map(str::to_string))
+                .unwrap_or_default(),
+            format_ms(timing.ms),
+            timing.bytes,
+            timing
+                .mib_per_sec
+                .map_or("-".to_string(), |rate| format!("{:.1}", rate))
+        );
+    }
+    let total: u64 = timings.iter().map(|t| t.ms).sum();
+    println!("{:<18} {:>10}", "total", format_ms(total));
+}
+
+fn format_ms(ms: u64) -> String {
+    format!("{:.2}s", ms as f64 / 1000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::progress::ProgressState;
+
+    #[test]
+    fn test_bench_timings() {
+        let state = ProgressState::default();
+        let bench = Bench::new(Some(&state));
+        bench.event(Event::Stage {
+            stage: Stage::Download,
+        });
+        bench.event(Event::Bytes {
+            downloaded: 1024,
+            total: None,
+        });
+        bench.event(Event::Bytes {
+            downloaded: 4096,
+            total: None,
+        });
+        bench.event(Event::Stage {
+            stage: Stage::CopySources,
+        });
+        bench.event(Event::Processed { bytes: 10 });
+        bench.event(Event::Processed { bytes: 20 });
+        bench.event(Event::Stage { stage: Stage::Done });
+
+        let timings = bench.timings();
+        assert_eq!(timings.len(), 2);
+        assert_eq!(timings[0].stage, Stage::Download);
+        assert_eq!(timings[0].bytes, 4096);
+        assert_eq!(timings[1].bytes, 30);
+        // Events still reach the wrapped sink
+        assert_eq!(state.snapshot().percent, 100);
+    }
+}
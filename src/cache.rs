@@ -0,0 +1,227 @@
+//! Cache module - content-addressed cache of extraction and patch results
+//!
+//! Extracting `app.asar` depends only on the installer, and patching on the
+//! installer, the patch rules and the options that shape the mod. Both results
+//! are stored under `stages` in the cache directory, keyed by a SHA-256 of
+//! those inputs and the patcher version (which carries the mod code), so a
+//! rerun that only changes e.g. `--package` or `--show-diff` copies them back
+//! instead of downloading, extracting and patching again.
+
+use crate::config;
+use crate::patcher::PatchOptions;
+use crate::paths;
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{debug, info, warn};
+
+/// Entries kept per kind; the least recently used are removed first
+pub const MAX_ENTRIES: usize = 3;
+
+/// Marks a complete entry; rewritten on every hit to track recent use
+const COMPLETE_MARKER: &str = ".complete";
+
+/// What a cache entry holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// `src` tree extracted from `app.asar` and the app icon
+    Sources,
+    /// Patched `mod` tree and the rule outcomes
+    Mod,
+}
+
+impl Kind {
+    fn dir_name(self) -> &'static str {
+        match self {
+            Kind::Sources => "sources",
+            Kind::Mod => "mod",
+        }
+    }
+}
+
+/// Stage outputs by content key
+#[derive(Debug, Clone)]
+pub struct StageCache {
+    root: PathBuf,
+}
+
+impl StageCache {
+    pub fn new(root: &Path) -> Self {
+        Self {
+            root: root.to_path_buf(),
+        }
+    }
+
+    /// `stages` in the platform cache directory, unless `options` disable caching
+    pub fn for_options(options: &PatchOptions) -> Option<Self> {
+        if options.no_cache {
+            return None;
+        }
+        config::cache_dir().map(|dir| Self::new(&dir.join("stages")))
+    }
+
+    /// Directory of the complete entry `key`, if there is one
+    pub fn get(&self, kind: Kind, key: &str) -> Option<PathBuf> {
+        let entry = self.root.join(kind.dir_name()).join(key);
+        let marker = entry.join(COMPLETE_MARKER);
+        if !marker.is_file() {
+            return None;
+        }
+        let _ = fs::write(&marker, b"");
+        info!("Reusing cached {} {}", kind.dir_name(), &key[..12]);
+        Some(entry)
+    }
+
+    /// Store entry `key`, filled by `fill` in a fresh directory. Failures are
+    /// only logged: the run itself has already succeeded.
+    pub fn put(&self, kind: Kind, key: &str, fill: impl FnOnce(&Path) -> Result<()>) {
+        if let Err(e) = self.try_put(kind, key, fill) {
+            warn!("Failed to cache {}: {:#}", kind.dir_name(), e);
+        }
+    }
+
+    fn try_put(&self, kind: Kind, key: &str, fill: impl FnOnce(&Path) -> Result<()>) -> Result<()> {
+        let dir = self.root.join(kind.dir_name());
+        fs::create_dir_all(&dir).with_context(|| format!("Failed to create {:?}", dir))?;
+
+        // Filled next to its final place and renamed, so readers never see half an entry
+        let staging = tempfile::Builder::new()
+            .prefix(&format!(".{}-", key))
+            .tempdir_in(&dir)?;
+        fill(staging.path())?;
+        fs::write(staging.path().join(COMPLETE_MARKER), b"")?;
+
+        let entry = dir.join(key);
+        if entry.exists() {
+            paths::remove_dir_all(&entry)?;
+        }
+        paths::rename(&staging.keep(), &entry)?;
+        debug!("Cached {} {}", kind.dir_name(), key);
+
+        self.prune(kind, MAX_ENTRIES);
+        Ok(())
+    }
+
+    /// Remove all but the `keep` most recently used entries of `kind`
+    fn prune(&self, kind: Kind, keep: usize) {
+        let Ok(entries) = fs::read_dir(self.root.join(kind.dir_name())) else {
+            return;
+        };
+        let mut entries: Vec<_> = entries
+            .flatten()
+            .filter_map(|entry| {
+                let used = fs::metadata(entry.path().join(COMPLETE_MARKER))
+                    .and_then(|m| m.modified())
+                    .ok()?;
+                Some((used, entry.path()))
+            })
+            .collect();
+        entries.sort_by_key(|(used, _)| std::cmp::Reverse(*used));
+        for (_, path) in entries.into_iter().skip(keep) {
+            match paths::remove_dir_all(&path) {
+                Ok(()) => debug!("Evicted {:?} from the cache", path),
+                Err(e) => warn!("{:#}", e),
+            }
+        }
+    }
+}
+
+/// Key of the sources extracted from the installer with SHA-512 `installer_hash`
+pub fn sources_key(installer_hash: &str) -> String {
+    key(&[env!("CARGO_PKG_VERSION"), "sources", installer_hash])
+}
+
+/// Key of the mod patched from that installer with the rules hashed as
+/// `rules_hash` and `options`. Options that don't change the mod tree (dry
+/// run, diffs, packaging, `app.asar`, timing, dependency installation) are left
+/// out, as is the rule source: the rules fetched are part of `rules_hash`.
+pub fn mod_key(installer_hash: &str, rules_hash: &str, options: &PatchOptions) -> Result<String> {
+    let shaping = PatchOptions {
+        dry_run: false,
+        show_diff: false,
+        auto_install_deps: false,
+        no_cache: false,
+        packages: Vec::new(),
+        package_url: None,
+        electron_version: None,
+        sign: None,
+        asar: None,
+        bench: false,
+        remote_rules: None,
+        ..options.clone()
+    };
+    let options = serde_json::to_string(&shaping)?;
+    Ok(key(&[
+        env!("CARGO_PKG_VERSION"),
+        "mod",
+        installer_hash,
+        rules_hash,
+        &options,
+    ]))
+}
+
+fn key(parts: &[&str]) -> String {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        // Length-prefixed so no two part lists hash the same input
+        hasher.update((part.len() as u64).to_le_bytes());
+        hasher.update(part.as_bytes());
+    }
+    hex::encode(hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::package::PackageFormat;
+
+    #[test]
+    fn test_mod_key() {
+        let options = PatchOptions::default();
+        let key = mod_key("c2hhNTEy", "rules", &options).unwrap();
+        assert_eq!(key.len(), 64);
+        assert_ne!(key, sources_key("c2hhNTEy"));
+
+        // Packaging and reporting options reuse the same mod
+        let packaged = PatchOptions {
+            packages: vec![PackageFormat::Scoop],
+            show_diff: true,
+            ..options.clone()
+        };
+        assert_eq!(mod_key("c2hhNTEy", "rules", &packaged).unwrap(), key);
+
+        let headers = PatchOptions {
+            banned_headers: vec!["X-Extra".to_string()],
+            ..options.clone()
+        };
+        assert_ne!(mod_key("c2hhNTEy", "rules", &headers).unwrap(), key);
+        assert_ne!(mod_key("c2hhNTEy", "other", &options).unwrap(), key);
+    }
+
+    #[test]
+    fn test_stage_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = StageCache::new(dir.path());
+        let key = sources_key("c2hhNTEy");
+        assert!(cache.get(Kind::Sources, &key).is_none());
+
+        cache.put(Kind::Sources, &key, |entry| {
+            fs::create_dir_all(entry.join("src"))?;
+            fs::write(entry.join("src").join("package.json"), "{}")?;
+            Ok(())
+        });
+        let entry = cache.get(Kind::Sources, &key).unwrap();
+        assert!(entry.join("src").join("package.json").is_file());
+
+        // A failed fill leaves no entry behind
+        cache.put(Kind::Mod, &key, |_| anyhow::bail!("disk full"));
+        assert!(cache.get(Kind::Mod, &key).is_none());
+
+        for i in 0..MAX_ENTRIES + 2 {
+            cache.put(Kind::Sources, &sources_key(&i.to_string()), |_| Ok(()));
+        }
+        let kept = fs::read_dir(dir.path().join("sources")).unwrap().count();
+        assert_eq!(kept, MAX_ENTRIES);
+    }
+}
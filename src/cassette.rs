@@ -0,0 +1,165 @@
+//! Cassette module - records HTTP responses to disk and replays them
+//!
+//! With `--record DIR` every response body the patcher downloads (update
+//! manifests, installers, release assets, ...) is also saved to `DIR`, one file
+//! per URL. `--replay DIR` serves the same requests from there without touching
+//! the network, so debugging sessions and tests run offline and deterministically.
+//! POSTs (webhooks) are skipped while replaying.
+
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use tracing::{debug, info};
+
+/// Longest file name used for a URL before it is shortened with a hash
+const MAX_NAME_LEN: usize = 160;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Record,
+    Replay,
+}
+
+/// A directory of recorded responses
+#[derive(Debug, Clone)]
+pub struct Cassette {
+    dir: PathBuf,
+    mode: Mode,
+}
+
+/// Cassette used for all requests, set once at startup
+static CASSETTE: OnceLock<Cassette> = OnceLock::new();
+
+/// Record to or replay from `dir` for all further requests
+pub fn set(dir: &Path, mode: Mode) {
+    if CASSETTE.set(Cassette::new(dir, mode)).is_ok() {
+        info!("{:?} HTTP responses in {:?}", mode, dir);
+    }
+}
+
+/// Recorded body of `url` when replaying, `None` when requests go to the network
+pub fn replay(url: &str) -> Option<Result<Vec<u8>>> {
+    let cassette = CASSETTE.get().filter(|c| c.mode == Mode::Replay)?;
+    Some(cassette.load(url))
+}
+
+/// File holding the recorded body of `url` when replaying
+pub fn replay_file(url: &str) -> Option<Result<PathBuf>> {
+    let cassette = CASSETTE.get().filter(|c| c.mode == Mode::Replay)?;
+    let path = cassette.path(url);
+    Some(if path.is_file() {
+        Ok(path)
+    } else {
+        Err(cassette.missing(url))
+    })
+}
+
+/// Whether POSTs and other side effects should be skipped
+pub fn is_replaying() -> bool {
+    CASSETTE.get().is_some_and(|c| c.mode == Mode::Replay)
+}
+
+/// Save `body` of `url` when recording
+pub fn record(url: &str, body: &[u8]) -> Result<()> {
+    match CASSETTE.get().filter(|c| c.mode == Mode::Record) {
+        Some(cassette) => cassette.save(url, body),
+        None => Ok(()),
+    }
+}
+
+/// Save the downloaded file `path` as the body of `url` when recording
+pub fn record_file(url: &str, path: &Path) -> Result<()> {
+    match CASSETTE.get().filter(|c| c.mode == Mode::Record) {
+        Some(cassette) => {
+            fs::create_dir_all(&cassette.dir)?;
+            fs::copy(path, cassette.path(url))
+                .with_context(|| format!("Failed to record {}", url))?;
+            debug!("Recorded {}", url);
+            Ok(())
+        }
+        None => Ok(()),
+    }
+}
+
+impl Cassette {
+    pub fn new(dir: &Path, mode: Mode) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+            mode,
+        }
+    }
+
+    pub fn load(&self, url: &str) -> Result<Vec<u8>> {
+        let path = self.path(url);
+        debug!("Replaying {} from {:?}", url, path);
+        fs::read(&path).map_err(|_| self.missing(url))
+    }
+
+    pub fn save(&self, url: &str, body: &[u8]) -> Result<()> {
+        fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create {:?}", self.dir))?;
+        let path = self.path(url);
+        fs::write(&path, body).with_context(|| format!("Failed to record {}", url))?;
+        debug!("Recorded {} to {:?}", url, path);
+        Ok(())
+    }
+
+    /// File of `url`: the URL without its scheme, with unsafe characters replaced
+    pub fn path(&self, url: &str) -> PathBuf {
+        let bare = url.split_once("://").map_or(url, |(_, rest)| rest);
+        let mut name: String = bare
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || matches!(c, '.' | '-') {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        if name.len() > MAX_NAME_LEN {
+            let hash = hex::encode(Sha256::digest(url.as_bytes()));
+            name.truncate(MAX_NAME_LEN - 17);
+            name = format!("{}-{}", name, &hash[..16]);
+        }
+        self.dir.join(name)
+    }
+
+    fn missing(&self, url: &str) -> anyhow::Error {
+        anyhow::anyhow!(
+            "No recorded response for {} in {:?} (record one with --record)",
+            url,
+            self.dir
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cassette_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let recorder = Cassette::new(dir.path(), Mode::Record);
+        let url = "https://music-desktop-application.s3.yandex.net/stable/latest.yml";
+        recorder.save(url, b"version: 5.0.0").unwrap();
+        assert_eq!(
+            recorder.path(url),
+            dir.path()
+                .join("music-desktop-application.s3.yandex.net_stable_latest.yml")
+        );
+
+        let player = Cassette::new(dir.path(), Mode::Replay);
+        assert_eq!(player.load(url).unwrap(), b"version: 5.0.0");
+        assert!(player.load("https://example.com/other").is_err());
+
+        let long = format!("https://example.com/{}", "a".repeat(300));
+        let name = player.path(&long);
+        let name = name.file_name().unwrap().to_str().unwrap();
+        assert_eq!(name.len(), MAX_NAME_LEN);
+        assert_ne!(player.path(&long), player.path(&format!("{}b", long)));
+    }
+}
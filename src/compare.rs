@@ -0,0 +1,385 @@
+//! Compare module - source changes between two Yandex Music versions
+//!
+//! `compare-versions OLD NEW` takes the extracted sources of both versions,
+//! from their builds in the output directory or from a dry run of the builds
+//! on the update server, and diffs the files the built-in patches target
+//! (`package.json`, `config.js`, `index.js`, `createWindow.js`,
+//! `systemMenu.js`). Every hunk is tagged with the patch anchors and rules
+//! matching inside it, and rules that match the old version but not the new one
+//! are listed, so a profile can be updated from the report.
+
+use crate::compat;
+use crate::patcher::{self, ChangeKind, PatchOptions};
+use crate::patches;
+use crate::rules::{FileLayout, PatchRule, ProfileSet};
+use anyhow::{Context, Result};
+use globset::Glob;
+use regex::Regex;
+use serde::Serialize;
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+/// Unchanged lines shown around each change
+const CONTEXT_LINES: usize = 3;
+
+/// Result of `compare-versions`
+#[derive(Debug, Clone, Serialize)]
+pub struct VersionComparison {
+    pub old: String,
+    pub new: String,
+    pub old_sources: PathBuf,
+    pub new_sources: PathBuf,
+    pub files: Vec<FileComparison>,
+    /// Anchors and rules that match the old version but not the new one
+    pub broken: Vec<String>,
+}
+
+/// A patch target file in both versions
+#[derive(Debug, Clone, Serialize)]
+pub struct FileComparison {
+    /// Path relative to the app root in the new version
+    pub path: String,
+    /// Path in the old version, when the file moved
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_path: Option<String>,
+    /// `None` when the file did not change
+    pub change: Option<ChangeKind>,
+    /// Anchors and rules targeting the file
+    pub rules: Vec<RuleMatches>,
+    pub hunks: Vec<Hunk>,
+}
+
+/// Matches of an anchor or rule in both versions of a file
+#[derive(Debug, Clone, Serialize)]
+pub struct RuleMatches {
+    pub rule: String,
+    pub anchor: bool,
+    pub old: usize,
+    pub new: usize,
+}
+
+/// A changed region of a file
+#[derive(Debug, Clone, Serialize)]
+pub struct Hunk {
+    /// 1-based first line in the old and the new version
+    pub old_line: usize,
+    pub new_line: usize,
+    /// Anchors and rules matching inside the hunk in either version
+    pub rules: Vec<String>,
+    /// The hunk in unified diff format
+    pub diff: String,
+}
+
+/// `app.asar` sources of `version`: the `src` tree of its build in `output`
+/// (patched or dry run), or of a dry run of its build on the update server
+pub async fn sources(version: &str, output: &Path, options: &PatchOptions) -> Result<PathBuf> {
+    for dry_run in [false, true] {
+        let dir = patcher::build_dir(output, version, dry_run).join("src");
+        if dir.is_dir() {
+            info!("Using the sources of Yandex Music {} in {:?}", version, dir);
+            return Ok(dir);
+        }
+    }
+    let build = compat::find_build(Some(version))
+        .await
+        .with_context(|| format!("No build of Yandex Music {} in {:?}", version, output))?;
+    let options = PatchOptions {
+        dry_run: true,
+        ..options.clone()
+    };
+    info!("Extracting Yandex Music {}", version);
+    patcher::process_build(&build, output, &options, None).await?;
+    Ok(patcher::build_dir(output, version, true).join("src"))
+}
+
+/// Compare the patch target files of `old` and `new`, with the anchors and
+/// rules of the profile the old version is patched with
+pub fn compare(
+    old: &str,
+    new: &str,
+    old_sources: &Path,
+    new_sources: &Path,
+    profile: Option<&str>,
+) -> Result<VersionComparison> {
+    let profiles = ProfileSet::load()?;
+    let old_profile = profiles.select(old, profile)?;
+    let new_layout = profiles
+        .select(new, profile)
+        .map(|profile| profile.layout.clone())
+        .unwrap_or_else(|_| old_profile.layout.clone());
+
+    let anchors = patches::anchor_rules(&old_profile.layout);
+    let rules = old_profile.collect_rules(old)?;
+    let all: Vec<(&PatchRule, bool)> = anchors
+        .iter()
+        .map(|rule| (rule, true))
+        .chain(rules.iter().map(|rule| (rule, false)))
+        .collect();
+
+    let files: Vec<(String, String)> = target_files(&old_profile.layout)
+        .into_iter()
+        .zip(target_files(&new_layout))
+        .collect();
+    let files = compare_files(old_sources, new_sources, &files, &all)?;
+
+    let mut broken: Vec<String> = Vec::new();
+    for matches in files.iter().flat_map(|file| &file.rules) {
+        if matches.old > 0 && matches.new == 0 && !broken.contains(&matches.rule) {
+            broken.push(matches.rule.clone());
+        }
+    }
+
+    Ok(VersionComparison {
+        old: old.to_string(),
+        new: new.to_string(),
+        old_sources: old_sources.to_path_buf(),
+        new_sources: new_sources.to_path_buf(),
+        files,
+        broken,
+    })
+}
+
+/// Files the built-in patches target, in the order they are reported
+fn target_files(layout: &FileLayout) -> Vec<String> {
+    vec![
+        layout.package_json.clone(),
+        layout.config_js.clone(),
+        layout.main_js.clone(),
+        layout.create_window_js.clone(),
+        layout.system_menu_js.clone(),
+    ]
+}
+
+/// Diff each `(old path, new path)` of `files`, tagging the hunks with the
+/// `rules` (and whether they are anchors) matching in them
+fn compare_files(
+    old_root: &Path,
+    new_root: &Path,
+    files: &[(String, String)],
+    rules: &[(&PatchRule, bool)],
+) -> Result<Vec<FileComparison>> {
+    let mut comparisons = Vec::new();
+    for (old_path, new_path) in files {
+        let old_text = read_text(&old_root.join(old_path))?;
+        let new_text = read_text(&new_root.join(new_path))?;
+        let change = match (&old_text, &new_text) {
+            (None, None) => continue,
+            (None, Some(_)) => Some(ChangeKind::Added),
+            (Some(_), None) => Some(ChangeKind::Removed),
+            (Some(old), Some(new)) if old != new => Some(ChangeKind::Modified),
+            _ => None,
+        };
+        let (old_text, new_text) = (old_text.unwrap_or_default(), new_text.unwrap_or_default());
+
+        // Lines of every match of the rules targeting the file
+        let mut targeting = Vec::new();
+        for &(rule, anchor) in rules {
+            let files = Glob::new(&rule.files)
+                .with_context(|| format!("Invalid file glob in rule {}", rule.name))?
+                .compile_matcher();
+            if !files.is_match(old_path) && !files.is_match(new_path) {
+                continue;
+            }
+            let old_lines = match_lines(rule, &old_text)?;
+            let new_lines = match_lines(rule, &new_text)?;
+            targeting.push((rule, anchor, old_lines, new_lines));
+        }
+
+        let (old_short, new_short) = (
+            patcher::shorten_lines(&old_text),
+            patcher::shorten_lines(&new_text),
+        );
+        let diff = similar::TextDiff::from_lines(&old_short, &new_short);
+        let mut hunks = Vec::new();
+        for hunk in diff
+            .unified_diff()
+            .context_radius(CONTEXT_LINES)
+            .iter_hunks()
+        {
+            let (Some(first), Some(last)) = (hunk.ops().first(), hunk.ops().last()) else {
+                continue;
+            };
+            let old_range = first.old_range().start..last.old_range().end;
+            let new_range = first.new_range().start..last.new_range().end;
+            let touches = |lines: &[usize], range: &Range<usize>| {
+                lines.iter().any(|line| range.contains(line))
+            };
+            hunks.push(Hunk {
+                old_line: old_range.start + 1,
+                new_line: new_range.start + 1,
+                rules: targeting
+                    .iter()
+                    .filter(|(_, _, old, new)| touches(old, &old_range) || touches(new, &new_range))
+                    .map(|(rule, ..)| rule.name.clone())
+                    .collect(),
+                diff: hunk.to_string(),
+            });
+        }
+
+        comparisons.push(FileComparison {
+            path: new_path.clone(),
+            old_path: (old_path != new_path).then(|| old_path.clone()),
+            change,
+            rules: targeting
+                .iter()
+                .map(|(rule, anchor, old, new)| RuleMatches {
+                    rule: rule.name.clone(),
+                    anchor: *anchor,
+                    old: old.len(),
+                    new: new.len(),
+                })
+                .collect(),
+            hunks,
+        });
+    }
+    Ok(comparisons)
+}
+
+/// Text of `path`, `None` when it does not exist
+fn read_text(path: &Path) -> Result<Option<String>> {
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let bytes = fs::read(path).with_context(|| format!("Failed to read {:?}", path))?;
+    Ok(Some(String::from_utf8_lossy(&bytes).into_owned()))
+}
+
+/// 0-based lines the matches of `rule` start on
+fn match_lines(rule: &PatchRule, content: &str) -> Result<Vec<usize>> {
+    let starts: Vec<usize> = if rule.regex {
+        Regex::new(&rule.find)
+            .with_context(|| format!("Invalid regex in rule {}", rule.name))?
+            .find_iter(content)
+            .map(|m| m.start())
+            .collect()
+    } else {
+        content
+            .match_indices(rule.find.as_str())
+            .map(|(start, _)| start)
+            .collect()
+    };
+    Ok(starts
+        .into_iter()
+        .map(|start| content[..start].matches('\n').count())
+        .collect())
+}
+
+/// Print `comparison` for people; with `rules_only` only the hunks touching an
+/// anchor or rule are shown
+pub fn print_report(comparison: &VersionComparison, rules_only: bool) {
+    for file in &comparison.files {
+        let marker = match file.change {
+            Some(ChangeKind::Added) => 'A',
+            Some(ChangeKind::Removed) => 'D',
+            Some(ChangeKind::Modified) => 'M',
+            None => '=',
+        };
+        match &file.old_path {
+            Some(old_path) => println!("{} {} (was {})", marker, file.path, old_path),
+            None => println!("{} {}", marker, file.path),
+        }
+        for matches in &file.rules {
+            let status = if matches.new > 0 {
+                "OK"
+            } else if matches.old > 0 {
+                "MISS"
+            } else {
+                "--"
+            };
+            let kind = if matches.anchor { "anchor" } else { "rule" };
+            println!(
+                "  [{:<4}] {} {} ({} -> {})",
+                status, kind, matches.rule, matches.old, matches.new
+            );
+        }
+        for hunk in &file.hunks {
+            if rules_only && hunk.rules.is_empty() {
+                continue;
+            }
+            println!();
+            if !hunk.rules.is_empty() {
+                println!(">>> {}", hunk.rules.join(", "));
+            }
+            print!("{}", hunk.diff);
+        }
+        println!();
+    }
+
+    println!(
+        "{}",
+        crate::i18n::tr!(
+            "Yandex Music {} -> {}: {} of {} target files changed, {} anchors and rules no longer match",
+            "Яндекс Музыка {} -> {}: изменено целевых файлов {} из {}, перестали совпадать якорей и правил: {}",
+            comparison.old,
+            comparison.new,
+            comparison.files.iter().filter(|f| f.change.is_some()).count(),
+            comparison.files.len(),
+            comparison.broken.len()
+        )
+    );
+    for rule in &comparison.broken {
+        println!("  [MISS] {}", rule);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compare_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let (old, new) = (dir.path().join("old"), dir.path().join("new"));
+        for root in [&old, &new] {
+            fs::create_dir_all(root.join("main")).unwrap();
+            fs::write(root.join("package.json"), r#"{"version": "1"}"#).unwrap();
+        }
+        let config = |devtools: &str| {
+            let mut lines: Vec<String> =
+                (0..20).map(|i| format!("const a{} = {};", i, i)).collect();
+            lines[2] = format!("  enableDevTools: {},", devtools);
+            lines[15] = "const a15 = 'changed';".to_string();
+            lines.join("\n")
+        };
+        fs::write(
+            old.join("main/config.js"),
+            config("false").replace("'changed'", "15"),
+        )
+        .unwrap();
+        fs::write(new.join("main/config.js"), config("!1")).unwrap();
+        fs::write(old.join("main/index.js"), "app.start()").unwrap();
+
+        let anchor = PatchRule {
+            name: "config-devtools".to_string(),
+            files: "main/config.js".to_string(),
+            find: r"enableDevTools:\s*false".to_string(),
+            replace: "$0".to_string(),
+            regex: true,
+            versions: None,
+        };
+        let files = [
+            ("package.json".to_string(), "package.json".to_string()),
+            ("main/config.js".to_string(), "main/config.js".to_string()),
+            ("main/index.js".to_string(), "main/index.js".to_string()),
+            (
+                "main/lib/menu.js".to_string(),
+                "main/lib/menu.js".to_string(),
+            ),
+        ];
+        let files = compare_files(&old, &new, &files, &[(&anchor, true)]).unwrap();
+        assert_eq!(files.len(), 3);
+        assert_eq!(files[0].change, None);
+        assert_eq!(files[2].change, Some(ChangeKind::Removed));
+
+        let config = &files[1];
+        assert_eq!(config.change, Some(ChangeKind::Modified));
+        assert_eq!((config.rules[0].old, config.rules[0].new), (1, 0));
+        assert_eq!(config.hunks.len(), 2);
+        assert_eq!(config.hunks[0].rules, ["config-devtools"]);
+        assert!(config.hunks[0].diff.contains("+  enableDevTools: !1,"));
+        assert!(config.hunks[1].rules.is_empty());
+        assert_eq!(config.hunks[1].new_line, 13);
+    }
+}
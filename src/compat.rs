@@ -0,0 +1,379 @@
+//! Compat module - checking the patch rules against upcoming app versions
+//!
+//! `compat-check` dry-runs a build (the newest one of all channels unless a
+//! version is given), then looks for what became of every patch anchor and
+//! rule that no longer matches: the longest start of its search text still
+//! found in the sources and the code around it. Broken rules can then be fixed
+//! from the report instead of from a user's bug report.
+
+use crate::api::{self, AppBuild};
+use crate::patcher::{self, PatchOptions, PatchReport};
+use crate::patches::{self, Compatibility};
+use crate::rules::{PatchRule, ProfileSet};
+use anyhow::{Context, Result};
+use globset::Glob;
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+use walkdir::WalkDir;
+
+/// File name of the report written to the dry-run build directory
+pub const COMPAT_REPORT_FILE: &str = "compat-report.json";
+
+/// Shortest start of a search text worth showing as a near match
+const MIN_PREFIX: usize = 8;
+
+/// Near matches shown per rule
+const MAX_SNIPPETS: usize = 3;
+
+/// Source shown around a near match, in bytes on each side
+const SNIPPET_CONTEXT: usize = 160;
+
+/// Result of `compat-check`
+#[derive(Debug, Clone, Serialize)]
+pub struct CompatReport {
+    pub version: String,
+    pub channel: String,
+    pub profile: String,
+    pub compatibility: Compatibility,
+    /// Dry-run build directory with the extracted sources
+    pub output: PathBuf,
+    /// Anchors and rules checked
+    pub checked: usize,
+    /// Anchors and rules that no longer match
+    pub broken: Vec<BrokenRule>,
+}
+
+impl CompatReport {
+    pub fn is_compatible(&self) -> bool {
+        self.broken.is_empty()
+    }
+}
+
+/// A patch anchor or rule without matches
+#[derive(Debug, Clone, Serialize)]
+pub struct BrokenRule {
+    pub rule: String,
+    /// `true` for the anchors of the built-in patches
+    pub anchor: bool,
+    pub files: String,
+    pub find: String,
+    pub regex: bool,
+    /// Where the start of `find` is still found, longest first
+    pub snippets: Vec<Snippet>,
+}
+
+/// Source around the longest start of a search text found in a file
+#[derive(Debug, Clone, Serialize)]
+pub struct Snippet {
+    /// Path relative to the app root
+    pub path: String,
+    /// 1-based line of the match
+    pub line: usize,
+    /// The start of the search text found
+    pub matched: String,
+    pub context: String,
+}
+
+/// The build of `version`, or the newest of all channels. Channels that cannot
+/// be fetched are skipped.
+pub async fn find_build(version: Option<&str>) -> Result<AppBuild> {
+    let mut builds = Vec::new();
+    for (channel, result) in api::get_all_channels().await {
+        match result {
+            Ok(found) => builds.extend(found),
+            Err(e) => warn!("Skipping the {} channel: {:#}", channel, e),
+        }
+    }
+    match version {
+        Some(version) => builds
+            .into_iter()
+            .find(|build| build.version == version)
+            .with_context(|| {
+                format!(
+                    "Yandex Music {} is not the latest build of any channel ({})",
+                    version,
+                    api::CHANNELS.join(", ")
+                )
+            }),
+        // The first installer of the newest version, as `patch` takes it
+        None => builds
+            .into_iter()
+            .rev()
+            .max_by_key(|build| semver::Version::parse(&build.version).ok())
+            .context("No builds found"),
+    }
+}
+
+/// Dry-run `build` into `output` with `options` and report the anchors and
+/// rules that no longer match, with near matches from its sources
+pub async fn check(
+    build: &AppBuild,
+    output: &Path,
+    options: &PatchOptions,
+) -> Result<CompatReport> {
+    let options = PatchOptions {
+        dry_run: true,
+        ..options.clone()
+    };
+    info!(
+        "Checking the patch rules against Yandex Music {}",
+        build.version
+    );
+    let report = patcher::process_build(build, output, &options, None).await?;
+
+    let build_dir = patcher::build_dir(output, &build.version, true);
+    let compat = analyze(&build_dir, build, options.profile.as_deref(), &report)?;
+    fs::write(
+        build_dir.join(COMPAT_REPORT_FILE),
+        serde_json::to_string_pretty(&compat)?,
+    )?;
+    Ok(compat)
+}
+
+/// Look up the unmatched anchors and rules of `report` in the sources of the
+/// dry run in `build_dir`
+fn analyze(
+    build_dir: &Path,
+    build: &AppBuild,
+    profile: Option<&str>,
+    report: &PatchReport,
+) -> Result<CompatReport> {
+    let profiles = ProfileSet::load()?;
+    let profile = profiles.select(&build.version, profile.or(Some(&report.profile)))?;
+    let anchors = patches::anchor_rules(&profile.layout);
+    let rules = profile.collect_rules(&build.version)?;
+    let source_dir = build_dir.join("src");
+
+    let mut broken = Vec::new();
+    for name in report.unmatched() {
+        let (rule, anchor) = match anchors.iter().find(|rule| rule.name == name) {
+            Some(rule) => (rule, true),
+            None => match rules.iter().find(|rule| rule.name == name) {
+                Some(rule) => (rule, false),
+                None => continue,
+            },
+        };
+        broken.push(BrokenRule {
+            rule: rule.name.clone(),
+            anchor,
+            files: rule.files.clone(),
+            find: rule.find.clone(),
+            regex: rule.regex,
+            snippets: near_matches(&source_dir, rule)?,
+        });
+    }
+
+    Ok(CompatReport {
+        version: build.version.clone(),
+        channel: build.channel.clone(),
+        profile: profile.name.clone(),
+        compatibility: report.compatibility,
+        output: build_dir.to_path_buf(),
+        checked: report.anchors.len() + report.rules.len(),
+        broken,
+    })
+}
+
+/// Literal start of a search text: `find` itself, or for a regex the text
+/// before its first pattern syntax
+fn literal_prefix(rule: &PatchRule) -> String {
+    if !rule.regex {
+        return rule.find.clone();
+    }
+    let mut prefix = String::new();
+    let mut chars = rule.find.chars().peekable();
+    while let Some(c) = chars.next() {
+        let literal = match c {
+            '\\' => match chars.next() {
+                Some(escaped) if !escaped.is_ascii_alphanumeric() => escaped,
+                _ => break,
+            },
+            '.' | '^' | '$' | '*' | '+' | '?' | '(' | ')' | '[' | ']' | '{' | '|' => break,
+            c => c,
+        };
+        // A character quantified with `*`, `?` or `{0,…}` may be absent
+        if matches!(chars.peek(), Some('*' | '?' | '{')) {
+            break;
+        }
+        prefix.push(literal);
+    }
+    prefix
+}
+
+/// Where the longest start of the rule's search text is found in the files it
+/// targets below `root`
+pub fn near_matches(root: &Path, rule: &PatchRule) -> Result<Vec<Snippet>> {
+    let files = Glob::new(&rule.files)
+        .with_context(|| format!("Invalid file glob in rule {}", rule.name))?
+        .compile_matcher();
+    let needle = literal_prefix(rule);
+    let boundaries: Vec<usize> = needle
+        .char_indices()
+        .map(|(i, _)| i)
+        .skip(1)
+        .chain([needle.len()])
+        .collect();
+
+    let mut found: Vec<(usize, Snippet)> = Vec::new();
+    for entry in WalkDir::new(root)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+    {
+        let Ok(relative) = entry.path().strip_prefix(root) else {
+            continue;
+        };
+        let relative = relative.to_string_lossy().replace('\\', "/");
+        if !files.is_match(&relative) {
+            continue;
+        }
+        let Ok(content) = String::from_utf8(fs::read(entry.path())?) else {
+            continue;
+        };
+
+        // Every start of a found start is found too, so the longest is bisected
+        let longest = boundaries.partition_point(|&end| content.contains(&needle[..end]));
+        let length = if longest == 0 {
+            0
+        } else {
+            boundaries[longest - 1]
+        };
+        if length < MIN_PREFIX.min(needle.len()) || length == 0 {
+            continue;
+        }
+        let matched = &needle[..length];
+        let Some(start) = content.find(matched) else {
+            continue;
+        };
+        found.push((
+            length,
+            Snippet {
+                path: relative,
+                line: content[..start].matches('\n').count() + 1,
+                matched: matched.to_string(),
+                context: context(&content, start, start + length),
+            },
+        ));
+    }
+
+    found.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.path.cmp(&b.1.path)));
+    Ok(found
+        .into_iter()
+        .take(MAX_SNIPPETS)
+        .map(|(_, snippet)| snippet)
+        .collect())
+}
+
+/// Source around `start..end`: up to `SNIPPET_CONTEXT` bytes on each side,
+/// within two lines
+fn context(content: &str, start: usize, end: usize) -> String {
+    let mut from = start.saturating_sub(SNIPPET_CONTEXT);
+    while !content.is_char_boundary(from) {
+        from += 1;
+    }
+    let mut to = (end + SNIPPET_CONTEXT).min(content.len());
+    while !content.is_char_boundary(to) {
+        to -= 1;
+    }
+    if let Some(newline) = content[from..start].match_indices('\n').rev().nth(2) {
+        from += newline.0 + 1;
+    }
+    if let Some(newline) = content[end..to].match_indices('\n').nth(2) {
+        to = end + newline.0;
+    }
+    content[from..to].to_string()
+}
+
+/// Print `report` for people
+pub fn print_report(report: &CompatReport) {
+    println!(
+        "{}",
+        crate::i18n::tr!(
+            "Yandex Music {} ({}), profile {}: {} of {} anchors and rules no longer match",
+            "Яндекс Музыка {} ({}), профиль {}: не совпадает якорей и правил: {} из {}",
+            report.version,
+            report.channel,
+            report.profile,
+            report.broken.len(),
+            report.checked
+        )
+    );
+    for broken in &report.broken {
+        println!();
+        let kind = if broken.anchor { "anchor" } else { "rule" };
+        println!("[MISS] {} {} in {}", kind, broken.rule, broken.files);
+        println!("       find: {}", broken.find);
+        if broken.snippets.is_empty() {
+            println!(
+                "       {}",
+                crate::i18n::tr!("no similar code found", "похожий код не найден")
+            );
+        }
+        for snippet in &broken.snippets {
+            println!(
+                "       {}:{} ({} {:?})",
+                snippet.path,
+                snippet.line,
+                crate::i18n::tr!("found", "найдено"),
+                snippet.matched
+            );
+            for line in snippet.context.lines() {
+                println!("         | {}", line);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(files: &str, find: &str, regex: bool) -> PatchRule {
+        PatchRule {
+            name: "test".to_string(),
+            files: files.to_string(),
+            find: find.to_string(),
+            replace: String::new(),
+            regex,
+            versions: None,
+        }
+    }
+
+    #[test]
+    fn test_near_matches() {
+        assert_eq!(
+            literal_prefix(&rule("", r"enableDevTools:\s*false", true)),
+            "enableDevTools:"
+        );
+        assert_eq!(
+            literal_prefix(&rule("", r"deviceInfo_js_1\.devicePlatform", true)),
+            "deviceInfo_js_1.devicePlatform"
+        );
+        assert_eq!(literal_prefix(&rule("", r"minWidth?: 7", true)), "minWidt");
+
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("main")).unwrap();
+        fs::write(
+            dir.path().join("main/config.js"),
+            "exports.config = {\n  app: {\n    enableDevTools: !1,\n  },\n};\n",
+        )
+        .unwrap();
+        fs::write(dir.path().join("main/other.js"), "enableDevTools: false").unwrap();
+
+        let snippets = near_matches(
+            dir.path(),
+            &rule("main/config.js", "enableDevTools: false", false),
+        )
+        .unwrap();
+        assert_eq!(snippets.len(), 1);
+        assert_eq!(snippets[0].path, "main/config.js");
+        assert_eq!(snippets[0].line, 3);
+        assert_eq!(snippets[0].matched, "enableDevTools: ");
+        assert!(snippets[0].context.contains("enableDevTools: !1,"));
+
+        let missing = near_matches(dir.path(), &rule("main/*.js", "somethingElse", false));
+        assert!(missing.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,468 @@
+//! Configuration module - locates user configuration files
+//!
+//! User-provided files live in the platform config directory
+//! (`~/.config/yandex-music-mod` on Linux, `~/Library/Application Support/yandex-music-mod`
+//! on macOS, `%APPDATA%\yandex-music-mod\config` on Windows).
+//! In portable mode (`--portable` or a `portable.flag` beside the executable)
+//! the config, cache and data directories are `config`, `cache` and `data`
+//! next to the executable instead.
+
+use crate::api::Arch;
+use crate::asar::AsarOptions;
+use crate::package::PackageFormat;
+use crate::patcher::PatchOptions;
+use crate::patches::{self, ModFeature};
+use crate::paths;
+use crate::remote_rules;
+use crate::sign::SignOptions;
+use anyhow::{Context, Result};
+use directories::ProjectDirs;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use tracing::{debug, info};
+
+/// File name of the CLI configuration with defaults for command-line options
+pub const CONFIG_FILE: &str = "config.toml";
+
+/// Output directory of older releases, relative to the working directory
+pub const LEGACY_OUTPUT: &str = ".versions";
+
+/// Name of the output directory inside the platform data directory
+pub const DEFAULT_OUTPUT_NAME: &str = "versions";
+
+/// File name of the user preload script appended after the built-in one
+pub const USER_PRELOAD_FILE: &str = "preload.user.js";
+
+/// File name of user patch profiles that replace the built-in ones
+pub const USER_PROFILES_FILE: &str = "profiles.toml";
+
+/// File name of extra promo selectors hidden by the renderer CSS
+pub const USER_PROMO_SELECTORS_FILE: &str = "promo-selectors.txt";
+
+/// File name of extra header names removed from API requests
+pub const USER_BANNED_HEADERS_FILE: &str = "banned-headers.txt";
+
+/// Marker file beside the executable that turns on portable mode
+pub const PORTABLE_FLAG_FILE: &str = "portable.flag";
+
+/// Directory of the executable in portable mode, decided once per process
+static PORTABLE_DIR: OnceLock<Option<PathBuf>> = OnceLock::new();
+
+/// Theme of the mod settings panel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Theme {
+    Dark,
+    Light,
+}
+
+impl Theme {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Theme::Dark => "dark",
+            Theme::Light => "light",
+        }
+    }
+}
+
+/// Defaults read from `config.toml`; command-line flags take precedence
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    /// Output directory for builds
+    pub output: Option<String>,
+    /// Update channel builds are fetched from (`stable` by default)
+    pub channel: Option<String>,
+    /// Installer architecture (this machine's by default)
+    pub arch: Option<Arch>,
+    /// Proxy for downloads, also the default `network/proxy` of the patched app
+    pub proxy: Option<String>,
+    /// Base URLs of download mirrors tried when the update server fails
+    pub mirrors: Vec<String>,
+    /// Desktop notifications about new releases and patched builds (`toast`
+    /// feature); on by default
+    pub notifications: Option<bool>,
+    /// Default theme of the mod settings panel
+    pub theme: Option<Theme>,
+    /// Default language of the mod UI (`ru`, `en`)
+    pub language: Option<String>,
+    /// Defaults for `patch` options
+    pub patch: PatchConfig,
+    /// Extra defaults written to `mod_settings.json` of the patched app
+    pub settings: BTreeMap<String, toml::Value>,
+}
+
+/// `[patch]` table of `config.toml`
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct PatchConfig {
+    pub auto_devtools: bool,
+    pub min_width: Option<u32>,
+    pub min_height: Option<u32>,
+    pub stock_min_size: bool,
+    pub profile: Option<String>,
+    pub strict: bool,
+    pub show_diff: bool,
+    pub auto_install_deps: bool,
+    /// Launch the app after patching, as for `--run`
+    pub run: bool,
+    /// Webhook notified after each run, as for `--notify-url`
+    pub notify_url: Option<String>,
+    /// Package formats, as for `--package`
+    pub package: Vec<PackageFormat>,
+    /// Where packaged archives will be hosted, as for `--package-url`
+    pub package_url: Option<String>,
+    /// Electron version of packages, as for `--electron-version`
+    pub electron_version: Option<String>,
+    /// Certificate packages are signed with, as for `--sign-cert`; the
+    /// password comes from `YMM_SIGN_PASSWORD`
+    pub sign_cert: Option<String>,
+    /// Timestamp server for signing, as for `--sign-timestamp-url`
+    pub sign_timestamp_url: Option<String>,
+    /// Certificate subject used as the MSIX publisher, as for `--sign-publisher`
+    pub sign_publisher: Option<String>,
+    /// Pack the mod into `app.asar`, as for `--pack-asar`
+    pub pack_asar: bool,
+    /// Ordering file of `app.asar`, as for `--asar-ordering`
+    pub asar_ordering: Option<String>,
+    /// Globs kept out of `app.asar`, as for `--asar-unpack`
+    pub asar_unpack: Vec<String>,
+    pub promo_selectors_url: Option<String>,
+    /// Fetch the latest rules before patching, as for `--remote-rules`
+    pub remote_rules: bool,
+    /// Where the rules are fetched from, as for `--rules-url`
+    pub rules_url: Option<String>,
+    /// Settings profile the app starts with, as for `--settings-profile`
+    pub settings_profile: Option<String>,
+    /// Optional mod features, as for `--enable`
+    pub enable: Vec<ModFeature>,
+    /// Extra banned headers, as for `--ban-header`
+    pub ban_headers: Vec<String>,
+}
+
+impl Config {
+    /// Parse a configuration from TOML
+    pub fn from_toml(content: &str) -> Result<Self> {
+        toml::from_str(content).context("Failed to parse configuration")
+    }
+
+    /// Loads `path`, or `config.toml` from the config directory if no path is given.
+    /// A missing default file yields the default configuration.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let content = match path {
+            Some(path) => Some(
+                fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read config {:?}", path))?,
+            ),
+            None => read_config_file(CONFIG_FILE)?,
+        };
+
+        match content {
+            Some(content) => Self::from_toml(&content),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Mod settings the patched app starts with when they are not set yet
+    pub fn default_settings(&self) -> Result<BTreeMap<String, serde_json::Value>> {
+        let mut settings = BTreeMap::new();
+        for (key, value) in &self.settings {
+            settings.insert(key.clone(), serde_json::to_value(value)?);
+        }
+        if let Some(proxy) = &self.proxy {
+            settings.insert("network/proxy".to_string(), proxy.as_str().into());
+        }
+        if let Some(theme) = self.theme {
+            settings.insert("mod/theme".to_string(), theme.as_str().into());
+        }
+        if let Some(language) = &self.language {
+            settings.insert("mod/language".to_string(), language.as_str().into());
+        }
+        Ok(settings)
+    }
+
+    /// Output directory: `output` if given, else `output` from the config
+    /// (both expanded), else [`default_output`]
+    pub fn output_dir(&self, output: Option<&Path>) -> PathBuf {
+        match (output, &self.output) {
+            (Some(output), _) => paths::expand(output),
+            (None, Some(output)) => paths::expand(output),
+            (None, None) => default_output(),
+        }
+    }
+
+    /// `app.asar` options from `--pack-asar` and friends, falling back to the
+    /// `[patch]` table; `None` unless packing is enabled in either
+    pub fn asar_options(
+        &self,
+        pack_asar: bool,
+        ordering: Option<&Path>,
+        unpack: Vec<String>,
+    ) -> Option<AsarOptions> {
+        let defaults = &self.patch;
+        (pack_asar || defaults.pack_asar).then(|| AsarOptions {
+            ordering: ordering
+                .map(paths::expand)
+                .or_else(|| defaults.asar_ordering.as_ref().map(paths::expand)),
+            unpack: if unpack.is_empty() {
+                defaults.asar_unpack.clone()
+            } else {
+                unpack
+            },
+            jobs: None,
+        })
+    }
+
+    /// Signing options from `--sign-cert` and friends, falling back to the
+    /// `[patch]` table; `None` unless a certificate is set in either
+    pub fn sign_options(
+        &self,
+        cert: Option<&Path>,
+        password: Option<String>,
+        timestamp_url: Option<String>,
+        publisher: Option<String>,
+    ) -> Option<SignOptions> {
+        let defaults = &self.patch;
+        let cert = cert
+            .map(paths::expand)
+            .or_else(|| defaults.sign_cert.as_ref().map(paths::expand))?;
+        Some(SignOptions {
+            cert,
+            password,
+            timestamp_url: timestamp_url.or_else(|| defaults.sign_timestamp_url.clone()),
+            publisher: publisher.or_else(|| defaults.sign_publisher.clone()),
+        })
+    }
+
+    /// Base URL of the remote rules from `--remote-rules` and `--rules-url`,
+    /// falling back to the `[patch]` table; `None` unless enabled in either
+    pub fn remote_rules(&self, enabled: bool, url: Option<String>) -> Option<String> {
+        let defaults = &self.patch;
+        let url = url.or_else(|| defaults.rules_url.clone());
+        (enabled || defaults.remote_rules || url.is_some())
+            .then(|| url.unwrap_or_else(|| remote_rules::RULES_BASE_URL.to_string()))
+    }
+
+    /// Patch options taken from the `[patch]` table alone (GUI and `watch`)
+    pub fn patch_options(&self) -> Result<PatchOptions> {
+        let defaults = &self.patch;
+        let (min_width, min_height) = if defaults.stock_min_size {
+            (None, None)
+        } else {
+            (
+                Some(defaults.min_width.unwrap_or(patches::DEFAULT_MIN_WIDTH)),
+                Some(defaults.min_height.unwrap_or(patches::DEFAULT_MIN_HEIGHT)),
+            )
+        };
+
+        let mut banned_headers = defaults.ban_headers.clone();
+        if let Some(content) = read_config_file(USER_BANNED_HEADERS_FILE)? {
+            banned_headers.extend(patches::parse_line_list(&content));
+        }
+        let promo_selectors = match read_config_file(USER_PROMO_SELECTORS_FILE)? {
+            Some(content) => patches::parse_line_list(&content),
+            None => Vec::new(),
+        };
+
+        Ok(PatchOptions {
+            window: patches::WindowOptions {
+                auto_devtools: defaults.auto_devtools,
+                min_width,
+                min_height,
+            },
+            user_preload_js: load_user_preload()?,
+            profile: defaults.profile.clone(),
+            dry_run: false,
+            promo_selectors,
+            features: defaults.enable.clone(),
+            banned_headers,
+            show_diff: false,
+            default_settings: self.default_settings()?,
+            settings_profile: defaults.settings_profile.clone(),
+            auto_install_deps: defaults.auto_install_deps,
+            packages: defaults.package.clone(),
+            package_url: defaults.package_url.clone(),
+            electron_version: defaults.electron_version.clone(),
+            sign: self.sign_options(None, None, None, None),
+            no_cache: false,
+            asar: self.asar_options(false, None, Vec::new()),
+            bench: false,
+            remote_rules: self.remote_rules(false, None),
+        })
+    }
+}
+
+fn exe_dir() -> Option<PathBuf> {
+    std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_path_buf))
+}
+
+/// Keep the config, cache and data directories next to the executable, as
+/// `--portable` asks. Must run before any of them is looked up.
+pub fn set_portable() -> Result<()> {
+    let dir = exe_dir().context("Cannot locate the directory of the executable")?;
+    if PORTABLE_DIR.set(Some(dir.clone())).is_err() {
+        anyhow::ensure!(
+            portable_dir() == Some(dir.as_path()),
+            "Portable mode was set too late"
+        );
+    }
+    info!("Portable mode: keeping all files in {:?}", dir);
+    Ok(())
+}
+
+/// Directory of the executable in portable mode: set with [`set_portable`] or
+/// detected from a `portable.flag` beside the executable
+pub fn portable_dir() -> Option<&'static Path> {
+    PORTABLE_DIR
+        .get_or_init(|| {
+            exe_dir()
+                .filter(|dir| dir.join(PORTABLE_FLAG_FILE).is_file())
+                .inspect(|dir| info!("Portable mode: {:?} found in {:?}", PORTABLE_FLAG_FILE, dir))
+        })
+        .as_deref()
+}
+
+fn project_dir(portable: &str, platform: fn(&ProjectDirs) -> &Path) -> Option<PathBuf> {
+    match portable_dir() {
+        Some(dir) => Some(dir.join(portable)),
+        None => {
+            ProjectDirs::from("", "", "yandex-music-mod").map(|dirs| platform(&dirs).to_path_buf())
+        }
+    }
+}
+
+/// Returns the platform-specific configuration directory
+pub fn config_dir() -> Option<PathBuf> {
+    project_dir("config", ProjectDirs::config_dir)
+}
+
+/// Returns the platform-specific cache directory
+pub fn cache_dir() -> Option<PathBuf> {
+    project_dir("cache", ProjectDirs::cache_dir)
+}
+
+/// Returns the platform-specific local data directory
+pub fn data_dir() -> Option<PathBuf> {
+    project_dir("data", ProjectDirs::data_local_dir)
+}
+
+/// Directory for the installer and its extracted files: the OS temp directory
+/// (`TMPDIR`, `TEMP` on Windows), or `cache/tmp` in portable mode
+pub fn temp_dir() -> PathBuf {
+    match portable_dir() {
+        Some(dir) => dir.join("cache").join("tmp"),
+        None => std::env::temp_dir(),
+    }
+}
+
+/// Output directory used when neither `--output` nor `config.toml` sets one.
+///
+/// `versions` in the platform data directory (e.g. `~/.local/share/yandex-music-mod`
+/// on Linux, `%LOCALAPPDATA%\yandex-music-mod\data` on Windows), so builds no longer
+/// land wherever the patcher was started. A `.versions` directory left in the
+/// working directory by older releases keeps being used.
+pub fn default_output() -> PathBuf {
+    let legacy = Path::new(LEGACY_OUTPUT);
+    if legacy.is_dir() {
+        debug!("Using {:?} from an older release", legacy);
+        return legacy.to_path_buf();
+    }
+    data_dir()
+        .map(|dir| dir.join(DEFAULT_OUTPUT_NAME))
+        .unwrap_or_else(|| legacy.to_path_buf())
+}
+
+/// Reads a file from the config directory, if present
+pub fn read_config_file(name: &str) -> Result<Option<String>> {
+    let Some(dir) = config_dir() else {
+        debug!("No config directory available on this platform");
+        return Ok(None);
+    };
+
+    let path = dir.join(name);
+    if !path.exists() {
+        debug!("No {} at {:?}", name, path);
+        return Ok(None);
+    }
+
+    let content =
+        fs::read_to_string(&path).with_context(|| format!("Failed to read {:?}", path))?;
+    info!("Loaded {} from {:?}", name, path);
+    Ok(Some(content))
+}
+
+/// Loads `preload.user.js` from the config directory, if present
+pub fn load_user_preload() -> Result<Option<String>> {
+    read_config_file(USER_PRELOAD_FILE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config() {
+        let config = Config::from_toml(
+            r#"
+output = "D:/builds"
+proxy = "socks5://127.0.0.1:1080"
+mirrors = ["https://mirror.example/yandex-music"]
+arch = "arm64"
+theme = "light"
+notifications = false
+
+[patch]
+min-width = 400
+remote-rules = true
+enable = ["autostart", "mini-player"]
+
+[settings]
+"window/alwaysOnTop" = true
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(config.output.as_deref(), Some("D:/builds"));
+        assert_eq!(config.mirrors, ["https://mirror.example/yandex-music"]);
+        assert_eq!(config.notifications, Some(false));
+        assert_eq!(config.arch, Some(Arch::Arm64));
+        assert_eq!(config.patch.min_width, Some(400));
+        assert_eq!(
+            config.patch.enable,
+            vec![ModFeature::Autostart, ModFeature::MiniPlayer]
+        );
+
+        let settings = config.default_settings().unwrap();
+        assert_eq!(settings["network/proxy"], "socks5://127.0.0.1:1080");
+        assert_eq!(settings["mod/theme"], "light");
+        assert_eq!(settings["window/alwaysOnTop"], true);
+
+        assert_eq!(
+            config.remote_rules(false, None).as_deref(),
+            Some(remote_rules::RULES_BASE_URL)
+        );
+        assert_eq!(Config::default().remote_rules(false, None), None);
+
+        assert!(Config::from_toml("unknown = 1").is_err());
+    }
+
+    #[test]
+    fn test_output_dir() {
+        let config = Config::from_toml(r#"output = "builds""#).unwrap();
+        assert_eq!(config.output_dir(None), PathBuf::from("builds"));
+        assert_eq!(
+            config.output_dir(Some(Path::new("other"))),
+            PathBuf::from("other")
+        );
+        assert_eq!(Config::default().output_dir(None), default_output());
+        assert!(
+            default_output() == Path::new(LEGACY_OUTPUT)
+                || default_output().ends_with(DEFAULT_OUTPUT_NAME)
+        );
+    }
+}
@@ -0,0 +1,483 @@
+//! Delta module - compact patch files between the stock and patched app
+//!
+//! `delta` stores what turns a build's stock `src` tree (the extracted
+//! `app.asar`) into its `mod` tree: every modified file as a zstd frame
+//! compressed against the stock file as dictionary (zstd's patch-from mode),
+//! the mod's own files verbatim and the removed paths. The result is a zip of a
+//! few hundred kilobytes that contains no Yandex code, so it can be shared
+//! instead of a full build. `apply-delta` rebuilds the `mod` tree from a stock
+//! `app.asar` (or its extracted tree) and checks each file against its SHA-256.
+
+use crate::lock::BuildLock;
+use crate::manifest::{self, ModManifest, MANIFEST_FORMAT};
+use crate::patcher;
+use crate::paths;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use tracing::info;
+use walkdir::WalkDir;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+/// Version of the delta format
+pub const DELTA_FORMAT: u32 = 1;
+
+/// Extension of delta files
+pub const DELTA_EXTENSION: &str = "ymmdelta";
+
+/// Index of the changes inside the delta zip
+const DELTA_INDEX: &str = "delta.json";
+
+/// Directory of the file contents and patches inside the delta zip
+const DATA_DIR: &str = "data";
+
+/// zstd level of the patches; deltas are made once and applied many times
+const ZSTD_LEVEL: i32 = 19;
+
+/// Largest file a patch may produce. The size comes from the shared delta and
+/// is allocated up front, so it is bounded well above any app file.
+const MAX_PATCHED_SIZE: u64 = 512 * 1024 * 1024;
+
+/// How a file of the `mod` tree is made from the stock sources
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Op {
+    /// Stock file patched with the stored zstd frame
+    Patch,
+    /// File stored verbatim
+    Add,
+    /// Stock file absent from the mod
+    Remove,
+}
+
+/// A changed file, by path relative to the app root
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeltaEntry {
+    pub path: String,
+    pub op: Op,
+    /// SHA-256 of the stock file a patch applies to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub base_sha256: Option<String>,
+    /// SHA-256 of the resulting file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// Size of the resulting file
+    #[serde(default)]
+    pub size: u64,
+}
+
+/// Contents of `delta.json`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeltaIndex {
+    pub format: u32,
+    /// Version of the patcher that made the mod
+    pub patcher_version: String,
+    pub app_version: String,
+    /// Published SHA-512 of the installer the stock sources come from
+    pub source_hash: String,
+    pub profile: String,
+    pub rules_hash: String,
+    pub entries: Vec<DeltaEntry>,
+}
+
+impl DeltaIndex {
+    pub fn count(&self, op: Op) -> usize {
+        self.entries.iter().filter(|e| e.op == op).count()
+    }
+}
+
+/// Write the delta between the `src` and `mod` trees of the patched build in
+/// `build_dir` to `dst`
+pub fn create(build_dir: &Path, dst: &Path) -> Result<DeltaIndex> {
+    let manifest = manifest::load(build_dir)?;
+    let source_dir = build_dir.join("src");
+    let modded_dir = build_dir.join("mod");
+    if !source_dir.is_dir() || !modded_dir.is_dir() {
+        anyhow::bail!("{:?} has no src and mod trees to compare", build_dir);
+    }
+
+    let source = list_files(&source_dir)?;
+    let modded = list_files(&modded_dir)?;
+    let mut index = DeltaIndex {
+        format: DELTA_FORMAT,
+        patcher_version: manifest.patcher_version,
+        app_version: manifest.app_version,
+        source_hash: manifest.source_hash,
+        profile: manifest.profile,
+        rules_hash: manifest.rules_hash,
+        entries: Vec::new(),
+    };
+
+    let file = File::create(dst).with_context(|| format!("Failed to create {:?}", dst))?;
+    let mut zip = ZipWriter::new(file);
+    let stored = FileOptions::default().compression_method(CompressionMethod::Stored);
+    let deflated = FileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    for (path, modded_path) in &modded {
+        let new = fs::read(modded_path)?;
+        let entry = match source.get(path) {
+            Some(source_path) => {
+                let old = fs::read(source_path)?;
+                if old == new {
+                    continue;
+                }
+                zip.start_file(format!("{}/{}", DATA_DIR, path), stored)?;
+                zip.write_all(&zstd_patch(&old, &new)?)?;
+                DeltaEntry {
+                    path: path.clone(),
+                    op: Op::Patch,
+                    base_sha256: Some(sha256(&old)),
+                    sha256: Some(sha256(&new)),
+                    size: new.len() as u64,
+                }
+            }
+            None => {
+                zip.start_file(format!("{}/{}", DATA_DIR, path), deflated)?;
+                zip.write_all(&new)?;
+                DeltaEntry {
+                    path: path.clone(),
+                    op: Op::Add,
+                    base_sha256: None,
+                    sha256: Some(sha256(&new)),
+                    size: new.len() as u64,
+                }
+            }
+        };
+        index.entries.push(entry);
+    }
+    for path in source.keys().filter(|path| !modded.contains_key(*path)) {
+        index.entries.push(DeltaEntry {
+            path: path.clone(),
+            op: Op::Remove,
+            base_sha256: None,
+            sha256: None,
+            size: 0,
+        });
+    }
+
+    zip.start_file(DELTA_INDEX, deflated)?;
+    zip.write_all(serde_json::to_string_pretty(&index)?.as_bytes())?;
+    zip.finish()?;
+    info!(
+        "Delta of {} file(s) written to {:?}",
+        index.entries.len(),
+        dst
+    );
+    Ok(index)
+}
+
+/// Rebuild the patched build from `delta` and the stock sources at `source`,
+/// either `app.asar` or the tree extracted from it. The build is written to
+/// its version directory in `output_dir`, which is returned with the index.
+pub fn apply(delta: &Path, source: &Path, output_dir: &Path) -> Result<(DeltaIndex, PathBuf)> {
+    let file = File::open(delta).with_context(|| format!("Failed to open {:?}", delta))?;
+    let mut zip = ZipArchive::new(file).with_context(|| format!("{:?} is not a delta", delta))?;
+    let index: DeltaIndex = {
+        let mut content = String::new();
+        zip.by_name(DELTA_INDEX)
+            .with_context(|| format!("{:?} has no {}", delta, DELTA_INDEX))?
+            .read_to_string(&mut content)?;
+        serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse {}", DELTA_INDEX))?
+    };
+    if index.format != DELTA_FORMAT {
+        anyhow::bail!(
+            "Unsupported delta format {} (expected {})",
+            index.format,
+            DELTA_FORMAT
+        );
+    }
+
+    // An app.asar is extracted first; the temp directory lives until the copy below
+    let extracted;
+    let source_dir = if source.is_file() {
+        extracted = tempfile::tempdir()?;
+        patcher::extract_asar(source, extracted.path())?;
+        extracted.path()
+    } else {
+        source
+    };
+
+    // The version names the build directory, which is deleted below
+    if !is_plain_version(&index.app_version) {
+        anyhow::bail!("Delta has an invalid app version {:?}", index.app_version);
+    }
+    let build_dir = patcher::build_dir(output_dir, &index.app_version, false);
+    if build_dir.parent() != Some(output_dir) {
+        anyhow::bail!("{:?} is not inside {:?}", build_dir, output_dir);
+    }
+    let _lock = BuildLock::acquire(&build_dir)?;
+    if build_dir.exists() {
+        info!("Removing existing build directory: {:?}", build_dir);
+        paths::remove_dir_all(&build_dir)?;
+    }
+    // A failed apply leaves no half-written build behind
+    if let Err(e) = rebuild(&mut zip, &index, source_dir, &build_dir) {
+        if build_dir.exists() {
+            let _ = paths::remove_dir_all(&build_dir);
+        }
+        return Err(e);
+    }
+    info!(
+        "Applied {} change(s) to Yandex Music {}",
+        index.entries.len(),
+        index.app_version
+    );
+    Ok((index, build_dir))
+}
+
+/// Whether `version` is a single plain path component, e.g. `5.40.1`
+fn is_plain_version(version: &str) -> bool {
+    !version.is_empty()
+        && version != "."
+        && version != ".."
+        && version
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_' | '+'))
+}
+
+/// Write the `src` and `mod` trees and the manifest of `index` into `build_dir`
+fn rebuild(
+    zip: &mut ZipArchive<File>,
+    index: &DeltaIndex,
+    source_dir: &Path,
+    build_dir: &Path,
+) -> Result<()> {
+    let source_copy = build_dir.join("src");
+    let modded_dir = build_dir.join("mod");
+    patcher::copy_dir_all(source_dir, &source_copy)?;
+    patcher::copy_dir_all(source_dir, &modded_dir)?;
+
+    for entry in &index.entries {
+        // Paths come from a shared file: never write outside the build
+        let relative = Path::new(&entry.path);
+        if !relative
+            .components()
+            .all(|c| matches!(c, std::path::Component::Normal(_)))
+        {
+            anyhow::bail!("Delta contains an unsafe path {:?}", entry.path);
+        }
+        let target = modded_dir.join(relative);
+        match entry.op {
+            Op::Remove => {
+                if target.exists() {
+                    paths::remove_file(&target)?;
+                }
+                continue;
+            }
+            Op::Add => {
+                if let Some(parent) = target.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                let content = read_data(zip, &entry.path)?;
+                fs::write(&target, content)?;
+            }
+            Op::Patch => {
+                let old = fs::read(&target).with_context(|| {
+                    format!(
+                        "{} is missing from the sources; are they Yandex Music {}?",
+                        entry.path, index.app_version
+                    )
+                })?;
+                if entry.base_sha256.as_deref() != Some(sha256(&old).as_str()) {
+                    anyhow::bail!(
+                        "{} differs from the one the delta was made for; the sources are not Yandex Music {}",
+                        entry.path,
+                        index.app_version
+                    );
+                }
+                let patch = read_data(zip, &entry.path)?;
+                fs::write(&target, zstd_apply(&old, &patch, entry.size)?)?;
+            }
+        }
+        let written = fs::read(&target)?;
+        if entry.sha256.as_deref() != Some(sha256(&written).as_str()) {
+            anyhow::bail!("{} does not match its checksum after applying", entry.path);
+        }
+    }
+
+    manifest::save(
+        build_dir,
+        ModManifest {
+            format: MANIFEST_FORMAT,
+            patcher_version: index.patcher_version.clone(),
+            app_version: index.app_version.clone(),
+            profile: index.profile.clone(),
+            rules_hash: index.rules_hash.clone(),
+            source_hash: index.source_hash.clone(),
+            files: manifest::hash_files(build_dir)?,
+        },
+    )?;
+    Ok(())
+}
+
+fn read_data(zip: &mut ZipArchive<File>, path: &str) -> Result<Vec<u8>> {
+    let name = format!("{}/{}", DATA_DIR, path);
+    let mut file = zip
+        .by_name(&name)
+        .with_context(|| format!("Delta has no data for {}", path))?;
+    let mut content = Vec::new();
+    file.read_to_end(&mut content)?;
+    Ok(content)
+}
+
+/// Files below `root` by path relative to it, with `/` separators
+fn list_files(root: &Path) -> Result<BTreeMap<String, PathBuf>> {
+    let mut files = BTreeMap::new();
+    for entry in WalkDir::new(root) {
+        let entry = entry?;
+        if entry.file_type().is_file() {
+            let relative = entry
+                .path()
+                .strip_prefix(root)?
+                .to_string_lossy()
+                .replace('\\', "/");
+            files.insert(relative, entry.into_path());
+        }
+    }
+    Ok(files)
+}
+
+/// Smallest zstd window covering `len` bytes of dictionary and data
+fn window_log(len: usize) -> u32 {
+    (usize::BITS - len.max(1).leading_zeros()).clamp(10, 30)
+}
+
+/// `new` compressed with `old` as dictionary
+fn zstd_patch(old: &[u8], new: &[u8]) -> Result<Vec<u8>> {
+    let mut compressor = zstd::bulk::Compressor::with_dictionary(ZSTD_LEVEL, old)?;
+    compressor.set_parameter(zstd::stream::raw::CParameter::WindowLog(window_log(
+        old.len() + new.len(),
+    )))?;
+    Ok(compressor.compress(new)?)
+}
+
+/// Undo [`zstd_patch`]: the file of `size` bytes made from `old` and `patch`
+fn zstd_apply(old: &[u8], patch: &[u8], size: u64) -> Result<Vec<u8>> {
+    if size > MAX_PATCHED_SIZE {
+        anyhow::bail!(
+            "Patched file size {} exceeds the limit of {} bytes",
+            size,
+            MAX_PATCHED_SIZE
+        );
+    }
+    let mut decompressor = zstd::bulk::Decompressor::with_dictionary(old)?;
+    decompressor.set_parameter(zstd::stream::raw::DParameter::WindowLogMax(30))?;
+    Ok(decompressor.decompress(patch, size as usize)?)
+}
+
+fn sha256(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::AppBuild;
+    use crate::rules::ProfileSet;
+
+    #[test]
+    fn test_delta_roundtrip() {
+        let temp = tempfile::tempdir().unwrap();
+        let build_dir = temp.path().join("builds").join("5.0.0");
+        let src = build_dir.join("src");
+        let modded = build_dir.join("mod");
+        let bundle: String = (0..2000)
+            .map(|i| format!("var a{} = {};\n", i, i))
+            .collect();
+        for dir in [&src, &modded] {
+            fs::create_dir_all(dir.join("app")).unwrap();
+            fs::write(dir.join("package.json"), "{}").unwrap();
+        }
+        fs::write(src.join("app").join("main.js"), &bundle).unwrap();
+        fs::write(src.join("app").join("splash.png"), b"PNG").unwrap();
+        fs::write(
+            modded.join("app").join("main.js"),
+            bundle.replace("var a1000 = 1000;", "var a1000 = 'mod';"),
+        )
+        .unwrap();
+        fs::create_dir_all(modded.join("app").join("yandexMusicMod")).unwrap();
+        fs::write(
+            modded
+                .join("app")
+                .join("yandexMusicMod")
+                .join("renderer.js"),
+            "// mod",
+        )
+        .unwrap();
+
+        let build = AppBuild {
+            path: "Yandex_Music_x64_5.0.0.exe".to_string(),
+            hash: "c2hhNTEy".to_string(),
+            size: 0,
+            release_date: None,
+            update_probability: None,
+            version: "5.0.0".to_string(),
+            deprecated_versions: None,
+            channel: "stable".to_string(),
+        };
+        let profiles = ProfileSet::load().unwrap();
+        let profile = profiles.select("5.0.0", None).unwrap();
+        manifest::write(&build_dir, &build, profile).unwrap();
+
+        let delta = temp.path().join(format!("5.0.0.{}", DELTA_EXTENSION));
+        let index = create(&build_dir, &delta).unwrap();
+        assert_eq!(
+            (
+                index.count(Op::Patch),
+                index.count(Op::Add),
+                index.count(Op::Remove)
+            ),
+            (1, 1, 1)
+        );
+        assert!(fs::metadata(&delta).unwrap().len() < bundle.len() as u64 / 4);
+
+        let output = temp.path().join("applied");
+        let (_, applied) = apply(&delta, &src, &output).unwrap();
+        assert_eq!(applied, output.join("5.0.0"));
+        for path in [
+            "package.json",
+            "app/main.js",
+            "app/yandexMusicMod/renderer.js",
+        ] {
+            assert_eq!(
+                fs::read(applied.join("mod").join(path)).unwrap(),
+                fs::read(modded.join(path)).unwrap()
+            );
+        }
+        assert!(!applied.join("mod").join("app").join("splash.png").exists());
+        assert!(manifest::verify(&applied).unwrap().is_intact());
+
+        // Sources of another version are refused
+        let other = temp.path().join("other");
+        fs::create_dir_all(other.join("app")).unwrap();
+        fs::write(other.join("app").join("main.js"), "var b;").unwrap();
+        let bad = temp.path().join("bad");
+        assert!(apply(&delta, &other, &bad).is_err());
+        assert!(!bad.join("5.0.0").exists());
+
+        // The version from the delta must not lead out of the output directory
+        let victim = temp.path().join("victim");
+        fs::create_dir_all(&victim).unwrap();
+        for app_version in ["../victim", victim.to_str().unwrap(), "..", "a/b", ""] {
+            let mut index = index.clone();
+            index.app_version = app_version.to_string();
+            let crafted = temp.path().join("crafted.ymmdelta");
+            let mut zip = ZipWriter::new(File::create(&crafted).unwrap());
+            zip.start_file(DELTA_INDEX, FileOptions::default()).unwrap();
+            zip.write_all(serde_json::to_string(&index).unwrap().as_bytes())
+                .unwrap();
+            zip.finish().unwrap();
+            assert!(apply(&crafted, &src, &output).is_err());
+            assert!(victim.exists());
+        }
+
+        // Sizes from the delta are not allocated blindly
+        assert!(zstd_apply(b"old", b"", MAX_PATCHED_SIZE + 1).is_err());
+    }
+}
@@ -0,0 +1,72 @@
+//! Deps module - installs missing external tools
+//!
+//! On Windows a missing 7-Zip can be installed with winget, Chocolatey or Scoop.
+//! Builds with the built-in extractors (`native-extract`) don't need it.
+
+use crate::i18n::tr;
+use crate::patcher;
+use crate::prompt;
+use anyhow::Result;
+use std::process::Command;
+use tracing::{info, warn};
+
+/// Package managers tried in order: (program, install arguments)
+const PACKAGE_MANAGERS: &[(&str, &[&str])] = &[
+    (
+        "winget",
+        &[
+            "install",
+            "--id",
+            "7zip.7zip",
+            "-e",
+            "--silent",
+            "--accept-package-agreements",
+            "--accept-source-agreements",
+        ],
+    ),
+    ("choco", &["install", "7zip", "-y"]),
+    ("scoop", &["install", "7zip"]),
+];
+
+/// Make sure 7-Zip is available, offering to install it on Windows.
+///
+/// With `auto_install` the user is not asked; otherwise the prompt defaults to no,
+/// so non-interactive runs keep the old behaviour of failing during extraction.
+/// Builds with the built-in 7z extractor don't need 7-Zip at all.
+pub async fn ensure_7z(auto_install: bool) -> Result<()> {
+    if !cfg!(target_os = "windows")
+        || cfg!(feature = "native-extract")
+        || patcher::find_7z_executable().is_some()
+    {
+        return Ok(());
+    }
+
+    let question = tr!(
+        "7-Zip was not found. Install it now?",
+        "7-Zip не найден. Установить сейчас?"
+    );
+    if !auto_install && !prompt::confirm(&question, false) {
+        return Ok(());
+    }
+
+    for (program, args) in PACKAGE_MANAGERS {
+        info!("Installing 7-Zip with {}", program);
+        match Command::new(program).args(*args).status() {
+            Ok(status) if status.success() && patcher::find_7z_executable().is_some() => {
+                info!("7-Zip installed with {}", program);
+                return Ok(());
+            }
+            Ok(status) => warn!("{} exited with {}", program, status),
+            Err(e) => info!("{} is not available: {}", program, e),
+        }
+    }
+
+    warn!(
+        "{}",
+        tr!(
+            "Could not install 7-Zip; install it from https://www.7-zip.org",
+            "Не удалось установить 7-Zip; установите его с https://www.7-zip.org"
+        )
+    );
+    Ok(())
+}
@@ -0,0 +1,236 @@
+//! Dev module - edit-test loop for authors of rules, preload scripts and mods
+//!
+//! `dev --watch` polls the external inputs of a patched build: the user files
+//! of the config directory (`profiles.toml`, `preload.user.js`,
+//! `promo-selectors.txt`, ...), a rules directory used in place of the built-in
+//! rule files and the external mods folder. On every change it redoes only the
+//! stages the changed files feed: the patches and rules, the mod files, or
+//! nothing for external mods, which the app loads itself on start. With `run`
+//! the app is restarted after each change.
+
+use crate::config::{self, Config};
+use crate::patcher::{self, PatchOptions};
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command};
+use std::time::{Duration, SystemTime};
+use tracing::{debug, info, warn};
+use walkdir::WalkDir;
+
+/// Stages to redo after a change, cheapest first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Change {
+    /// External mods: the app only needs a restart
+    Mods,
+    /// Mod files in the HTML root (renderer code, styles, feature assets)
+    Assets,
+    /// Built-in patches, profile rules and HTML injection
+    Patches,
+}
+
+/// Inputs watched by `dev`
+#[derive(Debug, Clone, Default)]
+pub struct WatchSet {
+    /// Files and directories the patches are made from (config directory,
+    /// rules directory, config file)
+    pub inputs: Vec<PathBuf>,
+    /// External mods folder
+    pub mods_dir: Option<PathBuf>,
+}
+
+/// Modification time and size of every watched file
+type Snapshot = BTreeMap<PathBuf, (Option<SystemTime>, u64)>;
+
+impl WatchSet {
+    /// Stages a change to `path` affects
+    fn classify(&self, path: &Path) -> Change {
+        if self
+            .mods_dir
+            .as_ref()
+            .is_some_and(|dir| path.starts_with(dir))
+        {
+            Change::Mods
+        } else if path
+            .file_name()
+            .is_some_and(|name| name == config::USER_PROMO_SELECTORS_FILE)
+        {
+            Change::Assets
+        } else {
+            Change::Patches
+        }
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        self.inputs
+            .iter()
+            .chain(&self.mods_dir)
+            .flat_map(|root| WalkDir::new(root).into_iter().filter_map(|e| e.ok()))
+            .filter(|e| e.file_type().is_file())
+            .filter_map(|e| {
+                let metadata = e.metadata().ok()?;
+                Some((e.into_path(), (metadata.modified().ok(), metadata.len())))
+            })
+            .collect()
+    }
+
+    /// The most expensive change between two snapshots, if any file was
+    /// added, removed or modified
+    fn detect(&self, before: &Snapshot, after: &Snapshot) -> Option<Change> {
+        let removed = before.keys().filter(|path| !after.contains_key(*path));
+        let changed = after
+            .iter()
+            .filter(|(path, stamp)| before.get(*path) != Some(stamp))
+            .map(|(path, _)| path);
+        removed
+            .chain(changed)
+            .inspect(|path| debug!("Changed: {:?}", path))
+            .map(|path| self.classify(path))
+            .max()
+    }
+}
+
+/// Patch options of the config file, without packages: `dev` runs the `mod`
+/// directory
+fn load_options(config_path: Option<&Path>) -> Result<PatchOptions> {
+    let mut options = Config::load(config_path)?.patch_options()?;
+    options.packages.clear();
+    Ok(options)
+}
+
+/// Redo the stages of `change` on the patched build in `build_dir`
+async fn apply(build_dir: &Path, change: Change, options: &PatchOptions) -> Result<()> {
+    match change {
+        Change::Patches => {
+            let report = patcher::reapply_patches(build_dir, options)?;
+            let missing = report
+                .anchors
+                .iter()
+                .chain(&report.rules)
+                .filter(|outcome| outcome.matches == 0)
+                .count();
+            info!(
+                "Patched again: {} rule(s), {} without matches",
+                report.rules.len(),
+                missing
+            );
+        }
+        Change::Assets => {
+            let files = patcher::refresh_assets(build_dir, options).await?;
+            info!("Refreshed {} mod file(s)", files.len());
+        }
+        Change::Mods => info!("External mods changed"),
+    }
+    Ok(())
+}
+
+/// Stop the app started with [`patcher::spawn_app`], Electron included
+fn stop_app(app: &mut Child) {
+    #[cfg(unix)]
+    let _ = Command::new("kill")
+        .args(["-TERM", "--", &format!("-{}", app.id())])
+        .status();
+    #[cfg(windows)]
+    let _ = Command::new("taskkill")
+        .args(["/T", "/F", "/PID", &app.id().to_string()])
+        .status();
+    let _ = app.kill();
+    let _ = app.wait();
+}
+
+/// Bring the patched build in `build_dir` up to date with the inputs of
+/// `watch_set`, then with `watch` redo the affected stages on every change,
+/// checking every `interval` until interrupted. Failures are logged and the
+/// loop goes on, so broken rules can be fixed in place.
+pub async fn dev(
+    config_path: Option<&Path>,
+    build_dir: &Path,
+    watch_set: &WatchSet,
+    watch: bool,
+    interval: Duration,
+    run: bool,
+) -> Result<()> {
+    let mod_dir = build_dir.join("mod");
+    let mut before = watch_set.snapshot();
+    apply(build_dir, Change::Patches, &load_options(config_path)?).await?;
+    let mut app = if run {
+        Some(patcher::spawn_app(&mod_dir)?)
+    } else {
+        None
+    };
+    if !watch {
+        return Ok(());
+    }
+
+    info!(
+        "Watching {} file(s) for changes, press Ctrl-C to stop",
+        before.len()
+    );
+    loop {
+        tokio::time::sleep(interval).await;
+        let after = watch_set.snapshot();
+        let Some(change) = watch_set.detect(&before, &after) else {
+            continue;
+        };
+        before = after;
+
+        let result = match load_options(config_path) {
+            Ok(options) => apply(build_dir, change, &options).await,
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(()) => {
+                if let Some(running) = &mut app {
+                    info!("Restarting the app");
+                    stop_app(running);
+                    match patcher::spawn_app(&mod_dir) {
+                        Ok(started) => *running = started,
+                        Err(e) => warn!("{:#}", e),
+                    }
+                } else if change == Change::Mods {
+                    info!("Restart the app to load them");
+                }
+            }
+            Err(e) => warn!("{:#}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_detect_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_dir = dir.path().join("config");
+        let mods_dir = dir.path().join("mods");
+        fs::create_dir_all(&config_dir).unwrap();
+        fs::create_dir_all(&mods_dir).unwrap();
+        fs::write(config_dir.join(config::USER_PROFILES_FILE), "").unwrap();
+        let watch_set = WatchSet {
+            inputs: vec![config_dir.clone(), dir.path().join("missing")],
+            mods_dir: Some(mods_dir.clone()),
+        };
+
+        let before = watch_set.snapshot();
+        assert_eq!(before.len(), 1);
+        assert_eq!(watch_set.detect(&before, &watch_set.snapshot()), None);
+
+        fs::write(mods_dir.join("dark.css"), "body {}").unwrap();
+        let after = watch_set.snapshot();
+        assert_eq!(watch_set.detect(&before, &after), Some(Change::Mods));
+
+        fs::write(config_dir.join(config::USER_PROMO_SELECTORS_FILE), ".ad").unwrap();
+        let promo = watch_set.snapshot();
+        assert_eq!(watch_set.detect(&after, &promo), Some(Change::Assets));
+
+        fs::remove_file(config_dir.join(config::USER_PROFILES_FILE)).unwrap();
+        fs::remove_file(mods_dir.join("dark.css")).unwrap();
+        assert_eq!(
+            watch_set.detect(&promo, &watch_set.snapshot()),
+            Some(Change::Patches)
+        );
+    }
+}
@@ -0,0 +1,301 @@
+//! Doctor module - diagnoses the environment before patching
+//!
+//! Checks the external tools, disk space, output directory and network access
+//! the pipeline needs, and suggests a fix for every problem found.
+
+use crate::api;
+use crate::i18n::tr;
+use crate::patcher;
+use crate::paths;
+use serde::Serialize;
+use std::path::Path;
+use std::process::Command;
+
+/// Free space needed for the installer, the extracted app and its patched copy
+const REQUIRED_SPACE: u64 = 2 * 1024 * 1024 * 1024;
+
+/// Outcome of a single check
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Ok,
+    Warn,
+    Fail,
+}
+
+impl Status {
+    fn label(self) -> &'static str {
+        match self {
+            Status::Ok => "OK  ",
+            Status::Warn => "WARN",
+            Status::Fail => "FAIL",
+        }
+    }
+}
+
+/// Result of a single environment check
+#[derive(Debug, Clone, Serialize)]
+pub struct Check {
+    pub name: &'static str,
+    pub status: Status,
+    pub detail: String,
+    /// Suggested fix when the check did not pass
+    pub fix: Option<String>,
+}
+
+impl Check {
+    fn ok(name: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: Status::Ok,
+            detail: detail.into(),
+            fix: None,
+        }
+    }
+
+    fn problem(
+        name: &'static str,
+        status: Status,
+        detail: impl Into<String>,
+        fix: impl Into<String>,
+    ) -> Self {
+        Self {
+            name,
+            status,
+            detail: detail.into(),
+            fix: Some(fix.into()),
+        }
+    }
+}
+
+/// Run all checks for patching into `output` from `channel`
+pub async fn run_checks(output: &Path, channel: &str) -> Vec<Check> {
+    vec![
+        check_7z(),
+        check_asar(),
+        check_output_dir(output),
+        check_disk_space(output),
+        check_update_server(channel).await,
+    ]
+}
+
+/// Print the checks as a table followed by the suggested fixes
+pub fn print_report(checks: &[Check]) {
+    println!("{}", tr!("\nEnvironment check", "\nПроверка окружения"));
+    println!("{}", "=".repeat(60));
+    for check in checks {
+        println!(
+            "[{}] {:<16} {}",
+            check.status.label(),
+            check.name,
+            check.detail
+        );
+    }
+    println!("{}", "-".repeat(60));
+
+    let problems: Vec<_> = checks.iter().filter(|c| c.fix.is_some()).collect();
+    if problems.is_empty() {
+        println!("{}", tr!("Everything looks good.", "Всё в порядке."));
+        return;
+    }
+    for check in problems {
+        println!(
+            "{}: {}",
+            check.name,
+            check.fix.as_deref().unwrap_or_default()
+        );
+    }
+}
+
+fn check_7z() -> Check {
+    match patcher::find_7z_executable() {
+        Some(path) => Check::ok("7-Zip", tr!("found {}", "найден {}", path.display())),
+        None if cfg!(feature = "native-extract") => Check::ok(
+            "7-Zip",
+            tr!(
+                "not found; not needed, the built-in extractor is used",
+                "не найден; не нужен, используется встроенный распаковщик"
+            ),
+        ),
+        None => Check::problem(
+            "7-Zip",
+            Status::Fail,
+            tr!("not found", "не найден"),
+            if cfg!(target_os = "windows") {
+                tr!(
+                    "Install 7-Zip from https://www.7-zip.org/ or run `patch --auto-install-deps`",
+                    "Установите 7-Zip с https://www.7-zip.org/ или запустите `patch --auto-install-deps`"
+                )
+            } else if cfg!(target_os = "macos") {
+                tr!(
+                    "Install p7zip: `brew install p7zip`",
+                    "Установите p7zip: `brew install p7zip`"
+                )
+            } else {
+                tr!(
+                    "Install p7zip: `apt install p7zip-full` (or your distribution's equivalent)",
+                    "Установите p7zip: `apt install p7zip-full` (или аналог для вашего дистрибутива)"
+                )
+            },
+        ),
+    }
+}
+
+fn check_asar() -> Check {
+    let responds = |cmd: &str, args: &[&str]| {
+        Command::new(cmd)
+            .args(args)
+            .output()
+            .is_ok_and(|output| output.status.success())
+    };
+
+    if cfg!(feature = "native-extract") {
+        // Tried first; asar and npx are only the fallback
+        Check::ok("asar", tr!("built-in extractor", "встроенный распаковщик"))
+    } else if responds("asar", &["--version"]) {
+        Check::ok("asar", tr!("found asar", "найден asar"))
+    } else if responds(patcher::NPX, &["--version"]) {
+        Check::ok(
+            "asar",
+            tr!(
+                "found npx (asar runs via `npx asar`)",
+                "найден npx (asar запускается через `npx asar`)"
+            ),
+        )
+    } else {
+        Check::problem(
+            "asar",
+            Status::Fail,
+            tr!("neither asar nor npx found", "asar и npx не найдены"),
+            tr!(
+                "Install Node.js and run `npm install -g asar`",
+                "Установите Node.js и выполните `npm install -g asar`"
+            ),
+        )
+    }
+}
+
+fn check_output_dir(output: &Path) -> Check {
+    match paths::ensure_writable(output) {
+        Ok(()) => Check::ok(
+            "Output dir",
+            tr!("{} is writable", "{} доступна для записи", output.display()),
+        ),
+        Err(e) => Check::problem(
+            "Output dir",
+            Status::Fail,
+            tr!(
+                "cannot write to {}: {:#}",
+                "нет доступа на запись в {}: {:#}",
+                output.display(),
+                e
+            ),
+            tr!(
+                "Choose another directory with --output or fix its permissions",
+                "Выберите другую директорию через --output или исправьте права доступа"
+            ),
+        ),
+    }
+}
+
+fn check_disk_space(output: &Path) -> Check {
+    match paths::available_space(output) {
+        Ok(free) if free >= REQUIRED_SPACE => Check::ok(
+            "Disk space",
+            tr!("{} free", "свободно {}", format_size(free)),
+        ),
+        Ok(free) => Check::problem(
+            "Disk space",
+            Status::Fail,
+            tr!(
+                "{} free, about {} needed",
+                "свободно {}, нужно около {}",
+                format_size(free),
+                format_size(REQUIRED_SPACE)
+            ),
+            tr!(
+                "Free up space or choose an output directory on another drive with --output",
+                "Освободите место или выберите директорию на другом диске через --output"
+            ),
+        ),
+        Err(e) => Check::problem(
+            "Disk space",
+            Status::Warn,
+            tr!(
+                "could not determine free space: {:#}",
+                "не удалось определить свободное место: {:#}",
+                e
+            ),
+            tr!(
+                "Make sure about {} are free",
+                "Убедитесь, что свободно около {}",
+                format_size(REQUIRED_SPACE)
+            ),
+        ),
+    }
+}
+
+async fn check_update_server(channel: &str) -> Check {
+    match api::get_latest_build(channel).await {
+        Ok(builds) => match builds.first() {
+            Some(build) => Check::ok(
+                "Update server",
+                tr!(
+                    "reachable, latest {} is {}",
+                    "доступен, последняя {} — {}",
+                    channel,
+                    build.version
+                ),
+            ),
+            None => Check::problem(
+                "Update server",
+                Status::Fail,
+                tr!(
+                    "no builds published on channel {}",
+                    "в канале {} нет сборок",
+                    channel
+                ),
+                tr!(
+                    "Check the channel name passed with --channel or set in config.toml",
+                    "Проверьте имя канала в --channel или config.toml"
+                ),
+            ),
+        },
+        Err(e) => Check::problem(
+            "Update server",
+            Status::Fail,
+            tr!("unreachable: {}", "недоступен: {}", e),
+            tr!(
+                "Check your internet connection, or set `proxy` in config.toml",
+                "Проверьте подключение к интернету или задайте `proxy` в config.toml"
+            ),
+        ),
+    }
+}
+
+fn format_size(bytes: u64) -> String {
+    format!("{:.1} GB", bytes as f64 / (1024.0 * 1024.0 * 1024.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_output_dir_check() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("nested").join(".versions");
+
+        let check = check_output_dir(&output);
+        assert_eq!(check.status, Status::Ok);
+        assert!(output.exists());
+        assert!(fs::read_dir(&output).unwrap().next().is_none());
+
+        let file = dir.path().join("file");
+        fs::write(&file, b"").unwrap();
+        let check = check_output_dir(&file.join("sub"));
+        assert_eq!(check.status, Status::Fail);
+        assert!(check.fix.is_some());
+    }
+}
@@ -0,0 +1,139 @@
+//! Electron-builder module - builds installers of the patched app with Node.js
+//!
+//! Bridges to the workflow of the original TypeScript project: the `build`
+//! config the patcher injects into `package.json` is written out as
+//! `electron-builder.json`, pointed at the existing `mod` directory, and handed
+//! to `npx electron-builder`. Signing uses electron-builder's own environment
+//! (`CSC_LINK`, `CSC_KEY_PASSWORD`, ...), which `--sign-cert` fills in.
+
+use crate::paths;
+use crate::sign::SignOptions;
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tracing::info;
+
+/// Directory of the config and installers inside the package directory
+pub const BUILDER_DIR: &str = "electron-builder";
+
+/// Config file passed to electron-builder
+const CONFIG_FILE: &str = "electron-builder.json";
+
+/// Build installers for the current platform from the mod of `build_dir`.
+/// Returns the config and the produced artifacts.
+pub(crate) fn package(
+    build_dir: &Path,
+    electron_version: Option<&str>,
+    package_dir: &Path,
+    sign: Option<&SignOptions>,
+) -> Result<Vec<PathBuf>> {
+    // Absolute but not canonical paths: Node tools choke on Windows `\\?\` paths
+    let builder_dir = std::path::absolute(package_dir.join(BUILDER_DIR))?;
+    let output_dir = builder_dir.join("dist");
+    let mod_dir = std::path::absolute(build_dir.join("mod"))?;
+    if builder_dir.exists() {
+        paths::remove_dir_all(&builder_dir)?;
+    }
+    fs::create_dir_all(&builder_dir)?;
+
+    let config_path = builder_dir.join(CONFIG_FILE);
+    let config = config(&mod_dir, &output_dir, electron_version)?;
+    fs::write(&config_path, serde_json::to_string_pretty(&config)?)?;
+
+    info!("Running electron-builder in {:?}", mod_dir);
+    let mut command = Command::new("npx");
+    command
+        .args(["--yes", "electron-builder", "--config"])
+        .arg(&config_path)
+        .arg("--projectDir")
+        .arg(&mod_dir);
+    if let Some(sign) = sign {
+        command.envs(sign.electron_builder_env());
+    }
+    let output = command
+        .output()
+        .context("Failed to run electron-builder (is Node.js/npx in PATH?)")?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let log = if stderr.trim().is_empty() {
+            stdout
+        } else {
+            stderr
+        };
+        anyhow::bail!("electron-builder failed:\n{}", log.trim());
+    }
+
+    let mut written = vec![config_path];
+    for entry in fs::read_dir(&output_dir)? {
+        let path = entry?.path();
+        if path.is_file() {
+            written.push(path);
+        }
+    }
+    info!("Wrote electron-builder artifacts to {:?}", output_dir);
+    Ok(written)
+}
+
+/// The `build` section of the mod's `package.json`, with the app and output
+/// directories set and dependency rebuilds disabled: the extracted app already
+/// ships its compiled `node_modules`
+fn config(
+    mod_dir: &Path,
+    output_dir: &Path,
+    electron_version: Option<&str>,
+) -> Result<serde_json::Value> {
+    let package_json: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(mod_dir.join("package.json"))?)?;
+    let mut config = match &package_json["build"] {
+        serde_json::Value::Object(build) => serde_json::Value::Object(build.clone()),
+        _ => anyhow::bail!(
+            "No electron-builder config in {:?}",
+            mod_dir.join("package.json")
+        ),
+    };
+
+    config["directories"] = serde_json::json!({
+        "app": mod_dir,
+        "output": output_dir,
+    });
+    config["protocols"] = serde_json::json!([{
+        "name": crate::package::PACKAGE_NAME,
+        "schemes": [crate::package::URL_SCHEME],
+    }]);
+    config["npmRebuild"] = serde_json::json!(false);
+    config["buildDependenciesFromSource"] = serde_json::json!(false);
+
+    let electron_version = match electron_version {
+        Some(version) => Some(version.to_string()),
+        None => crate::package::electron_version(mod_dir).ok(),
+    };
+    if let Some(version) = electron_version {
+        config["electronVersion"] = serde_json::json!(version);
+    }
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builder_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let package_json =
+            crate::patches::patch_package_json(r#"{"name": "yandex-music"}"#).unwrap();
+        fs::write(dir.path().join("package.json"), package_json).unwrap();
+
+        let built = config(dir.path(), &dir.path().join("dist"), Some("32.2.0")).unwrap();
+        assert_eq!(built["appId"], "ru.yandex.desktop.music.mod");
+        assert_eq!(built["electronVersion"], "32.2.0");
+        assert_eq!(built["npmRebuild"], false);
+        assert_eq!(built["protocols"][0]["schemes"][0], "yandexmusic");
+        assert_eq!(built["directories"]["app"], serde_json::json!(dir.path()));
+
+        fs::write(dir.path().join("package.json"), "{}").unwrap();
+        assert!(config(dir.path(), dir.path(), None).is_err());
+    }
+}
@@ -0,0 +1,187 @@
+//! Elevate module - administrator rights for per-machine installs
+//!
+//! Installed for all users, Yandex Music lives under `Program Files` (or
+//! `/opt`), where `apply` may not write without administrator rights. The write
+//! access is checked before any file is swapped, so nothing is left
+//! half-replaced; on Windows the patcher then starts itself again with the same
+//! arguments and working directory through the UAC prompt and waits for that
+//! run, elsewhere it tells to rerun the command with `sudo`.
+
+use crate::error::PatcherError;
+use crate::i18n::tr;
+use anyhow::Result;
+use std::ffi::OsString;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Hidden flag marking the run started through the UAC prompt
+pub const ELEVATED_FLAG: &str = "--elevated";
+
+/// Hidden flag with the working directory of the relaunched run; elevated
+/// processes start in System32, where relative paths would not resolve
+pub const ELEVATED_DIR_FLAG: &str = "--elevated-dir";
+
+/// Exit code of the relaunch when the UAC prompt is declined (`ERROR_CANCELLED`)
+#[cfg_attr(not(windows), allow(dead_code))]
+const CANCELLED_CODE: i32 = 1223;
+
+static ELEVATED: AtomicBool = AtomicBool::new(false);
+
+/// Mark this run as the elevated one, so it never relaunches itself again
+pub fn set_elevated(elevated: bool) {
+    ELEVATED.store(elevated, Ordering::Relaxed);
+}
+
+pub fn is_elevated() -> bool {
+    ELEVATED.load(Ordering::Relaxed)
+}
+
+/// Check that files can be created in `dir`, failing with
+/// [`PatcherError::AccessDenied`] when the user lacks the rights
+pub fn check_access(dir: &Path) -> Result<()> {
+    let probe = dir.join(".write-test");
+    match std::fs::write(&probe, b"ok").and_then(|_| std::fs::remove_file(&probe)) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+            Err(anyhow::Error::new(e).context(PatcherError::AccessDenied(format!("{:?}", dir))))
+        }
+        Err(e) => Err(anyhow::Error::new(e).context(format!("{:?} is not writable", dir))),
+    }
+}
+
+/// Whether `error` comes from missing rights rather than another failure
+pub fn is_access_denied(error: &anyhow::Error) -> bool {
+    matches!(
+        error.downcast_ref::<PatcherError>(),
+        Some(PatcherError::AccessDenied(_))
+    ) || error.chain().any(|cause| {
+        cause
+            .downcast_ref::<io::Error>()
+            .is_some_and(|e| e.kind() == io::ErrorKind::PermissionDenied)
+    })
+}
+
+/// Run this command again with administrator rights and wait for it. Fails
+/// when the run fails, and with [`PatcherError::AccessDenied`] when the rights
+/// are declined or cannot be asked for on this platform.
+pub fn relaunch() -> Result<()> {
+    if is_elevated() {
+        return Err(PatcherError::AccessDenied(tr!(
+            "denied even with administrator rights",
+            "запрещён даже с правами администратора"
+        ))
+        .into());
+    }
+    let args: Vec<OsString> = std::env::args_os().skip(1).collect();
+    relaunch_with(&args)
+}
+
+#[cfg(windows)]
+fn relaunch_with(args: &[OsString]) -> Result<()> {
+    let exe = std::env::current_exe()?;
+    tracing::info!(
+        "Administrator rights are needed; starting {:?} through the UAC prompt",
+        exe
+    );
+    let dir = std::env::current_dir()?;
+    let status = std::process::Command::new("powershell")
+        .args(["-NoProfile", "-NonInteractive", "-Command"])
+        .arg(relaunch_script(&exe, args, &dir))
+        .status()?;
+    match status.code() {
+        Some(0) => Ok(()),
+        Some(CANCELLED_CODE) => Err(PatcherError::AccessDenied(tr!(
+            "administrator rights were not granted",
+            "права администратора не предоставлены"
+        ))
+        .into()),
+        code => anyhow::bail!(tr!(
+            "The run with administrator rights failed (exit code {})",
+            "Запуск с правами администратора завершился ошибкой (код {})",
+            code.map_or("?".to_string(), |code| code.to_string())
+        )),
+    }
+}
+
+#[cfg(not(windows))]
+fn relaunch_with(args: &[OsString]) -> Result<()> {
+    let command: Vec<String> = args
+        .iter()
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .collect();
+    Err(PatcherError::AccessDenied(tr!(
+        "administrator rights are needed; run `sudo yandex-music-mod {}`",
+        "нужны права администратора; запустите `sudo yandex-music-mod {}`",
+        command.join(" ")
+    ))
+    .into())
+}
+
+/// PowerShell starting `exe` with `args`, `--elevated` and the working
+/// directory `dir` through the UAC prompt, exiting with its exit code or
+/// `CANCELLED_CODE` when declined
+#[cfg_attr(not(windows), allow(dead_code))]
+fn relaunch_script(exe: &Path, args: &[OsString], dir: &Path) -> String {
+    let arguments: Vec<String> = args
+        .iter()
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .chain([
+            ELEVATED_FLAG.to_string(),
+            ELEVATED_DIR_FLAG.to_string(),
+            dir.to_string_lossy().into_owned(),
+        ])
+        .map(|arg| crate::schedule::windows_quote(&arg))
+        .collect();
+    format!(
+        "try {{ $p = Start-Process -FilePath {} -ArgumentList {} -Verb RunAs -Wait -PassThru \
+         -ErrorAction Stop }} catch {{ exit {} }}; exit $p.ExitCode",
+        powershell_quote(&exe.to_string_lossy()),
+        powershell_quote(&arguments.join(" ")),
+        CANCELLED_CODE
+    )
+}
+
+/// Single-quoted PowerShell string
+#[cfg_attr(not(windows), allow(dead_code))]
+fn powershell_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_elevation() {
+        let script = relaunch_script(
+            Path::new(r"C:\Program Files\Mod's\yandex-music-mod.exe"),
+            &[
+                "apply".into(),
+                "--path".into(),
+                r"C:\Program Files\YandexMusic".into(),
+                "--output".into(),
+                r"builds\mod".into(),
+            ],
+            Path::new(r"D:\My builds"),
+        );
+        assert!(script.contains(r"-FilePath 'C:\Program Files\Mod''s\yandex-music-mod.exe'"));
+        // The relative --output resolves against the caller's directory
+        assert!(script.contains(concat!(
+            r#"-ArgumentList 'apply --path "C:\Program Files\YandexMusic" --output builds\mod "#,
+            r#"--elevated --elevated-dir "D:\My builds"'"#
+        )));
+        assert!(script.contains("exit 1223"));
+
+        let dir = tempfile::tempdir().unwrap();
+        assert!(check_access(dir.path()).is_ok());
+        let denied = check_access(&dir.path().join("missing")).unwrap_err();
+        assert!(!is_access_denied(&denied));
+
+        let denied: anyhow::Error = io::Error::from(io::ErrorKind::PermissionDenied).into();
+        assert!(is_access_denied(&denied.context("Failed to copy")));
+        assert!(is_access_denied(&anyhow::Error::new(
+            PatcherError::AccessDenied("C:\\Program Files".to_string())
+        )));
+    }
+}
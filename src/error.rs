@@ -1,13 +1,23 @@
 //! Custom error types for the patcher
+//!
+//! The library works with [`anyhow::Error`] internally and attaches a
+//! [`PatcherError`] where a failure class is known, so callers can tell failures
+//! apart with [`code`] instead of matching messages. Codes are stable and double
+//! as the process exit codes of the CLI.
 
 use thiserror::Error;
 
+/// Exit code of failures without a more specific class
+pub const GENERIC_CODE: u8 = 1;
+
 #[derive(Error, Debug)]
-#[allow(dead_code)]
 pub enum PatcherError {
     #[error("Failed to download build: {0}")]
     DownloadError(String),
 
+    #[error("Downloaded installer does not match the published SHA-512 of {0}")]
+    ChecksumMismatch(String),
+
     #[error("Failed to extract archive: {0}")]
     ExtractionError(String),
 
@@ -23,12 +33,90 @@ pub enum PatcherError {
     #[error("ASAR extraction failed: {0}")]
     AsarError(String),
 
+    #[error("Required tool missing: {0}")]
+    MissingDependency(String),
+
     #[error("Patching failed: {0}")]
     PatchError(String),
 
+    #[error("Not enough disk space: {0}")]
+    InsufficientSpace(String),
+
+    #[error("Build directory locked: {0}")]
+    BuildLocked(String),
+
+    #[error("Access denied: {0}")]
+    AccessDenied(String),
+
     #[error("IO error: {0}")]
     IoError(#[from] std::io::Error),
 
     #[error("HTTP error: {0}")]
     HttpError(#[from] reqwest::Error),
 }
+
+impl PatcherError {
+    /// Stable numeric code of the failure class
+    pub fn code(&self) -> u8 {
+        match self {
+            PatcherError::HttpError(_) => 10,
+            PatcherError::DownloadError(_) => 11,
+            PatcherError::ChecksumMismatch(_) => 12,
+            PatcherError::YamlParseError(_) => 13,
+            PatcherError::InvalidBuildInfo(_) => 14,
+            PatcherError::MissingDependency(_) => 20,
+            PatcherError::ExtractionError(_) => 21,
+            PatcherError::AsarError(_) => 22,
+            PatcherError::FileNotFound(_) => 23,
+            PatcherError::PatchError(_) => 30,
+            PatcherError::IoError(_) => 40,
+            PatcherError::InsufficientSpace(_) => 41,
+            PatcherError::BuildLocked(_) => 42,
+            PatcherError::AccessDenied(_) => 43,
+        }
+    }
+}
+
+/// Code of the outermost [`PatcherError`] attached to `error`.
+///
+/// Unclassified HTTP and I/O errors in the chain count as their [`PatcherError`] class.
+pub fn code(error: &anyhow::Error) -> u8 {
+    // Unlike `chain()`, `downcast_ref` also finds errors attached as context
+    if let Some(e) = error.downcast_ref::<PatcherError>() {
+        return e.code();
+    }
+    error
+        .chain()
+        .find_map(|cause| {
+            if cause.is::<reqwest::Error>() {
+                Some(10)
+            } else if cause.is::<std::io::Error>() {
+                Some(40)
+            } else {
+                None
+            }
+        })
+        .unwrap_or(GENERIC_CODE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn test_error_codes() {
+        assert_eq!(code(&anyhow::anyhow!("boom")), GENERIC_CODE);
+
+        assert_eq!(code(&std::io::Error::other("disk").into()), 40);
+
+        // The outermost class wins over its causes
+        let extraction: anyhow::Result<()> = Err(std::io::Error::other("disk").into());
+        let extraction = extraction
+            .context(PatcherError::ExtractionError("build.exe".to_string()))
+            .context("Patching 5.0.0")
+            .unwrap_err();
+        assert_eq!(code(&extraction), 21);
+        assert!(format!("{:#}", extraction).contains("Failed to extract archive"));
+    }
+}
@@ -0,0 +1,224 @@
+//! C ABI for native launchers and C#/C++ frontends
+//!
+//! Declared in `include/yandex_music_mod.h`. Call [`ymm_init`] once, then
+//! [`ymm_fetch_latest`] and [`ymm_patch`] from any thread. Functions that fail
+//! return null or the [`error::code`] of the failure; [`ymm_last_error`]
+//! describes it.
+
+use crate::api;
+use crate::config::Config;
+use crate::error;
+use crate::patcher;
+use crate::paths;
+use crate::progress::{Event, ProgressSink, ProgressState};
+use anyhow::{Context, Result};
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, c_void, CStr, CString};
+use std::path::Path;
+use std::ptr;
+use std::sync::OnceLock;
+use tokio::runtime::Runtime;
+
+/// Called with the overall progress (0-100) and the current stage, which is only
+/// valid during the call
+pub type ProgressCallback =
+    extern "C" fn(percent: u32, message: *const c_char, user_data: *mut c_void);
+
+struct Engine {
+    runtime: Runtime,
+    config: Config,
+}
+
+static ENGINE: OnceLock<Engine> = OnceLock::new();
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Load the configuration (`config.toml` from the config directory if
+/// `config_path` is null) and start the runtime. Returns 0 on success.
+///
+/// Later calls after a successful one do nothing.
+///
+/// # Safety
+///
+/// `config_path` must be null or a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn ymm_init(config_path: *const c_char) -> c_int {
+    status(unsafe { opt_str(config_path) }.and_then(|path| {
+        if ENGINE.get().is_some() {
+            return Ok(());
+        }
+        let config = Config::load(path.map(Path::new))?;
+        if let Some(proxy) = &config.proxy {
+            api::set_proxy(proxy);
+        }
+        let runtime = Runtime::new().context("Failed to start the async runtime")?;
+        let _ = ENGINE.set(Engine { runtime, config });
+        Ok(())
+    }))
+}
+
+/// Latest builds of `channel` (the configured channel if null) as a JSON array,
+/// or null on failure. Free the result with [`ymm_string_free`].
+///
+/// # Safety
+///
+/// `channel` must be null or a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn ymm_fetch_latest(channel: *const c_char) -> *mut c_char {
+    let result = unsafe { opt_str(channel) }.and_then(|channel| {
+        let engine = engine()?;
+        let channel = engine.channel(channel);
+        let builds = engine.runtime.block_on(api::get_latest_build(&channel))?;
+        Ok(CString::new(serde_json::to_string(&builds)?)?)
+    });
+    match result {
+        Ok(json) => json.into_raw(),
+        Err(e) => {
+            set_last_error(&e);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Patch the latest build of `channel` into `output` (configured values if
+/// null) with the options from the `[patch]` table. Returns 0 on success.
+///
+/// `callback`, if given, is called on the calling thread as the run progresses.
+///
+/// # Safety
+///
+/// `channel` and `output` must be null or valid NUL-terminated strings;
+/// `user_data` is passed to `callback` unchanged.
+#[no_mangle]
+pub unsafe extern "C" fn ymm_patch(
+    channel: *const c_char,
+    output: *const c_char,
+    callback: Option<ProgressCallback>,
+    user_data: *mut c_void,
+) -> c_int {
+    let args =
+        unsafe { opt_str(channel) }.and_then(|channel| Ok((channel, unsafe { opt_str(output) }?)));
+    status(args.and_then(|(channel, output)| {
+        let engine = engine()?;
+        let channel = engine.channel(channel);
+        let output = engine.config.output_dir(output.map(Path::new));
+        paths::ensure_writable(&output)?;
+
+        engine.runtime.block_on(async {
+            let builds = api::get_latest_build(&channel).await?;
+            let build = builds.first().context("No builds found")?;
+            let options = engine.config.patch_options()?;
+
+            let sink = callback.map(|callback| CallbackSink {
+                callback,
+                user_data,
+                state: ProgressState::default(),
+            });
+            let progress = sink.as_ref().map(|sink| sink as &dyn ProgressSink);
+            patcher::process_build(build, &output, &options, progress).await?;
+            Ok(())
+        })
+    }))
+}
+
+/// Message of the last failure on this thread, or null. Valid until the next
+/// call into this library on the same thread.
+#[no_mangle]
+pub extern "C" fn ymm_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ref().map_or(ptr::null(), |e| e.as_ptr()))
+}
+
+/// Free a string returned by this library
+///
+/// # Safety
+///
+/// `s` must be null or a pointer returned by this library, freed only once.
+#[no_mangle]
+pub unsafe extern "C" fn ymm_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(unsafe { CString::from_raw(s) });
+    }
+}
+
+/// Forwards progress to a C callback
+struct CallbackSink {
+    callback: ProgressCallback,
+    user_data: *mut c_void,
+    state: ProgressState,
+}
+
+// The pipeline runs on the thread that called `ymm_patch`, so the callback and
+// `user_data` are only ever used there
+unsafe impl Send for CallbackSink {}
+unsafe impl Sync for CallbackSink {}
+
+impl ProgressSink for CallbackSink {
+    fn event(&self, event: Event) {
+        self.state.event(event);
+        let snapshot = self.state.snapshot();
+        let message = CString::new(snapshot.message().replace('\0', "")).unwrap_or_default();
+        (self.callback)(snapshot.percent as u32, message.as_ptr(), self.user_data);
+    }
+}
+
+impl Engine {
+    fn channel(&self, channel: Option<&str>) -> String {
+        channel
+            .or(self.config.channel.as_deref())
+            .unwrap_or(api::DEFAULT_CHANNEL)
+            .to_string()
+    }
+}
+
+fn engine() -> Result<&'static Engine> {
+    ENGINE.get().context("ymm_init was not called")
+}
+
+/// Borrow a nullable C string
+unsafe fn opt_str<'a>(s: *const c_char) -> Result<Option<&'a str>> {
+    if s.is_null() {
+        return Ok(None);
+    }
+    let s = unsafe { CStr::from_ptr(s) };
+    Ok(Some(s.to_str().context("Argument is not valid UTF-8")?))
+}
+
+/// Record the outcome of a call and turn it into a status code
+fn status(result: Result<()>) -> c_int {
+    match result {
+        Ok(()) => {
+            LAST_ERROR.with(|e| e.borrow_mut().take());
+            0
+        }
+        Err(e) => {
+            set_last_error(&e);
+            error::code(&e).into()
+        }
+    }
+}
+
+fn set_last_error(error: &anyhow::Error) {
+    let message = format!("{:#}", error).replace('\0', "");
+    LAST_ERROR.with(|e| *e.borrow_mut() = CString::new(message).ok());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_last_error() {
+        assert_eq!(status(Err(anyhow::anyhow!("boom"))), 1);
+        let message = unsafe { CStr::from_ptr(ymm_last_error()) };
+        assert_eq!(message.to_str().unwrap(), "boom");
+
+        status(Ok(()));
+        assert!(ymm_last_error().is_null());
+
+        let invalid = c"\xff";
+        assert!(unsafe { opt_str(invalid.as_ptr()) }.is_err());
+        assert!(unsafe { opt_str(ptr::null()) }.unwrap().is_none());
+    }
+}
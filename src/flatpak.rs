@@ -0,0 +1,205 @@
+//! Flatpak module - files for building a Flatpak of the patched app on Linux
+//!
+//! The installer only ships a Windows Electron, so the manifest pairs the mod
+//! with the official Linux Electron release of the same version on top of the
+//! Electron base app. Everything is written to `<build dir>/package/flatpak`;
+//! build it with `flatpak-builder --user --install build <app id>.yml`.
+
+use crate::api;
+use crate::msix;
+use crate::package::{PACKAGE_ID, PACKAGE_NAME, URL_SCHEME};
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+/// Flatpak application ID, the `appId` the patcher puts into `package.json`
+pub const FLATPAK_ID: &str = "ru.yandex.desktop.music.mod";
+
+/// Directory of the manifest inside the package directory
+pub const FLATPAK_DIR: &str = "flatpak";
+
+/// Freedesktop runtime and Electron base app branch
+const RUNTIME_VERSION: &str = "23.08";
+
+/// Sandbox permissions: display, sound, network, and the MPRIS name Chromium
+/// registers for media keys and desktop players
+const FINISH_ARGS: [&str; 9] = [
+    "--share=ipc",
+    "--share=network",
+    "--socket=x11",
+    "--socket=wayland",
+    "--socket=pulseaudio",
+    "--device=dri",
+    "--own-name=org.mpris.MediaPlayer2.chromium.*",
+    "--talk-name=org.freedesktop.Notifications",
+    "--env=ELECTRON_TRASH=gio",
+];
+
+/// Write the Flatpak manifest and its sources for the mod of `build_dir`.
+/// The Linux Electron release of `electron_version` is verified by its checksum.
+pub(crate) async fn package(
+    build_dir: &Path,
+    electron_version: &str,
+    package_dir: &Path,
+) -> Result<Vec<PathBuf>> {
+    let flatpak_dir = package_dir.join(FLATPAK_DIR);
+    fs::create_dir_all(&flatpak_dir)?;
+
+    let electron = electron_source(electron_version).await?;
+    let icon = fs::read(build_dir.join("icon.ico"))
+        .ok()
+        .and_then(|ico| msix::largest_png(&ico));
+
+    let files = [
+        (format!("{}.sh", PACKAGE_ID), LAUNCHER.to_string()),
+        (format!("{}.desktop", FLATPAK_ID), desktop_entry()),
+        (
+            format!("{}.yml", FLATPAK_ID),
+            manifest(electron, icon.as_ref().map(|(size, _)| *size))?,
+        ),
+    ];
+
+    let mut written = Vec::new();
+    for (name, content) in files {
+        let path = flatpak_dir.join(&name);
+        fs::write(&path, content).with_context(|| format!("Failed to write {:?}", path))?;
+        written.push(path);
+    }
+    if let Some((_, png)) = icon {
+        let path = flatpak_dir.join("icon.png");
+        fs::write(&path, png)?;
+        written.push(path);
+    }
+    info!("Wrote Flatpak manifest to {:?}", flatpak_dir);
+    Ok(written)
+}
+
+/// Starts the mod with the bundled Electron through the base app's zypak sandbox helper
+const LAUNCHER: &str = "#!/bin/sh\n\
+    exec zypak-wrapper /app/electron/electron /app/lib/yandex-music-mod \"$@\"\n";
+
+fn desktop_entry() -> String {
+    format!(
+        "[Desktop Entry]\n\
+         Type=Application\n\
+         Name={PACKAGE_NAME}\n\
+         Comment=Patched Yandex Music desktop app\n\
+         Exec={PACKAGE_ID} %U\n\
+         Icon={FLATPAK_ID}\n\
+         Categories=AudioVideo;Audio;Player;\n\
+         MimeType=x-scheme-handler/{URL_SCHEME};\n\
+         StartupWMClass=Яндекс Музыка\n"
+    )
+}
+
+/// Flatpak source of the Linux x64 Electron release
+async fn electron_source(version: &str) -> Result<serde_json::Value> {
+    let version = version.trim_start_matches('v');
+    let base = format!(
+        "https://github.com/electron/electron/releases/download/v{}",
+        version
+    );
+    let file_name = format!("electron-v{}-linux-x64.zip", version);
+    let checksums = api::fetch_text(&format!("{}/SHASUMS256.txt", base))
+        .await
+        .with_context(|| format!("Failed to fetch checksums of Electron {}", version))?;
+    let sha256 = electron_checksum(&checksums, &file_name)
+        .with_context(|| format!("No checksum for {} in SHASUMS256.txt", file_name))?;
+
+    Ok(serde_json::json!({
+        "type": "archive",
+        "url": format!("{}/{}", base, file_name),
+        "sha256": sha256,
+        "dest": "electron",
+        "strip-components": 0,
+    }))
+}
+
+/// Checksum of `file_name` in a `SHASUMS256.txt` of an Electron release
+fn electron_checksum(checksums: &str, file_name: &str) -> Option<String> {
+    checksums.lines().find_map(|line| {
+        let (hash, name) = line.split_once(char::is_whitespace)?;
+        (name.trim().trim_start_matches('*') == file_name).then(|| hash.to_string())
+    })
+}
+
+/// flatpak-builder manifest; `icon_size` is the width of `icon.png`, if written
+fn manifest(electron: serde_json::Value, icon_size: Option<u32>) -> Result<String> {
+    let mut build_commands = vec![
+        "cp -a electron /app/electron".to_string(),
+        "cp -a mod /app/lib/yandex-music-mod".to_string(),
+        format!("install -Dm755 {PACKAGE_ID}.sh /app/bin/{PACKAGE_ID}"),
+        format!("install -Dm644 {FLATPAK_ID}.desktop -t /app/share/applications"),
+    ];
+    let mut sources = vec![
+        electron,
+        serde_json::json!({ "type": "dir", "path": "../../mod", "dest": "mod" }),
+        serde_json::json!({ "type": "file", "path": format!("{PACKAGE_ID}.sh") }),
+        serde_json::json!({ "type": "file", "path": format!("{FLATPAK_ID}.desktop") }),
+    ];
+    if let Some(size) = icon_size {
+        build_commands.push(format!(
+            "install -Dm644 icon.png /app/share/icons/hicolor/{size}x{size}/apps/{FLATPAK_ID}.png"
+        ));
+        sources.push(serde_json::json!({ "type": "file", "path": "icon.png" }));
+    }
+
+    let manifest = serde_json::json!({
+        "app-id": FLATPAK_ID,
+        "runtime": "org.freedesktop.Platform",
+        "runtime-version": RUNTIME_VERSION,
+        "sdk": "org.freedesktop.Sdk",
+        "base": "org.electronjs.Electron2.BaseApp",
+        "base-version": RUNTIME_VERSION,
+        "command": PACKAGE_ID,
+        "separate-locales": false,
+        "finish-args": FINISH_ARGS,
+        "modules": [{
+            "name": PACKAGE_ID,
+            "buildsystem": "simple",
+            "build-commands": build_commands,
+            "sources": sources,
+        }],
+    });
+    Ok(serde_yaml::to_string(&manifest)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flatpak_manifest() {
+        let checksums = "aaa *electron-v32.2.0-darwin-x64.zip\n\
+                         bbb *electron-v32.2.0-linux-x64.zip\n";
+        assert_eq!(
+            electron_checksum(checksums, "electron-v32.2.0-linux-x64.zip").as_deref(),
+            Some("bbb")
+        );
+        assert_eq!(
+            electron_checksum(checksums, "electron-v1.0.0-linux-x64.zip"),
+            None
+        );
+
+        let electron = serde_json::json!({ "type": "archive", "dest": "electron" });
+        let manifest: serde_yaml::Value =
+            serde_yaml::from_str(&manifest(electron, Some(256)).unwrap()).unwrap();
+        assert_eq!(manifest["app-id"], FLATPAK_ID);
+        let finish_args = manifest["finish-args"].as_sequence().unwrap();
+        for permission in ["--share=network", "--socket=pulseaudio"] {
+            assert!(finish_args.iter().any(|arg| arg == permission));
+        }
+        assert!(finish_args
+            .iter()
+            .any(|arg| arg.as_str().unwrap().contains("org.mpris.MediaPlayer2")));
+
+        let module = &manifest["modules"][0];
+        assert_eq!(module["sources"].as_sequence().unwrap().len(), 5);
+        assert!(module["build-commands"][4]
+            .as_str()
+            .unwrap()
+            .contains("hicolor/256x256"));
+        assert!(desktop_entry().contains("MimeType=x-scheme-handler/yandexmusic;"));
+    }
+}
@@ -0,0 +1,315 @@
+//! Generic module - patching any Electron app's `app.asar`
+//!
+//! `patch-asar` runs the rule and injection stages of the pipeline on an
+//! arbitrary `app.asar` (or its extracted directory) instead of a downloaded
+//! Yandex Music build. Without `--rules` the Yandex Music profile is applied,
+//! as `patch` would; a rules file instead describes find/replace rules for the
+//! app and the renderer assets to inject into its HTML pages, which makes the
+//! patcher usable on related Electron apps (Alice, Disk) for experiments.
+
+use crate::asar::{self, AsarOptions, AsarReport};
+use crate::patcher::{self, HtmlOutcome, PatchOptions};
+use crate::rules::{self, PatchRule, ProfileSet, RuleOutcome};
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+use walkdir::WalkDir;
+
+/// Directory the injected assets are copied to, below the HTML root
+pub const INJECTED_DIR: &str = "injected";
+
+/// A rules file for `patch-asar --rules`
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct GenericProfile {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Directory holding the HTML pages, relative to the app root
+    pub html_root: String,
+    /// Scripts (`.js`) and styles (`.css`) added to every page, relative to
+    /// the rules file
+    pub inject: Vec<PathBuf>,
+    /// Rules; `versions` ranges are matched against `version` of `package.json`
+    pub rules: Vec<PatchRule>,
+}
+
+impl GenericProfile {
+    /// Read a rules file, resolving `inject` against its directory; `name`
+    /// defaults to the file name
+    pub fn load(path: &Path) -> Result<Self> {
+        let content =
+            fs::read_to_string(path).with_context(|| format!("Failed to read {:?}", path))?;
+        let mut profile: Self =
+            toml::from_str(&content).with_context(|| format!("Failed to parse {:?}", path))?;
+        if profile.name.is_empty() {
+            profile.name = path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default();
+        }
+        let base = path.parent().unwrap_or(Path::new(""));
+        for asset in &mut profile.inject {
+            *asset = base.join(&*asset);
+        }
+        Ok(profile)
+    }
+}
+
+/// Result of `patch-asar`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GenericReport {
+    pub input: PathBuf,
+    pub output: PathBuf,
+    /// `name` and `version` of the app's `package.json`
+    pub app_name: Option<String>,
+    pub app_version: Option<String>,
+    /// The rules file's name, or the Yandex Music profile applied
+    pub profile: String,
+    pub rules: Vec<RuleOutcome>,
+    pub html: Vec<HtmlOutcome>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub asar: Option<AsarReport>,
+}
+
+/// Patch `input` (an `app.asar` or a directory) into `output`: an `.asar`
+/// path is packed with `asar_options`, anything else is written as a
+/// directory. Without `profile` the Yandex Music profile and `options` apply.
+pub fn patch_asar(
+    input: &Path,
+    output: &Path,
+    profile: Option<&GenericProfile>,
+    options: &PatchOptions,
+    asar_options: &AsarOptions,
+) -> Result<GenericReport> {
+    let work = tempfile::tempdir()?;
+    let app_dir = work.path().join("app");
+    if input.is_file() {
+        patcher::extract_asar(input, &app_dir)
+            .with_context(|| format!("Failed to extract {:?}", input))?;
+    } else if input.is_dir() {
+        patcher::copy_dir_all(input, &app_dir)?;
+    } else {
+        anyhow::bail!("{:?} does not exist", input);
+    }
+
+    let package: serde_json::Value = fs::read_to_string(app_dir.join("package.json"))
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default();
+    let field = |name: &str| package[name].as_str().map(str::to_string);
+    let (app_name, app_version) = (field("name"), field("version"));
+    info!(
+        "Patching {} {} from {:?}",
+        app_name.as_deref().unwrap_or("app"),
+        app_version.as_deref().unwrap_or("?"),
+        input
+    );
+
+    let (profile_name, rules, html) = match profile {
+        Some(profile) => {
+            let rules = match &app_version {
+                Some(version) => rules::for_version(&profile.rules, version),
+                None => profile.rules.iter().collect(),
+            };
+            let outcomes = rules::apply_rules(&app_dir, &rules, false)?;
+            let html = inject_assets(&app_dir.join(&profile.html_root), &profile.inject)?;
+            (profile.name.clone(), outcomes, html)
+        }
+        None => {
+            let version = app_version
+                .as_deref()
+                .context("package.json has no version to select the patch profile from")?;
+            let profiles = ProfileSet::load()?;
+            let yandex = profiles.select(version, options.profile.as_deref())?;
+            let (_, rules, html) = patcher::patch_app(&app_dir, yandex, version, options, None)?;
+            (yandex.name.clone(), rules, html)
+        }
+    };
+
+    let is_asar = output.extension().is_some_and(|ext| ext == "asar");
+    let asar = if is_asar {
+        if let Some(parent) = output.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        Some(asar::pack(&app_dir, output, asar_options)?)
+    } else {
+        if output.exists() {
+            anyhow::bail!("{:?} already exists", output);
+        }
+        patcher::copy_dir_all(&app_dir, output)?;
+        None
+    };
+    info!("Patched app written to {:?}", output);
+
+    Ok(GenericReport {
+        input: input.to_path_buf(),
+        output: output.to_path_buf(),
+        app_name,
+        app_version,
+        profile: profile_name,
+        rules,
+        html,
+        asar,
+    })
+}
+
+/// Default output next to `input`: `app.asar` becomes `app.patched.asar`
+pub fn default_output(input: &Path) -> PathBuf {
+    let stem = input
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "app".to_string());
+    input.with_file_name(format!("{}.patched.asar", stem))
+}
+
+/// Copy `assets` to `html_root/injected` and reference them from the
+/// `<head>` of every HTML page below `html_root`
+fn inject_assets(html_root: &Path, assets: &[PathBuf]) -> Result<Vec<HtmlOutcome>> {
+    if assets.is_empty() {
+        return Ok(Vec::new());
+    }
+    let injected_dir = html_root.join(INJECTED_DIR);
+    fs::create_dir_all(&injected_dir)?;
+    let mut names = Vec::new();
+    for asset in assets {
+        let name = asset
+            .file_name()
+            .with_context(|| format!("{:?} is not a file", asset))?;
+        fs::copy(asset, injected_dir.join(name))
+            .with_context(|| format!("Failed to copy {:?}", asset))?;
+        names.push(name.to_string_lossy().into_owned());
+    }
+
+    let head = Regex::new(r"(?i)<head(\s[^>]*)?>").unwrap();
+    let mut outcomes = Vec::new();
+    for entry in WalkDir::new(html_root)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| {
+            e.file_type().is_file()
+                && e.path()
+                    .extension()
+                    .is_some_and(|ext| ext == "html" || ext == "htm")
+        })
+    {
+        let path = entry.path();
+        let relative = path.strip_prefix(html_root).unwrap_or(path);
+        // Relative links work for file:// pages and custom protocols alike
+        let prefix = "../".repeat(relative.components().count() - 1);
+        let tags: String = names
+            .iter()
+            .map(|name| asset_tag(&format!("{}{}/{}", prefix, INJECTED_DIR, name)))
+            .collect();
+
+        let content = fs::read_to_string(path)?;
+        let injected = match head.find(&content) {
+            Some(tag) => {
+                let mut patched = content.clone();
+                patched.insert_str(tag.end(), &tags);
+                fs::write(path, patched)?;
+                true
+            }
+            None => {
+                warn!("No <head> to inject the assets into in {:?}", path);
+                false
+            }
+        };
+        outcomes.push(HtmlOutcome {
+            path: relative.to_string_lossy().replace('\\', "/"),
+            injected,
+        });
+    }
+    outcomes.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(outcomes)
+}
+
+fn asset_tag(src: &str) -> String {
+    if src.ends_with(".css") {
+        format!(r#"<link rel="stylesheet" href="{}">"#, src)
+    } else {
+        format!(r#"<script src="{}"></script>"#, src)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_patch_asar_generic() {
+        let dir = tempfile::tempdir().unwrap();
+        let app = dir.path().join("app");
+        fs::create_dir_all(app.join("dist").join("pages")).unwrap();
+        fs::write(
+            app.join("package.json"),
+            r#"{"name": "alice", "version": "2.1.0"}"#,
+        )
+        .unwrap();
+        fs::write(app.join("dist").join("main.js"), "isBeta=!1").unwrap();
+        fs::write(
+            app.join("dist").join("pages").join("index.html"),
+            "<html><head lang=\"ru\"><title>x</title></head></html>",
+        )
+        .unwrap();
+
+        fs::write(dir.path().join("mod.js"), "console.log(1)").unwrap();
+        fs::write(
+            dir.path().join("rules.toml"),
+            r#"
+            name = "alice"
+            html_root = "dist"
+            inject = ["mod.js"]
+
+            [[rules]]
+            name = "beta"
+            files = "dist/*.js"
+            find = "isBeta=!1"
+            replace = "isBeta=!0"
+
+            [[rules]]
+            name = "old"
+            files = "dist/*.js"
+            find = "isBeta"
+            replace = "x"
+            versions = "<2.0.0"
+            "#,
+        )
+        .unwrap();
+        let profile = GenericProfile::load(&dir.path().join("rules.toml")).unwrap();
+
+        let output = dir.path().join("out");
+        let report = patch_asar(
+            &app,
+            &output,
+            Some(&profile),
+            &PatchOptions::default(),
+            &AsarOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(report.app_version.as_deref(), Some("2.1.0"));
+        assert_eq!(report.rules.len(), 1);
+        assert_eq!(report.rules[0].matches, 1);
+        assert_eq!(
+            fs::read_to_string(output.join("dist").join("main.js")).unwrap(),
+            "isBeta=!0"
+        );
+        let page =
+            fs::read_to_string(output.join("dist").join("pages").join("index.html")).unwrap();
+        assert!(page.contains(r#"<head lang="ru"><script src="../injected/mod.js"></script>"#));
+        assert!(output
+            .join("dist")
+            .join(INJECTED_DIR)
+            .join("mod.js")
+            .exists());
+        assert!(report.html[0].injected);
+
+        assert_eq!(
+            default_output(Path::new("/apps/app.asar")),
+            Path::new("/apps/app.patched.asar")
+        );
+    }
+}
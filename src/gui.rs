@@ -0,0 +1,238 @@
+//! GUI module - a small native window for users who start the exe from Explorer
+//!
+//! Shows the latest build, a single "Patch" button with per-stage progress and,
+//! once patching is done, actions to open the output folder or launch the app.
+//! Only built with the `gui` feature.
+
+use crate::api::{self, AppBuild};
+use crate::config::Config;
+use crate::i18n::tr;
+use crate::patcher;
+use crate::patches::{self, Compatibility};
+use crate::paths;
+use crate::progress::ProgressState;
+use anyhow::{anyhow, Result};
+use eframe::egui;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tokio::runtime::Handle;
+use tracing::{error, info};
+
+/// State shared between the window and the background tasks
+enum State {
+    Fetching,
+    Ready(AppBuild),
+    Patching(AppBuild),
+    Done { version: String, mod_dir: PathBuf },
+    Failed(String),
+}
+
+struct PatcherApp {
+    state: Arc<Mutex<State>>,
+    progress: Arc<ProgressState>,
+    runtime: Handle,
+    config: Arc<Config>,
+    channel: String,
+    output: PathBuf,
+}
+
+/// Open the GUI and block until its window is closed
+pub fn run(config: Config, channel: String, output: PathBuf) -> Result<()> {
+    let app = PatcherApp {
+        state: Arc::new(Mutex::new(State::Fetching)),
+        progress: Arc::default(),
+        runtime: Handle::current(),
+        config: Arc::new(config),
+        channel,
+        output,
+    };
+    app.fetch_build();
+
+    let options = eframe::NativeOptions {
+        viewport: egui::ViewportBuilder::default()
+            .with_inner_size([420.0, 240.0])
+            .with_resizable(false),
+        ..Default::default()
+    };
+
+    eframe::run_native(
+        "Yandex Music Mod",
+        options,
+        Box::new(|_cc| Ok(Box::new(app))),
+    )
+    .map_err(|e| anyhow!("Failed to start GUI: {}", e))
+}
+
+impl PatcherApp {
+    fn set_state(state: &Mutex<State>, new: State) {
+        *state.lock().unwrap() = new;
+    }
+
+    /// Fetch the latest build in the background
+    fn fetch_build(&self) {
+        let state = Arc::clone(&self.state);
+        let channel = self.channel.clone();
+        self.runtime.spawn(async move {
+            let new = match api::get_latest_build(&channel).await {
+                Ok(builds) => match builds.into_iter().next() {
+                    Some(build) => State::Ready(build),
+                    None => State::Failed(tr!("No builds found", "Сборки не найдены")),
+                },
+                Err(e) => State::Failed(format!("{:#}", e)),
+            };
+            Self::set_state(&state, new);
+        });
+    }
+
+    /// Patch `build` in the background, reporting stages through `self.progress`
+    fn start_patch(&self, build: AppBuild) {
+        Self::set_state(&self.state, State::Patching(build.clone()));
+        self.progress.reset();
+
+        let state = Arc::clone(&self.state);
+        let progress = Arc::clone(&self.progress);
+        let config = Arc::clone(&self.config);
+        let output = self.output.clone();
+        self.runtime.spawn(async move {
+            let result = async {
+                let options = config.patch_options()?;
+                patcher::process_build(&build, &output, &options, Some(&*progress)).await
+            }
+            .await;
+
+            let new = match result {
+                Ok(_) => {
+                    info!("Successfully patched Yandex Music v{}", build.version);
+                    State::Done {
+                        mod_dir: patcher::build_dir(&output, &build.version, false).join("mod"),
+                        version: build.version,
+                    }
+                }
+                Err(e) => {
+                    error!("Patching failed: {:#}", e);
+                    State::Failed(format!("{:#}", e))
+                }
+            };
+            Self::set_state(&state, new);
+        });
+    }
+
+    fn build_info(ui: &mut egui::Ui, build: &AppBuild) {
+        egui::Grid::new("build").num_columns(2).show(ui, |ui| {
+            ui.label(tr!("Version:", "Версия:"));
+            ui.strong(&build.version);
+            ui.end_row();
+            if let Some(date) = &build.release_date {
+                ui.label(tr!("Released:", "Выпущена:"));
+                ui.label(date);
+                ui.end_row();
+            }
+            ui.label(tr!("Size:", "Размер:"));
+            ui.label(tr!("{:.1} MB", "{:.1} МБ", build.size as f64 / 1_048_576.0));
+            ui.end_row();
+        });
+
+        match patches::check_compatibility(&build.version) {
+            Compatibility::Known => {}
+            Compatibility::Untested => {
+                ui.label(tr!(
+                    "This version has not been tested; patches should still apply.",
+                    "Эта версия не проверялась; патчи должны примениться."
+                ));
+            }
+            Compatibility::Newer => {
+                ui.colored_label(
+                    ui.visuals().warn_fg_color,
+                    tr!(
+                        "This version is newer than the latest tested one ({}).\n\
+                         Some patches may no longer apply.",
+                        "Эта версия новее последней проверенной ({}).\n\
+                         Часть патчей может не примениться.",
+                        patches::latest_known_version()
+                    ),
+                );
+            }
+        }
+    }
+}
+
+impl eframe::App for PatcherApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        let mut patch = None;
+        let mut retry = false;
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.heading("Yandex Music Mod");
+            ui.label(tr!("Patcher v{}", "Патчер v{}", env!("CARGO_PKG_VERSION")));
+            ui.separator();
+
+            match &*self.state.lock().unwrap() {
+                State::Fetching => {
+                    ui.horizontal(|ui| {
+                        ui.spinner();
+                        ui.label(tr!(
+                            "Fetching latest {} build...",
+                            "Получение последней сборки {}...",
+                            self.channel
+                        ));
+                    });
+                }
+                State::Ready(build) => {
+                    Self::build_info(ui, build);
+                    ui.add_space(8.0);
+                    if ui.button(tr!("Patch", "Пропатчить")).clicked() {
+                        patch = Some(build.clone());
+                    }
+                }
+                State::Patching(build) => {
+                    Self::build_info(ui, build);
+                    ui.add_space(8.0);
+                    let progress = self.progress.snapshot();
+                    ui.add(
+                        egui::ProgressBar::new(progress.percent as f32 / 100.0)
+                            .text(progress.message())
+                            .animate(true),
+                    );
+                }
+                State::Done { version, mod_dir } => {
+                    ui.label(tr!(
+                        "Yandex Music v{} patched successfully!",
+                        "Яндекс Музыка v{} успешно пропатчена!",
+                        version
+                    ));
+                    ui.label(mod_dir.display().to_string());
+                    ui.add_space(8.0);
+                    ui.horizontal(|ui| {
+                        if ui.button(tr!("Open folder", "Открыть папку")).clicked() {
+                            if let Err(e) = paths::open_folder(mod_dir) {
+                                error!("{:#}", e);
+                            }
+                        }
+                        if ui.button(tr!("Launch", "Запустить")).clicked() {
+                            if let Err(e) = patcher::launch_app(mod_dir) {
+                                error!("{:#}", e);
+                            }
+                        }
+                    });
+                }
+                State::Failed(message) => {
+                    ui.colored_label(ui.visuals().error_fg_color, tr!("Error", "Ошибка"));
+                    ui.label(message);
+                    ui.add_space(8.0);
+                    retry = ui.button(tr!("Retry", "Повторить")).clicked();
+                }
+            }
+        });
+
+        if let Some(build) = patch {
+            self.start_patch(build);
+        }
+        if retry {
+            Self::set_state(&self.state, State::Fetching);
+            self.fetch_build();
+        }
+
+        // Background tasks update the state without waking the UI
+        ctx.request_repaint_after(std::time::Duration::from_millis(100));
+    }
+}
@@ -0,0 +1,71 @@
+//! Localization of user-facing output (English and Russian)
+//!
+//! Messages are written inline with [`tr!`], one format string per language.
+//! Log messages stay in English so bug reports read the same for everyone.
+
+use std::sync::OnceLock;
+
+/// Language of user-facing output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum Lang {
+    En,
+    Ru,
+}
+
+impl Lang {
+    /// Parse a locale such as `ru-RU`, `ru_RU.UTF-8` or `en`
+    pub fn from_locale(locale: &str) -> Option<Self> {
+        let code = locale.split(['-', '_', '.']).next()?.to_ascii_lowercase();
+        match code.as_str() {
+            "ru" => Some(Lang::Ru),
+            "en" => Some(Lang::En),
+            _ => None,
+        }
+    }
+
+    /// Language of the operating system, English if unsupported
+    pub fn detect() -> Self {
+        sys_locale::get_locale()
+            .as_deref()
+            .and_then(Self::from_locale)
+            .unwrap_or(Lang::En)
+    }
+}
+
+static LANG: OnceLock<Lang> = OnceLock::new();
+
+/// Set the output language once at startup
+pub fn set_lang(lang: Lang) {
+    let _ = LANG.set(lang);
+}
+
+/// Current output language, detected from the OS if never set
+pub fn lang() -> Lang {
+    *LANG.get_or_init(Lang::detect)
+}
+
+/// `format!` with a separate format string per language: `tr!("en {}", "ru {}", arg)`
+#[macro_export]
+macro_rules! tr {
+    ($en:literal, $ru:literal $(, $arg:expr)* $(,)?) => {
+        match $crate::i18n::lang() {
+            $crate::i18n::Lang::En => format!($en $(, $arg)*),
+            $crate::i18n::Lang::Ru => format!($ru $(, $arg)*),
+        }
+    };
+}
+pub use tr;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_locale() {
+        assert_eq!(Lang::from_locale("ru-RU"), Some(Lang::Ru));
+        assert_eq!(Lang::from_locale("ru_RU.UTF-8"), Some(Lang::Ru));
+        assert_eq!(Lang::from_locale("EN"), Some(Lang::En));
+        assert_eq!(Lang::from_locale("de-DE"), None);
+    }
+}
@@ -0,0 +1,578 @@
+//! Install module - Yandex Music installations on this machine
+//!
+//! `status` finds the installed desktop app where its installers put it: the
+//! uninstall entries of the Windows registry and the default install folders
+//! (`%LOCALAPPDATA%\Programs`, `/Applications`, `/opt`, `~/.local`). The
+//! version is read from `package.json` inside `app.asar` without extracting
+//! the archive, and the package name tells the stock app from one patched by
+//! this mod (`YandexMusicMod`) or by the original one.
+//!
+//! `apply` puts the `app.asar` packed by `patch --pack-asar` into the
+//! installation, keeping the stock one as `app.asar.orig` for `apply --restore`.
+
+use crate::asar;
+use crate::elevate;
+use crate::i18n::tr;
+use crate::migrate;
+use crate::patcher;
+use crate::paths;
+use crate::versions;
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+/// Folder names the installers give the app
+const INSTALL_NAMES: &[&str] = &["YandexMusic", "Яндекс Музыка", "yandex-music"];
+
+/// Where the app archive or folder sits inside an installation
+const APP_PATHS: &[&str] = &[
+    "resources/app.asar",
+    "Contents/Resources/app.asar",
+    "app.asar",
+    "resources/app",
+    "Contents/Resources/app",
+];
+
+/// Suffix of the stock `app.asar` kept by `apply`
+pub const ORIGINAL_SUFFIX: &str = ".orig";
+
+/// Whether and by what an installed app is patched
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum InstallState {
+    Stock,
+    Patched,
+    /// Patched by the original TypeScript mod
+    OriginalMod,
+    /// `package.json` could not be read
+    Unknown,
+}
+
+/// Where an installation was found
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum InstallSource {
+    Registry,
+    KnownPath,
+    /// Installed from the Microsoft Store; read-only, patched through `sideload`
+    Store,
+    Argument,
+}
+
+/// An installed Yandex Music app
+#[derive(Debug, Clone, Serialize)]
+pub struct Installation {
+    /// Install folder
+    pub path: PathBuf,
+    /// `app.asar` or the unpacked app folder
+    pub app: PathBuf,
+    pub version: Option<String>,
+    pub state: InstallState,
+    pub source: InstallSource,
+    /// Complete patched build of the same version in the output directory
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub patched_build: Option<PathBuf>,
+}
+
+/// Install folders to look at, in the order they are reported
+pub fn candidates() -> Vec<(PathBuf, InstallSource)> {
+    let mut dirs: Vec<(PathBuf, InstallSource)> = registry_dirs()
+        .into_iter()
+        .map(|dir| (dir, InstallSource::Registry))
+        .collect();
+
+    let mut roots = Vec::new();
+    if cfg!(windows) {
+        if let Some(local) = std::env::var_os("LOCALAPPDATA") {
+            roots.push(PathBuf::from(local).join("Programs"));
+        }
+        roots.extend(std::env::var_os("ProgramFiles").map(PathBuf::from));
+    } else if cfg!(target_os = "macos") {
+        roots.push(PathBuf::from("/Applications"));
+        if let Some(dirs) = directories::BaseDirs::new() {
+            roots.push(dirs.home_dir().join("Applications"));
+        }
+    } else {
+        if let Some(dirs) = directories::BaseDirs::new() {
+            roots.push(dirs.home_dir().join(".local/share"));
+            roots.push(dirs.home_dir().join(".local/opt"));
+        }
+        roots.extend(["/opt", "/usr/lib", "/usr/share"].map(PathBuf::from));
+    }
+    for root in roots {
+        for name in INSTALL_NAMES {
+            let name = if cfg!(target_os = "macos") {
+                format!("{}.app", name)
+            } else {
+                name.to_string()
+            };
+            dirs.push((root.join(name), InstallSource::KnownPath));
+        }
+    }
+    dirs.extend(
+        crate::store::packages()
+            .iter()
+            .filter_map(|package| package.app_root())
+            .map(|dir| (dir, InstallSource::Store)),
+    );
+    dirs
+}
+
+/// Inspect the installations among `candidates` and `extra` folders, and look
+/// for patched builds of their versions in `output`
+pub fn detect(output: &Path, extra: &[PathBuf]) -> Result<Vec<Installation>> {
+    let builds = versions::scan(output).unwrap_or_default();
+    let mut found: Vec<Installation> = Vec::new();
+    let dirs = extra
+        .iter()
+        .map(|dir| (dir.clone(), InstallSource::Argument))
+        .chain(candidates());
+    for (dir, source) in dirs {
+        let Some(mut install) = inspect(&dir, source) else {
+            if source == InstallSource::Argument {
+                anyhow::bail!(tr!(
+                    "No Yandex Music app found in {:?}",
+                    "В {:?} не найдено приложение Яндекс Музыки",
+                    dir
+                ));
+            }
+            continue;
+        };
+        let canonical = fs::canonicalize(&install.app).unwrap_or(install.app.clone());
+        if found
+            .iter()
+            .any(|other| fs::canonicalize(&other.app).unwrap_or(other.app.clone()) == canonical)
+        {
+            continue;
+        }
+        install.patched_build = builds
+            .iter()
+            .find(|b| {
+                Some(&b.version) == install.version.as_ref()
+                    && b.complete
+                    && !b.dry_run
+                    && !b.legacy
+            })
+            .map(|b| b.path.clone());
+        found.push(install);
+    }
+    Ok(found)
+}
+
+/// The installation in `dir`, if it holds an app
+pub fn inspect(dir: &Path, source: InstallSource) -> Option<Installation> {
+    let app = APP_PATHS
+        .iter()
+        .map(|path| dir.join(path))
+        .find(|path| path.is_file() || path.join("package.json").is_file())?;
+    let package = read_package_json(&app).ok();
+    let field = |name: &str| {
+        package
+            .as_ref()
+            .and_then(|p| p[name].as_str())
+            .map(str::to_string)
+    };
+    let state = match field("name") {
+        None => InstallState::Unknown,
+        Some(name) if name == migrate::APP_DATA_NAME => InstallState::Patched,
+        Some(name) if migrate::LEGACY_DATA_NAMES.contains(&name.as_str()) => {
+            InstallState::OriginalMod
+        }
+        Some(_) => InstallState::Stock,
+    };
+    Some(Installation {
+        path: dir.to_path_buf(),
+        version: field("version"),
+        app,
+        state,
+        source,
+        patched_build: None,
+    })
+}
+
+/// Result of `apply`
+#[derive(Debug, Clone, Serialize)]
+pub struct ApplyReport {
+    /// `app.asar` of the installation
+    pub app: PathBuf,
+    pub version: Option<String>,
+    /// Build the patched `app.asar` was taken from; `None` after `--restore`
+    pub build: Option<PathBuf>,
+    /// The stock `app.asar` kept next to the patched one
+    pub original: PathBuf,
+}
+
+/// `path` with `suffix` appended to its file name
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// Replace the `app.asar` of `install` with the one packed into its patched
+/// build. Write access is checked before anything is changed, failing with
+/// [`crate::error::PatcherError::AccessDenied`] without administrator rights.
+pub fn apply(install: &Installation) -> Result<ApplyReport> {
+    anyhow::ensure!(
+        install.source != InstallSource::Store,
+        tr!(
+            "Yandex Music from the Microsoft Store cannot be changed; patch a copy with `sideload`",
+            "Яндекс Музыку из Microsoft Store нельзя изменить; пропатчите копию командой `sideload`"
+        )
+    );
+    anyhow::ensure!(
+        install.app.is_file(),
+        "Only installations with an app.asar can be patched in place, not {:?}",
+        install.app
+    );
+    let build = install.patched_build.as_ref().with_context(|| {
+        tr!(
+            "No patched build of Yandex Music {}; run `patch --pack-asar` first",
+            "Нет пропатченной сборки Яндекс Музыки {}; сначала запустите `patch --pack-asar`",
+            install.version.as_deref().unwrap_or("?")
+        )
+    })?;
+    let packed = build.join(asar::ASAR_FILE);
+    anyhow::ensure!(
+        packed.is_file(),
+        tr!(
+            "{:?} has no app.asar; patch it again with `patch --pack-asar`",
+            "В {:?} нет app.asar; пропатчите заново командой `patch --pack-asar`",
+            build
+        )
+    );
+
+    let resources = install
+        .app
+        .parent()
+        .context("app.asar has no parent folder")?;
+    elevate::check_access(resources)?;
+
+    // Copied beside the archive first, so a failed copy leaves the app as it was
+    let staged = with_suffix(&install.app, ".new");
+    paths::copy_file(&packed, &staged)?;
+
+    let original = with_suffix(&install.app, ORIGINAL_SUFFIX);
+    let unpacked = with_suffix(&install.app, ".unpacked");
+    let original_unpacked = with_suffix(&original, ".unpacked");
+    if original.exists() && install.state != InstallState::Stock {
+        // Patched before: the stock archive is already kept
+        paths::remove_file(&install.app)?;
+        if unpacked.exists() {
+            paths::remove_dir_all(&unpacked)?;
+        }
+    } else {
+        // Yandex replaced the patched archive with a newer stock one since the
+        // last `apply`: keep that instead of the old version's
+        if original.exists() {
+            paths::remove_file(&original)?;
+        }
+        if original_unpacked.exists() {
+            paths::remove_dir_all(&original_unpacked)?;
+        }
+        paths::rename(&install.app, &original)?;
+        if unpacked.exists() {
+            paths::rename(&unpacked, &original_unpacked)?;
+        }
+    }
+
+    paths::rename(&staged, &install.app)?;
+    let packed_unpacked = with_suffix(&packed, ".unpacked");
+    if packed_unpacked.is_dir() {
+        patcher::copy_dir_all(&packed_unpacked, &unpacked)?;
+    }
+
+    Ok(ApplyReport {
+        app: install.app.clone(),
+        version: install.version.clone(),
+        build: Some(build.clone()),
+        original,
+    })
+}
+
+/// Put the stock `app.asar` kept by [`apply`] back
+pub fn restore(install: &Installation) -> Result<ApplyReport> {
+    let original = with_suffix(&install.app, ORIGINAL_SUFFIX);
+    anyhow::ensure!(
+        original.is_file(),
+        tr!(
+            "No stock app.asar kept at {:?}; nothing to restore",
+            "Исходный app.asar не найден в {:?}; восстанавливать нечего",
+            original
+        )
+    );
+    let resources = install
+        .app
+        .parent()
+        .context("app.asar has no parent folder")?;
+    elevate::check_access(resources)?;
+
+    let unpacked = with_suffix(&install.app, ".unpacked");
+    let original_unpacked = with_suffix(&original, ".unpacked");
+    if unpacked.exists() {
+        paths::remove_dir_all(&unpacked)?;
+    }
+    paths::rename(&original, &install.app)?;
+    if original_unpacked.exists() {
+        paths::rename(&original_unpacked, &unpacked)?;
+    }
+
+    Ok(ApplyReport {
+        app: install.app.clone(),
+        version: install.version.clone(),
+        build: None,
+        original,
+    })
+}
+
+/// `package.json` of an app folder, or of an `app.asar` read from its header
+/// and the one entry only
+pub fn read_package_json(app: &Path) -> Result<serde_json::Value> {
+    let content = if app.is_dir() {
+        fs::read(app.join("package.json"))?
+    } else {
+        let mut file = File::open(app).with_context(|| format!("Failed to open {:?}", app))?;
+        let mut prefix = [0u8; 16];
+        file.read_exact(&mut prefix)
+            .context("Truncated ASAR header")?;
+        let header_size = u32::from_le_bytes(prefix[4..8].try_into().unwrap()) as usize;
+        if header_size < 8 || header_size as u64 > file.metadata()?.len() {
+            anyhow::bail!("{:?} is not an ASAR archive", app);
+        }
+        let mut header = prefix.to_vec();
+        header.resize(header_size + 8, 0);
+        file.read_exact(&mut header[16..])
+            .context("Truncated ASAR header")?;
+        let (header, data_offset) = patcher::asar_header(&header)?;
+
+        let entry = &header["files"]["package.json"];
+        if entry["unpacked"].as_bool() == Some(true) {
+            let mut unpacked = app.as_os_str().to_owned();
+            unpacked.push(".unpacked");
+            fs::read(Path::new(&unpacked).join("package.json"))?
+        } else {
+            let size = entry["size"]
+                .as_u64()
+                .with_context(|| format!("{:?} has no package.json", app))?;
+            let offset: u64 = entry["offset"]
+                .as_str()
+                .and_then(|offset| offset.parse().ok())
+                .context("Invalid ASAR entry offset")?;
+            file.seek(SeekFrom::Start(data_offset as u64 + offset))?;
+            let mut content = vec![0u8; size as usize];
+            file.read_exact(&mut content)?;
+            content
+        }
+    };
+    serde_json::from_slice(&content).with_context(|| format!("Invalid package.json in {:?}", app))
+}
+
+/// Install folders from the uninstall entries of the registry
+#[cfg(windows)]
+fn registry_dirs() -> Vec<PathBuf> {
+    const UNINSTALL_KEYS: &[&str] = &[
+        r"HKCU\Software\Microsoft\Windows\CurrentVersion\Uninstall",
+        r"HKLM\Software\Microsoft\Windows\CurrentVersion\Uninstall",
+        r"HKLM\Software\WOW6432Node\Microsoft\Windows\CurrentVersion\Uninstall",
+    ];
+    let mut dirs = Vec::new();
+    for key in UNINSTALL_KEYS {
+        let Ok(output) = std::process::Command::new("reg")
+            .args(["query", key, "/s"])
+            .output()
+        else {
+            continue;
+        };
+        dirs.extend(parse_uninstall_entries(&String::from_utf8_lossy(
+            &output.stdout,
+        )));
+    }
+    dirs
+}
+
+#[cfg(not(windows))]
+fn registry_dirs() -> Vec<PathBuf> {
+    Vec::new()
+}
+
+/// Install folders of the Yandex Music entries in `reg query /s` output:
+/// `InstallLocation`, or the folder of `DisplayIcon`
+#[cfg_attr(not(windows), allow(dead_code))]
+fn parse_uninstall_entries(output: &str) -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    for entry in output.split("HKEY_").skip(1) {
+        let value = |name: &str| {
+            entry.lines().find_map(|line| {
+                let mut parts = line.trim().splitn(3, "    ");
+                (parts.next() == Some(name))
+                    .then(|| parts.nth(1).map(|v| v.trim().to_string()))
+                    .flatten()
+            })
+        };
+        let is_yandex_music = value("DisplayName").is_some_and(|name| {
+            let name = name.to_lowercase();
+            name.contains("yandex music") || name.contains("яндекс музыка")
+        });
+        if !is_yandex_music {
+            continue;
+        }
+        let dir = value("InstallLocation")
+            .filter(|location| !location.is_empty())
+            .map(|location| PathBuf::from(location.trim_matches('"')))
+            .or_else(|| {
+                let icon = value("DisplayIcon")?;
+                let exe = icon.split(',').next()?.trim_matches('"');
+                exe.rsplit_once(['\\', '/'])
+                    .map(|(dir, _)| PathBuf::from(dir))
+            });
+        if let Some(dir) = dir {
+            if !dirs.contains(&dir) {
+                dirs.push(dir);
+            }
+        }
+    }
+    dirs
+}
+
+/// Print the installations found
+pub fn print_report(installs: &[Installation]) {
+    if installs.is_empty() {
+        println!(
+            "{}",
+            tr!(
+                "No Yandex Music installation found; pass --path <DIR>",
+                "Установленная Яндекс Музыка не найдена; укажите --path <DIR>"
+            )
+        );
+        return;
+    }
+    for install in installs {
+        let state = match install.state {
+            InstallState::Stock => tr!("not patched", "не пропатчена"),
+            InstallState::Patched => tr!("patched", "пропатчена"),
+            InstallState::OriginalMod => {
+                tr!(
+                    "patched by the original mod",
+                    "пропатчена оригинальным модом"
+                )
+            }
+            InstallState::Unknown => tr!("unknown", "неизвестно"),
+        };
+        println!(
+            "{} {} — {}",
+            tr!("Yandex Music", "Яндекс Музыка"),
+            install.version.as_deref().unwrap_or("?"),
+            state
+        );
+        println!("  {:?}", install.app);
+        if install.source == InstallSource::Store {
+            println!(
+                "{}",
+                tr!(
+                    "  Installed from the Microsoft Store and read-only; patch a copy with `sideload`",
+                    "  Установлена из Microsoft Store и доступна только для чтения; пропатчите копию командой `sideload`"
+                )
+            );
+        } else if install.state != InstallState::Patched {
+            match &install.patched_build {
+                Some(build) => println!(
+                    "{}",
+                    tr!(
+                        "  Patched build of this version: {:?}",
+                        "  Пропатченная сборка этой версии: {:?}",
+                        build
+                    )
+                ),
+                None => println!(
+                    "{}",
+                    tr!(
+                        "  Patch it with `patch --pack-asar`, then run `apply`",
+                        "  Пропатчите её командой `patch --pack-asar`, затем запустите `apply`"
+                    )
+                ),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asar::{self, AsarOptions};
+
+    #[test]
+    fn test_detect_installation() {
+        let dir = tempfile::tempdir().unwrap();
+        let app = dir.path().join("app");
+        fs::create_dir_all(app.join("main")).unwrap();
+        fs::write(app.join("main/index.js"), "console.log(1)").unwrap();
+        fs::write(
+            app.join("package.json"),
+            r#"{"name": "YandexMusicMod", "version": "5.40.0"}"#,
+        )
+        .unwrap();
+        let install_dir = dir.path().join("YandexMusic");
+        fs::create_dir_all(install_dir.join("resources")).unwrap();
+        asar::pack(
+            &app,
+            &install_dir.join("resources/app.asar"),
+            &AsarOptions::default(),
+        )
+        .unwrap();
+
+        let install = inspect(&install_dir, InstallSource::Argument).unwrap();
+        assert_eq!(install.version.as_deref(), Some("5.40.0"));
+        assert_eq!(install.state, InstallState::Patched);
+        assert!(inspect(dir.path(), InstallSource::KnownPath).is_none());
+
+        let installs = detect(
+            &dir.path().join("versions"),
+            std::slice::from_ref(&install_dir),
+        )
+        .unwrap();
+        assert_eq!(installs.len(), 1);
+        assert!(detect(dir.path(), &[dir.path().join("none")]).is_err());
+
+        let mut stock = install.clone();
+        assert!(apply(&stock).is_err());
+        let build = dir.path().join("versions").join("5.40.0");
+        fs::create_dir_all(&build).unwrap();
+        fs::write(build.join(asar::ASAR_FILE), "patched").unwrap();
+        stock.patched_build = Some(build);
+        let report = apply(&stock).unwrap();
+        assert_eq!(fs::read_to_string(&stock.app).unwrap(), "patched");
+        assert!(report.original.is_file());
+        apply(&stock).unwrap();
+        restore(&stock).unwrap();
+        assert_eq!(
+            inspect(&install_dir, InstallSource::Argument)
+                .unwrap()
+                .version
+                .as_deref(),
+            Some("5.40.0")
+        );
+        assert!(!report.original.exists());
+        assert!(restore(&stock).is_err());
+
+        // An app update put a new stock archive over the patched one
+        apply(&stock).unwrap();
+        fs::write(&stock.app, "stock update").unwrap();
+        stock.state = InstallState::Stock;
+        apply(&stock).unwrap();
+        assert_eq!(
+            fs::read_to_string(&report.original).unwrap(),
+            "stock update"
+        );
+        assert_eq!(fs::read_to_string(&stock.app).unwrap(), "patched");
+
+        let reg = "\r\nHKEY_CURRENT_USER\\Software\\Microsoft\\Windows\\CurrentVersion\\Uninstall\\{abc}\r\n    DisplayName    REG_SZ    Yandex Music\r\n    DisplayIcon    REG_SZ    C:\\Users\\me\\AppData\\Local\\Programs\\YandexMusic\\Yandex Music.exe,0\r\n\r\nHKEY_CURRENT_USER\\Software\\Microsoft\\Windows\\CurrentVersion\\Uninstall\\Other\r\n    DisplayName    REG_SZ    Other\r\n    InstallLocation    REG_SZ    C:\\Other\r\n";
+        assert_eq!(
+            parse_uninstall_entries(reg),
+            [PathBuf::from(
+                "C:\\Users\\me\\AppData\\Local\\Programs\\YandexMusic"
+            )]
+        );
+    }
+}
@@ -0,0 +1,102 @@
+//! Interrupt module - Ctrl-C handling with cleanup of incomplete outputs
+//!
+//! Runs register what they are writing with a [`Partial`] guard. When the
+//! patcher is interrupted, [`handle_ctrl_c`] removes every registered output
+//! that has not been finished, so the next run (or `versions`) never sees a
+//! half-written build, and exits with [`INTERRUPTED_CODE`].
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing::{info, warn};
+
+/// Exit code of an interrupted run (128 + SIGINT, as shells report it)
+pub const INTERRUPTED_CODE: u8 = 130;
+
+/// Outputs of runs in progress
+static PARTIAL: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// A file or directory that is incomplete while the guard is alive
+#[derive(Debug)]
+pub struct Partial {
+    path: PathBuf,
+}
+
+impl Partial {
+    pub fn new(path: &Path) -> Self {
+        PARTIAL.lock().unwrap().push(path.to_path_buf());
+        Self {
+            path: path.to_path_buf(),
+        }
+    }
+}
+
+impl Drop for Partial {
+    // Finished or failed normally: failed runs keep their output for inspection
+    fn drop(&mut self) {
+        let mut partial = PARTIAL.lock().unwrap();
+        if let Some(pos) = partial.iter().position(|p| *p == self.path) {
+            partial.remove(pos);
+        }
+    }
+}
+
+/// Wait for Ctrl-C, remove incomplete outputs unless `keep_temp` and exit
+pub async fn handle_ctrl_c(keep_temp: bool) {
+    if tokio::signal::ctrl_c().await.is_err() {
+        return;
+    }
+    eprintln!();
+    warn!("Interrupted");
+    if !keep_temp {
+        remove_partial();
+    }
+    std::process::exit(INTERRUPTED_CODE.into());
+}
+
+/// Remove all registered outputs; returns the ones that existed
+fn remove_partial() -> Vec<PathBuf> {
+    let partial = std::mem::take(&mut *PARTIAL.lock().unwrap());
+    partial
+        .into_iter()
+        .filter(|path| {
+            let result = if path.is_dir() {
+                fs::remove_dir_all(path)
+            } else if path.exists() {
+                fs::remove_file(path)
+            } else {
+                return false;
+            };
+            match result {
+                Ok(()) => info!("Removed incomplete {:?}", path),
+                Err(e) => warn!("Failed to remove incomplete {:?}: {}", path, e),
+            }
+            true
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_remove_partial() {
+        let dir = tempfile::tempdir().unwrap();
+        let build_dir = dir.path().join("5.0.0");
+        let installer = dir.path().join("5.0.0.exe");
+        let finished = dir.path().join("4.0.0");
+        fs::create_dir_all(build_dir.join("temp")).unwrap();
+        fs::write(&installer, b"MZ").unwrap();
+        fs::create_dir_all(&finished).unwrap();
+
+        let _build = Partial::new(&build_dir);
+        let _installer = Partial::new(&installer);
+        drop(Partial::new(&finished));
+
+        let removed = remove_partial();
+        assert_eq!(removed, [build_dir.clone(), installer.clone()]);
+        assert!(!build_dir.exists() && !installer.exists());
+        assert!(finished.exists());
+    }
+}
@@ -0,0 +1,255 @@
+//! Journal module - crash-safe record of a run's progress in the build directory
+//!
+//! `process_build` keeps `journal.json` in the build directory while it runs:
+//! the stages started and finished, the staging directory in the OS temp
+//! directory and the SHA-512 of the verified installer. Each update replaces
+//! the file atomically and a successful run deletes it, so a journal found by
+//! the next run means the previous one crashed or was killed. That run then
+//! reuses the verified installer instead of downloading it again and removes
+//! the abandoned staging directory.
+
+use crate::paths;
+use crate::progress::Stage;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{debug, info, warn};
+
+/// Name of the journal in the build directory
+pub const JOURNAL_FILE: &str = "journal.json";
+
+/// Name of the downloaded installer in the staging directory
+pub const INSTALLER_FILE: &str = "build.exe";
+
+/// Prefix of staging directories in the OS temp directory
+pub const STAGING_PREFIX: &str = "yandex-music-mod-";
+
+/// Staging directories older than this are left over from dead runs
+pub const STALE_STAGING_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// A stage of the run and whether it finished
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StageRecord {
+    pub stage: Stage,
+    pub finished: bool,
+}
+
+/// Progress of a run, rewritten after every change
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Journal {
+    #[serde(skip)]
+    path: PathBuf,
+    pub pid: u32,
+    pub version: String,
+    /// Unix time the run started
+    pub started_at: u64,
+    pub staging: Option<PathBuf>,
+    /// SHA-512 of the installer, once downloaded and verified
+    pub installer_hash: Option<String>,
+    pub stages: Vec<StageRecord>,
+}
+
+impl Journal {
+    /// Start the journal of a run on `version` in `build_dir`
+    pub fn start(build_dir: &Path, version: &str, staging: &Path) -> Result<Self> {
+        let journal = Self {
+            path: build_dir.join(JOURNAL_FILE),
+            pid: std::process::id(),
+            version: version.to_string(),
+            started_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+            staging: Some(staging.to_path_buf()),
+            installer_hash: None,
+            stages: Vec::new(),
+        };
+        journal.save()?;
+        Ok(journal)
+    }
+
+    /// Journal left in `build_dir` by an earlier run, if it did not finish
+    pub fn load(build_dir: &Path) -> Result<Option<Self>> {
+        let path = build_dir.join(JOURNAL_FILE);
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {:?}", path)),
+        };
+        let mut journal: Self = serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse {:?}", path))?;
+        journal.path = path;
+        Ok(Some(journal))
+    }
+
+    /// Record that `stage` started; the stage before it has finished
+    pub fn begin(&mut self, stage: Stage) -> Result<()> {
+        if let Some(last) = self.stages.last_mut() {
+            last.finished = true;
+        }
+        self.stages.push(StageRecord {
+            stage,
+            finished: false,
+        });
+        self.save()
+    }
+
+    /// Record the SHA-512 of the verified installer
+    pub fn set_installer_hash(&mut self, hash: &str) -> Result<()> {
+        self.installer_hash = Some(hash.to_string());
+        self.save()
+    }
+
+    /// Stage the run was in when it stopped
+    pub fn last_stage(&self) -> Option<Stage> {
+        self.stages
+            .iter()
+            .rev()
+            .find(|s| !s.finished)
+            .map(|s| s.stage)
+    }
+
+    /// The run finished: delete the journal
+    pub fn finish(self) -> Result<()> {
+        fs::remove_file(&self.path).with_context(|| format!("Failed to remove {:?}", self.path))
+    }
+
+    /// Move the verified installer of an interrupted run to `dst` if it is the
+    /// build with SHA-512 `hash`. Returns whether it was salvaged.
+    pub fn salvage_installer(&self, hash: &str, dst: &Path) -> bool {
+        let (Some(staging), Some(recorded)) = (&self.staging, &self.installer_hash) else {
+            return false;
+        };
+        if recorded != hash {
+            return false;
+        }
+        let installer = staging.join(INSTALLER_FILE);
+        if !installer.is_file() {
+            return false;
+        }
+        // The staging directory is in the same temp directory, so this is a rename
+        match paths::rename(&installer, dst) {
+            Ok(()) => {
+                info!("Reusing the installer downloaded by the interrupted run");
+                true
+            }
+            Err(e) => {
+                warn!("{:#}", e);
+                false
+            }
+        }
+    }
+
+    /// Remove the staging directory of the interrupted run
+    pub fn remove_staging(&self) {
+        let Some(staging) = &self.staging else {
+            return;
+        };
+        if staging.exists() {
+            match paths::remove_dir_all(staging) {
+                Ok(()) => info!("Removed stale staging directory {:?}", staging),
+                Err(e) => warn!("{:#}", e),
+            }
+        }
+    }
+
+    fn save(&self) -> Result<()> {
+        // Written next to the journal and renamed over it, so a crash never
+        // leaves a truncated journal behind
+        let mut temp = self.path.clone().into_os_string();
+        temp.push(".tmp");
+        let temp = PathBuf::from(temp);
+        fs::write(&temp, serde_json::to_string_pretty(self)? + "\n")
+            .with_context(|| format!("Failed to write {:?}", temp))?;
+        fs::rename(&temp, &self.path)
+            .with_context(|| format!("Failed to write {:?}", self.path))?;
+        debug!("Journal: {:?}", self.stages.last());
+        Ok(())
+    }
+}
+
+/// Remove staging directories in `temp_dir` older than `max_age`: those of
+/// runs that were killed before their journal could be read again
+pub fn clean_stale_staging(temp_dir: &Path, max_age: Duration) {
+    let Ok(entries) = fs::read_dir(temp_dir) else {
+        return;
+    };
+    let now = SystemTime::now();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let is_staging = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.starts_with(STAGING_PREFIX));
+        if !is_staging || !path.is_dir() {
+            continue;
+        }
+        let age = entry
+            .metadata()
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|modified| now.duration_since(modified).ok());
+        if age.is_some_and(|age| age >= max_age) {
+            match paths::remove_dir_all(&path) {
+                Ok(()) => info!("Removed stale staging directory {:?}", path),
+                Err(e) => warn!("{:#}", e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_journal_recovery() {
+        let dir = tempfile::tempdir().unwrap();
+        let build_dir = dir.path().join("5.0.0");
+        let staging = dir.path().join(format!("{}5.0.0-abc", STAGING_PREFIX));
+        fs::create_dir_all(&build_dir).unwrap();
+        fs::create_dir_all(&staging).unwrap();
+        fs::write(staging.join(INSTALLER_FILE), b"MZ").unwrap();
+
+        assert!(Journal::load(&build_dir).unwrap().is_none());
+        let mut journal = Journal::start(&build_dir, "5.0.0", &staging).unwrap();
+        journal.begin(Stage::Download).unwrap();
+        journal.set_installer_hash("c2hhNTEy").unwrap();
+        journal.begin(Stage::ExtractInstaller).unwrap();
+        drop(journal);
+
+        // The next run finds the interrupted one
+        let previous = Journal::load(&build_dir).unwrap().unwrap();
+        assert_eq!(previous.last_stage(), Some(Stage::ExtractInstaller));
+        assert_eq!(previous.stages[0].stage, Stage::Download);
+        assert!(previous.stages[0].finished);
+
+        let dst = dir.path().join("new.exe");
+        assert!(!previous.salvage_installer("other", &dst));
+        assert!(previous.salvage_installer("c2hhNTEy", &dst));
+        assert_eq!(fs::read(&dst).unwrap(), b"MZ");
+        previous.remove_staging();
+        assert!(!staging.exists());
+
+        let journal = Journal::start(&build_dir, "5.0.0", &staging).unwrap();
+        journal.finish().unwrap();
+        assert!(Journal::load(&build_dir).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_clean_stale_staging() {
+        let dir = tempfile::tempdir().unwrap();
+        let stale = dir.path().join(format!("{}5.0.0-abc", STAGING_PREFIX));
+        let other = dir.path().join("other-app-abc");
+        fs::create_dir_all(&stale).unwrap();
+        fs::create_dir_all(&other).unwrap();
+
+        clean_stale_staging(dir.path(), STALE_STAGING_AGE);
+        assert!(stale.exists());
+
+        clean_stale_staging(dir.path(), Duration::ZERO);
+        assert!(!stale.exists());
+        assert!(other.exists());
+    }
+}
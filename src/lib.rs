@@ -0,0 +1,61 @@
+//! YandexMusicMod - Rust implementation
+//!
+//! The patch engine behind the `yandex-music-mod` CLI. Besides the Rust API it is
+//! built as a C library (see [`ffi`]) for native launchers and other frontends.
+
+pub mod api;
+pub mod asar;
+pub mod backup;
+pub mod bench;
+pub mod cache;
+pub mod cassette;
+pub mod compare;
+pub mod compat;
+pub mod config;
+pub mod delta;
+pub mod deps;
+pub mod dev;
+pub mod doctor;
+pub mod electron_builder;
+pub mod elevate;
+pub mod error;
+pub mod ffi;
+pub mod flatpak;
+pub mod generic;
+#[cfg(feature = "gui")]
+pub mod gui;
+pub mod i18n;
+pub mod install;
+pub mod interrupt;
+pub mod journal;
+pub mod lock;
+pub mod manifest;
+pub mod migrate;
+pub mod msix;
+pub mod notify;
+pub mod package;
+pub mod patcher;
+pub mod patches;
+pub mod paths;
+pub mod plan;
+pub mod progress;
+pub mod prompt;
+pub mod remote_rules;
+pub mod rules;
+pub mod schedule;
+pub mod self_update;
+pub mod selftest;
+#[cfg(feature = "serve")]
+pub mod serve;
+#[cfg(feature = "native-extract")]
+pub mod sevenz;
+pub mod sign;
+pub mod store;
+#[cfg(feature = "toast")]
+pub mod toast;
+#[cfg(feature = "tray")]
+pub mod tray;
+pub mod versions;
+pub mod watch;
+#[cfg(feature = "wrapper")]
+pub mod wrapper;
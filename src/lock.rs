@@ -0,0 +1,108 @@
+//! Lock module - keeps concurrent runs out of the same build directory
+//!
+//! `process_build` starts by deleting the build directory, so a second run on
+//! the same version (e.g. `watch` plus a manual `patch`) would destroy the
+//! first one's files. Each run holds an exclusive lock on `.<build dir>.lock`
+//! next to the build directory; the lock file itself outlives the directory
+//! and is never deleted, so no run can lock a stale copy of it. The holder's
+//! pid goes to `.<build dir>.lock.pid`, as Windows locks the whole lock file
+//! against reads through other handles.
+
+use crate::error::PatcherError;
+use anyhow::{Context, Result};
+use fs2::FileExt;
+use std::fs::{self, File, OpenOptions};
+use std::path::{Path, PathBuf};
+
+/// Exclusive lock of a build directory, released on drop
+#[derive(Debug)]
+pub struct BuildLock {
+    file: File,
+    path: PathBuf,
+}
+
+impl BuildLock {
+    /// Lock `build_dir`, failing right away if another process holds the lock
+    pub fn acquire(build_dir: &Path) -> Result<Self> {
+        let path = lock_path(build_dir);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).with_context(|| format!("Failed to create {:?}", parent))?;
+        }
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .with_context(|| format!("Failed to open lock file {:?}", path))?;
+
+        if file.try_lock_exclusive().is_err() {
+            let owner = fs::read_to_string(pid_path(&path)).unwrap_or_default();
+            let owner = match owner.trim() {
+                "" => String::new(),
+                pid => format!(" (pid {})", pid),
+            };
+            return Err(PatcherError::BuildLocked(format!(
+                "{:?} is in use by another run{}",
+                build_dir, owner
+            ))
+            .into());
+        }
+
+        // Record the owner for the error message of competing runs
+        fs::write(pid_path(&path), std::process::id().to_string())?;
+        Ok(Self { file, path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for BuildLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(pid_path(&self.path));
+        let _ = FileExt::unlock(&self.file);
+    }
+}
+
+/// `.<name>.lock` next to `build_dir`
+fn lock_path(build_dir: &Path) -> PathBuf {
+    let name = build_dir.file_name().unwrap_or_default();
+    let mut lock_name = std::ffi::OsString::from(".");
+    lock_name.push(name);
+    lock_name.push(".lock");
+    build_dir.with_file_name(lock_name)
+}
+
+/// `<lock file>.pid` with the pid of the lock holder
+fn pid_path(lock_path: &Path) -> PathBuf {
+    let mut name = lock_path.as_os_str().to_owned();
+    name.push(".pid");
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_lock() {
+        let dir = tempfile::tempdir().unwrap();
+        let build_dir = dir.path().join("5.0.0");
+
+        let lock = BuildLock::acquire(&build_dir).unwrap();
+        assert_eq!(lock.path(), dir.path().join(".5.0.0.lock"));
+
+        let error = BuildLock::acquire(&build_dir).unwrap_err();
+        assert_eq!(crate::error::code(&error), 42);
+        assert!(error.to_string().contains(&std::process::id().to_string()));
+
+        // Other versions are independent
+        BuildLock::acquire(&dir.path().join("5.0.0-dry-run")).unwrap();
+
+        drop(lock);
+        assert!(!dir.path().join(".5.0.0.lock.pid").exists());
+        BuildLock::acquire(&build_dir).unwrap();
+    }
+}
@@ -2,24 +2,54 @@
 //!
 //! A fast patcher for Yandex Music desktop application that enables premium features.
 //! This is a Rust rewrite of the original TypeScript YandexMusicBetaMod project.
+//! The command-line frontend; the patch engine lives in the library crate.
 
-mod api;
-mod error;
-mod patcher;
-mod patches;
+#![cfg_attr(
+    all(target_os = "windows", feature = "windows-gui"),
+    windows_subsystem = "windows"
+)]
 
-use anyhow::Result;
+#[cfg(all(target_os = "windows", feature = "windows-gui"))]
+mod winconsole;
+
+use anyhow::{Context, Result};
 use clap::{Parser, Subcommand};
 use indicatif::{ProgressBar, ProgressStyle};
+use patches::{Compatibility, ModFeature};
+use serde::Serialize;
 use std::io::{self, Write};
-use tracing::{info, Level};
-use tracing_subscriber::FmtSubscriber;
+use std::sync::Mutex;
+use tracing::{info, warn, Level};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::fmt::{self, writer::BoxMakeWriter};
+use tracing_subscriber::prelude::*;
+#[cfg(feature = "gui")]
+use yandex_music_mod::gui;
+use yandex_music_mod::i18n::{self, tr};
+use yandex_music_mod::progress::{format_bytes, Event, ProgressSink, ProgressState};
+#[cfg(feature = "serve")]
+use yandex_music_mod::serve;
+#[cfg(feature = "toast")]
+use yandex_music_mod::toast;
+#[cfg(feature = "tray")]
+use yandex_music_mod::tray;
+#[cfg(feature = "wrapper")]
+use yandex_music_mod::wrapper;
+use yandex_music_mod::{
+    api, asar, backup, bench, cassette, compare, compat, config, delta, dev, doctor, elevate,
+    error, generic, install, interrupt, manifest, migrate, notify, package, patcher, patches,
+    paths, plan, prompt, remote_rules, rules, schedule, self_update, selftest, store, versions,
+    watch,
+};
 
 /// Wait for user to press Enter before exiting.
 /// This is useful when the program is run by double-clicking the exe on Windows,
 /// so users can see the output before the console window closes.
 fn wait_for_enter() {
-    print!("\nPress Enter to exit...");
+    print!(
+        "{}",
+        tr!("\nPress Enter to exit...", "\nНажмите Enter для выхода...")
+    );
     let _ = io::stdout().flush();
     let _ = io::stdin().read_line(&mut String::new());
 }
@@ -27,8 +57,14 @@ fn wait_for_enter() {
 /// Check if we're running in an interactive console that will stay open,
 /// or if we were likely launched by double-clicking the exe.
 fn should_wait_before_exit() -> bool {
+    // The GUI-subsystem build only has a console when started from a terminal
+    #[cfg(all(target_os = "windows", feature = "windows-gui"))]
+    {
+        !winconsole::has_console()
+    }
+
     // On Windows, check if we have a console attached that we own
-    #[cfg(target_os = "windows")]
+    #[cfg(all(target_os = "windows", not(feature = "windows-gui")))]
     {
         // If there's no TERM, SHELL, or other terminal indicators,
         // and we're on Windows, we're likely double-clicked
@@ -56,145 +92,2199 @@ struct Cli {
     /// Enable verbose logging
     #[arg(short, long, global = true)]
     verbose: bool,
+
+    /// Console log level (overrides --verbose)
+    #[arg(long, global = true, value_enum, conflicts_with = "verbose")]
+    log_level: Option<LogLevel>,
+
+    /// Also write the full log (at least debug level) to this file
+    #[arg(long, global = true, value_name = "PATH")]
+    log_file: Option<std::path::PathBuf>,
+
+    /// Configuration file (default: config.toml in the config directory)
+    #[arg(long, global = true, value_name = "PATH")]
+    config: Option<std::path::PathBuf>,
+
+    /// Update channel to fetch builds from (default: stable)
+    #[arg(long, global = true)]
+    channel: Option<String>,
+
+    /// Installer architecture to take (default: this machine's)
+    #[arg(long, global = true, value_enum)]
+    arch: Option<api::Arch>,
+
+    /// Download mirror tried when the update server fails (repeatable)
+    #[arg(long = "mirror", global = true, value_name = "URL")]
+    mirrors: Vec<String>,
+
+    /// Print results as JSON on stdout; logs go to stderr
+    #[arg(long, global = true)]
+    json: bool,
+
+    /// Save every HTTP response to this directory for a later --replay
+    #[arg(long, global = true, value_name = "DIR")]
+    record: Option<std::path::PathBuf>,
+
+    /// Serve HTTP responses recorded with --record instead of using the network
+    #[arg(long, global = true, value_name = "DIR", conflicts_with = "record")]
+    replay: Option<std::path::PathBuf>,
+
+    /// Never prompt or wait for input; use defaults (implied by the CI variable)
+    #[arg(
+        short = 'y',
+        long = "yes",
+        visible_alias = "non-interactive",
+        global = true
+    )]
+    non_interactive: bool,
+
+    /// Language of the output (default: `language` from config.toml, then the OS language)
+    #[arg(long, global = true, value_enum)]
+    lang: Option<i18n::Lang>,
+
+    /// Disable colored output (also disabled by a non-empty NO_COLOR variable)
+    #[arg(long, global = true)]
+    no_color: bool,
+
+    /// Draw the progress bar with ASCII characters only, for consoles that mangle Unicode
+    #[arg(long, global = true)]
+    ascii: bool,
+
+    /// Keep incomplete builds and downloads when interrupted with Ctrl-C
+    #[arg(long, global = true)]
+    keep_temp: bool,
+
+    /// Keep the config, cache and builds next to the executable (implied by a portable.flag there)
+    #[arg(long, global = true)]
+    portable: bool,
+
+    /// Set on the run started through the UAC prompt
+    #[arg(long, global = true, hide = true)]
+    elevated: bool,
+
+    /// Working directory of the run started through the UAC prompt
+    #[arg(long, global = true, hide = true, value_name = "DIR")]
+    elevated_dir: Option<std::path::PathBuf>,
 }
 
+impl Cli {
+    /// Whether colored output is allowed; see <https://no-color.org>
+    fn color(&self) -> bool {
+        !self.no_color && std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+    }
+}
+
+/// Value of `--log-level`
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum LogLevel {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl From<LogLevel> for Level {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Trace => Level::TRACE,
+            LogLevel::Debug => Level::DEBUG,
+            LogLevel::Info => Level::INFO,
+            LogLevel::Warn => Level::WARN,
+            LogLevel::Error => Level::ERROR,
+        }
+    }
+}
+
+// Parsed once per process, so the size of `Patch` doesn't matter
+#[allow(clippy::large_enum_variant)]
 #[derive(Subcommand)]
 enum Commands {
-    /// Patch the latest stable Yandex Music build
+    /// Patch the latest Yandex Music build
     Patch {
-        /// Output directory for the patched build
-        #[arg(short, long, default_value = ".versions")]
-        output: String,
+        /// Output directory for the patched build; `~` and `$VAR`/`%VAR%` are expanded [default: versions in the data directory]
+        #[arg(short, long)]
+        output: Option<std::path::PathBuf>,
 
         /// Enable auto-open devtools on startup
         #[arg(long)]
         auto_devtools: bool,
+
+        /// Minimum window width in pixels [default: 360]
+        #[arg(long)]
+        min_width: Option<u32>,
+
+        /// Minimum window height in pixels [default: 550]
+        #[arg(long)]
+        min_height: Option<u32>,
+
+        /// Keep the stock minimum window size instead of --min-width/--min-height
+        #[arg(long, conflicts_with_all = ["min_width", "min_height"])]
+        stock_min_size: bool,
+
+        /// Patch profile to use instead of selecting one by app version
+        #[arg(long)]
+        profile: Option<String>,
+
+        /// Fail instead of warning when the build is newer than any known compatible version
+        #[arg(long)]
+        strict: bool,
+
+        /// Only report which patch anchors and rules match; don't write a patched build
+        #[arg(long)]
+        dry_run: bool,
+
+        /// URL of an additional promo selector list (one CSS selector per line)
+        #[arg(long)]
+        promo_selectors_url: Option<String>,
+
+        /// Fetch the latest rule sets from the project repository (hash-verified, falls back to the built-in ones)
+        #[arg(long)]
+        remote_rules: bool,
+
+        /// Fetch the rule sets from this base URL instead (implies --remote-rules)
+        #[arg(long, value_name = "URL")]
+        rules_url: Option<String>,
+
+        /// Optional mod feature to inject (can be repeated)
+        #[arg(long = "enable", value_enum)]
+        features: Vec<ModFeature>,
+
+        /// Settings profile the patched app starts with (each profile has its own mod_settings.json)
+        #[arg(long, value_name = "NAME")]
+        settings_profile: Option<String>,
+
+        /// Extra header to strip from music API requests (can be repeated)
+        #[arg(long = "ban-header")]
+        banned_headers: Vec<String>,
+
+        /// Print unified diffs of the patched text files
+        #[arg(long, conflicts_with = "dry_run")]
+        show_diff: bool,
+
+        /// Launch the patched app once patching is done
+        #[arg(long, conflicts_with = "dry_run")]
+        run: bool,
+
+        /// Install a missing 7-Zip without asking (Windows: winget, choco, scoop or portable 7zr.exe)
+        #[arg(long)]
+        auto_install_deps: bool,
+
+        /// POST a JSON summary of the run to this URL when it finishes
+        #[arg(long, value_name = "URL")]
+        notify_url: Option<String>,
+
+        /// Package the patched build in this format: package manager manifests, MSIX, Flatpak or electron-builder installers (repeatable)
+        #[arg(long = "package", value_name = "FORMAT", conflicts_with = "dry_run")]
+        packages: Vec<package::PackageFormat>,
+
+        /// URL the portable zip will be hosted under, used in the manifests [default: local file]
+        #[arg(long, value_name = "URL")]
+        package_url: Option<String>,
+
+        /// Electron version for Flatpak, electron-builder and the launch.cmd of packages without a runtime [default: from the app's package.json]
+        #[arg(long, value_name = "VERSION")]
+        electron_version: Option<String>,
+
+        /// Sign the launcher, MSIX and installers of --package with this PKCS#12 certificate (signtool or osslsigncode)
+        #[arg(long, value_name = "PFX")]
+        sign_cert: Option<std::path::PathBuf>,
+
+        /// Password of the signing certificate [default: the YMM_SIGN_PASSWORD variable]
+        #[arg(long, value_name = "PASSWORD", requires = "sign_cert")]
+        sign_password: Option<String>,
+
+        /// Timestamp server for signatures [default: http://timestamp.digicert.com]
+        #[arg(long, value_name = "URL")]
+        sign_timestamp_url: Option<String>,
+
+        /// Subject of the signing certificate (CN=...), used as the MSIX publisher
+        #[arg(long, value_name = "SUBJECT")]
+        sign_publisher: Option<String>,
+
+        /// Write the installer hash, rules hash and options of this run to a plan file
+        #[arg(long, value_name = "PATH", conflicts_with = "dry_run")]
+        export_plan: Option<std::path::PathBuf>,
+
+        /// Replay a plan file exported with --export-plan; patch options come from the plan
+        #[arg(long, value_name = "PATH", conflicts_with_all = ["dry_run", "export_plan"])]
+        plan: Option<std::path::PathBuf>,
+
+        /// Extract and patch from scratch instead of reusing cached results of earlier runs
+        #[arg(long)]
+        no_cache: bool,
+
+        /// Also pack the mod into app.asar in the build directory, to replace the one of an installed app
+        #[arg(long, conflicts_with = "dry_run")]
+        pack_asar: bool,
+
+        /// File listing app.asar paths to place first, one per line (as for `asar pack --ordering`)
+        #[arg(long, value_name = "PATH")]
+        asar_ordering: Option<std::path::PathBuf>,
+
+        /// Glob of files kept outside app.asar in app.asar.unpacked (repeatable)
+        #[arg(long, value_name = "GLOB")]
+        asar_unpack: Vec<String>,
+
+        /// Threads hashing files for app.asar [default: one per CPU]
+        #[arg(long, value_name = "N")]
+        asar_jobs: Option<usize>,
+
+        /// Time each stage and print wall time, bytes and throughput (also in the patch report)
+        #[arg(long)]
+        bench: bool,
+
+        /// Only regenerate the mod files (renderer code, styles, feature assets) and packages of the newest patched build
+        #[arg(long, conflicts_with_all = ["dry_run", "plan", "export_plan", "show_diff", "run"])]
+        refresh_assets: bool,
+    },
+
+    /// Poll for new releases and patch each one automatically
+    Watch {
+        /// Time between update checks, e.g. 30m or 6h
+        #[arg(long, default_value = "6h", value_parser = humantime::parse_duration)]
+        interval: std::time::Duration,
+
+        /// Output directory for patched builds [default: versions in the data directory]
+        #[arg(short, long)]
+        output: Option<std::path::PathBuf>,
+
+        /// Launch each newly patched build
+        #[arg(long)]
+        run: bool,
+
+        /// POST a JSON summary of every patch run to this URL
+        #[arg(long, value_name = "URL")]
+        notify_url: Option<String>,
+    },
+
+    /// Check once whether the latest build is patched yet, and with --patch patch it
+    CheckUpdate {
+        /// Output directory for patched builds [default: versions in the data directory]
+        #[arg(short, long)]
+        output: Option<std::path::PathBuf>,
+
+        /// Patch the latest build if it is not patched yet
+        #[arg(long)]
+        patch: bool,
+
+        /// POST a JSON summary of the patch run to this URL
+        #[arg(long, value_name = "URL")]
+        notify_url: Option<String>,
+    },
+
+    /// Register or remove a periodic update check (Task Scheduler or a systemd user timer)
+    Schedule {
+        #[command(subcommand)]
+        action: ScheduleAction,
+    },
+
+    /// Patch a build again from changed rules, preload scripts and mods, for rule authors
+    Dev {
+        /// Version to patch again [default: newest patched build]
+        version: Option<String>,
+
+        /// Keep watching and redo the affected stages on every change
+        #[arg(long)]
+        watch: bool,
+
+        /// Time between checks for changes
+        #[arg(long, default_value = "1s", value_parser = humantime::parse_duration)]
+        interval: std::time::Duration,
+
+        /// Directory of rule files (profiles.toml, <rule set>.toml) used instead of the built-in ones
+        #[arg(long, value_name = "DIR")]
+        rules_dir: Option<std::path::PathBuf>,
+
+        /// External mods folder [default: mods in the app data folder]
+        #[arg(long, value_name = "DIR")]
+        mods_dir: Option<std::path::PathBuf>,
+
+        /// Launch the build, and restart it after every change
+        #[arg(long)]
+        run: bool,
+
+        /// Output directory containing the build [default: versions in the data directory]
+        #[arg(short, long)]
+        output: Option<std::path::PathBuf>,
     },
 
     /// Download the latest Yandex Music build without patching
     Download {
-        /// Output directory for the downloaded build
-        #[arg(short, long, default_value = ".versions")]
-        output: String,
+        /// Output directory for the downloaded build [default: versions in the data directory]
+        #[arg(short, long)]
+        output: Option<std::path::PathBuf>,
     },
 
     /// Show information about the latest available build
-    Info,
+    Info {
+        /// Compare the latest builds of the stable, beta and alpha channels side by side
+        #[arg(long)]
+        all_channels: bool,
+    },
+
+    /// Check an installer against the published SHA-512 and size
+    VerifyDownload {
+        /// Path to the installer
+        path: std::path::PathBuf,
+    },
+
+    /// Patch the bundled fixture files and check the result
+    Selftest,
+
+    /// Dry-run the patch rules on the newest (or given) build and show where the broken ones went
+    CompatCheck {
+        /// Version to check; must be the latest build of a channel [default: newest of all channels]
+        version: Option<String>,
+
+        /// Output directory for the dry-run build [default: versions in the data directory]
+        #[arg(short, long)]
+        output: Option<std::path::PathBuf>,
+    },
+
+    /// Diff the files the patches target between two versions, marking the changes around patch anchors and rules
+    CompareVersions {
+        /// Version the patch profile works with, e.g. 5.40.1
+        old: String,
+
+        /// Version to update the profile for, e.g. 5.41.0
+        new: String,
+
+        /// Output directory with builds of both versions, and for dry runs of missing ones [default: versions in the data directory]
+        #[arg(short, long)]
+        output: Option<std::path::PathBuf>,
+
+        /// Show only the changes touching a patch anchor or rule
+        #[arg(long)]
+        rules_only: bool,
+    },
+
+    /// Check a patched build against the checksums in its mod-manifest.json
+    Verify {
+        /// Version to check [default: newest patched build]
+        version: Option<String>,
+
+        /// Output directory containing the build [default: versions in the data directory]
+        #[arg(short, long)]
+        output: Option<std::path::PathBuf>,
+    },
+
+    /// Write a compact delta from the stock to the patched app of a build, to share instead of the build
+    Delta {
+        /// Version to write the delta of [default: newest patched build]
+        version: Option<String>,
+
+        /// Output directory containing the build [default: versions in the data directory]
+        #[arg(short, long)]
+        output: Option<std::path::PathBuf>,
+
+        /// Delta file to write [default: <version>.ymmdelta in the build directory]
+        #[arg(long, value_name = "PATH")]
+        file: Option<std::path::PathBuf>,
+    },
+
+    /// Rebuild a patched build from a delta and the stock app.asar (or its extracted tree)
+    ApplyDelta {
+        /// Delta file written by `delta`
+        delta: std::path::PathBuf,
+
+        /// Stock app.asar of the same Yandex Music version, or the directory extracted from it
+        #[arg(long, value_name = "PATH")]
+        source: std::path::PathBuf,
+
+        /// Output directory for the patched build [default: versions in the data directory]
+        #[arg(short, long)]
+        output: Option<std::path::PathBuf>,
+    },
+
+    /// Apply rules and inject assets into any Electron app's app.asar (the Yandex Music profile by default)
+    PatchAsar {
+        /// app.asar of the app, or the directory extracted from it
+        #[arg(long, value_name = "PATH")]
+        input: std::path::PathBuf,
+
+        /// Rules file (TOML) with find/replace rules and assets to inject [default: the Yandex Music profile]
+        #[arg(long, value_name = "PATH")]
+        rules: Option<std::path::PathBuf>,
+
+        /// Script (.js) or style (.css) to add to every page, besides those of the rules file (repeatable)
+        #[arg(long, value_name = "FILE", requires = "rules")]
+        inject: Vec<std::path::PathBuf>,
+
+        /// Where to write the patched app: an .asar file or a directory [default: <input>.patched.asar]
+        #[arg(short, long)]
+        output: Option<std::path::PathBuf>,
+
+        /// Glob of files kept outside app.asar in app.asar.unpacked (repeatable)
+        #[arg(long, value_name = "GLOB")]
+        asar_unpack: Vec<String>,
+    },
+
+    /// Copy Yandex Music installed from the Microsoft Store out of WindowsApps and patch the copy
+    Sideload {
+        /// App folder to copy (the one holding resources/app.asar) [default: the Store install]
+        #[arg(long, value_name = "DIR")]
+        from: Option<std::path::PathBuf>,
+
+        /// Folder for the patched copy [default: %LOCALAPPDATA%\Programs\YandexMusicMod]
+        #[arg(long, value_name = "DIR")]
+        to: Option<std::path::PathBuf>,
+
+        /// Glob of files kept outside app.asar in app.asar.unpacked (repeatable)
+        #[arg(long, value_name = "GLOB")]
+        asar_unpack: Vec<String>,
+    },
+
+    /// Show which files the mod added, removed or changed in a patched build
+    Diff {
+        /// Version to compare [default: newest patched build]
+        version: Option<String>,
+
+        /// Output directory containing the build [default: versions in the data directory]
+        #[arg(short, long)]
+        output: Option<std::path::PathBuf>,
+
+        /// Print unified diffs of modified text files
+        #[arg(long)]
+        unified: bool,
+    },
+
+    /// Import the settings of the original YandexMusicBetaMod into mod_settings.json
+    ImportSettings {
+        /// Settings file of the original mod [default: found in the app data folder]
+        #[arg(long)]
+        from: Option<std::path::PathBuf>,
+
+        /// Settings file to import into [default: the patched app's active profile]
+        #[arg(long)]
+        to: Option<std::path::PathBuf>,
+
+        /// Replace settings that are already set
+        #[arg(long)]
+        overwrite: bool,
+    },
+
+    /// Export or import the mod settings, profiles and external mods
+    Settings {
+        #[command(subcommand)]
+        action: SettingsAction,
+    },
+
+    /// Show the installed Yandex Music version and whether it is patched
+    Status {
+        /// Install folder to check besides the detected ones (repeatable)
+        #[arg(long)]
+        path: Vec<std::path::PathBuf>,
+
+        /// Output directory to look for patched builds in [default: versions in the data directory]
+        #[arg(short, long)]
+        output: Option<std::path::PathBuf>,
+    },
+
+    /// Replace the app.asar of the installed app with the patched one (asks for administrator rights if needed)
+    Apply {
+        /// Install folder to patch [default: the first one detected]
+        #[arg(long)]
+        path: Option<std::path::PathBuf>,
+
+        /// Output directory to look for patched builds in [default: versions in the data directory]
+        #[arg(short, long)]
+        output: Option<std::path::PathBuf>,
+
+        /// Put the stock app.asar kept by an earlier `apply` back
+        #[arg(long)]
+        restore: bool,
+    },
+
+    /// List the patched builds in the output directory
+    Versions {
+        /// Output directory to scan [default: versions in the data directory]
+        #[arg(short, long)]
+        output: Option<std::path::PathBuf>,
+    },
+
+    /// Check 7-Zip, asar, disk space, output directory and network access
+    Doctor {
+        /// Output directory to check [default: versions in the data directory]
+        #[arg(short, long)]
+        output: Option<std::path::PathBuf>,
+    },
+
+    /// Update this patcher to the latest GitHub release
+    SelfUpdate {
+        /// Only check whether an update is available
+        #[arg(long)]
+        check: bool,
+
+        /// Reinstall even if up to date, or replace a local build
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Serve a REST API to trigger and monitor patch runs (GET /latest, POST /patch, GET /status, GET /builds)
+    #[cfg(feature = "serve")]
+    Serve {
+        /// Port to listen on
+        #[arg(long, default_value_t = serve::DEFAULT_PORT)]
+        port: u16,
+
+        /// Address to listen on; use 0.0.0.0 to accept requests from the LAN
+        #[arg(long, default_value = "127.0.0.1")]
+        host: std::net::IpAddr,
+
+        /// Output directory for patched builds [default: versions in the data directory]
+        #[arg(short, long)]
+        output: Option<std::path::PathBuf>,
+    },
+
+    /// Open the patcher window (default when started by double-click)
+    #[cfg(feature = "gui")]
+    Gui,
+
+    /// Open the web version of Yandex Music with the mod in a native window, without the Electron app
+    #[cfg(feature = "wrapper")]
+    App,
+
+    /// Show a tray icon that notifies about new releases and patches them on request
+    #[cfg(feature = "tray")]
+    Tray {
+        /// Time between update checks, e.g. 30m or 6h
+        #[arg(long, default_value = "6h", value_parser = humantime::parse_duration)]
+        interval: std::time::Duration,
+
+        /// Output directory for patched builds [default: versions in the data directory]
+        #[arg(short, long)]
+        output: Option<std::path::PathBuf>,
+    },
+
+    /// Write the base renderer assets (renderer.js, renderer.css) and their
+    /// assets.json to a directory. The release job signs assets.json with the
+    /// release key into assets.json.sig
+    ExportAssets {
+        /// Output directory for the assets
+        #[arg(short, long, default_value = ".")]
+        output: std::path::PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+enum SettingsAction {
+    /// Write the settings and the mods folder of the patched app to a zip archive
+    Export {
+        /// Archive to write
+        #[arg(short, long, default_value = backup::DEFAULT_ARCHIVE)]
+        output: std::path::PathBuf,
+
+        /// Data folder of the patched app [default: YandexMusicMod in the app data folder]
+        #[arg(long)]
+        data_dir: Option<std::path::PathBuf>,
+    },
+
+    /// Restore settings and mods from an archive made with `settings export`
+    Import {
+        /// Archive to restore
+        archive: std::path::PathBuf,
+
+        /// Data folder of the patched app [default: YandexMusicMod in the app data folder]
+        #[arg(long)]
+        data_dir: Option<std::path::PathBuf>,
+
+        /// Replace files that already exist (settings are kept as .json.bak)
+        #[arg(long)]
+        overwrite: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum ScheduleAction {
+    /// Run `check-update --patch` periodically in the background
+    Install {
+        /// Time between update checks, e.g. 30m or 6h
+        #[arg(long, default_value = "6h", value_parser = humantime::parse_duration)]
+        interval: std::time::Duration,
+
+        /// Output directory for patched builds [default: versions in the data directory]
+        #[arg(short, long)]
+        output: Option<std::path::PathBuf>,
+    },
+
+    /// Remove the periodic update check
+    Remove,
 }
 
 #[tokio::main]
 async fn main() {
-    let wait_on_exit = should_wait_before_exit();
+    // Attach before any output so help and errors reach the terminal
+    #[cfg(all(target_os = "windows", feature = "windows-gui"))]
+    winconsole::has_console();
+
+    let cli = Cli::parse();
+    let json = cli.json;
+    prompt::set_non_interactive(cli.non_interactive);
+
+    // The run started through the UAC prompt has a console window of its own
+    let wait_on_exit = (should_wait_before_exit() || cli.elevated) && prompt::is_interactive();
+    // Without arguments a double-clicked exe opens the GUI, which reports results itself
+    #[cfg(feature = "gui")]
+    let wait_on_exit = wait_on_exit && std::env::args_os().len() > 1;
 
-    match run().await {
+    // Without a console there is nothing to wait in; report through message boxes instead
+    #[cfg(all(target_os = "windows", feature = "windows-gui"))]
+    let message_boxes = !winconsole::has_console()
+        && !prompt::is_non_interactive()
+        && std::env::args_os().len() > 1;
+
+    match run(cli).await {
         Ok(_) => {
+            #[cfg(all(target_os = "windows", feature = "windows-gui"))]
+            if message_boxes {
+                winconsole::message_box(
+                    "Yandex Music Mod",
+                    &tr!(
+                        "Operation completed successfully!",
+                        "Операция успешно завершена!"
+                    ),
+                    false,
+                );
+            }
             if wait_on_exit {
-                println!("\nOperation completed successfully!");
+                println!(
+                    "{}",
+                    tr!(
+                        "\nOperation completed successfully!",
+                        "\nОперация успешно завершена!"
+                    )
+                );
                 wait_for_enter();
             }
         }
         Err(e) => {
-            eprintln!("\nError: {:#}", e);
+            if json {
+                println!(
+                    "{}",
+                    serde_json::json!({ "error": format!("{:#}", e), "code": error::code(&e) })
+                );
+            } else {
+                eprintln!("{}", tr!("\nError: {:#}", "\nОшибка: {:#}", e));
+            }
+            #[cfg(all(target_os = "windows", feature = "windows-gui"))]
+            if message_boxes {
+                winconsole::message_box("Yandex Music Mod", &format!("{:#}", e), true);
+            }
             if wait_on_exit {
                 wait_for_enter();
             }
-            std::process::exit(1);
+            std::process::exit(error::code(&e).into());
         }
     }
 }
 
-async fn run() -> Result<()> {
-    let cli = Cli::parse();
+/// Warn (or fail with `--strict`) when the build is outside the compatibility matrix
+fn check_compatibility(version: &str, strict: bool, dry_run: bool) -> Result<()> {
+    match patches::check_compatibility(version) {
+        Compatibility::Known => {}
+        Compatibility::Untested => {
+            warn!(
+                "Yandex Music {} is not in the list of tested versions; patches should still apply",
+                version
+            );
+        }
+        Compatibility::Newer => {
+            let banner = "!".repeat(72);
+            eprintln!("\n{}", banner);
+            eprintln!(
+                "{}",
+                tr!(
+                    "WARNING: Yandex Music {} is newer than the latest version this patcher\n\
+                     is known to work with ({}). Some patches may no longer apply.",
+                    "ВНИМАНИЕ: Яндекс Музыка {} новее последней версии, с которой проверен\n\
+                     патчер ({}). Часть патчей может не примениться.",
+                    version,
+                    patches::latest_known_version()
+                )
+            );
+            if !dry_run {
+                eprintln!(
+                    "{}",
+                    tr!(
+                        "Run `yandex-music-mod patch --dry-run` to see which rules no longer match.",
+                        "Запустите `yandex-music-mod patch --dry-run`, чтобы увидеть несовпадающие правила."
+                    )
+                );
+            }
+            eprintln!("{}\n", banner);
 
-    // Initialize logging
-    let level = if cli.verbose {
-        Level::DEBUG
+            if strict && !dry_run {
+                anyhow::bail!(tr!(
+                    "Refusing to patch untested version {} in --strict mode",
+                    "Непроверенная версия {} не патчится в режиме --strict",
+                    version
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Collect extra promo selectors from the config directory and an optional remote list
+async fn load_promo_selectors(url: Option<&str>) -> Result<Vec<String>> {
+    let mut selectors = Vec::new();
+
+    if let Some(content) = config::read_config_file(config::USER_PROMO_SELECTORS_FILE)? {
+        selectors.extend(patches::parse_line_list(&content));
+    }
+
+    if let Some(url) = url {
+        match api::fetch_text(url).await {
+            Ok(content) => {
+                let remote = patches::parse_line_list(&content);
+                info!("Loaded {} promo selector(s) from {}", remote.len(), url);
+                selectors.extend(remote);
+            }
+            Err(e) => warn!("Failed to fetch promo selectors from {}: {:#}", url, e),
+        }
+    }
+
+    Ok(selectors)
+}
+
+/// Print which anchors and rules matched during a dry run
+/// Side-by-side table of the latest build of every channel, marking the
+/// channels ahead of stable
+fn print_channels(channels: &[(&str, Result<Vec<api::AppBuild>>)]) {
+    let parse = |version: &str| semver::Version::parse(version).ok();
+    let stable = channels
+        .iter()
+        .find(|(channel, _)| *channel == api::DEFAULT_CHANNEL)
+        .and_then(|(_, result)| result.as_ref().ok()?.first())
+        .and_then(|build| parse(&build.version));
+
+    println!(
+        "{:<8} {:<12} {:>10}  {:<12} {:>7}",
+        tr!("Channel", "Канал"),
+        tr!("Version", "Версия"),
+        tr!("Size", "Размер"),
+        tr!("Released", "Выпуск"),
+        tr!("Rollout", "Раздача")
+    );
+    println!("{}", "-".repeat(60));
+    for (channel, result) in channels {
+        let build = match result {
+            Ok(builds) => builds.first(),
+            Err(e) => {
+                println!("{:<8} {}", channel, tr!("error: {:#}", "ошибка: {:#}", e));
+                continue;
+            }
+        };
+        let Some(build) = build else {
+            println!("{:<8} {}", channel, tr!("no builds", "нет сборок"));
+            continue;
+        };
+        let released = build
+            .release_date
+            .as_deref()
+            .map(|date| date.get(..10).unwrap_or(date))
+            .unwrap_or("-");
+        let rollout = build
+            .update_probability
+            .map(|p| format!("{:.0}%", p * 100.0))
+            .unwrap_or_else(|| "-".to_string());
+        let ahead = match (parse(&build.version), &stable) {
+            (Some(version), Some(stable)) if version > *stable => {
+                tr!("  ahead of stable", "  новее stable")
+            }
+            _ => String::new(),
+        };
+        println!(
+            "{:<8} {:<12} {:>10}  {:<12} {:>7}{}",
+            channel,
+            build.version,
+            format_bytes(build.size, None),
+            released,
+            rollout,
+            ahead
+        );
+    }
+}
+
+fn print_dry_run_report(report: &patcher::PatchReport) {
+    println!(
+        "{}",
+        tr!(
+            "\nDry run for Yandex Music {} (profile: {})",
+            "\nПробный запуск для Яндекс Музыки {} (профиль: {})",
+            report.version,
+            report.profile
+        )
+    );
+    println!("{}", "=".repeat(60));
+    for outcome in report.anchors.iter().chain(&report.rules) {
+        let status = if outcome.matches > 0 { "OK  " } else { "MISS" };
+        println!(
+            "[{}] {:<40} {}",
+            status,
+            outcome.rule,
+            tr!("{} match(es)", "совпадений: {}", outcome.matches)
+        );
+    }
+    println!("{}", "-".repeat(60));
+
+    let unmatched = report.unmatched();
+    if unmatched.is_empty() {
+        println!(
+            "{}",
+            tr!(
+                "All patch anchors and rules matched.",
+                "Все якоря и правила патчей совпали."
+            )
+        );
+    } else {
+        println!(
+            "{}",
+            tr!(
+                "{} rule(s) no longer match: {}",
+                "Не совпадает правил: {}: {}",
+                unmatched.len(),
+                unmatched.join(", ")
+            )
+        );
+    }
+}
+
+/// Set up console logging and the optional `--log-file`
+fn init_logging(cli: &Cli) -> Result<()> {
+    let level = match cli.log_level {
+        Some(level) => level.into(),
+        None if cli.verbose => Level::DEBUG,
+        None => Level::INFO,
+    };
+
+    // With --json, stdout is reserved for the result
+    let writer = if cli.json {
+        BoxMakeWriter::new(io::stderr)
     } else {
-        Level::INFO
+        BoxMakeWriter::new(io::stdout)
+    };
+    let console = fmt::layer()
+        .with_ansi(cli.color())
+        .with_writer(writer)
+        .with_filter(LevelFilter::from_level(level));
+
+    let file = match &cli.log_file {
+        Some(path) => {
+            let file = std::fs::File::create(path)
+                .with_context(|| format!("Failed to create log file {:?}", path))?;
+            Some(
+                fmt::layer()
+                    .with_ansi(false)
+                    .with_writer(Mutex::new(file))
+                    .with_filter(LevelFilter::from_level(level.max(Level::DEBUG))),
+            )
+        }
+        None => None,
     };
 
-    let subscriber = FmtSubscriber::builder().with_max_level(level).finish();
-    tracing::subscriber::set_global_default(subscriber)?;
+    tracing_subscriber::registry()
+        .with(console)
+        .with(file)
+        .try_init()?;
+    Ok(())
+}
+
+/// Renders patch progress on an indicatif progress bar
+struct BarSink<'a> {
+    bar: &'a ProgressBar,
+    state: ProgressState,
+}
+
+impl<'a> BarSink<'a> {
+    fn new(bar: &'a ProgressBar) -> Self {
+        Self {
+            bar,
+            state: ProgressState::default(),
+        }
+    }
+}
+
+impl ProgressSink for BarSink<'_> {
+    fn event(&self, event: Event) {
+        self.state.event(event);
+        let snapshot = self.state.snapshot();
+        self.bar.set_position(snapshot.percent);
+        self.bar.set_message(snapshot.message());
+    }
+}
+
+/// Style of the patch progress bar
+fn progress_style(color: bool, ascii: bool) -> Result<ProgressStyle> {
+    let template = if color {
+        "{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({eta}) {msg}"
+    } else {
+        "{spinner} [{elapsed_precise}] [{bar:40}] {pos}/{len} ({eta}) {msg}"
+    };
+    let style = ProgressStyle::default_bar()
+        .template(template)?
+        .progress_chars("#>-");
+    // The default spinner uses Braille characters
+    Ok(if ascii {
+        style.tick_chars("|/-\\ ")
+    } else {
+        style
+    })
+}
+
+/// Print `value` as pretty JSON on stdout
+fn print_json<T: Serialize + ?Sized>(value: &T) -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+/// Version of the newest patched build in `output`
+/// Newest complete build of this patcher; builds of the original mod lack the
+/// trees and reports the commands using this need
+fn newest_version(output: &std::path::Path) -> Result<String> {
+    versions::scan(output)?
+        .into_iter()
+        .rev()
+        .find(|b| b.complete && !b.dry_run && !b.legacy)
+        .map(|b| b.version)
+        .with_context(|| {
+            tr!(
+                "No patched builds in {:?}",
+                "Нет пропатченных сборок в {:?}",
+                output
+            )
+        })
+}
+
+async fn run(cli: Cli) -> Result<()> {
+    let json = cli.json;
+    let (color, ascii) = (cli.color(), cli.ascii);
+
+    // Relative paths of the relaunched run refer to the caller's directory
+    if let Some(dir) = &cli.elevated_dir {
+        std::env::set_current_dir(dir)
+            .with_context(|| format!("Failed to change to the directory {:?}", dir))?;
+    }
+
+    init_logging(&cli)?;
+    tokio::spawn(interrupt::handle_ctrl_c(cli.keep_temp));
+    elevate::set_elevated(cli.elevated);
+
+    if cli.portable {
+        config::set_portable()?;
+    } else {
+        // Look for a portable.flag before any directory is resolved
+        config::portable_dir();
+    }
+    let config = config::Config::load(cli.config.as_deref())?;
+    let lang = cli
+        .lang
+        .or_else(|| config.language.as_deref().and_then(i18n::Lang::from_locale));
+    i18n::set_lang(lang.unwrap_or_else(i18n::Lang::detect));
+
+    #[cfg(feature = "gui")]
+    let command = match cli.command {
+        None if should_wait_before_exit() && !prompt::is_non_interactive() => Some(Commands::Gui),
+        command => command,
+    };
+    #[cfg(not(feature = "gui"))]
+    let command = cli.command;
 
     // Default to Patch command if no subcommand is provided
-    let command = cli.command.unwrap_or_else(|| {
-        println!("No command specified, defaulting to 'patch' command...\n");
+    let command = command.unwrap_or_else(|| {
+        eprintln!(
+            "{}",
+            tr!(
+                "No command specified, defaulting to 'patch' command...\n",
+                "Команда не указана, выполняется 'patch'...\n"
+            )
+        );
         Commands::Patch {
-            output: ".versions".to_string(),
+            output: None,
             auto_devtools: false,
+            min_width: None,
+            min_height: None,
+            stock_min_size: false,
+            profile: None,
+            strict: false,
+            dry_run: false,
+            promo_selectors_url: None,
+            remote_rules: false,
+            rules_url: None,
+            features: Vec::new(),
+            settings_profile: None,
+            banned_headers: Vec::new(),
+            show_diff: false,
+            run: false,
+            auto_install_deps: false,
+            notify_url: None,
+            packages: Vec::new(),
+            package_url: None,
+            electron_version: None,
+            sign_cert: None,
+            sign_password: None,
+            sign_timestamp_url: None,
+            sign_publisher: None,
+            export_plan: None,
+            plan: None,
+            no_cache: false,
+            pack_asar: false,
+            asar_ordering: None,
+            asar_unpack: Vec::new(),
+            asar_jobs: None,
+            bench: false,
+            refresh_assets: false,
         }
     });
 
+    if let Some(proxy) = &config.proxy {
+        api::set_proxy(proxy);
+    }
+    #[cfg(feature = "toast")]
+    toast::set_enabled(config.notifications.unwrap_or(true));
+    if let Some(arch) = cli.arch.or(config.arch) {
+        api::set_arch(arch);
+    }
+    api::set_mirrors(if cli.mirrors.is_empty() {
+        &config.mirrors
+    } else {
+        &cli.mirrors
+    });
+    if let Some(dir) = &cli.record {
+        cassette::set(dir, cassette::Mode::Record);
+    } else if let Some(dir) = &cli.replay {
+        cassette::set(dir, cassette::Mode::Replay);
+    }
+    let channel = cli
+        .channel
+        .or_else(|| config.channel.clone())
+        .unwrap_or_else(|| api::DEFAULT_CHANNEL.to_string());
+
     match command {
         Commands::Patch {
             output,
             auto_devtools,
+            min_width,
+            min_height,
+            stock_min_size,
+            profile,
+            strict,
+            dry_run,
+            promo_selectors_url,
+            remote_rules,
+            rules_url,
+            features,
+            settings_profile,
+            mut banned_headers,
+            show_diff,
+            run,
+            auto_install_deps,
+            notify_url,
+            packages,
+            package_url,
+            electron_version,
+            sign_cert,
+            sign_password,
+            sign_timestamp_url,
+            sign_publisher,
+            export_plan,
+            plan,
+            no_cache,
+            pack_asar,
+            asar_ordering,
+            asar_unpack,
+            asar_jobs,
+            bench,
+            refresh_assets,
         } => {
-            info!("Fetching latest stable build information...");
+            // Command-line flags override config.toml
+            let defaults = &config.patch;
+            let output = config.output_dir(output.as_deref());
+            paths::ensure_writable(&output)?;
+            let auto_devtools = auto_devtools || defaults.auto_devtools;
+            let stock_min_size = stock_min_size
+                || (min_width.is_none() && min_height.is_none() && defaults.stock_min_size);
+            let min_width = min_width
+                .or(defaults.min_width)
+                .unwrap_or(patches::DEFAULT_MIN_WIDTH);
+            let min_height = min_height
+                .or(defaults.min_height)
+                .unwrap_or(patches::DEFAULT_MIN_HEIGHT);
+            let profile = profile.or_else(|| defaults.profile.clone());
+            let strict = strict || defaults.strict;
+            let auto_install_deps = auto_install_deps || defaults.auto_install_deps;
+            let show_diff = (show_diff || defaults.show_diff) && !dry_run;
+            let run = (run || defaults.run) && !dry_run;
+            let notify_url = notify_url.or_else(|| defaults.notify_url.clone());
+            let packages = if packages.is_empty() {
+                defaults.package.clone()
+            } else {
+                packages
+            };
+            let package_url = package_url.or_else(|| defaults.package_url.clone());
+            let electron_version = electron_version.or_else(|| defaults.electron_version.clone());
+            let promo_selectors_url =
+                promo_selectors_url.or_else(|| defaults.promo_selectors_url.clone());
+            let features = if features.is_empty() {
+                defaults.enable.clone()
+            } else {
+                features
+            };
+            banned_headers.extend(defaults.ban_headers.iter().cloned());
+
+            let plan = plan.map(|path| plan::PatchPlan::load(&path)).transpose()?;
+            // Fetched up front so plans are exported and checked against them
+            let rules_url = config.remote_rules(remote_rules, rules_url).or_else(|| {
+                plan.as_ref()
+                    .and_then(|plan| plan.options.remote_rules.clone())
+            });
+            if let Some(url) = &rules_url {
+                remote_rules::refresh(url).await?;
+            }
+            let mut options = match &plan {
+                Some(plan) => plan.options()?,
+                None => {
+                    if let Some(content) =
+                        config::read_config_file(config::USER_BANNED_HEADERS_FILE)?
+                    {
+                        banned_headers.extend(patches::parse_line_list(&content));
+                    }
 
-            let builds = api::get_stable_build().await?;
+                    patcher::PatchOptions {
+                        window: patches::WindowOptions {
+                            auto_devtools,
+                            min_width: (!stock_min_size).then_some(min_width),
+                            min_height: (!stock_min_size).then_some(min_height),
+                        },
+                        user_preload_js: config::load_user_preload()?,
+                        profile,
+                        dry_run,
+                        promo_selectors: load_promo_selectors(promo_selectors_url.as_deref())
+                            .await?,
+                        features,
+                        banned_headers,
+                        show_diff,
+                        default_settings: config.default_settings()?,
+                        settings_profile: settings_profile
+                            .or_else(|| defaults.settings_profile.clone()),
+                        auto_install_deps,
+                        packages,
+                        package_url,
+                        electron_version,
+                        sign: None,
+                        no_cache: false,
+                        asar: None,
+                        bench: false,
+                        remote_rules: rules_url,
+                    }
+                }
+            };
+            options.no_cache = no_cache;
+            options.bench = bench;
+            if let Some(sign) = config.sign_options(
+                sign_cert.as_deref(),
+                sign_password,
+                sign_timestamp_url,
+                sign_publisher,
+            ) {
+                options.sign = Some(sign);
+            }
+            if let Some(asar) =
+                config.asar_options(pack_asar, asar_ordering.as_deref(), asar_unpack)
+            {
+                options.asar = (!dry_run).then_some(asar::AsarOptions {
+                    jobs: asar_jobs,
+                    ..asar
+                });
+            }
 
-            if builds.is_empty() {
-                anyhow::bail!("No builds found");
+            if refresh_assets {
+                let version = newest_version(&output)?;
+                let build_dir = patcher::build_dir(&output, &version, false);
+                let files = patcher::refresh_assets(&build_dir, &options).await?;
+                if json {
+                    print_json(&serde_json::json!({
+                        "version": version,
+                        "output": build_dir,
+                        "files": files,
+                    }))?;
+                } else {
+                    info!(
+                        "{}",
+                        tr!(
+                            "Refreshed {} mod file(s) of build {}",
+                            "Обновлено файлов мода сборки {1}: {0}",
+                            files.len(),
+                            version
+                        )
+                    );
+                }
+                return Ok(());
             }
 
-            let build = &builds[0];
-            info!("Found build: {} (version {})", build.path, build.version);
+            let builds;
+            let build = match &plan {
+                Some(plan) => {
+                    info!(
+                        "Replaying plan for build {} (version {})",
+                        plan.build.path, plan.build.version
+                    );
+                    &plan.build
+                }
+                None => {
+                    info!("Fetching latest {} build information...", channel);
 
-            let pb = ProgressBar::new(100);
-            pb.set_style(
-                ProgressStyle::default_bar()
-                    .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({eta}) {msg}")?
-                    .progress_chars("#>-"),
-            );
+                    builds = api::get_latest_build(&channel).await?;
+
+                    if builds.is_empty() {
+                        anyhow::bail!(tr!("No builds found", "Сборки не найдены"));
+                    }
+
+                    info!(
+                        "Found build: {} (version {})",
+                        builds[0].path, builds[0].version
+                    );
+                    &builds[0]
+                }
+            };
 
-            patcher::process_build(build, &output, auto_devtools, Some(&pb)).await?;
+            check_compatibility(&build.version, strict, dry_run)?;
 
-            pb.finish_with_message("Patching complete!");
-            info!("Successfully patched Yandex Music v{}", build.version);
+            let pb = if json {
+                ProgressBar::hidden()
+            } else {
+                ProgressBar::new(100)
+            };
+            pb.set_style(progress_style(color, ascii)?);
+
+            let show_diff = options.show_diff;
+
+            let started = std::time::Instant::now();
+            let result =
+                patcher::process_build(build, &output, &options, Some(&BarSink::new(&pb))).await;
+            if let Some(url) = &notify_url {
+                let build_dir = patcher::build_dir(&output, &build.version, dry_run);
+                let notification =
+                    notify::Notification::new(&build.version, build_dir, dry_run, started, &result);
+                notify::send(url, &notification).await;
+            }
+            let report = result?;
+            if let Some(path) = &export_plan {
+                plan::PatchPlan::new(build, &options)?.save(path)?;
+            }
+
+            if json {
+                pb.finish();
+                let mut value = serde_json::to_value(&report)?;
+                value["output"] =
+                    serde_json::to_value(patcher::build_dir(&output, &report.version, dry_run))?;
+                if show_diff {
+                    value["diffs"] = serde_json::to_value(&report.diffs)?;
+                }
+                print_json(&value)?;
+            } else if dry_run {
+                pb.finish_with_message(tr!("Dry run complete!", "Пробный запуск завершён!"));
+                print_dry_run_report(&report);
+                if bench {
+                    bench::print_table(&report.timings);
+                }
+            } else {
+                pb.finish_with_message(tr!("Patching complete!", "Патчинг завершён!"));
+                for file in &report.diffs {
+                    print!("{}", file.diff);
+                }
+                if bench {
+                    bench::print_table(&report.timings);
+                }
+                info!("Successfully patched Yandex Music v{}", build.version);
+            }
+
+            let build_dir = patcher::build_dir(&output, &build.version, false);
+            // Double-click runs wait for Enter, which keeps the buttons working
+            #[cfg(feature = "toast")]
+            if !dry_run && !run {
+                toast::patched(&build.version, &build_dir);
+            }
+            if run {
+                patcher::launch_app(&build_dir.join("mod"))?;
+            }
+        }
+
+        Commands::Watch {
+            interval,
+            output,
+            run,
+            notify_url,
+        } => {
+            let output = config.output_dir(output.as_deref());
+            paths::ensure_writable(&output)?;
+            let run = run || config.patch.run;
+            let notify_url = notify_url.or_else(|| config.patch.notify_url.clone());
+            watch::watch(
+                &config,
+                &channel,
+                &output,
+                interval,
+                run,
+                notify_url.as_deref(),
+            )
+            .await?;
+        }
+
+        Commands::CheckUpdate {
+            output,
+            patch,
+            notify_url,
+        } => {
+            let output = config.output_dir(output.as_deref());
+            if patch {
+                paths::ensure_writable(&output)?;
+            }
+            let notify_url = notify_url.or_else(|| config.patch.notify_url.clone());
+            let check =
+                watch::check_update(&config, &channel, &output, patch, notify_url.as_deref())
+                    .await?;
+            if json {
+                return print_json(&check);
+            }
+            match (&check.version, check.update_available) {
+                (None, _) => println!("{}", tr!("No builds published", "Сборки не опубликованы")),
+                (Some(version), false) => println!(
+                    "{}",
+                    tr!(
+                        "Yandex Music {} is already patched",
+                        "Яндекс Музыка {} уже пропатчена",
+                        version
+                    )
+                ),
+                (Some(version), true) if check.patched => println!(
+                    "{}",
+                    tr!(
+                        "Patched the new Yandex Music {}",
+                        "Пропатчена новая Яндекс Музыка {}",
+                        version
+                    )
+                ),
+                (Some(version), true) => println!(
+                    "{}",
+                    tr!(
+                        "New Yandex Music {} is available; run with --patch to patch it",
+                        "Доступна новая Яндекс Музыка {}; запустите с --patch, чтобы пропатчить",
+                        version
+                    )
+                ),
+            }
+        }
+
+        Commands::Schedule { action } => match action {
+            ScheduleAction::Install { interval, output } => {
+                let output = config.output_dir(output.as_deref());
+                paths::ensure_writable(&output)?;
+                let command = schedule::check_command(cli.config.as_deref(), &channel, &output)?;
+                let schedule = schedule::install(command, interval)?;
+                if json {
+                    return print_json(&schedule);
+                }
+                println!(
+                    "{}",
+                    tr!(
+                        "New releases of the {} channel will be checked for every {} and patched into {:?}",
+                        "Новые версии канала {} будут проверяться каждые {} и патчиться в {:?}",
+                        channel,
+                        humantime::format_duration(interval),
+                        output
+                    )
+                );
+            }
+            ScheduleAction::Remove => {
+                let files = schedule::remove()?;
+                if json {
+                    return print_json(&serde_json::json!({ "removed": files }));
+                }
+                println!(
+                    "{}",
+                    tr!(
+                        "Removed the scheduled update check",
+                        "Периодическая проверка обновлений удалена"
+                    )
+                );
+            }
+        },
+
+        Commands::Dev {
+            version,
+            watch,
+            interval,
+            rules_dir,
+            mods_dir,
+            run,
+            output,
+        } => {
+            let output = config.output_dir(output.as_deref());
+            let version = match version {
+                Some(version) => version,
+                None => newest_version(&output)?,
+            };
+            let build_dir = patcher::build_dir(&output, &version, false);
+
+            let mut watch_set = dev::WatchSet::default();
+            watch_set.inputs.extend(config::config_dir());
+            watch_set.inputs.extend(cli.config.clone());
+            if let Some(dir) = rules_dir {
+                watch_set.inputs.push(dir.clone());
+                rules::use_remote_rules(dir);
+            }
+            watch_set.mods_dir = mods_dir.or_else(|| {
+                migrate::app_data_dir().map(|dir| dir.join(patches::EXTERNAL_MODS_DIR))
+            });
+            dev::dev(
+                cli.config.as_deref(),
+                &build_dir,
+                &watch_set,
+                watch,
+                interval,
+                run,
+            )
+            .await?;
         }
 
         Commands::Download { output } => {
-            info!("Fetching latest stable build information...");
+            let output = config.output_dir(output.as_deref());
+            paths::ensure_writable(&output)?;
+            info!("Fetching latest {} build information...", channel);
 
-            let builds = api::get_stable_build().await?;
+            let builds = api::get_latest_build(&channel).await?;
 
             if builds.is_empty() {
-                anyhow::bail!("No builds found");
+                anyhow::bail!(tr!("No builds found", "Сборки не найдены"));
             }
 
             let build = &builds[0];
             info!("Found build: {} (version {})", build.path, build.version);
 
-            let output_path = format!("{}/{}.exe", output, build.version);
+            let output_path = output.join(format!("{}.exe", build.version));
+
+            info!("Downloading to {:?}...", output_path);
+            let partial = interrupt::Partial::new(&output_path);
+            let hash = api::download_build(build, &output_path, None).await?;
+            if hash != build.hash {
+                let _ = std::fs::remove_file(&output_path);
+                return Err(error::PatcherError::ChecksumMismatch(build.version.clone()).into());
+            }
+            drop(partial);
+
+            info!("Download complete: {:?}", output_path);
+            if json {
+                print_json(&serde_json::json!({
+                    "version": build.version,
+                    "path": output_path,
+                }))?;
+            }
+        }
+
+        #[cfg(feature = "serve")]
+        Commands::Serve { port, host, output } => {
+            let output = config.output_dir(output.as_deref());
+            paths::ensure_writable(&output)?;
+            serve::serve(config, channel, output, (host, port).into()).await?;
+        }
+
+        #[cfg(feature = "gui")]
+        Commands::Gui => {
+            let output = config.output_dir(None);
+            gui::run(config, channel, output)?;
+        }
+
+        #[cfg(feature = "wrapper")]
+        Commands::App => {
+            wrapper::run(&config)?;
+        }
+
+        #[cfg(feature = "tray")]
+        Commands::Tray { interval, output } => {
+            let output = config.output_dir(output.as_deref());
+            paths::ensure_writable(&output)?;
+            tray::run(config, channel, output, interval)?;
+        }
+
+        Commands::ExportAssets { output } => {
+            let output = paths::expand(&output);
             std::fs::create_dir_all(&output)?;
+            let assets = [
+                ("renderer.js", patches::MOD_RENDERER_JS),
+                ("renderer.css", patches::MOD_RENDERER_CSS),
+            ];
+            for (name, content) in assets {
+                std::fs::write(output.join(name), content)?;
+            }
+            std::fs::write(
+                output.join("assets.json"),
+                patches::generate_assets_manifest(&assets),
+            )?;
+            info!("Renderer assets written to {:?}", output);
+            if json {
+                print_json(&serde_json::json!({ "output": output }))?;
+            }
+        }
+
+        Commands::Status { path, output } => {
+            let output = config.output_dir(output.as_deref());
+            let installs = install::detect(&output, &path)?;
+            if json {
+                print_json(&installs)?;
+            } else {
+                install::print_report(&installs);
+            }
+        }
 
-            info!("Downloading to {}...", output_path);
-            api::download_build(build, &output_path).await?;
+        Commands::Apply {
+            path,
+            output,
+            restore,
+        } => {
+            let output = config.output_dir(output.as_deref());
+            let extra: Vec<_> = path.iter().map(paths::expand).collect();
+            let install = install::detect(&output, &extra)?
+                .into_iter()
+                .next()
+                .context(tr!(
+                    "No Yandex Music installation found; pass --path <DIR>",
+                    "Установленная Яндекс Музыка не найдена; укажите --path <DIR>"
+                ))?;
+            let result = if restore {
+                install::restore(&install)
+            } else {
+                install::apply(&install)
+            };
+            let report = match result {
+                Err(e) if elevate::is_access_denied(&e) && !elevate::is_elevated() => {
+                    warn!("{:#}", e);
+                    elevate::relaunch()?;
+                    if json {
+                        print_json(&serde_json::json!({ "elevated": true }))?;
+                    }
+                    return Ok(());
+                }
+                result => result?,
+            };
 
-            info!("Download complete: {}", output_path);
+            if json {
+                print_json(&report)?;
+            } else if restore {
+                println!(
+                    "{}",
+                    tr!(
+                        "Stock app.asar restored in {:?}",
+                        "Исходный app.asar восстановлен в {:?}",
+                        report.app
+                    )
+                );
+            } else {
+                println!(
+                    "{}",
+                    tr!(
+                        "Yandex Music {} patched in place: {:?} (stock archive kept as {:?})",
+                        "Яндекс Музыка {} пропатчена на месте: {:?} (исходный архив сохранён как {:?})",
+                        report.version.as_deref().unwrap_or("?"),
+                        report.app,
+                        report.original
+                    )
+                );
+            }
+        }
+
+        Commands::Versions { output } => {
+            let output = config.output_dir(output.as_deref());
+            let builds = versions::scan(&output)?;
+            if json {
+                print_json(&builds)?;
+            } else {
+                versions::print_table(&output, &builds);
+            }
         }
 
-        Commands::Info => {
-            info!("Fetching latest stable build information...");
+        Commands::Selftest => {
+            let report = selftest::run()?;
+            if json {
+                print_json(&report)?;
+            } else {
+                selftest::print_report(&report);
+            }
 
-            let builds = api::get_stable_build().await?;
+            if !report.passed() {
+                anyhow::bail!(tr!(
+                    "Selftest failed: the patches no longer fit the {} fixtures",
+                    "Самопроверка не пройдена: патчи не подходят к образцам {}",
+                    selftest::FIXTURE_VERSION
+                ));
+            }
+            if !json {
+                println!(
+                    "{}",
+                    tr!(
+                        "\nSelftest passed (profile {})",
+                        "\nСамопроверка пройдена (профиль {})",
+                        report.profile
+                    )
+                );
+            }
+        }
+
+        Commands::Delta {
+            version,
+            output,
+            file,
+        } => {
+            let output = config.output_dir(output.as_deref());
+            let version = match version {
+                Some(version) => version,
+                None => newest_version(&output)?,
+            };
+            let build_dir = patcher::build_dir(&output, &version, false);
+            let file = file.unwrap_or_else(|| {
+                build_dir.join(format!("{}.{}", version, delta::DELTA_EXTENSION))
+            });
+            let index = delta::create(&build_dir, &file)?;
+
+            if json {
+                print_json(&serde_json::json!({
+                    "version": version,
+                    "path": file,
+                    "size": std::fs::metadata(&file)?.len(),
+                    "entries": index.entries,
+                }))?;
+            } else {
+                println!(
+                    "{}",
+                    tr!(
+                        "Delta of Yandex Music {} written to {:?}: {} patched, {} added, {} removed",
+                        "Дельта Яндекс Музыки {} записана в {:?}: изменено {}, добавлено {}, удалено {}",
+                        version,
+                        file,
+                        index.count(delta::Op::Patch),
+                        index.count(delta::Op::Add),
+                        index.count(delta::Op::Remove)
+                    )
+                );
+            }
+        }
+
+        Commands::ApplyDelta {
+            delta: delta_file,
+            source,
+            output,
+        } => {
+            let output = config.output_dir(output.as_deref());
+            paths::ensure_writable(&output)?;
+            let (index, build_dir) = delta::apply(&delta_file, &source, &output)?;
+
+            if json {
+                print_json(&serde_json::json!({
+                    "version": index.app_version,
+                    "output": build_dir,
+                }))?;
+            } else {
+                println!(
+                    "{}",
+                    tr!(
+                        "Yandex Music {} patched from the delta into {:?}",
+                        "Яндекс Музыка {} пропатчена из дельты в {:?}",
+                        index.app_version,
+                        build_dir.join("mod")
+                    )
+                );
+            }
+        }
+
+        Commands::PatchAsar {
+            input,
+            rules,
+            inject,
+            output,
+            asar_unpack,
+        } => {
+            let input = paths::expand(&input);
+            let output = output
+                .map(|path| paths::expand(&path))
+                .unwrap_or_else(|| generic::default_output(&input));
+            let profile = match rules {
+                Some(path) => {
+                    let mut profile = generic::GenericProfile::load(&paths::expand(&path))?;
+                    profile.inject.extend(inject);
+                    Some(profile)
+                }
+                None => None,
+            };
+            let asar_options = config
+                .asar_options(true, None, asar_unpack)
+                .unwrap_or_default();
+            let report = generic::patch_asar(
+                &input,
+                &output,
+                profile.as_ref(),
+                &config.patch_options()?,
+                &asar_options,
+            )?;
+
+            if json {
+                print_json(&report)?;
+            } else {
+                let matched = report.rules.iter().filter(|r| r.matches > 0).count();
+                let injected = report.html.iter().filter(|h| h.injected).count();
+                println!(
+                    "{}",
+                    tr!(
+                        "{} {} patched with {}: {} of {} rules matched, {} page(s) injected, written to {:?}",
+                        "{} {} пропатчен профилем {}: сработало правил {} из {}, страниц с инъекцией {}, записан в {:?}",
+                        report.app_name.as_deref().unwrap_or("app"),
+                        report.app_version.as_deref().unwrap_or("?"),
+                        report.profile,
+                        matched,
+                        report.rules.len(),
+                        injected,
+                        report.output
+                    )
+                );
+            }
+        }
+
+        Commands::Sideload {
+            from,
+            to,
+            asar_unpack,
+        } => {
+            let source = match from {
+                Some(path) => paths::expand(&path),
+                None => store::packages()
+                    .iter()
+                    .find_map(|package| package.app_root())
+                    .with_context(|| {
+                        tr!(
+                            "No Yandex Music from the Microsoft Store found; pass --from <DIR>",
+                            "Яндекс Музыка из Microsoft Store не найдена; укажите --from <DIR>"
+                        )
+                    })?,
+            };
+            let target = match to {
+                Some(path) => paths::expand(&path),
+                None => {
+                    store::default_target().context("LOCALAPPDATA is not set; pass --to <DIR>")?
+                }
+            };
+            let asar_options = config
+                .asar_options(true, None, asar_unpack)
+                .unwrap_or_default();
+            let report =
+                store::sideload(&source, &target, &config.patch_options()?, &asar_options)?;
+
+            if json {
+                print_json(&report)?;
+            } else {
+                let matched = report.patch.rules.iter().filter(|r| r.matches > 0).count();
+                println!(
+                    "{}",
+                    tr!(
+                        "Yandex Music {} copied to {:?} and patched: {} of {} rules matched",
+                        "Яндекс Музыка {} скопирована в {:?} и пропатчена: сработало правил {} из {}",
+                        report.patch.app_version.as_deref().unwrap_or("?"),
+                        report.target,
+                        matched,
+                        report.patch.rules.len()
+                    )
+                );
+                if let Some(exe) = &report.executable {
+                    println!("{}", tr!("Run {:?}", "Запускайте {:?}", exe));
+                }
+                println!(
+                    "{}",
+                    tr!(
+                        "The copy keeps its data apart from the Store app: sign in again. Rerun sideload after Store updates.",
+                        "Копия хранит данные отдельно от приложения из Store: войдите в аккаунт заново. После обновлений из Store запустите sideload снова."
+                    )
+                );
+            }
+        }
+
+        Commands::CompareVersions {
+            old,
+            new,
+            output,
+            rules_only,
+        } => {
+            let output = config.output_dir(output.as_deref());
+            paths::ensure_writable(&output)?;
+            let options = config.patch_options()?;
+            let old_sources = compare::sources(&old, &output, &options).await?;
+            let new_sources = compare::sources(&new, &output, &options).await?;
+            let comparison = compare::compare(
+                &old,
+                &new,
+                &old_sources,
+                &new_sources,
+                options.profile.as_deref(),
+            )?;
+            if json {
+                print_json(&comparison)?;
+            } else {
+                compare::print_report(&comparison, rules_only);
+            }
+        }
+
+        Commands::CompatCheck { version, output } => {
+            let output = config.output_dir(output.as_deref());
+            paths::ensure_writable(&output)?;
+            let build = compat::find_build(version.as_deref()).await?;
+            let report = compat::check(&build, &output, &config.patch_options()?).await?;
+            if json {
+                print_json(&report)?;
+            } else {
+                compat::print_report(&report);
+            }
+            if !report.is_compatible() {
+                anyhow::bail!(tr!(
+                    "{} patch anchor(s) and rule(s) no longer match Yandex Music {}",
+                    "С Яндекс Музыкой {1} больше не совпадает якорей и правил: {0}",
+                    report.broken.len(),
+                    report.version
+                ));
+            }
+        }
+
+        Commands::Verify { version, output } => {
+            let output = config.output_dir(output.as_deref());
+            let version = match version {
+                Some(version) => version,
+                None => newest_version(&output)?,
+            };
+            let build_dir = patcher::build_dir(&output, &version, false);
+            let integrity = manifest::verify(&build_dir)?;
+
+            if json {
+                print_json(&integrity)?;
+            } else {
+                for (marker, paths) in [
+                    ('M', &integrity.modified),
+                    ('D', &integrity.missing),
+                    ('A', &integrity.added),
+                ] {
+                    for path in paths {
+                        println!("{} {}", marker, path);
+                    }
+                }
+                if integrity.is_intact() {
+                    println!(
+                        "{}",
+                        tr!(
+                            "Yandex Music {} is intact",
+                            "Яндекс Музыка {} не повреждена",
+                            version
+                        )
+                    );
+                }
+            }
+
+            if !integrity.is_intact() {
+                anyhow::bail!(tr!(
+                    "Yandex Music {} differs from its manifest: {} modified, {} missing, {} added",
+                    "Яндекс Музыка {} отличается от манифеста: изменено {}, отсутствует {}, добавлено {}",
+                    version,
+                    integrity.modified.len(),
+                    integrity.missing.len(),
+                    integrity.added.len()
+                ));
+            }
+        }
+
+        Commands::ImportSettings {
+            from,
+            to,
+            overwrite,
+        } => {
+            let report = migrate::import(from.as_deref(), to.as_deref(), overwrite)?;
+            if json {
+                print_json(&report)?;
+            } else {
+                println!(
+                    "{}",
+                    tr!(
+                        "Imported {} setting(s) from {:?} into {:?}",
+                        "Импортировано настроек из {1:?} в {2:?}: {0}",
+                        report.imported.len(),
+                        report.from,
+                        report.to
+                    )
+                );
+                if !report.skipped.is_empty() {
+                    println!(
+                        "{}",
+                        tr!(
+                            "Kept (already set, use --overwrite): {}",
+                            "Оставлены (уже заданы, см. --overwrite): {}",
+                            report.skipped.join(", ")
+                        )
+                    );
+                }
+            }
+        }
+        Commands::Settings { action } => {
+            let data_dir = match &action {
+                SettingsAction::Export { data_dir, .. }
+                | SettingsAction::Import { data_dir, .. } => data_dir.clone(),
+            };
+            let data_dir = match data_dir {
+                Some(dir) => dir,
+                None => migrate::app_data_dir().context(tr!(
+                    "Cannot locate the app data folder; pass --data-dir",
+                    "Не удалось найти папку данных приложения; укажите --data-dir"
+                ))?,
+            };
+            let (report, summary) = match action {
+                SettingsAction::Export { output, .. } => {
+                    let report = backup::export(&data_dir, &output)?;
+                    let summary = tr!(
+                        "Exported {} file(s) from {:?} to {:?}",
+                        "Экспортировано файлов из {1:?} в {2:?}: {0}",
+                        report.files.len(),
+                        report.data_dir,
+                        report.archive
+                    );
+                    (report, summary)
+                }
+                SettingsAction::Import {
+                    archive, overwrite, ..
+                } => {
+                    let report = backup::import(&archive, &data_dir, overwrite)?;
+                    let summary = tr!(
+                        "Imported {} file(s) from {:?} into {:?}",
+                        "Импортировано файлов из {1:?} в {2:?}: {0}",
+                        report.files.len(),
+                        report.archive,
+                        report.data_dir
+                    );
+                    (report, summary)
+                }
+            };
+            if json {
+                print_json(&report)?;
+            } else {
+                println!("{}", summary);
+                if !report.skipped.is_empty() {
+                    println!(
+                        "{}",
+                        tr!(
+                            "Kept (already exist, use --overwrite): {}",
+                            "Оставлены (уже существуют, см. --overwrite): {}",
+                            report.skipped.join(", ")
+                        )
+                    );
+                }
+            }
+        }
+        Commands::Diff {
+            version,
+            output,
+            unified,
+        } => {
+            let output = config.output_dir(output.as_deref());
+            let version = match version {
+                Some(version) => version,
+                None => newest_version(&output)?,
+            };
+            let build_dir = patcher::build_dir(&output, &version, false);
+            let (source_dir, modded_dir) = (build_dir.join("src"), build_dir.join("mod"));
+            if !source_dir.is_dir() || !modded_dir.is_dir() {
+                anyhow::bail!(tr!(
+                    "{:?} has no src/ and mod/ trees to compare",
+                    "В {:?} нет деревьев src/ и mod/ для сравнения",
+                    build_dir
+                ));
+            }
+
+            let changes = patcher::compare_trees(&source_dir, &modded_dir, unified)?;
+            if json {
+                print_json(&changes)?;
+            } else {
+                for change in &changes {
+                    let marker = match change.kind {
+                        patcher::ChangeKind::Added => 'A',
+                        patcher::ChangeKind::Removed => 'D',
+                        patcher::ChangeKind::Modified => 'M',
+                    };
+                    println!("{} {}", marker, change.path);
+                }
+                for diff in changes.iter().filter_map(|c| c.diff.as_deref()) {
+                    print!("\n{}", diff);
+                }
+                println!(
+                    "{}",
+                    tr!(
+                        "\n{} file(s) changed in Yandex Music {}",
+                        "\nИзменено файлов в Яндекс Музыке {1}: {0}",
+                        changes.len(),
+                        version
+                    )
+                );
+            }
+        }
+
+        Commands::SelfUpdate { check, force } => {
+            let status = self_update::self_update(check, force).await?;
+            if json {
+                print_json(&status)?;
+            } else if status.updated {
+                println!("{}", tr!("Updated to {}", "Обновлено до {}", status.latest));
+            } else if status.current.as_deref() == Some(status.latest.as_str()) {
+                println!(
+                    "{}",
+                    tr!(
+                        "Already up to date ({})",
+                        "Установлена последняя версия ({})",
+                        status.latest
+                    )
+                );
+            } else {
+                let current = match &status.current {
+                    Some(tag) => tag.clone(),
+                    None => tr!("local build", "локальная сборка"),
+                };
+                println!(
+                    "{}",
+                    tr!(
+                        "Update available: {} (current: {})",
+                        "Доступно обновление: {} (текущая: {})",
+                        status.latest,
+                        current
+                    )
+                );
+            }
+        }
+
+        Commands::Doctor { output } => {
+            let output = config.output_dir(output.as_deref());
+            let checks = doctor::run_checks(&output, &channel).await;
+            if json {
+                print_json(&checks)?;
+            } else {
+                doctor::print_report(&checks);
+            }
+
+            if checks.iter().any(|c| c.status == doctor::Status::Fail) {
+                anyhow::bail!(tr!("Some checks failed", "Некоторые проверки не пройдены"));
+            }
+        }
+
+        Commands::VerifyDownload { path } => {
+            info!("Fetching latest {} build information...", channel);
+            let builds = api::get_latest_build(&channel).await?;
+            let result = api::verify_file(&path, &builds)?;
+
+            if json {
+                print_json(&result)?;
+            } else {
+                let status = |ok: bool| if ok { "OK  " } else { "FAIL" };
+                println!(
+                    "{}",
+                    tr!(
+                        "\nVerifying {:?} against {} ({})",
+                        "\nПроверка {:?} по {} ({})",
+                        path,
+                        result.file,
+                        result.version
+                    )
+                );
+                println!(
+                    "[{}] {}",
+                    status(result.size_ok),
+                    tr!(
+                        "Size: {} bytes (expected {})",
+                        "Размер: {} байт (ожидалось {})",
+                        result.actual_size,
+                        result.expected_size
+                    )
+                );
+                println!("[{}] SHA-512", status(result.hash_ok));
+            }
+
+            if !result.is_valid() {
+                anyhow::bail!(tr!(
+                    "{:?} does not match the published Yandex Music {} build",
+                    "{:?} не совпадает с опубликованной сборкой Яндекс Музыки {}",
+                    path,
+                    result.version
+                ));
+            }
+        }
+
+        Commands::Info { all_channels: true } => {
+            info!("Fetching the latest builds of all channels...");
+            let channels = api::get_all_channels().await;
+            if let Some(e) = channels
+                .iter()
+                .map(|(_, result)| result.as_ref().err())
+                .collect::<Option<Vec<_>>>()
+                .and_then(|errors| errors.into_iter().next())
+            {
+                anyhow::bail!(tr!(
+                    "No channel could be fetched: {:#}",
+                    "Не удалось получить ни один канал: {:#}",
+                    e
+                ));
+            }
+            if json {
+                let value: Vec<_> = channels
+                    .iter()
+                    .map(|(channel, result)| match result {
+                        Ok(builds) => serde_json::json!({ "channel": channel, "builds": builds }),
+                        Err(e) => {
+                            serde_json::json!({ "channel": channel, "error": format!("{:#}", e) })
+                        }
+                    })
+                    .collect();
+                return print_json(&value);
+            }
+            print_channels(&channels);
+        }
+
+        Commands::Info {
+            all_channels: false,
+        } => {
+            info!("Fetching latest {} build information...", channel);
+
+            let builds = api::get_latest_build(&channel).await?;
+
+            if json {
+                return print_json(&builds);
+            }
 
             if builds.is_empty() {
-                println!("No builds found");
+                println!("{}", tr!("No builds found", "Сборки не найдены"));
                 return Ok(());
             }
 
-            println!("\nAvailable builds:");
+            println!("{}", tr!("\nAvailable builds:", "\nДоступные сборки:"));
             println!("{}", "=".repeat(60));
 
             for build in builds {
-                println!("Version:      {}", build.version);
-                println!("File:         {}", build.path);
-                println!("Size:         {} bytes", build.size);
+                println!(
+                    "{}",
+                    tr!("Version:      {}", "Версия:       {}", build.version)
+                );
+                println!(
+                    "{}",
+                    tr!("File:         {}", "Файл:         {}", build.path)
+                );
+                println!(
+                    "{}",
+                    tr!(
+                        "Size:         {} bytes",
+                        "Размер:       {} байт",
+                        build.size
+                    )
+                );
                 println!("SHA-512:      {}...", &build.hash[..32]);
                 if let Some(date) = &build.release_date {
-                    println!("Release Date: {}", date);
+                    println!("{}", tr!("Release Date: {}", "Дата выпуска: {}", date));
                 }
                 println!("{}", "-".repeat(60));
             }
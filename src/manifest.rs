@@ -0,0 +1,210 @@
+//! Manifest module - checksums of a patched build for later integrity checks
+//!
+//! Every successful run writes `mod-manifest.json` into the build directory: the
+//! SHA-256 of each file, the patcher (and so mod) version, the hash of the patch
+//! rules and the SHA-512 of the installer the build came from. `verify` compares
+//! a build against it to find tampered, corrupted, missing or extra files.
+
+use crate::api::AppBuild;
+use crate::rules::PatchProfile;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::path::Path;
+use tracing::info;
+use walkdir::WalkDir;
+
+/// Name of the manifest in the build directory
+pub const MOD_MANIFEST_FILE: &str = "mod-manifest.json";
+
+/// Version of the manifest format
+pub const MANIFEST_FORMAT: u32 = 1;
+
+/// Checksums of a patched build
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModManifest {
+    pub format: u32,
+    /// Version of the patcher, which carries the mod code
+    pub patcher_version: String,
+    pub app_version: String,
+    pub profile: String,
+    /// SHA-256 of the profile and its rules, as in patch plans
+    pub rules_hash: String,
+    /// Published SHA-512 of the installer the build was made from
+    pub source_hash: String,
+    /// SHA-256 of every file, by path relative to the build directory
+    pub files: BTreeMap<String, String>,
+}
+
+/// Differences between a build and its manifest
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Integrity {
+    pub version: String,
+    /// Files whose content changed
+    pub modified: Vec<String>,
+    pub missing: Vec<String>,
+    /// Files not listed in the manifest
+    pub added: Vec<String>,
+}
+
+impl Integrity {
+    pub fn is_intact(&self) -> bool {
+        self.modified.is_empty() && self.missing.is_empty() && self.added.is_empty()
+    }
+}
+
+/// Hash the files of `build_dir` and write its manifest
+pub fn write(build_dir: &Path, build: &AppBuild, profile: &PatchProfile) -> Result<ModManifest> {
+    save(
+        build_dir,
+        ModManifest {
+            format: MANIFEST_FORMAT,
+            patcher_version: env!("CARGO_PKG_VERSION").to_string(),
+            app_version: build.version.clone(),
+            profile: profile.name.clone(),
+            rules_hash: crate::plan::rules_hash(profile, &build.version)?,
+            source_hash: build.hash.clone(),
+            files: hash_files(build_dir)?,
+        },
+    )
+}
+
+/// Rehash the files of `build_dir` after its mod files were regenerated,
+/// keeping the app version and source of the existing manifest
+pub fn refresh(build_dir: &Path, profile: &PatchProfile) -> Result<ModManifest> {
+    let previous = load(build_dir)?;
+    save(
+        build_dir,
+        ModManifest {
+            patcher_version: env!("CARGO_PKG_VERSION").to_string(),
+            profile: profile.name.clone(),
+            rules_hash: crate::plan::rules_hash(profile, &previous.app_version)?,
+            files: hash_files(build_dir)?,
+            ..previous
+        },
+    )
+}
+
+pub(crate) fn save(build_dir: &Path, manifest: ModManifest) -> Result<ModManifest> {
+    let path = build_dir.join(MOD_MANIFEST_FILE);
+    fs::write(&path, serde_json::to_string_pretty(&manifest)? + "\n")
+        .with_context(|| format!("Failed to write {:?}", path))?;
+    info!(
+        "Manifest of {} file(s) written to {:?}",
+        manifest.files.len(),
+        path
+    );
+    Ok(manifest)
+}
+
+pub fn load(build_dir: &Path) -> Result<ModManifest> {
+    let path = build_dir.join(MOD_MANIFEST_FILE);
+    let content =
+        fs::read_to_string(&path).with_context(|| format!("Failed to read {:?}", path))?;
+    let manifest: ModManifest =
+        serde_json::from_str(&content).with_context(|| format!("Failed to parse {:?}", path))?;
+    if manifest.format != MANIFEST_FORMAT {
+        anyhow::bail!(
+            "Unsupported manifest format {} (expected {})",
+            manifest.format,
+            MANIFEST_FORMAT
+        );
+    }
+    Ok(manifest)
+}
+
+/// Compare the files of `build_dir` with its manifest
+pub fn verify(build_dir: &Path) -> Result<Integrity> {
+    let manifest = load(build_dir)?;
+    let mut actual = hash_files(build_dir)?;
+
+    let mut integrity = Integrity {
+        version: manifest.app_version,
+        ..Default::default()
+    };
+    for (path, hash) in manifest.files {
+        match actual.remove(&path) {
+            Some(actual) if actual == hash => {}
+            Some(_) => integrity.modified.push(path),
+            None => integrity.missing.push(path),
+        }
+    }
+    integrity.added = actual.into_keys().collect();
+    Ok(integrity)
+}
+
+/// SHA-256 of each file below `build_dir` except the manifest and the run journal
+pub(crate) fn hash_files(build_dir: &Path) -> Result<BTreeMap<String, String>> {
+    let mut files = BTreeMap::new();
+    for entry in WalkDir::new(build_dir) {
+        let entry = entry?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let relative = entry
+            .path()
+            .strip_prefix(build_dir)?
+            .to_string_lossy()
+            .replace('\\', "/");
+        if relative == MOD_MANIFEST_FILE || relative == crate::journal::JOURNAL_FILE {
+            continue;
+        }
+        let mut hasher = Sha256::new();
+        std::io::copy(&mut File::open(entry.path())?, &mut hasher)
+            .with_context(|| format!("Failed to read {:?}", entry.path()))?;
+        files.insert(relative, hex::encode(hasher.finalize()));
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rules::ProfileSet;
+
+    #[test]
+    fn test_manifest_verify() {
+        let dir = tempfile::tempdir().unwrap();
+        let build_dir = dir.path().join("5.0.0");
+        fs::create_dir_all(build_dir.join("mod").join("app")).unwrap();
+        fs::write(build_dir.join("mod").join("package.json"), "{}").unwrap();
+        fs::write(
+            build_dir.join("mod").join("app").join("index.html"),
+            "<html>",
+        )
+        .unwrap();
+
+        let build = AppBuild {
+            path: "Yandex_Music_x64_5.0.0.exe".to_string(),
+            hash: "c2hhNTEy".to_string(),
+            size: 1024,
+            release_date: None,
+            update_probability: None,
+            version: "5.0.0".to_string(),
+            deprecated_versions: None,
+            channel: "stable".to_string(),
+        };
+        let profiles = ProfileSet::load().unwrap();
+        let profile = profiles.select(&build.version, None).unwrap();
+        let manifest = write(&build_dir, &build, profile).unwrap();
+        assert_eq!(manifest.files.len(), 2);
+        assert!(manifest.files.contains_key("mod/app/index.html"));
+        assert_eq!(load(&build_dir).unwrap().source_hash, build.hash);
+        assert!(verify(&build_dir).unwrap().is_intact());
+
+        fs::write(
+            build_dir.join("mod").join("app").join("index.html"),
+            "<evil>",
+        )
+        .unwrap();
+        fs::remove_file(build_dir.join("mod").join("package.json")).unwrap();
+        fs::write(build_dir.join("mod").join("extra.js"), "").unwrap();
+        let integrity = verify(&build_dir).unwrap();
+        assert!(!integrity.is_intact());
+        assert_eq!(integrity.modified, ["mod/app/index.html"]);
+        assert_eq!(integrity.missing, ["mod/package.json"]);
+        assert_eq!(integrity.added, ["mod/extra.js"]);
+    }
+}
@@ -0,0 +1,226 @@
+//! Migrate module - settings of the original TypeScript mod
+//!
+//! Users coming from YandexMusicBetaMod have a settings file (download folder,
+//! window and proxy settings) in the data folder of their old build. The
+//! importer finds it, renames the keys to this mod's names and merges them
+//! into `mod_settings.json` of the patched app, keeping a backup of the file.
+
+use crate::patches;
+use anyhow::{Context, Result};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+/// Data folder of the patched app, named after `name` in its `package.json`
+pub const APP_DATA_NAME: &str = "YandexMusicMod";
+
+/// File the mod keeps its settings in
+pub const SETTINGS_FILE: &str = "mod_settings.json";
+
+/// Data folders of builds of the original mod
+pub(crate) const LEGACY_DATA_NAMES: &[&str] = &["YandexMusicBetaMod", "yandex-music-beta-mod"];
+
+/// Settings files of the original mod, newest layout first
+const LEGACY_SETTINGS_FILES: &[&str] = &["mod_settings.json", "settings.json"];
+
+/// Keys of the original mod and their names in this mod
+const KEY_MAP: &[(&str, &str)] = &[
+    ("downloadPath", "downloadFolderPath"),
+    ("downloadFolder", "downloadFolderPath"),
+    ("alwaysOnTop", "window/alwaysOnTop"),
+    ("systemToolbar", "devtools/systemToolbar"),
+    ("enableSystemToolbar", "devtools/systemToolbar"),
+    ("proxy", "network/proxy"),
+    ("theme", "mod/theme"),
+    ("language", "mod/language"),
+];
+
+/// Result of `import-settings`
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportReport {
+    pub from: PathBuf,
+    pub to: PathBuf,
+    /// Keys written to the settings, with this mod's names
+    pub imported: Vec<String>,
+    /// Keys left alone because the settings already had them
+    pub skipped: Vec<String>,
+    /// Copy of the settings before the import
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub backup: Option<PathBuf>,
+}
+
+/// Folder Electron apps keep their data folders in (`%APPDATA%`,
+/// `~/Library/Application Support`, `~/.config`)
+pub fn app_data_root() -> Option<PathBuf> {
+    directories::BaseDirs::new().map(|dirs| dirs.config_dir().to_path_buf())
+}
+
+/// Data folder of the patched app (`userData` of Electron)
+pub fn app_data_dir() -> Option<PathBuf> {
+    app_data_root().map(|root| root.join(APP_DATA_NAME))
+}
+
+/// Settings file of the active profile of the patched app
+pub fn default_target() -> Option<PathBuf> {
+    app_data_dir().map(|dir| profile_settings(&dir))
+}
+
+/// Settings file of the profile selected in `mod_profile.json` of `data_dir`
+fn profile_settings(data_dir: &Path) -> PathBuf {
+    let active = fs::read_to_string(data_dir.join("mod_profile.json"))
+        .ok()
+        .and_then(|content| serde_json::from_str::<Value>(&content).ok())
+        .and_then(|profile| profile["active"].as_str().map(str::to_string))
+        .filter(|name| {
+            name != patches::DEFAULT_SETTINGS_PROFILE && patches::is_valid_settings_profile(name)
+        });
+    match active {
+        Some(name) => data_dir.join(format!("mod_settings.{}.json", name)),
+        None => data_dir.join(SETTINGS_FILE),
+    }
+}
+
+/// The first settings file of the original mod below `root`
+pub fn find_legacy_settings(root: &Path) -> Option<PathBuf> {
+    LEGACY_DATA_NAMES
+        .iter()
+        .flat_map(|name| {
+            LEGACY_SETTINGS_FILES
+                .iter()
+                .map(move |file| root.join(name).join(file))
+        })
+        .find(|path| path.is_file())
+}
+
+/// `legacy` settings with the keys renamed for this mod; keys without a
+/// counterpart are kept as they are
+pub fn convert(legacy: &Map<String, Value>) -> Map<String, Value> {
+    let mut converted = Map::new();
+    for (key, value) in legacy {
+        if value.is_null() {
+            continue;
+        }
+        let key = KEY_MAP
+            .iter()
+            .find(|(old, _)| old == key)
+            .map_or(key.as_str(), |(_, new)| new);
+        converted.entry(key).or_insert_with(|| value.clone());
+    }
+    converted
+}
+
+/// Merge the settings of the original mod from `from` (found automatically if
+/// `None`) into `to` (the patched app's `mod_settings.json` if `None`).
+/// Settings `to` already has are only replaced with `overwrite`.
+pub fn import(from: Option<&Path>, to: Option<&Path>, overwrite: bool) -> Result<ImportReport> {
+    let from = match from {
+        Some(from) => from.to_path_buf(),
+        None => app_data_root()
+            .and_then(|root| find_legacy_settings(&root))
+            .context("No settings of the original mod found; pass --from")?,
+    };
+    let to = match to {
+        Some(to) => to.to_path_buf(),
+        None => default_target().context("Cannot locate the app data folder; pass --to")?,
+    };
+
+    let legacy = read_settings(&from)?.with_context(|| format!("{:?} does not exist", from))?;
+    let existing = read_settings(&to)?;
+    let mut settings = existing.clone().unwrap_or_default();
+
+    let (mut imported, mut skipped) = (Vec::new(), Vec::new());
+    for (key, value) in convert(&legacy) {
+        if settings.contains_key(&key) && !overwrite {
+            skipped.push(key);
+        } else {
+            settings.insert(key.clone(), value);
+            imported.push(key);
+        }
+    }
+
+    let backup = match existing {
+        Some(_) if !imported.is_empty() => {
+            let backup = to.with_extension("json.bak");
+            fs::copy(&to, &backup).with_context(|| format!("Failed to back up {:?}", to))?;
+            Some(backup)
+        }
+        _ => None,
+    };
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&to, serde_json::to_string_pretty(&settings)?)
+        .with_context(|| format!("Failed to write {:?}", to))?;
+    info!(
+        "Imported {} setting(s) from {:?} into {:?}",
+        imported.len(),
+        from,
+        to
+    );
+
+    Ok(ImportReport {
+        from,
+        to,
+        imported,
+        skipped,
+        backup,
+    })
+}
+
+fn read_settings(path: &Path) -> Result<Option<Map<String, Value>>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(path).with_context(|| format!("Failed to read {:?}", path))?;
+    serde_json::from_str(&content)
+        .map(Some)
+        .with_context(|| format!("{:?} is not a JSON object", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_import_settings() {
+        let dir = tempfile::tempdir().unwrap();
+        let legacy_dir = dir.path().join("YandexMusicBetaMod");
+        fs::create_dir_all(&legacy_dir).unwrap();
+        fs::write(
+            legacy_dir.join("settings.json"),
+            r#"{"downloadPath": "D:/Music", "alwaysOnTop": true, "proxy": null, "custom": 1}"#,
+        )
+        .unwrap();
+        let from = find_legacy_settings(dir.path()).unwrap();
+        assert_eq!(from, legacy_dir.join("settings.json"));
+
+        let data_dir = dir.path().join(APP_DATA_NAME);
+        fs::create_dir_all(&data_dir).unwrap();
+        let to = profile_settings(&data_dir);
+        assert_eq!(to, data_dir.join(SETTINGS_FILE));
+        fs::write(&to, r#"{"window/alwaysOnTop": false}"#).unwrap();
+
+        let report = import(Some(&from), Some(&to), false).unwrap();
+        assert_eq!(report.imported, ["custom", "downloadFolderPath"]);
+        assert_eq!(report.skipped, ["window/alwaysOnTop"]);
+        assert!(report.backup.unwrap().exists());
+        let settings: Value = serde_json::from_str(&fs::read_to_string(&to).unwrap()).unwrap();
+        assert_eq!(
+            settings,
+            json!({"window/alwaysOnTop": false, "downloadFolderPath": "D:/Music", "custom": 1})
+        );
+
+        import(Some(&from), Some(&to), true).unwrap();
+        let settings: Value = serde_json::from_str(&fs::read_to_string(&to).unwrap()).unwrap();
+        assert_eq!(settings["window/alwaysOnTop"], true);
+
+        fs::write(data_dir.join("mod_profile.json"), r#"{"active": "work"}"#).unwrap();
+        assert_eq!(
+            profile_settings(&data_dir),
+            data_dir.join("mod_settings.work.json")
+        );
+    }
+}
@@ -0,0 +1,241 @@
+//! MSIX module - wraps a packaged build into an MSIX
+//!
+//! MSIX installs per user without admin rights and uninstalls cleanly, which
+//! keeps the patched app usable on machines where policy blocks raw executables.
+//! The package layout and `AppxManifest.xml` are written to `<build dir>/package/msix`;
+//! when `makeappx` from the Windows SDK is on `PATH` it is packed into a `.msix` too,
+//! and signed if a certificate is given.
+
+use crate::api::Arch;
+use crate::package::{AppLayout, PACKAGE_ID, PACKAGE_NAME, PUBLISHER, URL_SCHEME};
+use crate::paths;
+use crate::sign::SignOptions;
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tracing::{info, warn};
+
+/// Directory of the unpacked package inside the package directory
+pub const MSIX_DIR: &str = "msix";
+
+/// `Publisher` in the manifest unless the signing certificate has another subject
+pub const MSIX_PUBLISHER: &str = "CN=YandexMusicBetaModeFastLP";
+
+/// Logos required by the manifest, all rendered from the app icon
+const LOGOS: [&str; 3] = [
+    "StoreLogo.png",
+    "Square150x150Logo.png",
+    "Square44x44Logo.png",
+];
+
+/// Transparent 1x1 PNG used when the build has no icon
+const BLANK_PNG: [u8; 67] = [
+    0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x48, 0x44, 0x52,
+    0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x08, 0x06, 0x00, 0x00, 0x00, 0x1f, 0x15, 0xc4,
+    0x89, 0x00, 0x00, 0x00, 0x0a, 0x49, 0x44, 0x41, 0x54, 0x78, 0x9c, 0x63, 0x00, 0x01, 0x00, 0x00,
+    0x05, 0x00, 0x01, 0x0d, 0x0a, 0x2d, 0xb4, 0x00, 0x00, 0x00, 0x00, 0x49, 0x45, 0x4e, 0x44, 0xae,
+    0x42, 0x60, 0x82,
+];
+
+/// Write the MSIX layout of `layout` into `package_dir` and pack it if possible.
+/// Returns the written manifest, and the `.msix` when it was packed.
+pub(crate) fn package(
+    layout: &AppLayout,
+    build_dir: &Path,
+    version: &str,
+    package_dir: &Path,
+    sign: Option<&SignOptions>,
+) -> Result<Vec<PathBuf>> {
+    if layout.is_script() {
+        anyhow::bail!("MSIX needs the Electron runtime from the installer, none was kept");
+    }
+
+    let msix_dir = package_dir.join(MSIX_DIR);
+    if msix_dir.exists() {
+        paths::remove_dir_all(&msix_dir)?;
+    }
+    layout.copy_to(&msix_dir)?;
+
+    let logo = fs::read(build_dir.join("icon.ico"))
+        .ok()
+        .and_then(|ico| largest_png(&ico))
+        .map_or_else(|| BLANK_PNG.to_vec(), |(_, png)| png);
+    let assets = msix_dir.join("Assets");
+    fs::create_dir_all(&assets)?;
+    for name in LOGOS {
+        fs::write(assets.join(name), &logo)?;
+    }
+
+    let manifest_path = msix_dir.join("AppxManifest.xml");
+    let publisher = sign
+        .and_then(|sign| sign.publisher.as_deref())
+        .unwrap_or(MSIX_PUBLISHER);
+    fs::write(
+        &manifest_path,
+        manifest(version, &layout.launcher, layout.arch, publisher)?,
+    )
+    .with_context(|| format!("Failed to write {:?}", manifest_path))?;
+    info!("Wrote MSIX layout {:?}", msix_dir);
+
+    let mut written = vec![manifest_path];
+    let msix_path = package_dir.join(format!("{}-{}.msix", PACKAGE_ID, version));
+    match Command::new("makeappx")
+        .args(["pack", "/o", "/d"])
+        .arg(&msix_dir)
+        .arg("/p")
+        .arg(&msix_path)
+        .output()
+    {
+        Ok(output) if output.status.success() => {
+            info!("Packed {:?}", msix_path);
+            if let Some(sign) = sign {
+                sign.sign(&msix_path)?;
+            }
+            written.push(msix_path);
+        }
+        Ok(output) => anyhow::bail!(
+            "makeappx failed: {}",
+            String::from_utf8_lossy(&output.stdout).trim()
+        ),
+        Err(_) => warn!(
+            "makeappx not found; pack {:?} with the Windows SDK or install it unpacked with \
+             Add-AppxPackage -Register",
+            msix_dir
+        ),
+    }
+    Ok(written)
+}
+
+/// MSIX versions are four numeric parts; `5.12.3-beta` becomes `5.12.3.0`
+fn msix_version(version: &str) -> Result<String> {
+    let version = semver::Version::parse(version)
+        .with_context(|| format!("Invalid version for MSIX: {}", version))?;
+    Ok(format!(
+        "{}.{}.{}.0",
+        version.major, version.minor, version.patch
+    ))
+}
+
+fn manifest(version: &str, executable: &str, arch: Arch, publisher: &str) -> Result<String> {
+    let version = msix_version(version)?;
+    let arch = arch.as_str();
+    Ok(format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<Package xmlns="http://schemas.microsoft.com/appx/manifest/foundation/windows10"
+         xmlns:uap="http://schemas.microsoft.com/appx/manifest/uap/windows10"
+         xmlns:rescap="http://schemas.microsoft.com/appx/manifest/foundation/windows10/restrictedcapabilities"
+         IgnorableNamespaces="uap rescap">
+  <Identity Name="{PUBLISHER}.YandexMusicMod"
+            Publisher="{publisher}"
+            Version="{version}"
+            ProcessorArchitecture="{arch}" />
+  <Properties>
+    <DisplayName>{PACKAGE_NAME}</DisplayName>
+    <PublisherDisplayName>{PUBLISHER}</PublisherDisplayName>
+    <Logo>Assets\StoreLogo.png</Logo>
+  </Properties>
+  <Dependencies>
+    <TargetDeviceFamily Name="Windows.Desktop" MinVersion="10.0.17763.0" MaxVersionTested="10.0.22621.0" />
+  </Dependencies>
+  <Resources>
+    <Resource Language="en-us" />
+  </Resources>
+  <Applications>
+    <Application Id="YandexMusicMod" Executable="{executable}" EntryPoint="Windows.FullTrustApplication">
+      <uap:VisualElements DisplayName="{PACKAGE_NAME}"
+                          Description="Patched Yandex Music desktop app"
+                          BackgroundColor="transparent"
+                          Square150x150Logo="Assets\Square150x150Logo.png"
+                          Square44x44Logo="Assets\Square44x44Logo.png" />
+      <Extensions>
+        <uap:Extension Category="windows.protocol">
+          <uap:Protocol Name="{URL_SCHEME}">
+            <uap:DisplayName>{PACKAGE_NAME}</uap:DisplayName>
+          </uap:Protocol>
+        </uap:Extension>
+      </Extensions>
+    </Application>
+  </Applications>
+  <Capabilities>
+    <Capability Name="internetClient" />
+    <rescap:Capability Name="runFullTrust" />
+  </Capabilities>
+</Package>
+"#
+    ))
+}
+
+/// The largest PNG-encoded image of an ICO file with its width, if it has any
+pub(crate) fn largest_png(ico: &[u8]) -> Option<(u32, Vec<u8>)> {
+    let u16_at = |at: usize| Some(u16::from_le_bytes(ico.get(at..at + 2)?.try_into().ok()?));
+    let u32_at = |at: usize| Some(u32::from_le_bytes(ico.get(at..at + 4)?.try_into().ok()?));
+
+    if u16_at(0)? != 0 || u16_at(2)? != 1 {
+        return None;
+    }
+    (0..u16_at(4)? as usize)
+        .filter_map(|i| {
+            let entry = 6 + i * 16;
+            // A width byte of 0 means 256 pixels
+            let width = match *ico.get(entry)? {
+                0 => 256,
+                w => w as u32,
+            };
+            let size = u32_at(entry + 8)? as usize;
+            let offset = u32_at(entry + 12)? as usize;
+            let image = ico.get(offset..offset.checked_add(size)?)?;
+            image.starts_with(b"\x89PNG").then_some((width, image))
+        })
+        .max_by_key(|(width, _)| *width)
+        .map(|(width, image)| (width, image.to_vec()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_msix_layout() {
+        assert_eq!(msix_version("5.12.3-beta.1").unwrap(), "5.12.3.0");
+        assert!(msix_version("latest").is_err());
+
+        // ICO with a 16px BMP entry and a 256px PNG entry
+        let png = BLANK_PNG.to_vec();
+        let mut ico = vec![0, 0, 1, 0, 2, 0];
+        for (width, size, offset) in [(16u8, 4u32, 38u32), (0, png.len() as u32, 42)] {
+            ico.extend([width, width, 0, 0, 1, 0, 32, 0]);
+            ico.extend(size.to_le_bytes());
+            ico.extend(offset.to_le_bytes());
+        }
+        ico.extend([0u8; 4]);
+        ico.extend(&png);
+        assert_eq!(largest_png(&ico), Some((256, png)));
+        assert_eq!(largest_png(b"not an icon"), None);
+
+        let dir = tempfile::tempdir().unwrap();
+        let build_dir = dir.path();
+        fs::create_dir_all(build_dir.join("runtime").join("resources")).unwrap();
+        // PE header of an arm64 executable
+        let mut exe = vec![0u8; 64];
+        exe[..2].copy_from_slice(b"MZ");
+        exe[0x3C] = 64;
+        exe.extend(b"PE\0\0\x64\xAA");
+        fs::write(build_dir.join("runtime").join("Яндекс Музыка.exe"), exe).unwrap();
+        fs::create_dir_all(build_dir.join("mod")).unwrap();
+        fs::write(build_dir.join("mod").join("package.json"), "{}").unwrap();
+
+        let layout = AppLayout::of(build_dir).unwrap();
+        let package_dir = build_dir.join("package");
+        let files = package(&layout, build_dir, "5.0.0", &package_dir, None).unwrap();
+
+        let msix_dir = package_dir.join(MSIX_DIR);
+        assert!(msix_dir.join("resources/app/package.json").is_file());
+        assert!(msix_dir.join("Assets/Square44x44Logo.png").is_file());
+        let manifest = fs::read_to_string(&files[0]).unwrap();
+        assert!(manifest.contains(r#"Executable="Яндекс Музыка.exe""#));
+        assert!(manifest.contains(r#"Version="5.0.0.0""#));
+        assert!(manifest.contains(r#"ProcessorArchitecture="arm64""#));
+        assert!(manifest.contains(r#"<uap:Protocol Name="yandexmusic">"#));
+    }
+}
@@ -0,0 +1,88 @@
+//! Notify module - reports finished patch runs to a webhook
+//!
+//! With `--notify-url` (or `notify-url` in `[patch]`) a JSON summary is POSTed
+//! after every run, e.g. to keep a patched build on a network share up to date.
+
+use crate::api;
+use crate::error;
+use serde::Serialize;
+use std::path::PathBuf;
+use std::time::Instant;
+use tracing::{info, warn};
+
+/// Outcome of a patch run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    Success,
+    Failure,
+}
+
+/// Payload POSTed to the webhook
+#[derive(Debug, Clone, Serialize)]
+pub struct Notification {
+    pub version: String,
+    pub result: Outcome,
+    /// Run time in seconds
+    pub duration: f64,
+    /// Build directory of the run
+    pub output: PathBuf,
+    pub dry_run: bool,
+    pub error: Option<String>,
+    /// [`error::code`] of a failed run
+    pub code: Option<u8>,
+}
+
+impl Notification {
+    /// Summarize a run of `version` into `output` that started at `started`
+    pub fn new<T>(
+        version: &str,
+        output: PathBuf,
+        dry_run: bool,
+        started: Instant,
+        result: &anyhow::Result<T>,
+    ) -> Self {
+        Self {
+            version: version.to_string(),
+            result: match result {
+                Ok(_) => Outcome::Success,
+                Err(_) => Outcome::Failure,
+            },
+            duration: started.elapsed().as_secs_f64(),
+            output,
+            dry_run,
+            error: result.as_ref().err().map(|e| format!("{:#}", e)),
+            code: result.as_ref().err().map(error::code),
+        }
+    }
+}
+
+/// POST `notification` to `url`; failures are logged, never returned
+pub async fn send(url: &str, notification: &Notification) {
+    match api::post_json(url, notification).await {
+        Ok(()) => info!("Notified {}", url),
+        Err(e) => warn!("Failed to notify {}: {:#}", url, e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_notification_payload() {
+        let started = Instant::now();
+        let ok = Notification::new("5.0.0", "out/5.0.0".into(), false, started, &Ok(()));
+        let value = serde_json::to_value(&ok).unwrap();
+        assert_eq!(value["result"], "success");
+        assert_eq!(value["version"], "5.0.0");
+        assert!(value["error"].is_null());
+
+        let failed: anyhow::Result<()> = Err(anyhow::anyhow!("disk full"));
+        let failed = Notification::new("5.0.0", "out/5.0.0".into(), false, started, &failed);
+        let value = serde_json::to_value(&failed).unwrap();
+        assert_eq!(value["result"], "failure");
+        assert_eq!(value["error"], "disk full");
+        assert_eq!(value["code"], error::GENERIC_CODE);
+    }
+}
@@ -0,0 +1,670 @@
+//! Package module - packages a patched build for redistribution
+//!
+//! When packaging is requested the Electron runtime from the installer is kept
+//! in `<build dir>/runtime`, and the mod is placed into it as `resources/app`.
+//! That app is archived as a portable zip, and manifests for winget, Scoop and
+//! Chocolatey are generated around it so the build can be installed on other
+//! machines with a package manager; [`msix`](crate::msix) wraps the same app into
+//! an MSIX, and [`flatpak`](crate::flatpak) pairs the mod with a Linux Electron.
+//! With Node.js, [`electron_builder`](crate::electron_builder) builds installers instead.
+//! Everything is written to `<build dir>/package`. With [`SignOptions`] the
+//! launcher and installers are signed with the user's certificate.
+
+use crate::api::Arch;
+use crate::paths;
+use crate::sign::SignOptions;
+use crate::{electron_builder, flatpak, msix};
+use anyhow::{Context, Result};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use tracing::info;
+use walkdir::WalkDir;
+
+/// Package identifier used in all manifests
+pub const PACKAGE_ID: &str = "yandex-music-mod";
+
+/// Human-readable package name
+pub const PACKAGE_NAME: &str = "Yandex Music Mod";
+
+/// Publisher shown by the package managers
+pub const PUBLISHER: &str = "YandexMusicBetaModeFastLP";
+
+const HOMEPAGE: &str = "https://github.com/Jhon-Crow/YandexMusicBetaModeFastLP";
+
+/// URL scheme of links that open the desktop app from the browser
+pub const URL_SCHEME: &str = "yandexmusic";
+
+/// Where the install scripts keep the handler `URL_SCHEME` had before
+const PROTOCOL_STATE_KEY: &str = r"HKCU:\Software\yandex-music-mod";
+
+/// Starts the mod with Electron from npx when no runtime was kept
+const LAUNCHER_SCRIPT: &str = "launch.cmd";
+
+/// Directory of the archive and manifests inside the build directory
+pub const PACKAGE_DIR: &str = "package";
+
+/// Directory of the Electron runtime kept from the installer
+pub const RUNTIME_DIR: &str = "runtime";
+
+/// Package formats selectable with `patch --package`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "kebab-case")]
+pub enum PackageFormat {
+    /// winget manifests (version, installer and locale)
+    Winget,
+    /// Scoop app manifest
+    Scoop,
+    /// Chocolatey package (nuspec and install scripts)
+    Choco,
+    /// MSIX package for per-user installs
+    Msix,
+    /// Flatpak manifest for Linux
+    Flatpak,
+    /// Installers built by `npx electron-builder` (needs Node.js)
+    ElectronBuilder,
+}
+
+impl PackageFormat {
+    /// Whether the format is a manifest for the portable archive
+    fn uses_archive(self) -> bool {
+        matches!(
+            self,
+            PackageFormat::Winget | PackageFormat::Scoop | PackageFormat::Choco
+        )
+    }
+
+    /// Whether the format ships the Windows runtime from the installer
+    pub fn needs_runtime(self) -> bool {
+        !matches!(
+            self,
+            PackageFormat::Flatpak | PackageFormat::ElectronBuilder
+        )
+    }
+}
+
+/// Files of the runnable app and how it is started
+pub(crate) struct AppLayout {
+    /// Source directories and their path inside the package
+    roots: Vec<(PathBuf, &'static str)>,
+    /// File that starts the app, relative to the package root
+    pub launcher: String,
+    /// Architecture of the runtime; the launcher script runs any Electron
+    pub arch: Arch,
+    /// Electron the launcher script pins, from the mod's `package.json`
+    pub electron_version: Option<String>,
+}
+
+impl AppLayout {
+    /// The runtime with the mod as `resources/app` if it was kept, else the mod
+    /// under `app/` with a launcher script
+    pub fn of(build_dir: &Path) -> Result<Self> {
+        let runtime = build_dir.join(RUNTIME_DIR);
+        let mod_dir = build_dir.join("mod");
+        if !runtime.is_dir() {
+            return Ok(Self {
+                electron_version: electron_version(&mod_dir).ok(),
+                roots: vec![(mod_dir, "app/")],
+                launcher: LAUNCHER_SCRIPT.to_string(),
+                arch: Arch::X64,
+            });
+        }
+
+        let launcher = fs::read_dir(&runtime)?
+            .filter_map(|e| e.ok())
+            .map(|e| e.file_name().to_string_lossy().into_owned())
+            .filter(|name| name.to_ascii_lowercase().ends_with(".exe"))
+            .filter(|name| !name.to_ascii_lowercase().starts_with("uninstall"))
+            .min_by_key(|name| name.to_ascii_lowercase().starts_with("elevate"))
+            .with_context(|| format!("No executable in {:?}", runtime))?;
+        let arch = pe_arch(&runtime.join(&launcher)).unwrap_or(Arch::X64);
+        Ok(Self {
+            roots: vec![(runtime, ""), (mod_dir, "resources/app/")],
+            launcher,
+            arch,
+            electron_version: None,
+        })
+    }
+
+    /// Whether the launcher is the generated script instead of the app's executable
+    pub fn is_script(&self) -> bool {
+        self.launcher == LAUNCHER_SCRIPT
+    }
+
+    /// The launcher script, running the Electron version the app was built for
+    fn launcher_script(&self) -> Result<String> {
+        let version = self
+            .electron_version
+            .as_deref()
+            .context("Electron version not found in package.json, pass --electron-version")?;
+        Ok(format!(
+            "@echo off\r\nnpx --yes electron@{} \"%~dp0app\" %*\r\n",
+            version
+        ))
+    }
+
+    /// Every directory and file as (source, path inside the package), parents first
+    pub fn entries(&self) -> Result<Vec<(PathBuf, String)>> {
+        let mut entries = Vec::new();
+        for (root, prefix) in &self.roots {
+            for entry in WalkDir::new(root).sort_by_file_name() {
+                let entry = entry?;
+                let relative = entry.path().strip_prefix(root)?;
+                if relative.as_os_str().is_empty() {
+                    continue;
+                }
+                let name = format!(
+                    "{}{}",
+                    prefix,
+                    relative.to_string_lossy().replace('\\', "/")
+                );
+                entries.push((entry.into_path(), name));
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Copy the app into `dir`
+    pub fn copy_to(&self, dir: &Path) -> Result<()> {
+        fs::create_dir_all(dir)?;
+        if self.is_script() {
+            fs::write(dir.join(LAUNCHER_SCRIPT), self.launcher_script()?)?;
+        }
+        for (source, name) in self.entries()? {
+            let target = dir.join(&name);
+            // Symlinked directories are recreated as links, not entered
+            if fs::symlink_metadata(&source)?.is_dir() {
+                fs::create_dir_all(&target)?;
+            } else {
+                if let Some(parent) = target.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                paths::copy_file(&source, &target)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Architecture of a Windows executable, from the machine field of its PE header
+fn pe_arch(exe: &Path) -> Option<Arch> {
+    let mut file = fs::File::open(exe).ok()?;
+    let mut dos_header = [0u8; 64];
+    file.read_exact(&mut dos_header).ok()?;
+    let pe_offset = u32::from_le_bytes(dos_header[0x3C..0x40].try_into().ok()?);
+    let mut pe_header = [0u8; 6];
+    file.seek(SeekFrom::Start(pe_offset.into())).ok()?;
+    file.read_exact(&mut pe_header).ok()?;
+    if &pe_header[..4] != b"PE\0\0" {
+        return None;
+    }
+    match u16::from_le_bytes([pe_header[4], pe_header[5]]) {
+        0x8664 => Some(Arch::X64),
+        0xAA64 => Some(Arch::Arm64),
+        _ => None,
+    }
+}
+
+/// Move the installed app around `resources_dir` into the build's runtime
+/// directory, without the original `app.asar` the mod replaces
+pub fn keep_runtime(resources_dir: &Path, build_dir: &Path) -> Result<()> {
+    let app_root = resources_dir.parent().unwrap_or(resources_dir);
+    let runtime = build_dir.join(RUNTIME_DIR);
+    // The staging directory may be on another volume, where renaming fails
+    if paths::rename(app_root, &runtime).is_err() {
+        crate::patcher::copy_dir_all(app_root, &runtime)
+            .with_context(|| format!("Failed to copy {:?} to {:?}", app_root, runtime))?;
+    }
+
+    let relative = resources_dir
+        .strip_prefix(app_root)
+        .unwrap_or(Path::new(""));
+    paths::remove_file(&runtime.join(relative).join("app.asar"))?;
+    info!("Kept Electron runtime in {:?}", runtime);
+    Ok(())
+}
+
+/// The portable archive all manifests point to
+#[derive(Debug, Clone)]
+struct Archive {
+    url: String,
+    sha256: String,
+}
+
+/// Package the `mod` directory of `build_dir` in the given formats.
+///
+/// `base_url` is where the archive will be hosted; without it the manifests
+/// point to the local file. `electron_version` overrides the version read from
+/// the mod's `package.json` for formats that bring their own Electron.
+/// Returns the written files.
+pub async fn package(
+    build_dir: &Path,
+    version: &str,
+    formats: &[PackageFormat],
+    base_url: Option<&str>,
+    electron_version: Option<&str>,
+    sign: Option<&SignOptions>,
+) -> Result<Vec<PathBuf>> {
+    if formats.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let package_dir = build_dir.join(PACKAGE_DIR);
+    fs::create_dir_all(&package_dir)?;
+    let mut layout = AppLayout::of(build_dir)?;
+    if let Some(version) = electron_version {
+        layout.electron_version = Some(version.to_string());
+    }
+    let mut written = Vec::new();
+
+    // The archive and the MSIX copy the launcher, so it is signed in the runtime
+    if let Some(sign) = sign {
+        if !layout.is_script() && formats.iter().any(|f| f.needs_runtime()) {
+            sign.sign(&build_dir.join(RUNTIME_DIR).join(&layout.launcher))?;
+        }
+    }
+
+    let mut archive = None;
+    if formats.iter().any(|f| f.uses_archive()) {
+        let file_name = format!("{}-{}-portable.zip", PACKAGE_ID, version);
+        let archive_path = package_dir.join(&file_name);
+        write_archive(&layout, &archive_path)?;
+        info!("Wrote portable archive {:?}", archive_path);
+
+        let url = match base_url {
+            Some(base) => format!("{}/{}", base.trim_end_matches('/'), file_name),
+            None => file_url(&archive_path)?,
+        };
+        archive = Some(Archive {
+            sha256: hex::encode(Sha256::digest(fs::read(&archive_path)?)),
+            url,
+        });
+        written.push(archive_path);
+    }
+
+    for format in formats {
+        let files = match (format, &archive) {
+            (PackageFormat::Msix, _) => {
+                written.extend(msix::package(
+                    &layout,
+                    build_dir,
+                    version,
+                    &package_dir,
+                    sign,
+                )?);
+                continue;
+            }
+            (PackageFormat::Flatpak, _) => {
+                let electron_version = match electron_version {
+                    Some(version) => version.to_string(),
+                    None => self::electron_version(&build_dir.join("mod"))?,
+                };
+                written.extend(flatpak::package(build_dir, &electron_version, &package_dir).await?);
+                continue;
+            }
+            (PackageFormat::ElectronBuilder, _) => {
+                written.extend(electron_builder::package(
+                    build_dir,
+                    electron_version,
+                    &package_dir,
+                    sign,
+                )?);
+                continue;
+            }
+            (PackageFormat::Winget, Some(archive)) => winget_manifests(version, &layout, archive),
+            (PackageFormat::Scoop, Some(archive)) => vec![(
+                format!("scoop/{}.json", PACKAGE_ID),
+                scoop_manifest(version, &layout, archive)?,
+            )],
+            (PackageFormat::Choco, Some(archive)) => choco_package(version, &layout, archive),
+            (_, None) => unreachable!("manifest formats always build the archive"),
+        };
+        for (name, content) in files {
+            let path = package_dir.join(name);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&path, content).with_context(|| format!("Failed to write {:?}", path))?;
+            written.push(path);
+        }
+        info!("Wrote {:?} manifests", format);
+    }
+    Ok(written)
+}
+
+/// Electron version the app was built with, from `electron` in the
+/// (dev)dependencies of its `package.json`
+pub fn electron_version(mod_dir: &Path) -> Result<String> {
+    let package_json: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(mod_dir.join("package.json"))?)?;
+    ["devDependencies", "dependencies"]
+        .iter()
+        .find_map(|key| package_json[key]["electron"].as_str())
+        .map(|version| version.trim_start_matches(['^', '~', '=', 'v']).to_string())
+        .context("Electron version not found in package.json, pass --electron-version")
+}
+
+/// Zip the app of `layout`
+fn write_archive(layout: &AppLayout, archive_path: &Path) -> Result<()> {
+    use zip::write::FileOptions;
+
+    let mut zip = zip::ZipWriter::new(fs::File::create(archive_path)?);
+    let options = FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+
+    if layout.is_script() {
+        zip.start_file(LAUNCHER_SCRIPT, options)?;
+        zip.write_all(layout.launcher_script()?.as_bytes())?;
+    }
+    for (source, name) in layout.entries()? {
+        if source.is_dir() {
+            zip.add_directory(name, options)?;
+        } else {
+            zip.start_file(name, options)?;
+            zip.write_all(&fs::read(&source)?)?;
+        }
+    }
+    zip.finish()?;
+    Ok(())
+}
+
+/// `file://` URL of `path`, percent-encoding non-ASCII and reserved characters
+fn file_url(path: &Path) -> Result<String> {
+    let path = fs::canonicalize(path)?;
+    url::Url::from_file_path(&path)
+        .map(String::from)
+        .map_err(|_| anyhow::anyhow!("Cannot build a file URL for {:?}", path))
+}
+
+/// winget multi-file manifest (schema 1.6.0)
+fn winget_manifests(version: &str, layout: &AppLayout, archive: &Archive) -> Vec<(String, String)> {
+    let id = format!("{}.YandexMusicMod", PUBLISHER);
+    let header = |kind: &str| {
+        format!(
+            "# yaml-language-server: $schema=https://aka.ms/winget-manifest.{kind}.1.6.0.schema.json\n\
+             PackageIdentifier: {id}\n\
+             PackageVersion: {version}\n"
+        )
+    };
+
+    let version_manifest = format!(
+        "{}DefaultLocale: en-US\nManifestType: version\nManifestVersion: 1.6.0\n",
+        header("version")
+    );
+    let installer = format!(
+        "{}InstallerType: zip\n\
+         NestedInstallerType: portable\n\
+         NestedInstallerFiles:\n\
+         - RelativeFilePath: {}\n\
+         \x20 PortableCommandAlias: {PACKAGE_ID}\n\
+         Installers:\n\
+         - Architecture: {}\n\
+         \x20 InstallerUrl: {}\n\
+         \x20 InstallerSha256: {}\n\
+         ManifestType: installer\n\
+         ManifestVersion: 1.6.0\n",
+        header("installer"),
+        layout.launcher,
+        layout.arch.as_str(),
+        archive.url,
+        archive.sha256.to_uppercase()
+    );
+    let locale = format!(
+        "{}PackageLocale: en-US\n\
+         Publisher: {PUBLISHER}\n\
+         PackageName: {PACKAGE_NAME}\n\
+         PackageUrl: {HOMEPAGE}\n\
+         License: MIT\n\
+         ShortDescription: Patched Yandex Music desktop app\n\
+         ManifestType: defaultLocale\n\
+         ManifestVersion: 1.6.0\n",
+        header("defaultLocale")
+    );
+
+    vec![
+        (format!("winget/{id}.yaml"), version_manifest),
+        (format!("winget/{id}.installer.yaml"), installer),
+        (format!("winget/{id}.locale.en-US.yaml"), locale),
+    ]
+}
+
+fn scoop_manifest(version: &str, layout: &AppLayout, archive: &Archive) -> Result<String> {
+    let mut manifest = serde_json::json!({
+        "version": version,
+        "description": "Patched Yandex Music desktop app",
+        "homepage": HOMEPAGE,
+        "license": "MIT",
+        "bin": [[layout.launcher, PACKAGE_ID]],
+        "shortcuts": [[layout.launcher, PACKAGE_NAME]],
+        "installer": { "script": register_protocol(&layout.launcher, "$dir").lines().collect::<Vec<_>>() },
+        "uninstaller": { "script": unregister_protocol().lines().collect::<Vec<_>>() },
+    });
+    let download = serde_json::json!({ "url": archive.url, "hash": archive.sha256 });
+    match layout.arch {
+        Arch::X64 => {
+            manifest["url"] = download["url"].clone();
+            manifest["hash"] = download["hash"].clone();
+        }
+        // Scoop only offers an arm64 build on ARM machines
+        Arch::Arm64 => manifest["architecture"] = serde_json::json!({ "arm64": download }),
+    }
+    if layout.is_script() {
+        manifest["suggest"] = serde_json::json!({ "Node.js": "nodejs-lts" });
+    }
+    Ok(serde_json::to_string_pretty(&manifest)? + "\n")
+}
+
+/// Chocolatey nuspec plus install and uninstall scripts; installs to
+/// `%LOCALAPPDATA%\yandex-music-mod` with a desktop shortcut
+fn choco_package(version: &str, layout: &AppLayout, archive: &Archive) -> Vec<(String, String)> {
+    let dependencies = if layout.is_script() {
+        "\n    <dependencies>\n      <dependency id=\"nodejs-lts\" />\n    </dependencies>"
+    } else {
+        ""
+    };
+    let nuspec = format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<package xmlns="http://schemas.microsoft.com/packaging/2015/06/nuspec.xsd">
+  <metadata>
+    <id>{PACKAGE_ID}</id>
+    <version>{version}</version>
+    <title>{PACKAGE_NAME}</title>
+    <authors>{PUBLISHER}</authors>
+    <projectUrl>{HOMEPAGE}</projectUrl>
+    <description>Patched Yandex Music desktop app</description>{dependencies}
+  </metadata>
+  <files>
+    <file src="tools\**" target="tools" />
+  </files>
+</package>
+"#
+    );
+    let install = format!(
+        r#"$ErrorActionPreference = 'Stop'
+$installDir = Join-Path $env:LOCALAPPDATA '{PACKAGE_ID}'
+
+Install-ChocolateyZipPackage -PackageName '{PACKAGE_ID}' `
+  -Url64bit '{url}' `
+  -Checksum64 '{sha256}' -ChecksumType64 'sha256' `
+  -UnzipLocation $installDir
+
+Install-ChocolateyShortcut `
+  -ShortcutFilePath (Join-Path ([Environment]::GetFolderPath('Desktop')) '{PACKAGE_NAME}.lnk') `
+  -TargetPath (Join-Path $installDir '{launcher}') `
+  -WorkingDirectory $installDir
+
+{register}"#,
+        launcher = layout.launcher,
+        url = archive.url,
+        sha256 = archive.sha256,
+        register = register_protocol(&layout.launcher, "$installDir"),
+    );
+    let uninstall = format!(
+        r#"$ErrorActionPreference = 'Stop'
+Remove-Item -Recurse -Force (Join-Path $env:LOCALAPPDATA '{PACKAGE_ID}') -ErrorAction SilentlyContinue
+Remove-Item -Force (Join-Path ([Environment]::GetFolderPath('Desktop')) '{PACKAGE_NAME}.lnk') -ErrorAction SilentlyContinue
+
+{unregister}"#,
+        unregister = unregister_protocol(),
+    );
+
+    vec![
+        (format!("choco/{}.nuspec", PACKAGE_ID), nuspec),
+        ("choco/tools/chocolateyinstall.ps1".to_string(), install),
+        ("choco/tools/chocolateyuninstall.ps1".to_string(), uninstall),
+    ]
+}
+
+/// PowerShell registering `URL_SCHEME` for the current user to `launcher`
+/// in the folder `dir` (a PowerShell expression). The handler it replaces,
+/// usually the stock app's, is kept for [`unregister_protocol`].
+fn register_protocol(launcher: &str, dir: &str) -> String {
+    format!(
+        r#"$protocolKey = 'HKCU:\Software\Classes\{URL_SCHEME}'
+$command = '"' + (Join-Path {dir} '{launcher}') + '" "%1"'
+$previous = (Get-ItemProperty -Path "$protocolKey\shell\open\command" -ErrorAction SilentlyContinue).'(default)'
+if ($previous -and $previous -notlike '*{PACKAGE_ID}*') {{
+  New-Item -Path '{PROTOCOL_STATE_KEY}' -Force | Out-Null
+  Set-ItemProperty -Path '{PROTOCOL_STATE_KEY}' -Name 'PreviousProtocolCommand' -Value $previous
+}}
+New-Item -Path "$protocolKey\shell\open\command" -Force | Out-Null
+Set-ItemProperty -Path $protocolKey -Name '(default)' -Value 'URL:{PACKAGE_NAME}'
+Set-ItemProperty -Path $protocolKey -Name 'URL Protocol' -Value ''
+Set-ItemProperty -Path "$protocolKey\shell\open\command" -Name '(default)' -Value $command
+"#
+    )
+}
+
+/// PowerShell undoing [`register_protocol`]: the previous handler is restored,
+/// or the scheme removed if it had none. A handler registered after the mod's
+/// is left alone.
+fn unregister_protocol() -> String {
+    format!(
+        r#"$protocolKey = 'HKCU:\Software\Classes\{URL_SCHEME}'
+$previous = (Get-ItemProperty -Path '{PROTOCOL_STATE_KEY}' -ErrorAction SilentlyContinue).PreviousProtocolCommand
+$current = (Get-ItemProperty -Path "$protocolKey\shell\open\command" -ErrorAction SilentlyContinue).'(default)'
+if ($current -like '*{PACKAGE_ID}*') {{
+  if ($previous) {{
+    Set-ItemProperty -Path "$protocolKey\shell\open\command" -Name '(default)' -Value $previous
+  }} else {{
+    Remove-Item -Recurse -Force $protocolKey -ErrorAction SilentlyContinue
+  }}
+}}
+Remove-Item -Recurse -Force '{PROTOCOL_STATE_KEY}' -ErrorAction SilentlyContinue
+"#
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_package_manifests() {
+        let dir = tempfile::tempdir().unwrap();
+        let build_dir = dir.path().join("5.0.0");
+        fs::create_dir_all(build_dir.join("mod").join("app")).unwrap();
+        fs::write(
+            build_dir.join("mod").join("package.json"),
+            r#"{"devDependencies": {"electron": "^32.2.0"}}"#,
+        )
+        .unwrap();
+
+        let formats = [
+            PackageFormat::Winget,
+            PackageFormat::Scoop,
+            PackageFormat::Choco,
+        ];
+        let files = package(
+            &build_dir,
+            "5.0.0",
+            &formats,
+            Some("https://nas/builds/"),
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(files.len(), 1 + 3 + 1 + 3);
+
+        let archive = build_dir
+            .join(PACKAGE_DIR)
+            .join("yandex-music-mod-5.0.0-portable.zip");
+        let sha256 = hex::encode(Sha256::digest(fs::read(&archive).unwrap()));
+        let mut zip = zip::ZipArchive::new(fs::File::open(&archive).unwrap()).unwrap();
+        let mut launcher = String::new();
+        zip.by_name("launch.cmd")
+            .unwrap()
+            .read_to_string(&mut launcher)
+            .unwrap();
+        assert!(launcher.contains("npx --yes electron@32.2.0 "));
+        assert!(zip.by_name("app/package.json").is_ok());
+
+        let scoop = fs::read_to_string(
+            build_dir
+                .join(PACKAGE_DIR)
+                .join("scoop")
+                .join("yandex-music-mod.json"),
+        )
+        .unwrap();
+        let scoop: serde_json::Value = serde_json::from_str(&scoop).unwrap();
+        assert_eq!(scoop["hash"], sha256.as_str());
+        assert_eq!(
+            scoop["url"],
+            "https://nas/builds/yandex-music-mod-5.0.0-portable.zip"
+        );
+        assert!(scoop["installer"]["script"][0]
+            .as_str()
+            .unwrap()
+            .ends_with(r"Classes\yandexmusic'"));
+        assert!(scoop["uninstaller"]["script"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|line| line.as_str().unwrap().contains("PreviousProtocolCommand")));
+
+        let choco = build_dir.join(PACKAGE_DIR).join("choco").join("tools");
+        let install = fs::read_to_string(choco.join("chocolateyinstall.ps1")).unwrap();
+        assert!(install.contains(r#"(Join-Path $installDir 'launch.cmd') + '" "%1"'"#));
+        let uninstall = fs::read_to_string(choco.join("chocolateyuninstall.ps1")).unwrap();
+        assert!(uninstall.contains("Remove-Item -Recurse -Force $protocolKey"));
+
+        let installer = files
+            .iter()
+            .find(|p| p.to_string_lossy().ends_with(".installer.yaml"))
+            .unwrap();
+        let installer = fs::read_to_string(installer).unwrap();
+        assert!(installer.contains(&sha256.to_uppercase()));
+        let installer: serde_yaml::Value = serde_yaml::from_str(&installer).unwrap();
+        assert_eq!(installer["PackageVersion"], "5.0.0");
+    }
+
+    #[test]
+    fn test_electron_version() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("package.json"),
+            r#"{"devDependencies": {"electron": "^32.2.0"}}"#,
+        )
+        .unwrap();
+        assert_eq!(electron_version(dir.path()).unwrap(), "32.2.0");
+
+        fs::write(dir.path().join("package.json"), "{}").unwrap();
+        assert!(electron_version(dir.path()).is_err());
+    }
+
+    #[test]
+    fn test_file_url() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("Музыка 5.0.zip");
+        fs::write(&archive, b"").unwrap();
+
+        let url = file_url(&archive).unwrap();
+        assert!(url.starts_with("file:///"));
+        assert!(url.ends_with("/%D0%9C%D1%83%D0%B7%D1%8B%D0%BA%D0%B0%205.0.zip"));
+        let parsed = url::Url::parse(&url).unwrap().to_file_path().unwrap();
+        assert_eq!(parsed, fs::canonicalize(&archive).unwrap());
+    }
+}
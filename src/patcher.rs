@@ -7,69 +7,687 @@
 //! 4. Apply all patches to the JavaScript/JSON files
 //! 5. Rebuild the application
 
-use crate::api::{download_build, AppBuild};
-use crate::patches;
+use crate::api::{self, download_build, file_sha512, AppBuild, Arch};
+use crate::asar::{self, AsarOptions, AsarReport};
+use crate::bench::{Bench, StageTiming};
+use crate::cache::{self, StageCache};
+use crate::config;
+use crate::deps;
+use crate::error::PatcherError;
+use crate::i18n::tr;
+use crate::interrupt::Partial;
+use crate::journal::{self, Journal};
+use crate::lock::BuildLock;
+use crate::manifest;
+use crate::package::{self, PackageFormat};
+use crate::patches::{self, Compatibility, ModFeature, WindowOptions};
+use crate::paths;
+use crate::progress::{format_bytes, Event, ProgressSink, Stage};
+use crate::remote_rules;
+use crate::rules::{self, PatchProfile, ProfileSet, RuleOutcome};
+#[cfg(feature = "native-extract")]
+use crate::sevenz;
+use crate::sign::SignOptions;
+use crate::versions;
 use anyhow::{Context, Result};
-use indicatif::ProgressBar;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::OsString;
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::process::Command;
 use tracing::{debug, info, warn};
 use walkdir::WalkDir;
 
+/// Options controlling which patches are applied
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PatchOptions {
+    /// BrowserWindow settings (DevTools, minimum size)
+    pub window: WindowOptions,
+    /// User preload code appended after the built-in mod preload
+    pub user_preload_js: Option<String>,
+    /// Patch profile name overriding automatic selection by version
+    pub profile: Option<String>,
+    /// Only check which patch anchors and rules match; write no mod
+    pub dry_run: bool,
+    /// Promo selectors hidden in addition to the built-in list
+    pub promo_selectors: Vec<String>,
+    /// Optional mod features to inject
+    pub features: Vec<ModFeature>,
+    /// Headers removed from API requests in addition to the built-in list
+    pub banned_headers: Vec<String>,
+    /// Collect unified diffs of the patched text files
+    pub show_diff: bool,
+    /// Mod settings the app starts with when they are not set yet
+    pub default_settings: BTreeMap<String, serde_json::Value>,
+    /// Settings profile the app starts with until one is picked in the panel
+    pub settings_profile: Option<String>,
+    /// Install a missing 7-Zip without asking (Windows)
+    pub auto_install_deps: bool,
+    /// Package formats written next to the patched build
+    pub packages: Vec<PackageFormat>,
+    /// Where the packaged archive will be hosted, for the manifests
+    pub package_url: Option<String>,
+    /// Electron version for packages that bundle their own (default: from `package.json`)
+    pub electron_version: Option<String>,
+    /// Sign the packages with this certificate
+    pub sign: Option<SignOptions>,
+    /// Extract and patch from scratch instead of reusing cached results
+    pub no_cache: bool,
+    /// Also pack the mod into `app.asar` in the build directory
+    pub asar: Option<AsarOptions>,
+    /// Time the stages and add the timings to the report
+    pub bench: bool,
+    /// Fetch the latest rules from this base URL before patching
+    pub remote_rules: Option<String>,
+}
+
+/// Directory of the mod files inside the HTML root of the app
+pub const MOD_ASSETS_DIR: &str = "yandexMusicMod";
+
+/// npx; on Windows it is a batch script, which `Command` only finds with
+/// its extension
+pub const NPX: &str = if cfg!(windows) { "npx.cmd" } else { "npx" };
+
+/// Rule outcomes stored with a cached mod
+const CACHED_OUTCOMES_FILE: &str = "outcomes.json";
+
+/// File name of the patch report written to the build directory
+pub const PATCH_REPORT_FILE: &str = "patch-report.json";
+
+/// Summary of a patch run, written as JSON next to the build
+#[derive(Debug, Clone, Serialize)]
+pub struct PatchReport {
+    pub version: String,
+    pub profile: String,
+    pub compatibility: Compatibility,
+    pub dry_run: bool,
+    /// Anchors the built-in patches rely on
+    pub anchors: Vec<RuleOutcome>,
+    /// Declarative rules of the selected profile
+    pub rules: Vec<RuleOutcome>,
+    /// HTML files the mod was injected into
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub html: Vec<HtmlOutcome>,
+    /// Size and timings of the packed `app.asar` (only with `asar`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub asar: Option<AsarReport>,
+    /// Wall time and bytes of each stage (only with `bench`)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub timings: Vec<StageTiming>,
+    /// Unified diffs of modified text files (only with `show_diff`)
+    #[serde(skip)]
+    pub diffs: Vec<FileDiff>,
+}
+
+/// Result of injecting the mod into a single HTML file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HtmlOutcome {
+    /// Path relative to the HTML root
+    pub path: String,
+    /// `false` if the file has no `<head>` to inject into
+    pub injected: bool,
+}
+
+/// Unified diff of a single file between the original and patched sources
+#[derive(Debug, Clone, Serialize)]
+pub struct FileDiff {
+    /// Path relative to the app root
+    pub path: String,
+    pub diff: String,
+}
+
+/// Lines longer than this are shortened in diffs (minified bundles)
+const DIFF_MAX_LINE_LEN: usize = 200;
+
+/// How a file differs between the original and patched trees
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Modified,
+}
+
+/// A file that differs between the `src` and `mod` trees of a build
+#[derive(Debug, Clone, Serialize)]
+pub struct TreeChange {
+    /// Path relative to the app root
+    pub path: String,
+    pub kind: ChangeKind,
+    /// Unified diff of a modified text file, if requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub diff: Option<String>,
+}
+
+impl PatchReport {
+    /// Names of anchors and rules that matched nothing
+    pub fn unmatched(&self) -> Vec<&str> {
+        self.anchors
+            .iter()
+            .chain(&self.rules)
+            .filter(|o| o.matches == 0)
+            .map(|o| o.rule.as_str())
+            .collect()
+    }
+}
+
+/// Directory a build of `version` is written to inside `output_dir`
+pub fn build_dir(output_dir: &Path, version: &str, dry_run: bool) -> PathBuf {
+    // A dry run must not clobber an existing patched build
+    if dry_run {
+        output_dir.join(format!("{}-dry-run", version))
+    } else {
+        output_dir.join(version)
+    }
+}
+
 /// Process a build: download, extract, patch, and rebuild
 pub async fn process_build(
     build: &AppBuild,
-    output_dir: &str,
-    auto_devtools: bool,
-    progress: Option<&ProgressBar>,
-) -> Result<()> {
-    let build_dir = PathBuf::from(output_dir).join(&build.version);
-    let temp_dir = build_dir.join("temp");
-    let build_binary_path = temp_dir.join("build.exe");
-    let extract_dir = temp_dir.join("extracted");
+    output_dir: &Path,
+    options: &PatchOptions,
+    progress: Option<&dyn ProgressSink>,
+) -> Result<PatchReport> {
+    let options = &auto_repatch_options(options, output_dir);
+
+    // Times the stages on the way to the caller's sink
+    let bench = options.bench.then(|| Bench::new(progress));
+    let progress = match &bench {
+        Some(bench) => Some(bench as &dyn ProgressSink),
+        None => progress,
+    };
+
+    let build_dir = build_dir(output_dir, &build.version, options.dry_run);
     let build_source_dir = build_dir.join("src");
     let build_modded_dir = build_dir.join("mod");
 
+    // Held until the run ends; another run on this directory fails here instead
+    // of deleting files under this one
+    let _lock = BuildLock::acquire(&build_dir)?;
+
+    // A journal left behind means the previous run crashed or was killed
+    let interrupted = Journal::load(&build_dir).unwrap_or_else(|e| {
+        warn!("Ignoring unreadable run journal: {:#}", e);
+        None
+    });
+    if let Some(previous) = &interrupted {
+        warn!(
+            "The previous run on {} (pid {}) was interrupted during {:?}",
+            previous.version,
+            previous.pid,
+            previous.last_stage()
+        );
+    }
+
     // Clean up any existing build directory
     if build_dir.exists() {
         info!("Removing existing build directory: {:?}", build_dir);
-        fs::remove_dir_all(&build_dir)?;
+        paths::remove_dir_all(&build_dir)?;
     }
+    // Removed if the run is interrupted before it finishes
+    let _partial = Partial::new(&build_dir);
+
+    // The installer and its extracted files are staged in the OS temp directory,
+    // so only the results end up in the output directory
+    let staging = staging_dir(&build.version)?;
+    let _staging_partial = Partial::new(staging.path());
 
-    // Create directories
     fs::create_dir_all(&build_dir)?;
-    fs::create_dir_all(&extract_dir)?;
     fs::create_dir_all(&build_source_dir)?;
     fs::create_dir_all(&build_modded_dir)?;
+    let mut journal = Journal::start(&build_dir, &build.version, staging.path())?;
+
+    // Packages need the Electron runtime from the installer, which isn't cached
+    let needs_runtime = options.packages.iter().any(|f| f.needs_runtime()) && !options.dry_run;
+    let cache = StageCache::for_options(options);
+    let sources_key = cache::sources_key(&build.hash);
+    let cached_sources = cache
+        .as_ref()
+        .filter(|_| !needs_runtime)
+        .and_then(|cache| cache.get(cache::Kind::Sources, &sources_key));
+
+    if let Some(entry) = cached_sources {
+        stage(progress, Stage::ExtractAsar);
+        journal.begin(Stage::ExtractAsar)?;
+        info!("[1-5] Reusing the sources extracted from this installer earlier");
+
+        copy_dir_all(&entry.join("src"), &build_source_dir)?;
+        if options.bench {
+            processed(progress, versions::dir_size(&build_source_dir));
+        }
+        if entry.join("icon.ico").exists() {
+            fs::copy(entry.join("icon.ico"), build_dir.join("icon.ico"))?;
+        }
+        if let Some(previous) = &interrupted {
+            previous.remove_staging();
+        }
+    } else {
+        check_disk_space(output_dir, staging.path(), build, options)?;
+        deps::ensure_7z(options.auto_install_deps).await?;
+        extract_build(
+            build,
+            &build_dir,
+            staging,
+            interrupted.as_ref(),
+            &mut journal,
+            needs_runtime,
+            progress,
+        )
+        .await?;
+
+        if let Some(cache) = &cache {
+            cache.put(cache::Kind::Sources, &sources_key, |entry| {
+                copy_dir_all(&build_source_dir, &entry.join("src"))?;
+                if build_dir.join("icon.ico").exists() {
+                    fs::copy(build_dir.join("icon.ico"), entry.join("icon.ico"))?;
+                }
+                Ok(())
+            });
+        }
+    }
+    journal::clean_stale_staging(&config::temp_dir(), journal::STALE_STAGING_AGE);
+
+    if let Some(url) = &options.remote_rules {
+        remote_rules::refresh(url).await?;
+    }
+    let profiles = ProfileSet::load()?;
+    let profile = profiles.select(&build.version, options.profile.as_deref())?;
+
+    if options.dry_run {
+        stage(progress, Stage::CheckRules);
+        journal.begin(Stage::CheckRules)?;
+        info!("[6] Dry run: checking patch anchors and rules");
+
+        let report = PatchReport {
+            version: build.version.clone(),
+            profile: profile.name.clone(),
+            compatibility: patches::check_compatibility(&build.version),
+            dry_run: true,
+            anchors: check_patch_anchors(&build_source_dir, profile)?,
+            rules: apply_profile_rules(&build_source_dir, profile, &build.version, true)?,
+            html: Vec::new(),
+            asar: None,
+            timings: bench.as_ref().map(Bench::finish).unwrap_or_default(),
+            diffs: Vec::new(),
+        };
+        write_report(&build_dir, &report)?;
+        journal.finish()?;
+
+        stage(progress, Stage::Done);
+        return Ok(report);
+    }
+
+    let mod_key = cache::mod_key(
+        &build.hash,
+        &crate::plan::rules_hash(profile, &build.version)?,
+        options,
+    )?;
+    let cached_mod = cache
+        .as_ref()
+        .and_then(|cache| cache.get(cache::Kind::Mod, &mod_key));
+
+    let (anchors, rules, html) = if let Some(entry) = cached_mod {
+        stage(progress, Stage::CopySources);
+        journal.begin(Stage::CopySources)?;
+        info!("[6-9] Reusing the mod patched with the same rules and options earlier");
+
+        copy_dir_all(&entry.join("mod"), &build_modded_dir)?;
+        if options.bench {
+            processed(progress, versions::dir_size(&build_modded_dir));
+        }
+        let outcomes = fs::read_to_string(entry.join(CACHED_OUTCOMES_FILE))?;
+        serde_json::from_str(&outcomes).context("Failed to parse cached rule outcomes")?
+    } else {
+        stage(progress, Stage::CopySources);
+        journal.begin(Stage::CopySources)?;
+        info!("[6] Copying sources before modding");
+
+        copy_dir_all(&build_source_dir, &build_modded_dir)?;
+        if options.bench {
+            processed(progress, versions::dir_size(&build_modded_dir));
+        }
+        info!("Copy complete");
+
+        journal.begin(Stage::ApplyPatches)?;
+        let outcomes = patch_app(
+            &build_modded_dir,
+            profile,
+            &build.version,
+            options,
+            progress,
+        )?;
+
+        if let Some(cache) = &cache {
+            cache.put(cache::Kind::Mod, &mod_key, |entry| {
+                copy_dir_all(&build_modded_dir, &entry.join("mod"))?;
+                fs::write(
+                    entry.join(CACHED_OUTCOMES_FILE),
+                    serde_json::to_string(&outcomes)?,
+                )?;
+                Ok(())
+            });
+        }
+        outcomes
+    };
+
+    let diffs = if options.show_diff {
+        diff_patched_files(&build_source_dir, &build_modded_dir, profile, &rules)?
+    } else {
+        Vec::new()
+    };
+
+    let asar = match &options.asar {
+        Some(asar_options) => {
+            stage(progress, Stage::PackAsar);
+            journal.begin(Stage::PackAsar)?;
+            info!("[10] Packing the mod into {}", asar::ASAR_FILE);
+            let packed = asar::pack(
+                &build_modded_dir,
+                &build_dir.join(asar::ASAR_FILE),
+                asar_options,
+            )?;
+            processed(progress, packed.bytes);
+            Some(packed)
+        }
+        None => None,
+    };
+
+    let mut report = PatchReport {
+        version: build.version.clone(),
+        profile: profile.name.clone(),
+        compatibility: patches::check_compatibility(&build.version),
+        dry_run: false,
+        anchors,
+        rules,
+        html,
+        asar,
+        timings: Vec::new(),
+        diffs,
+    };
+    write_report(&build_dir, &report)?;
+
+    if !options.packages.is_empty() {
+        stage(progress, Stage::Package);
+        journal.begin(Stage::Package)?;
+        info!("[11] Packaging build");
+        package::package(
+            &build_dir,
+            &build.version,
+            &options.packages,
+            options.package_url.as_deref(),
+            options.electron_version.as_deref(),
+            options.sign.as_ref(),
+        )
+        .await?;
+        if options.bench {
+            processed(
+                progress,
+                versions::dir_size(&build_dir.join(package::PACKAGE_DIR)),
+            );
+        }
+    }
+
+    if let Some(bench) = &bench {
+        report.timings = bench.finish();
+        write_report(&build_dir, &report)?;
+    }
+
+    // Last, so it covers the packages too
+    manifest::write(&build_dir, build, profile)?;
+    journal.finish()?;
+
+    stage(progress, Stage::Done);
+    info!("Build {} patched successfully!", build.version);
+    info!("Output directory: {:?}", build_modded_dir);
+
+    Ok(report)
+}
+
+/// `options` with the defaults [`ModFeature::AutoRepatch`] needs to rerun this
+/// patcher into `output_dir`
+fn auto_repatch_options(options: &PatchOptions, output_dir: &Path) -> PatchOptions {
+    let mut options = options.clone();
+    if !options.features.contains(&ModFeature::AutoRepatch) {
+        return options;
+    }
+    // Only the CLI can be rerun; apps patched through the library download it
+    let patcher = std::env::current_exe().ok().filter(|exe| {
+        exe.file_stem()
+            .is_some_and(|stem| stem.to_string_lossy().starts_with(package::PACKAGE_ID))
+    });
+    let output_dir = std::path::absolute(output_dir).unwrap_or_else(|_| output_dir.to_path_buf());
+    for (key, value) in
+        patches::auto_repatch_settings(patcher.as_deref(), &output_dir, &options.features)
+    {
+        options.default_settings.entry(key).or_insert(value);
+    }
+    options
+}
+
+/// Regenerate the mod files (`yandexMusicMod` in the HTML root) of the patched
+/// build in `build_dir` with `options`, then its packages and manifest, without
+/// downloading, extracting or patching again. For changes to the renderer code,
+/// styles, promo selectors or feature assets. Returns the refreshed files.
+pub async fn refresh_assets(build_dir: &Path, options: &PatchOptions) -> Result<Vec<String>> {
+    let _lock = BuildLock::acquire(build_dir)?;
+    let modded_dir = build_dir.join("mod");
+    if !modded_dir.is_dir() {
+        return Err(PatcherError::FileNotFound(modded_dir.display().to_string()).into());
+    }
+    let manifest = manifest::load(build_dir)
+        .context("Only builds with a mod-manifest.json can be refreshed; patch it again")?;
+
+    let profiles = ProfileSet::load()?;
+    let profile_name = options.profile.as_deref().unwrap_or(&manifest.profile);
+    let profile = profiles.select(&manifest.app_version, Some(profile_name))?;
+
+    info!("Refreshing mod files of build {}", manifest.app_version);
+    let mod_dir = modded_dir
+        .join(&profile.layout.html_root)
+        .join(MOD_ASSETS_DIR);
+    if mod_dir.exists() {
+        paths::remove_dir_all(&mod_dir)?;
+    }
+    create_mod_files(&modded_dir, profile, options)?;
+
+    if let Some(asar_options) = &options.asar {
+        asar::pack(&modded_dir, &build_dir.join(asar::ASAR_FILE), asar_options)?;
+    }
+    if !options.packages.is_empty() {
+        info!("Repackaging build");
+        package::package(
+            build_dir,
+            &manifest.app_version,
+            &options.packages,
+            options.package_url.as_deref(),
+            options.electron_version.as_deref(),
+            options.sign.as_ref(),
+        )
+        .await?;
+    }
+    manifest::refresh(build_dir, profile)?;
+
+    let mut files: Vec<String> = WalkDir::new(&mod_dir)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .filter_map(|e| {
+            let relative = e.path().strip_prefix(&modded_dir).ok()?;
+            Some(relative.to_string_lossy().replace('\\', "/"))
+        })
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+/// Rule outcomes of the patch report of an earlier run
+#[derive(Deserialize)]
+struct ReportedRules {
+    rules: Vec<RuleOutcome>,
+}
+
+/// Re-apply the built-in patches, profile rules and HTML injection to the
+/// patched build in `build_dir` with `options`, then rewrite its report and
+/// manifest, without downloading or extracting again. The files patched by the
+/// previous run are restored from `src` first, so rules are never applied
+/// twice. For changes to the rules, the user preload script or the features;
+/// packages are not rebuilt.
+pub fn reapply_patches(build_dir: &Path, options: &PatchOptions) -> Result<PatchReport> {
+    let _lock = BuildLock::acquire(build_dir)?;
+    let options = &auto_repatch_options(options, build_dir.parent().unwrap_or(build_dir));
+    let source_dir = build_dir.join("src");
+    let modded_dir = build_dir.join("mod");
+    for dir in [&source_dir, &modded_dir] {
+        if !dir.is_dir() {
+            return Err(PatcherError::FileNotFound(dir.display().to_string()).into());
+        }
+    }
+    let manifest = manifest::load(build_dir)
+        .context("Only builds with a mod-manifest.json can be patched again in place")?;
+    let previous: ReportedRules = fs::read_to_string(build_dir.join(PATCH_REPORT_FILE))
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .context("Only builds with a patch report can be patched again in place")?;
+
+    let profiles = ProfileSet::load()?;
+    let profile_name = options.profile.as_deref().unwrap_or(&manifest.profile);
+    let profile = profiles.select(&manifest.app_version, Some(profile_name))?;
+    let layout = &profile.layout;
+
+    let mut restored: BTreeSet<String> = [
+        &layout.package_json,
+        &layout.config_js,
+        &layout.main_js,
+        &layout.preload_js,
+        &layout.create_window_js,
+        &layout.system_menu_js,
+    ]
+    .into_iter()
+    .cloned()
+    .collect();
+    restored.extend(previous.rules.into_iter().flat_map(|outcome| outcome.files));
+    for entry in WalkDir::new(source_dir.join(&layout.html_root))
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file() && is_html_file(e.path()))
+    {
+        if let Ok(relative) = entry.path().strip_prefix(&source_dir) {
+            restored.insert(relative.to_string_lossy().replace('\\', "/"));
+        }
+    }
+    info!(
+        "Restoring {} patched file(s) of build {}",
+        restored.len(),
+        manifest.app_version
+    );
+    for path in &restored {
+        let source = source_dir.join(path);
+        if source.is_file() {
+            fs::copy(&source, modded_dir.join(path))
+                .with_context(|| format!("Failed to restore {:?}", path))?;
+        }
+    }
+    let mod_dir = modded_dir.join(&layout.html_root).join(MOD_ASSETS_DIR);
+    if mod_dir.exists() {
+        paths::remove_dir_all(&mod_dir)?;
+    }
+
+    let (anchors, rules, html) =
+        patch_app(&modded_dir, profile, &manifest.app_version, options, None)?;
+    let asar = match &options.asar {
+        Some(asar_options) => Some(asar::pack(
+            &modded_dir,
+            &build_dir.join(asar::ASAR_FILE),
+            asar_options,
+        )?),
+        None => None,
+    };
+
+    let report = PatchReport {
+        version: manifest.app_version.clone(),
+        profile: profile.name.clone(),
+        compatibility: patches::check_compatibility(&manifest.app_version),
+        dry_run: false,
+        anchors,
+        rules,
+        html,
+        asar,
+        timings: Vec::new(),
+        diffs: Vec::new(),
+    };
+    write_report(build_dir, &report)?;
+    manifest::refresh(build_dir, profile)?;
+    Ok(report)
+}
 
-    update_progress(progress, 5, "Downloading build...");
+/// Download the installer and extract its `app.asar` into `build_dir/src`
+/// (stages 1 to 5), staging the installer in `staging`
+async fn extract_build(
+    build: &AppBuild,
+    build_dir: &Path,
+    staging: tempfile::TempDir,
+    interrupted: Option<&Journal>,
+    journal: &mut Journal,
+    needs_runtime: bool,
+    progress: Option<&dyn ProgressSink>,
+) -> Result<()> {
+    let build_binary_path = staging.path().join(journal::INSTALLER_FILE);
+    let extract_dir = staging.path().join("extracted");
+    fs::create_dir_all(&extract_dir)?;
+
+    stage(progress, Stage::Download);
+    journal.begin(Stage::Download)?;
     info!("[1] Downloading build {}", build.version);
 
-    download_build(build, build_binary_path.to_str().unwrap()).await?;
+    let salvaged = interrupted.is_some_and(|previous| {
+        let salvaged = previous.salvage_installer(&build.hash, &build_binary_path);
+        previous.remove_staging();
+        salvaged
+    });
+    let hash = if salvaged {
+        processed(progress, fs::metadata(&build_binary_path)?.len());
+        file_sha512(&build_binary_path)?
+    } else {
+        download_build(build, &build_binary_path, progress)
+            .await
+            .with_context(|| PatcherError::DownloadError(build.version.clone()))?
+    };
+    if hash != build.hash {
+        return Err(PatcherError::ChecksumMismatch(build.version.clone()).into());
+    }
+    journal.set_installer_hash(&build.hash)?;
     info!("Download complete");
 
-    update_progress(progress, 20, "Extracting installer...");
+    stage(progress, Stage::ExtractInstaller);
+    journal.begin(Stage::ExtractInstaller)?;
     info!(
         "[2] Extracting build {} to {:?}",
         build.version, extract_dir
     );
 
     extract_installer(&build_binary_path, &extract_dir)?;
+    processed(progress, fs::metadata(&build_binary_path)?.len());
     info!("Extraction complete");
 
-    update_progress(progress, 30, "Extracting nested archives...");
+    stage(progress, Stage::ExtractNested);
+    journal.begin(Stage::ExtractNested)?;
     info!("[3] Extracting nested archives (if any)");
 
     // NSIS installers often have nested 7z archives (e.g., app-64.7z)
-    extract_nested_archives(&extract_dir)?;
+    let arch = build.arch().unwrap_or_else(api::arch);
+    extract_nested_archives(&extract_dir, arch)?;
     info!("Nested archive extraction complete");
 
-    update_progress(progress, 35, "Locating and extracting app.asar...");
+    stage(progress, Stage::ExtractAsar);
+    journal.begin(Stage::ExtractAsar)?;
     info!("[4] Finding and extracting app.asar");
 
     // Search for app.asar recursively since installer structure varies
-    let app_asar_path = find_app_asar(&extract_dir)?;
+    let app_asar_path =
+        find_app_asar(&extract_dir).context(PatcherError::FileNotFound("app.asar".to_string()))?;
     info!("Found app.asar at {:?}", app_asar_path);
 
     // Try to find icon in same resources folder as app.asar
@@ -83,56 +701,318 @@ pub async fn process_build(
     }
 
     // Extract app.asar
-    extract_asar(&app_asar_path, &build_source_dir)?;
+    extract_asar(&app_asar_path, &build_dir.join("src"))
+        .with_context(|| PatcherError::AsarError(app_asar_path.display().to_string()))?;
+    processed(progress, fs::metadata(&app_asar_path)?.len());
     info!("Extracted app.asar");
 
-    update_progress(progress, 45, "Cleaning up temp files...");
+    // Packages ship the mod inside the Electron runtime from the installer
+    if needs_runtime {
+        package::keep_runtime(resources_dir, build_dir)?;
+    }
+
+    stage(progress, Stage::Cleanup);
+    journal.begin(Stage::Cleanup)?;
     info!("[5] Cleaning up temporary files");
 
-    fs::remove_dir_all(&temp_dir)?;
+    // Freshly extracted executables are what scanners lock, so retry
+    paths::remove_dir_all(&staging.keep())?;
     info!("Cleanup complete");
+    Ok(())
+}
 
-    update_progress(progress, 50, "Copying sources...");
-    info!("[6] Copying sources before modding");
-
-    copy_dir_all(&build_source_dir, &build_modded_dir)?;
-    info!("Copy complete");
-
-    update_progress(progress, 55, "Applying patches...");
+/// Patch the app sources copied to `modded_dir` and add the mod files (stages
+/// 7 to 9). Returns the outcomes of the patch anchors, of the profile rules and
+/// of the HTML injection.
+pub(crate) fn patch_app(
+    modded_dir: &Path,
+    profile: &PatchProfile,
+    app_version: &str,
+    options: &PatchOptions,
+    progress: Option<&dyn ProgressSink>,
+) -> Result<(Vec<RuleOutcome>, Vec<RuleOutcome>, Vec<HtmlOutcome>)> {
+    stage(progress, Stage::ApplyPatches);
     info!("[7] Patching application");
 
-    apply_patches(&build_modded_dir, auto_devtools)?;
+    let anchors = check_patch_anchors(modded_dir, profile)?;
+    apply_patches(modded_dir, profile, options)
+        .with_context(|| PatcherError::PatchError(app_version.to_string()))?;
+    let rules = apply_profile_rules(modded_dir, profile, app_version, false)
+        .with_context(|| PatcherError::PatchError(app_version.to_string()))?;
     info!("Patching complete");
 
-    update_progress(progress, 80, "Creating mod files...");
+    stage(progress, Stage::CreateModFiles);
     info!("[8] Creating mod files");
 
-    create_mod_files(&build_modded_dir)?;
+    create_mod_files(modded_dir, profile, options)?;
     info!("Mod files created");
 
-    update_progress(progress, 90, "Injecting mod into HTML...");
+    stage(progress, Stage::InjectHtml);
     info!("[9] Injecting mod into HTML files");
 
-    inject_mod_into_html(&build_modded_dir)?;
+    let html = inject_mod_into_html(modded_dir, profile)?;
     info!("HTML injection complete");
 
-    update_progress(progress, 100, "Done!");
-    info!("Build {} patched successfully!", build.version);
-    info!("Output directory: {:?}", build_modded_dir);
+    Ok((anchors, rules, html))
+}
 
+/// Disk usage of the pipeline relative to the installer size, measured on recent builds
+const INSTALLER_SPACE: f64 = 1.0;
+/// Extracted installer, including nested archives until they are unpacked
+const EXTRACTED_SPACE: f64 = 3.0;
+/// Extracted `app.asar` (`src`), and its patched copy (`mod`) each
+const SOURCES_SPACE: f64 = 1.5;
+/// Portable archive and package layouts
+const PACKAGES_SPACE: f64 = 2.0;
+
+/// Estimated peak disk usage of patching `build`, in bytes.
+///
+/// Includes the staged installer and extracted files, which share the volume
+/// when the output is in the temp directory. They are deleted before the
+/// sources are copied, so they never coexist with the patched copy.
+pub fn required_space(build: &AppBuild, options: &PatchOptions) -> u64 {
+    let extracting = INSTALLER_SPACE + EXTRACTED_SPACE + SOURCES_SPACE;
+    let mut peak = if options.dry_run {
+        extracting
+    } else {
+        extracting.max(2.0 * SOURCES_SPACE)
+    };
+    if !options.packages.is_empty() && !options.dry_run {
+        peak += PACKAGES_SPACE;
+    }
+    (build.size as f64 * peak) as u64
+}
+
+/// Space taken in the staging directory by the installer and its extracted files
+fn staging_space(build: &AppBuild) -> u64 {
+    (build.size as f64 * (INSTALLER_SPACE + EXTRACTED_SPACE)) as u64
+}
+
+/// Fresh directory in [`config::temp_dir`] for the installer and its extracted
+/// files, removed when dropped
+fn staging_dir(version: &str) -> Result<tempfile::TempDir> {
+    let temp_dir = config::temp_dir();
+    fs::create_dir_all(&temp_dir)?;
+    let staging = tempfile::Builder::new()
+        .prefix(&format!("{}{}-", journal::STAGING_PREFIX, version))
+        .tempdir_in(&temp_dir)
+        .context("Failed to create a staging directory in the temp directory")?;
+    debug!("Staging in {:?}", staging.path());
+    Ok(staging)
+}
+
+/// Fail before downloading when the output or staging volume is too small for the run
+fn check_disk_space(
+    output_dir: &Path,
+    staging_dir: &Path,
+    build: &AppBuild,
+    options: &PatchOptions,
+) -> Result<()> {
+    // Counts the staged files too, in case both are on the same volume
+    check_free_space(output_dir, required_space(build, options), "--output")?;
+    check_free_space(staging_dir, staging_space(build), "TMPDIR/TEMP")
+}
+
+fn check_free_space(dir: &Path, required: u64, hint: &str) -> Result<()> {
+    match paths::available_space(dir) {
+        Ok(free) if free < required => Err(PatcherError::InsufficientSpace(tr!(
+            "about {} needed in {:?}, {} free. Free up space or choose another {}",
+            "в {1:?} нужно около {0}, свободно {2}. Освободите место или выберите другой {3}",
+            format_bytes(required, None),
+            dir,
+            format_bytes(free, None),
+            hint
+        ))
+        .into()),
+        Ok(free) => {
+            debug!("{} free, about {} needed", free, required);
+            Ok(())
+        }
+        Err(e) => {
+            warn!("{:#}", e);
+            Ok(())
+        }
+    }
+}
+
+/// Start the patched app from its sources with Electron via npx
+pub fn launch_app(mod_dir: &Path) -> Result<()> {
+    spawn_app(mod_dir)?;
     Ok(())
 }
 
-fn update_progress(progress: Option<&ProgressBar>, pos: u64, msg: &str) {
-    if let Some(pb) = progress {
-        pb.set_position(pos);
-        pb.set_message(msg.to_string());
+/// [`launch_app`], keeping the handle of the started npx process. On Unix it
+/// leads a process group of its own, so Electron can be stopped with it.
+pub(crate) fn spawn_app(mod_dir: &Path) -> Result<std::process::Child> {
+    info!("Launching {:?}", mod_dir);
+    let mut command = Command::new(NPX);
+    command.args(["electron", "."]).current_dir(mod_dir);
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut command, 0);
+    command
+        .spawn()
+        .context("Failed to launch Electron (is Node.js/npx in PATH?)")
+}
+
+/// Write the patch report as JSON into the build directory
+fn write_report(build_dir: &Path, report: &PatchReport) -> Result<()> {
+    let path = build_dir.join(PATCH_REPORT_FILE);
+    fs::write(&path, serde_json::to_string_pretty(report)?)?;
+    info!("Patch report written to {:?}", path);
+    Ok(())
+}
+
+/// Diff the text files touched by the built-in patches and profile rules.
+///
+/// `index.js` and `preload.js` are skipped: the mod code is only appended there.
+fn diff_patched_files(
+    source_dir: &Path,
+    modded_dir: &Path,
+    profile: &PatchProfile,
+    rules: &[RuleOutcome],
+) -> Result<Vec<FileDiff>> {
+    let layout = &profile.layout;
+    let mut paths = vec![
+        layout.package_json.clone(),
+        layout.config_js.clone(),
+        layout.create_window_js.clone(),
+        layout.system_menu_js.clone(),
+    ];
+
+    for entry in WalkDir::new(source_dir.join(&layout.html_root))
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().extension().is_some_and(|ext| ext == "html"))
+    {
+        if let Ok(relative) = entry.path().strip_prefix(source_dir) {
+            paths.push(relative.to_string_lossy().replace('\\', "/"));
+        }
+    }
+    paths.extend(rules.iter().flat_map(|o| o.files.iter().cloned()));
+
+    let mut diffs = Vec::new();
+    for path in paths {
+        if diffs.iter().any(|d: &FileDiff| d.path == path) {
+            continue;
+        }
+        let (Ok(original), Ok(patched)) = (
+            fs::read_to_string(source_dir.join(&path)),
+            fs::read_to_string(modded_dir.join(&path)),
+        ) else {
+            debug!("Skipping diff of {}: not a text file in both trees", path);
+            continue;
+        };
+        if original == patched {
+            continue;
+        }
+        let diff = unified_diff(&path, &original, &patched);
+        diffs.push(FileDiff { path, diff });
+    }
+
+    Ok(diffs)
+}
+
+/// `text` with overly long (minified) lines cut for diffs; the lines stay where
+/// they are
+pub(crate) fn shorten_lines(text: &str) -> String {
+    text.lines()
+        .map(|line| match line.char_indices().nth(DIFF_MAX_LINE_LEN) {
+            Some((idx, _)) => format!("{}…", &line[..idx]),
+            None => line.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+        + "\n"
+}
+
+/// Render a unified diff, shortening overly long (minified) lines
+fn unified_diff(path: &str, original: &str, patched: &str) -> String {
+    let (original, patched) = (shorten_lines(original), shorten_lines(patched));
+
+    similar::TextDiff::from_lines(&original, &patched)
+        .unified_diff()
+        .context_radius(3)
+        .header(&format!("a/{}", path), &format!("b/{}", path))
+        .to_string()
+}
+
+/// Compare every file of `source_dir` with `modded_dir`, sorted by path
+pub fn compare_trees(
+    source_dir: &Path,
+    modded_dir: &Path,
+    with_diffs: bool,
+) -> Result<Vec<TreeChange>> {
+    let files = |root: &Path| -> BTreeSet<String> {
+        WalkDir::new(root)
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file())
+            .filter_map(|e| {
+                e.path()
+                    .strip_prefix(root)
+                    .ok()
+                    .map(|p| p.to_string_lossy().replace('\\', "/"))
+            })
+            .collect()
+    };
+    let source = files(source_dir);
+    let modded = files(modded_dir);
+
+    let mut changes = Vec::new();
+    for path in source.union(&modded) {
+        let kind = match (source.contains(path), modded.contains(path)) {
+            (true, false) => ChangeKind::Removed,
+            (false, true) => ChangeKind::Added,
+            _ => ChangeKind::Modified,
+        };
+        let mut diff = None;
+        if kind == ChangeKind::Modified {
+            let original = fs::read(source_dir.join(path))?;
+            let patched = fs::read(modded_dir.join(path))?;
+            if original == patched {
+                continue;
+            }
+            if with_diffs {
+                // Binary files are reported without a diff
+                if let (Ok(original), Ok(patched)) =
+                    (String::from_utf8(original), String::from_utf8(patched))
+                {
+                    diff = Some(unified_diff(path, &original, &patched));
+                }
+            }
+        }
+        changes.push(TreeChange {
+            path: path.clone(),
+            kind,
+            diff,
+        });
+    }
+
+    Ok(changes)
+}
+
+/// Check which anchors of the built-in patches are present in `app_dir`
+fn check_patch_anchors(app_dir: &Path, profile: &PatchProfile) -> Result<Vec<RuleOutcome>> {
+    let anchors = patches::anchor_rules(&profile.layout);
+    let anchors: Vec<_> = anchors.iter().collect();
+    rules::apply_rules(app_dir, &anchors, true)
+}
+
+fn stage(progress: Option<&dyn ProgressSink>, stage: Stage) {
+    if let Some(progress) = progress {
+        progress.event(Event::Stage { stage });
+    }
+}
+
+fn processed(progress: Option<&dyn ProgressSink>, bytes: u64) {
+    if let Some(progress) = progress {
+        progress.event(Event::Processed { bytes });
     }
 }
 
 /// Find 7-Zip executable on the system
 /// Checks common installation paths on Windows in addition to PATH lookup
-fn find_7z_executable() -> Option<PathBuf> {
+pub fn find_7z_executable() -> Option<PathBuf> {
     // First try PATH lookup for common command names
     for cmd in &["7z", "7zz", "7za"] {
         if let Ok(output) = Command::new(cmd).arg("--help").output() {
@@ -234,19 +1114,40 @@ fn find_app_asar(extract_dir: &Path) -> Result<PathBuf> {
         debug!("  {:?}", entry.path());
     }
 
-    anyhow::bail!(
+    anyhow::bail!(tr!(
         "app.asar not found in {:?}. The installer structure may have changed.",
+        "app.asar не найден в {:?}. Возможно, изменилась структура установщика.",
         extract_dir
-    )
+    ))
+}
+
+/// Keep only the app archive of `arch` when a multi-architecture NSIS installer
+/// ships one per architecture (`app-64.7z`, `app-arm64.7z`, `app-32.7z`), which
+/// would otherwise be extracted over each other
+fn select_app_archive(archives: &mut Vec<PathBuf>, arch: Arch) {
+    let wanted = match arch {
+        Arch::X64 => "app-64.7z",
+        Arch::Arm64 => "app-arm64.7z",
+    };
+    let is_app = |path: &PathBuf| {
+        path.file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with("app-"))
+    };
+    let has_wanted = archives
+        .iter()
+        .any(|path| path.file_name().is_some_and(|name| name == wanted));
+    if has_wanted && archives.iter().filter(|path| is_app(path)).count() > 1 {
+        debug!("Taking {} of the per-architecture app archives", wanted);
+        archives
+            .retain(|path| !is_app(path) || path.file_name().is_some_and(|name| name == wanted));
+    }
 }
 
 /// Extract nested 7z archives found in the extracted installer
 /// NSIS installers often contain nested archives like app-64.7z or app.7z
-fn extract_nested_archives(extract_dir: &Path) -> Result<()> {
-    let seven_zip = find_7z_executable();
-
+fn extract_nested_archives(extract_dir: &Path, arch: Arch) -> Result<()> {
     // Find all .7z files in the extracted directory
-    let archives: Vec<PathBuf> = WalkDir::new(extract_dir)
+    let mut archives: Vec<PathBuf> = WalkDir::new(extract_dir)
         .max_depth(3)
         .into_iter()
         .filter_map(|e| e.ok())
@@ -262,28 +1163,35 @@ fn extract_nested_archives(extract_dir: &Path) -> Result<()> {
         debug!("No nested archives found");
         return Ok(());
     }
+    select_app_archive(&mut archives, arch);
 
-    let executable = match &seven_zip {
-        Some(exe) => exe,
-        None => {
-            // If no nested archives need extraction, this is fine
-            // But if we found archives and can't extract them, that's a problem
-            warn!(
-                "Found {} nested archive(s) but 7z not available to extract them",
-                archives.len()
-            );
-            return Ok(());
-        }
-    };
-
+    let mut seven_zip = None;
     for archive in archives {
         info!("Extracting nested archive: {:?}", archive);
 
         // Extract to the same directory as the archive
         let output_dir = archive.parent().unwrap_or(extract_dir);
 
+        #[cfg(feature = "native-extract")]
+        match extract_nested_native(&archive, output_dir) {
+            Ok(()) => {
+                let _ = paths::remove_file(&archive);
+                continue;
+            }
+            Err(e) => warn!("Native extraction of {:?} failed: {:#}", archive, e),
+        }
+
+        let executable = match seven_zip.get_or_insert_with(find_7z_executable) {
+            Some(exe) => exe,
+            None => {
+                // The archive may not hold the app, so this is not fatal yet
+                warn!("7z not available to extract {:?}", archive);
+                continue;
+            }
+        };
         let result = Command::new(executable)
-            .args(["x", "-y", &format!("-o{}", output_dir.display())])
+            .args(["x", "-y"])
+            .arg(seven_zip_output(output_dir))
             .arg(&archive)
             .output();
 
@@ -292,7 +1200,7 @@ fn extract_nested_archives(extract_dir: &Path) -> Result<()> {
                 if output.status.success() {
                     info!("Successfully extracted nested archive: {:?}", archive);
                     // Remove the archive after extraction to save space
-                    let _ = fs::remove_file(&archive);
+                    let _ = paths::remove_file(&archive);
                 } else {
                     let stderr = String::from_utf8_lossy(&output.stderr);
                     warn!("Failed to extract {:?}: {}", archive, stderr);
@@ -307,6 +1215,24 @@ fn extract_nested_archives(extract_dir: &Path) -> Result<()> {
     Ok(())
 }
 
+/// Extract a nested `.7z` or `.nupkg` (zip) archive without external tools
+#[cfg(feature = "native-extract")]
+fn extract_nested_native(archive: &Path, output_dir: &Path) -> Result<()> {
+    if archive.extension().is_some_and(|ext| ext == "nupkg") {
+        extract_with_zip(archive, output_dir)
+    } else {
+        sevenz::extract(archive, output_dir).map(|_| ())
+    }
+}
+
+/// The `-o<dir>` switch of 7z, built from the raw path so non-Unicode
+/// directories are passed through unchanged
+fn seven_zip_output(dir: &Path) -> OsString {
+    let mut switch = OsString::from("-o");
+    switch.push(dir);
+    switch
+}
+
 /// Try to extract using a specific 7z executable path
 fn try_7z_extract(executable: &Path, installer_path: &Path, output_dir: &Path) -> Result<()> {
     let result = Command::new(executable)
@@ -334,11 +1260,19 @@ fn try_7z_extract(executable: &Path, installer_path: &Path, output_dir: &Path) -
     }
 }
 
-/// Extract the installer using 7z or a built-in extractor
+/// Extract the installer with the built-in extractor or 7z
 fn extract_installer(installer_path: &Path, output_dir: &Path) -> Result<()> {
+    // The app archive is stored as is in the installer, so no 7-Zip is needed
+    #[cfg(feature = "native-extract")]
+    match sevenz::extract(installer_path, output_dir) {
+        Ok(_) => return Ok(()),
+        Err(e) => warn!("Native installer extraction failed: {:#}", e),
+    }
+
     // Try to find and use 7z
-    if let Some(executable) = find_7z_executable() {
-        match try_7z_extract(&executable, installer_path, output_dir) {
+    let seven_zip = find_7z_executable();
+    if let Some(executable) = &seven_zip {
+        match try_7z_extract(executable, installer_path, output_dir) {
             Ok(_) => return Ok(()),
             Err(e) => {
                 warn!("7z extraction failed with {:?}: {}", executable, e);
@@ -362,6 +1296,7 @@ fn extract_installer(installer_path: &Path, output_dir: &Path) -> Result<()> {
     }
 
     // If all else fails, try using the zip crate (may work for some installers)
+    #[cfg(feature = "native-extract")]
     match extract_with_zip(installer_path, output_dir) {
         Ok(_) => return Ok(()),
         Err(e) => {
@@ -369,15 +1304,24 @@ fn extract_installer(installer_path: &Path, output_dir: &Path) -> Result<()> {
         }
     }
 
-    anyhow::bail!(
+    if seven_zip.is_some() {
+        return Err(PatcherError::ExtractionError(installer_path.display().to_string()).into());
+    }
+    Err(PatcherError::MissingDependency(tr!(
         "Failed to extract installer. Please install 7z/7zip and ensure it's in PATH.\n\
          On Windows: Download from https://www.7-zip.org/\n\
          On Linux: apt install p7zip-full\n\
-         On macOS: brew install p7zip"
-    )
+         On macOS: brew install p7zip",
+        "Не удалось распаковать установщик. Установите 7z/7zip и добавьте его в PATH.\n\
+         Windows: скачайте с https://www.7-zip.org/\n\
+         Linux: apt install p7zip-full\n\
+         macOS: brew install p7zip"
+    ))
+    .into())
 }
 
 /// Try to extract using the zip crate
+#[cfg(feature = "native-extract")]
 fn extract_with_zip(archive_path: &Path, output_dir: &Path) -> Result<()> {
     let file = fs::File::open(archive_path)?;
     let mut archive = zip::ZipArchive::new(file)?;
@@ -405,8 +1349,16 @@ fn extract_with_zip(archive_path: &Path, output_dir: &Path) -> Result<()> {
     Ok(())
 }
 
-/// Extract an ASAR archive
-fn extract_asar(asar_path: &Path, output_dir: &Path) -> Result<()> {
+/// Extract an ASAR archive with the built-in extractor, asar or npx asar
+pub(crate) fn extract_asar(asar_path: &Path, output_dir: &Path) -> Result<()> {
+    #[cfg(feature = "native-extract")]
+    match extract_asar_native(asar_path, output_dir) {
+        Ok(_) => return Ok(()),
+        Err(e) => {
+            warn!("Native asar extraction failed: {:#}", e);
+        }
+    }
+
     // Try using the asar command-line tool
     let result = Command::new("asar")
         .args(["extract"])
@@ -431,7 +1383,7 @@ fn extract_asar(asar_path: &Path, output_dir: &Path) -> Result<()> {
     }
 
     // Try using npx asar
-    let result = Command::new("npx")
+    let result = Command::new(NPX)
         .args(["asar", "extract"])
         .arg(asar_path)
         .arg(output_dir)
@@ -453,27 +1405,31 @@ fn extract_asar(asar_path: &Path, output_dir: &Path) -> Result<()> {
         }
     }
 
-    // Try using the asar crate
-    match extract_asar_native(asar_path, output_dir) {
-        Ok(_) => return Ok(()),
-        Err(e) => {
-            warn!("Native asar extraction failed: {}", e);
-        }
-    }
-
-    anyhow::bail!(
+    anyhow::bail!(tr!(
         "Failed to extract app.asar. Please install asar:\n\
          npm install -g asar\n\
-         Or ensure Node.js/npx is in PATH."
-    )
+         Or ensure Node.js/npx is in PATH.",
+        "Не удалось распаковать app.asar. Установите asar:\n\
+         npm install -g asar\n\
+         Или добавьте Node.js/npx в PATH."
+    ))
 }
 
 /// Native ASAR extraction using the asar crate
+///
+/// The crate neither reads symlink entries nor reports the executable flag, so
+/// both are taken from the raw header and restored after the files are written.
+#[cfg(feature = "native-extract")]
 fn extract_asar_native(asar_path: &Path, output_dir: &Path) -> Result<()> {
-    use asar::AsarReader;
+    use ::asar::{AsarReader, Header};
 
     let asar_data = fs::read(asar_path)?;
-    let reader = AsarReader::new(&asar_data, Some(asar_path.to_path_buf()))
+    let (mut header, offset) = asar_header(&asar_data).context("Failed to read ASAR archive")?;
+    let mut executables = Vec::new();
+    let mut links = Vec::new();
+    take_asar_links(&mut header, Path::new(""), &mut executables, &mut links);
+    let header: Header = serde_json::from_value(header).context("Failed to read ASAR archive")?;
+    let reader = AsarReader::new_from_header(header, offset, &asar_data, asar_path.to_path_buf())
         .context("Failed to read ASAR archive")?;
 
     for (path, file) in reader.files() {
@@ -489,32 +1445,120 @@ fn extract_asar_native(asar_path: &Path, output_dir: &Path) -> Result<()> {
         }
     }
 
+    #[cfg(unix)]
+    for path in executables {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = output_dir.join(path);
+        if let Ok(metadata) = fs::metadata(&path) {
+            let mut permissions = metadata.permissions();
+            permissions.set_mode(permissions.mode() | 0o111);
+            fs::set_permissions(&path, permissions)?;
+        }
+    }
+
+    for (path, link) in links {
+        // Links point from the archive root; refuse ones leaving the archive
+        if link
+            .components()
+            .any(|c| !matches!(c, std::path::Component::Normal(_)))
+        {
+            warn!(
+                "Skipping ASAR link {:?} to {:?} outside the archive",
+                path, link
+            );
+            continue;
+        }
+        let output_path = output_dir.join(&path);
+        if let Some(parent) = output_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let depth = path.components().count().saturating_sub(1);
+        let target: PathBuf = std::iter::repeat_n(Path::new(".."), depth)
+            .collect::<PathBuf>()
+            .join(&link);
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&target, &output_path)
+            .with_context(|| format!("Failed to link {:?} to {:?}", output_path, target))?;
+        #[cfg(not(unix))]
+        warn!("Skipping ASAR link {:?} to {:?}", output_path, target);
+    }
+
     Ok(())
 }
 
+/// Header JSON of an ASAR archive and the offset of its file data
+pub(crate) fn asar_header(data: &[u8]) -> Result<(serde_json::Value, usize)> {
+    let u32_at = |pos: usize| {
+        data.get(pos..pos + 4)
+            .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()) as usize)
+            .context("Truncated ASAR header")
+    };
+    // Size pickle (4, header size), then the header pickle (payload size, JSON length, JSON)
+    let header_size = u32_at(4)?;
+    let json_size = u32_at(12)?;
+    let json = data
+        .get(16..16 + json_size)
+        .context("Truncated ASAR header")?;
+    Ok((serde_json::from_slice(json)?, header_size + 8))
+}
+
+/// Remove the `link` entries below `node` into `links` as (path, target) and
+/// collect the paths of executable files
+#[cfg(feature = "native-extract")]
+fn take_asar_links(
+    node: &mut serde_json::Value,
+    path: &Path,
+    executables: &mut Vec<PathBuf>,
+    links: &mut Vec<(PathBuf, PathBuf)>,
+) {
+    let Some(files) = node.get_mut("files").and_then(|f| f.as_object_mut()) else {
+        return;
+    };
+    files.retain(|name, entry| {
+        let path = path.join(name);
+        if let Some(link) = entry.get("link").and_then(|l| l.as_str()) {
+            links.push((path, PathBuf::from(link.replace('\\', "/"))));
+            return false;
+        }
+        if entry.get("executable").and_then(|e| e.as_bool()) == Some(true) {
+            executables.push(path.clone());
+        }
+        take_asar_links(entry, &path, executables, links);
+        true
+    });
+}
+
 /// Recursively copy a directory
-fn copy_dir_all(src: &Path, dst: &Path) -> Result<()> {
+///
+/// Permission bits are kept and, on Unix, symlinks are recreated rather than
+/// followed, so helpers like `chrome-sandbox` stay usable.
+pub(crate) fn copy_dir_all(src: &Path, dst: &Path) -> Result<()> {
     fs::create_dir_all(dst)?;
     for entry in fs::read_dir(src)? {
         let entry = entry?;
+        // `file_type` doesn't follow symlinks
         let ty = entry.file_type()?;
         if ty.is_dir() {
             copy_dir_all(&entry.path(), &dst.join(entry.file_name()))?;
         } else {
-            fs::copy(entry.path(), dst.join(entry.file_name()))?;
+            paths::copy_file(&entry.path(), &dst.join(entry.file_name()))?;
         }
     }
+    // After the contents, in case the directory is read-only
+    fs::set_permissions(dst, fs::metadata(src)?.permissions())?;
     Ok(())
 }
 
 /// Apply all patches to the modded directory
-fn apply_patches(modded_dir: &Path, auto_devtools: bool) -> Result<()> {
-    let package_json_path = modded_dir.join("package.json");
-    let config_js_path = modded_dir.join("main").join("config.js");
-    let main_js_path = modded_dir.join("main").join("index.js");
-    let preload_js_path = modded_dir.join("main").join("lib").join("preload.js");
-    let create_window_js_path = modded_dir.join("main").join("lib").join("createWindow.js");
-    let system_menu_js_path = modded_dir.join("main").join("lib").join("systemMenu.js");
+fn apply_patches(modded_dir: &Path, profile: &PatchProfile, options: &PatchOptions) -> Result<()> {
+    let layout = &profile.layout;
+    let package_json_path = modded_dir.join(&layout.package_json);
+    let config_js_path = modded_dir.join(&layout.config_js);
+    let main_js_path = modded_dir.join(&layout.main_js);
+    let preload_js_path = modded_dir.join(&layout.preload_js);
+    let create_window_js_path = modded_dir.join(&layout.create_window_js);
+    let system_menu_js_path = modded_dir.join(&layout.system_menu_js);
 
     // Patch package.json
     if package_json_path.exists() {
@@ -544,7 +1588,19 @@ fn apply_patches(modded_dir: &Path, auto_devtools: bool) -> Result<()> {
     if create_window_js_path.exists() {
         info!("Patching createWindow.js");
         let content = fs::read_to_string(&create_window_js_path)?;
-        let patched = patches::patch_create_window_js(&content, auto_devtools);
+        let mut patched = patches::patch_create_window_js(&content, &options.window);
+        if options.features.contains(&ModFeature::Hardening) {
+            let stock_preload = fs::read_to_string(&preload_js_path).unwrap_or_default();
+            let needs_node = patches::preload_needs_node(&stock_preload)
+                || options
+                    .user_preload_js
+                    .as_deref()
+                    .is_some_and(patches::preload_needs_node);
+            if needs_node {
+                warn!("The preload scripts load Node.js modules, windows are not sandboxed");
+            }
+            patched = patches::harden_create_window_js(&patched, !needs_node);
+        }
         fs::write(&create_window_js_path, patched)?;
     }
 
@@ -552,12 +1608,40 @@ fn apply_patches(modded_dir: &Path, auto_devtools: bool) -> Result<()> {
     if main_js_path.exists() {
         info!("Patching index.js");
         let content = fs::read_to_string(&main_js_path)?;
-        let mut patched = patches::patch_main_js(&content);
+        let mut patched = patches::patch_main_js(&content, &options.banned_headers);
 
         // Append mod main.js
+        if let Some(profile) = &options.settings_profile {
+            if !patches::is_valid_settings_profile(profile) {
+                anyhow::bail!("Invalid settings profile name {:?}", profile);
+            }
+        }
         patched.push_str("\n\n// YandexMusicMod main.js\n");
+        patched.push_str(&patches::generate_default_settings_js(
+            &options.default_settings,
+            options.settings_profile.as_deref(),
+        ));
+        patched.push_str(&patches::generate_html_root_js(&layout.html_root));
+        patched.push_str(&patches::generate_release_key_js(
+            patches::RELEASE_PUBLIC_KEY_PEM,
+        ));
         patched.push_str(patches::MOD_MAIN_JS);
 
+        // Append devtools keyboard shortcut
+        patched.push_str("\n\n// YandexMusicMod devtools shortcut\n");
+        patched.push_str(patches::DEVTOOLS_SHORTCUT_JS);
+
+        // Append proxy support
+        patched.push_str("\n\n// YandexMusicMod proxy\n");
+        patched.push_str(patches::PROXY_MAIN_JS);
+
+        // Append enabled optional features
+        for feature in &options.features {
+            info!("Enabling feature: {:?}", feature);
+            patched.push_str(&format!("\n\n// YandexMusicMod feature: {:?}\n", feature));
+            patched.push_str(feature.main_js());
+        }
+
         fs::write(&main_js_path, patched)?;
     }
 
@@ -571,58 +1655,249 @@ fn apply_patches(modded_dir: &Path, auto_devtools: bool) -> Result<()> {
         patched.push_str("\n\n// YandexMusicMod preload.js\n");
         patched.push_str(patches::MOD_PRELOAD_JS);
 
+        // Append preload code of enabled features
+        for feature in &options.features {
+            let preload = feature.preload_js();
+            if !preload.is_empty() {
+                patched.push_str(&format!("\n\n// YandexMusicMod feature: {:?}\n", feature));
+                patched.push_str(preload);
+            }
+        }
+
+        // Append user preload.js from the config directory
+        if let Some(user_preload) = &options.user_preload_js {
+            info!("Appending user preload script");
+            patched.push_str("\n\n// YandexMusicMod preload.user.js\n");
+            patched.push_str(user_preload);
+        }
+
         fs::write(&preload_js_path, patched)?;
     }
 
     // Remove splash screen if it exists
-    let splash_screen_path = modded_dir.join("app").join("media").join("splash_screen");
+    let splash_screen_path = modded_dir
+        .join(&layout.html_root)
+        .join("media")
+        .join("splash_screen");
     if splash_screen_path.exists() {
         info!("Removing splash screen");
-        fs::remove_dir_all(&splash_screen_path)?;
+        paths::remove_dir_all(&splash_screen_path)?;
     }
 
     Ok(())
 }
 
+/// Apply the declarative rules of the selected profile for the given app version
+fn apply_profile_rules(
+    modded_dir: &Path,
+    profile: &PatchProfile,
+    app_version: &str,
+    dry_run: bool,
+) -> Result<Vec<RuleOutcome>> {
+    let rules = profile.collect_rules(app_version)?;
+    info!(
+        "Applying {} rule(s) of profile {} for version {}",
+        rules.len(),
+        profile.name,
+        app_version
+    );
+    let rules: Vec<_> = rules.iter().collect();
+    rules::apply_rules(modded_dir, &rules, dry_run)
+}
+
 /// Create mod files in the app directory
-fn create_mod_files(modded_dir: &Path) -> Result<()> {
-    let mod_dir = modded_dir.join("app").join("yandexMusicMod");
+fn create_mod_files(
+    modded_dir: &Path,
+    profile: &PatchProfile,
+    options: &PatchOptions,
+) -> Result<()> {
+    let mod_dir = modded_dir
+        .join(&profile.layout.html_root)
+        .join(MOD_ASSETS_DIR);
     fs::create_dir_all(&mod_dir)?;
 
     // Create renderer.js
-    fs::write(mod_dir.join("renderer.js"), patches::MOD_RENDERER_JS)?;
+    let js = patches::generate_renderer_js(&options.features);
+    fs::write(mod_dir.join("renderer.js"), js)?;
 
     // Create renderer.css
-    fs::write(mod_dir.join("renderer.css"), patches::MOD_RENDERER_CSS)?;
+    let css = patches::generate_renderer_css(&options.promo_selectors);
+    fs::write(mod_dir.join("renderer.css"), css)?;
+
+    // Create assets of enabled features
+    for feature in &options.features {
+        for (name, content) in feature.assets() {
+            fs::write(mod_dir.join(name), content)?;
+        }
+    }
 
     info!("Created mod files in {:?}", mod_dir);
     Ok(())
 }
 
 /// Inject mod scripts into all HTML files
-fn inject_mod_into_html(modded_dir: &Path) -> Result<()> {
-    let app_dir = modded_dir.join("app");
+fn inject_mod_into_html(modded_dir: &Path, profile: &PatchProfile) -> Result<Vec<HtmlOutcome>> {
+    let app_dir = modded_dir.join(&profile.layout.html_root);
 
-    for entry in WalkDir::new(&app_dir)
+    let files: Vec<PathBuf> = WalkDir::new(&app_dir)
         .into_iter()
         .filter_map(|e| e.ok())
-        .filter(|e| e.path().extension().is_some_and(|ext| ext == "html"))
-    {
-        let path = entry.path();
-        info!("Patching HTML: {:?}", path);
+        .filter(|e| e.file_type().is_file() && is_html_file(e.path()))
+        .map(|e| e.into_path())
+        .collect();
 
-        let content = fs::read_to_string(path)?;
-        let patched = patches::patch_html(&content);
-        fs::write(path, patched)?;
-    }
+    let mut outcomes = files
+        .par_iter()
+        .map(|path| {
+            let content =
+                fs::read_to_string(path).with_context(|| format!("Failed to read {:?}", path))?;
+            let patched = patches::patch_html(&content);
+            let injected = patched != content;
+            if injected {
+                info!("Patched HTML: {:?}", path);
+                fs::write(path, patched).with_context(|| format!("Failed to write {:?}", path))?;
+            } else {
+                warn!("No <head> to inject the mod into in {:?}", path);
+            }
+            Ok(HtmlOutcome {
+                path: path
+                    .strip_prefix(&app_dir)
+                    .unwrap_or(path)
+                    .to_string_lossy()
+                    .replace('\\', "/"),
+                injected,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    outcomes.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(outcomes)
+}
 
-    Ok(())
+/// HTML pages and their templates: `.html`, `.htm` and e.g. `index.html.tmpl`
+fn is_html_file(path: &Path) -> bool {
+    const HTML: [&str; 2] = ["html", "htm"];
+    const TEMPLATES: [&str; 5] = ["tmpl", "tpl", "template", "ejs", "hbs"];
+
+    let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+        return false;
+    };
+    let name = name.to_ascii_lowercase();
+    let mut parts = name.rsplit('.');
+    match (parts.next(), parts.next()) {
+        (Some(ext), _) if HTML.contains(&ext) => true,
+        (Some(ext), Some(inner)) => TEMPLATES.contains(&ext) && HTML.contains(&inner),
+        _ => false,
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_unified_diff() {
+        let diff = unified_diff("main/config.js", "a\nb\nc\n", "a\nB\nc\n");
+        assert!(diff.contains("--- a/main/config.js"));
+        assert!(diff.contains("-b\n+B"));
+
+        let long = "x".repeat(DIFF_MAX_LINE_LEN * 2);
+        let diff = unified_diff("app/chunk.js", &long, "y");
+        assert!(diff.contains('…'));
+        assert!(!diff.contains(&long));
+    }
+
+    #[test]
+    fn test_required_space() {
+        let build = AppBuild {
+            path: "Yandex_Music_x64_5.0.0.exe".to_string(),
+            hash: String::new(),
+            size: 100,
+            release_date: None,
+            update_probability: None,
+            version: "5.0.0".to_string(),
+            deprecated_versions: None,
+            channel: "stable".to_string(),
+        };
+        let mut options = PatchOptions::default();
+        assert_eq!(required_space(&build, &options), 550);
+
+        options.packages = vec![PackageFormat::Scoop];
+        assert_eq!(required_space(&build, &options), 750);
+        assert_eq!(staging_space(&build), 400);
+
+        let dir = tempfile::tempdir().unwrap();
+        let huge = AppBuild {
+            size: u64::MAX / 8,
+            ..build
+        };
+        let error = check_disk_space(dir.path(), dir.path(), &huge, &options).unwrap_err();
+        assert_eq!(crate::error::code(&error), 41);
+    }
+
+    #[test]
+    fn test_compare_trees() {
+        let temp = tempfile::tempdir().unwrap();
+        let src = temp.path().join("src");
+        let modded = temp.path().join("mod");
+        fs::create_dir_all(src.join("main")).unwrap();
+        fs::create_dir_all(modded.join("main")).unwrap();
+        fs::write(src.join("main/config.js"), "a\nb\n").unwrap();
+        fs::write(modded.join("main/config.js"), "a\nB\n").unwrap();
+        fs::write(src.join("same.js"), "x").unwrap();
+        fs::write(modded.join("same.js"), "x").unwrap();
+        fs::write(src.join("gone.js"), "x").unwrap();
+        fs::write(modded.join("mod.js"), "x").unwrap();
+
+        let changes = compare_trees(&src, &modded, true).unwrap();
+        let summary: Vec<_> = changes.iter().map(|c| (c.path.as_str(), c.kind)).collect();
+        assert_eq!(
+            summary,
+            [
+                ("gone.js", ChangeKind::Removed),
+                ("main/config.js", ChangeKind::Modified),
+                ("mod.js", ChangeKind::Added),
+            ]
+        );
+        assert!(changes[1].diff.as_deref().unwrap().contains("-b\n+B"));
+        assert!(changes[0].diff.is_none());
+    }
+
+    #[test]
+    fn test_inject_mod_into_html() {
+        let temp = tempfile::tempdir().unwrap();
+        let profiles = ProfileSet::builtin().unwrap();
+        let profile = profiles.select("5.40.1", None).unwrap();
+        let html_root = temp.path().join(&profile.layout.html_root);
+        fs::create_dir_all(html_root.join("pages")).unwrap();
+        fs::write(html_root.join("index.html"), "<head></head>").unwrap();
+        fs::write(html_root.join("pages").join("about.HTM"), "<head></head>").unwrap();
+        fs::write(html_root.join("pages").join("page.html.tmpl"), "<head>").unwrap();
+        fs::write(html_root.join("fragment.html"), "<div></div>").unwrap();
+        fs::write(html_root.join("main.js"), "'<head>'").unwrap();
+
+        let outcomes = inject_mod_into_html(temp.path(), profile).unwrap();
+        let summary: Vec<_> = outcomes
+            .iter()
+            .map(|o| (o.path.as_str(), o.injected))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("fragment.html", false),
+                ("index.html", true),
+                ("pages/about.HTM", true),
+                ("pages/page.html.tmpl", true),
+            ]
+        );
+        assert!(fs::read_to_string(html_root.join("index.html"))
+            .unwrap()
+            .contains("/yandexMusicMod/renderer.js"));
+        assert_eq!(
+            fs::read_to_string(html_root.join("main.js")).unwrap(),
+            "'<head>'"
+        );
+    }
+
     #[test]
     fn test_copy_dir_all() {
         let temp = tempfile::tempdir().unwrap();
@@ -636,5 +1911,286 @@ mod tests {
 
         assert!(dst.join("test.txt").exists());
         assert_eq!(fs::read_to_string(dst.join("test.txt")).unwrap(), "hello");
+
+        // Non-ASCII directories and file names, as under a Cyrillic user profile
+        let src = temp.path().join("Пользователь");
+        let dst = temp.path().join("Музыка").join("копия");
+        fs::create_dir_all(src.join("ресурсы")).unwrap();
+        fs::write(src.join("ресурсы").join("трек.txt"), "ля").unwrap();
+
+        copy_dir_all(&src, &dst).unwrap();
+        assert_eq!(
+            fs::read_to_string(dst.join("ресурсы").join("трек.txt")).unwrap(),
+            "ля"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_copy_dir_all_keeps_modes_and_links() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp = tempfile::tempdir().unwrap();
+        let src = temp.path().join("src");
+        let dst = temp.path().join("dst");
+        fs::create_dir_all(src.join("lib")).unwrap();
+        fs::write(src.join("chrome-sandbox"), "elf").unwrap();
+        fs::set_permissions(
+            src.join("chrome-sandbox"),
+            fs::Permissions::from_mode(0o755),
+        )
+        .unwrap();
+        fs::write(src.join("lib").join("libffmpeg.so"), "so").unwrap();
+        std::os::unix::fs::symlink("lib/libffmpeg.so", src.join("libffmpeg.so")).unwrap();
+        std::os::unix::fs::symlink("lib", src.join("lib64")).unwrap();
+
+        copy_dir_all(&src, &dst).unwrap();
+
+        let mode = fs::metadata(dst.join("chrome-sandbox"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o755);
+        for (link, target) in [("libffmpeg.so", "lib/libffmpeg.so"), ("lib64", "lib")] {
+            assert_eq!(fs::read_link(dst.join(link)).unwrap(), Path::new(target));
+        }
+        assert_eq!(fs::read_to_string(dst.join("libffmpeg.so")).unwrap(), "so");
+    }
+
+    #[cfg(all(unix, feature = "native-extract"))]
+    #[test]
+    fn test_extract_asar_native_keeps_modes_and_links() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let header = serde_json::json!({ "files": {
+            "helper": { "size": 2, "offset": "0", "executable": true },
+            "lib": { "files": {
+                "index.js": { "size": 1, "offset": "2" },
+                "current": { "link": "lib/index.js" },
+            }},
+            "main.js": { "link": "lib/index.js" },
+            "escape": { "link": "../outside" },
+        }});
+        let mut json = serde_json::to_vec(&header).unwrap();
+        let json_size = json.len() as u32;
+        json.resize(json.len().next_multiple_of(4), 0);
+        let header_size = json.len() as u32 + 8;
+        let mut archive = Vec::new();
+        for value in [4, header_size, header_size - 4, json_size] {
+            archive.extend(value.to_le_bytes());
+        }
+        archive.extend(json);
+        archive.extend(b"#!x");
+
+        let temp = tempfile::tempdir().unwrap();
+        let asar_path = temp.path().join("app.asar");
+        fs::write(&asar_path, archive).unwrap();
+        let out = temp.path().join("out");
+        extract_asar_native(&asar_path, &out).unwrap();
+
+        let mode = fs::metadata(out.join("helper"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o111, 0o111);
+        assert_eq!(fs::read_to_string(out.join("helper")).unwrap(), "#!");
+        assert_eq!(
+            fs::read_link(out.join("main.js")).unwrap(),
+            Path::new("lib/index.js")
+        );
+        assert_eq!(
+            fs::read_link(out.join("lib").join("current")).unwrap(),
+            Path::new("../lib/index.js")
+        );
+        assert_eq!(fs::read_to_string(out.join("lib/current")).unwrap(), "x");
+        assert!(fs::symlink_metadata(out.join("escape")).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_seven_zip_output() {
+        use std::os::unix::ffi::{OsStrExt, OsStringExt};
+
+        let dir = Path::new(std::ffi::OsStr::from_bytes(b"/tmp/\xd0\x9c\xff"));
+        assert_eq!(seven_zip_output(dir).into_vec(), b"-o/tmp/\xd0\x9c\xff");
+    }
+
+    #[tokio::test]
+    async fn test_refresh_assets() {
+        let temp = tempfile::tempdir().unwrap();
+        let build_dir = temp.path().join("5.40.1");
+        let profiles = ProfileSet::load().unwrap();
+        let profile = profiles.select("5.40.1", None).unwrap();
+        let mod_dir = build_dir
+            .join("mod")
+            .join(&profile.layout.html_root)
+            .join(MOD_ASSETS_DIR);
+        fs::create_dir_all(&mod_dir).unwrap();
+        fs::write(mod_dir.join("renderer.js"), "// old").unwrap();
+        fs::write(mod_dir.join("stale.css"), "").unwrap();
+
+        let build = AppBuild {
+            path: "Yandex_Music_x64_5.40.1.exe".to_string(),
+            hash: "c2hhNTEy".to_string(),
+            size: 0,
+            release_date: None,
+            update_probability: None,
+            version: "5.40.1".to_string(),
+            deprecated_versions: None,
+            channel: "stable".to_string(),
+        };
+        manifest::write(&build_dir, &build, profile).unwrap();
+
+        let files = refresh_assets(&build_dir, &PatchOptions::default())
+            .await
+            .unwrap();
+        let prefix = format!("{}/{}", profile.layout.html_root, MOD_ASSETS_DIR);
+        assert!(files.contains(&format!("{}/renderer.js", prefix)));
+        assert!(!mod_dir.join("stale.css").exists());
+        assert_ne!(
+            fs::read_to_string(mod_dir.join("renderer.js")).unwrap(),
+            "// old"
+        );
+        assert!(manifest::verify(&build_dir).unwrap().is_intact());
+        assert_eq!(manifest::load(&build_dir).unwrap().source_hash, build.hash);
+
+        assert!(
+            refresh_assets(&temp.path().join("missing"), &PatchOptions::default())
+                .await
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_reapply_patches() {
+        let temp = tempfile::tempdir().unwrap();
+        let build_dir = temp.path().join("5.40.1");
+        let source_dir = build_dir.join("src");
+        fs::create_dir_all(source_dir.join("main/lib")).unwrap();
+        fs::create_dir_all(source_dir.join("app")).unwrap();
+        fs::write(
+            source_dir.join("package.json"),
+            r#"{"name": "YandexMusic", "version": "5.40.1"}"#,
+        )
+        .unwrap();
+        fs::write(source_dir.join("main/lib/preload.js"), "// stock preload").unwrap();
+        fs::write(
+            source_dir.join("app/index.html"),
+            "<html><head></head></html>",
+        )
+        .unwrap();
+        copy_dir_all(&source_dir, &build_dir.join("mod")).unwrap();
+
+        let build = AppBuild {
+            path: "Yandex_Music_x64_5.40.1.exe".to_string(),
+            hash: "c2hhNTEy".to_string(),
+            size: 0,
+            release_date: None,
+            update_probability: None,
+            version: "5.40.1".to_string(),
+            deprecated_versions: None,
+            channel: "stable".to_string(),
+        };
+        let profiles = ProfileSet::load().unwrap();
+        let profile = profiles.select("5.40.1", None).unwrap();
+        manifest::write(&build_dir, &build, profile).unwrap();
+        fs::write(build_dir.join(PATCH_REPORT_FILE), r#"{"rules": []}"#).unwrap();
+
+        let options = PatchOptions::default();
+        let modded_dir = build_dir.join("mod");
+        let read = |path: &str| fs::read_to_string(modded_dir.join(path)).unwrap();
+        reapply_patches(&build_dir, &options).unwrap();
+        let (preload, html) = (read("main/lib/preload.js"), read("app/index.html"));
+        assert!(preload.contains("YandexMusicMod preload.js"));
+        assert!(html.contains("renderer.js"));
+
+        let report = reapply_patches(&build_dir, &options).unwrap();
+        assert_eq!(report.html.len(), 1);
+        assert_eq!(read("main/lib/preload.js"), preload);
+        assert_eq!(read("app/index.html"), html);
+        assert!(modded_dir
+            .join("app")
+            .join(MOD_ASSETS_DIR)
+            .join("renderer.js")
+            .exists());
+        assert!(manifest::verify(&build_dir).unwrap().is_intact());
+
+        fs::remove_file(build_dir.join(PATCH_REPORT_FILE)).unwrap();
+        assert!(reapply_patches(&build_dir, &options).is_err());
+    }
+
+    #[test]
+    fn test_apply_patches_appends_user_preload() {
+        let temp = tempfile::tempdir().unwrap();
+        let lib_dir = temp.path().join("main").join("lib");
+        fs::create_dir_all(&lib_dir).unwrap();
+        fs::write(lib_dir.join("preload.js"), "// stock preload").unwrap();
+
+        let options = PatchOptions {
+            user_preload_js: Some("window.userHook = true;".to_string()),
+            ..Default::default()
+        };
+        let profiles = ProfileSet::builtin().unwrap();
+        let profile = profiles.select("5.40.1", None).unwrap();
+        apply_patches(temp.path(), profile, &options).unwrap();
+
+        let preload = fs::read_to_string(lib_dir.join("preload.js")).unwrap();
+        let builtin = preload.find("YandexMusicMod preload.js").unwrap();
+        let user = preload.find("window.userHook = true;").unwrap();
+        assert!(
+            builtin < user,
+            "user preload must come after the built-in one"
+        );
+    }
+
+    #[test]
+    fn test_check_patch_anchors() {
+        let temp = tempfile::tempdir().unwrap();
+        let main_dir = temp.path().join("main");
+        fs::create_dir_all(&main_dir).unwrap();
+        fs::write(main_dir.join("index.js"), "(0, createWindow)();").unwrap();
+
+        let profiles = ProfileSet::builtin().unwrap();
+        let profile = profiles.select("5.40.1", None).unwrap();
+        let anchors = check_patch_anchors(temp.path(), profile).unwrap();
+
+        let report = PatchReport {
+            version: "5.40.1".to_string(),
+            profile: profile.name.clone(),
+            compatibility: Compatibility::Known,
+            dry_run: true,
+            anchors,
+            rules: Vec::new(),
+            html: Vec::new(),
+            asar: None,
+            timings: Vec::new(),
+            diffs: Vec::new(),
+        };
+        let unmatched = report.unmatched();
+        assert!(!unmatched.contains(&"main-create-window"));
+        assert!(unmatched.contains(&"config-devtools"));
+        assert_eq!(
+            fs::read_to_string(main_dir.join("index.js")).unwrap(),
+            "(0, createWindow)();"
+        );
+    }
+
+    #[test]
+    fn test_select_app_archive() {
+        let dir = Path::new("$PLUGINSDIR");
+        let all = vec![
+            dir.join("app-64.7z"),
+            dir.join("app-arm64.7z"),
+            dir.join("app-32.7z"),
+            dir.join("extra.7z"),
+        ];
+
+        let mut archives = all.clone();
+        select_app_archive(&mut archives, Arch::Arm64);
+        assert_eq!(archives, [dir.join("app-arm64.7z"), dir.join("extra.7z")]);
+
+        let mut archives = all[..1].to_vec();
+        select_app_archive(&mut archives, Arch::Arm64);
+        assert_eq!(archives, [dir.join("app-64.7z")]);
     }
 }
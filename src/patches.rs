@@ -3,7 +3,11 @@
 //! This module contains the actual code modifications that will be applied
 //! to the extracted Yandex Music application files.
 
+use crate::rules::{FileLayout, PatchRule};
+use semver::Version;
 use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
 
 /// Blocked analytics and telemetry URLs
 pub const BLOCKED_ANALYTICS_URLS: &[&str] = &[
@@ -25,6 +29,110 @@ pub const BANNED_HEADERS: &[&str] = &["x-yandex-music-device", "x-request-id"];
 /// Banned dependencies to remove from package.json
 pub const BANNED_DEPENDENCIES: &[&str] = &["@yandex-chats/signer"];
 
+/// App versions the current patch set is known to work with
+pub const KNOWN_COMPATIBLE_VERSIONS: &[&str] = &["5.36.2", "5.37.1", "5.38.0", "5.39.1", "5.40.1"];
+
+/// How a build version relates to the known compatible versions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Compatibility {
+    /// Listed in `KNOWN_COMPATIBLE_VERSIONS`
+    Known,
+    /// Not listed, but not newer than the latest known version
+    Untested,
+    /// Newer than every known version; patches may no longer match
+    Newer,
+}
+
+/// Check a build version against the compatibility matrix
+pub fn check_compatibility(app_version: &str) -> Compatibility {
+    if KNOWN_COMPATIBLE_VERSIONS.contains(&app_version) {
+        return Compatibility::Known;
+    }
+
+    let latest_known = KNOWN_COMPATIBLE_VERSIONS
+        .iter()
+        .filter_map(|v| Version::parse(v).ok())
+        .max();
+
+    match (Version::parse(app_version), latest_known) {
+        (Ok(version), Some(latest)) if version > latest => Compatibility::Newer,
+        (Ok(_), _) => Compatibility::Untested,
+        // Unparseable versions can't be ordered; treat them as newer to be safe
+        (Err(_), _) => Compatibility::Newer,
+    }
+}
+
+/// The latest version in the compatibility matrix
+pub fn latest_known_version() -> &'static str {
+    KNOWN_COMPATIBLE_VERSIONS
+        .iter()
+        .max_by_key(|v| Version::parse(v).ok())
+        .copied()
+        .unwrap_or_default()
+}
+
+/// Anchors the built-in patches rely on, expressed as no-op rules so a
+/// dry run can report which of them no longer match
+pub fn anchor_rules(layout: &FileLayout) -> Vec<PatchRule> {
+    let anchor = |name: &str, files: &str, find: &str| PatchRule {
+        name: name.to_string(),
+        files: files.to_string(),
+        find: find.to_string(),
+        replace: "$0".to_string(),
+        regex: true,
+        versions: None,
+    };
+
+    vec![
+        anchor(
+            "config-devtools",
+            &layout.config_js,
+            r"enableDevTools:\s*false",
+        ),
+        anchor(
+            "config-auto-update",
+            &layout.config_js,
+            r"enableAutoUpdate:\s*true",
+        ),
+        anchor(
+            "system-menu-toolbar",
+            &layout.system_menu_js,
+            r"deviceInfo_js_1\.devicePlatform === platform_js_1\.Platform\.MACOS",
+        ),
+        anchor(
+            "create-window-title-bar",
+            &layout.create_window_js,
+            r"titleBarStyle:\s*'hidden'",
+        ),
+        anchor(
+            "create-window-min-width",
+            &layout.create_window_js,
+            r"minWidth: 768",
+        ),
+        anchor(
+            "create-window-min-height",
+            &layout.create_window_js,
+            r"minHeight: 650",
+        ),
+        anchor(
+            "create-window-show",
+            &layout.create_window_js,
+            r"show: false",
+        ),
+        anchor(
+            "main-create-window",
+            &layout.main_js,
+            r"createWindow\)\(\);",
+        ),
+        anchor(
+            "html-head",
+            &format!("{}/**/*.html", layout.html_root),
+            r"<head>",
+        ),
+    ]
+}
+
 /// Patch the package.json file with mod settings
 pub fn patch_package_json(content: &str) -> anyhow::Result<String> {
     let mut json: Value = serde_json::from_str(content)?;
@@ -124,14 +232,23 @@ pub fn patch_config_js(content: &str) -> String {
         .replace("enableAutoUpdate:true", "enableAutoUpdate: false")
 }
 
-/// Generate the settings reader code for system menu
+/// Generate the settings reader code for system menu; it reads the settings
+/// of the active profile (see `MOD_MAIN_JS`)
 pub fn generate_settings_reader_js() -> String {
     r#"
 const fs = require("fs");
 const path = require("path");
 const electron = require("electron");
 const appFolder = electron.app.getPath("userData");
-const settingsFilePath = path.join(appFolder, "mod_settings.json");
+let activeProfile = "default";
+try {
+  const active = JSON.parse(fs.readFileSync(path.join(appFolder, "mod_profile.json"), "utf8")).active;
+  if (/^[\w-]{1,32}$/.test(active)) activeProfile = active;
+} catch (e) {}
+const settingsFilePath = path.join(
+  appFolder,
+  activeProfile === "default" ? "mod_settings.json" : `mod_settings.${activeProfile}.json`,
+);
 let enableSystemToolbar = false;
 try {
   enableSystemToolbar = JSON.parse(fs.readFileSync(settingsFilePath, "utf8"))["devtools/systemToolbar"];
@@ -152,29 +269,59 @@ pub fn patch_system_menu_js(content: &str) -> String {
     )
 }
 
+/// Default minimum window width set by the mod
+pub const DEFAULT_MIN_WIDTH: u32 = 360;
+
+/// Default minimum window height set by the mod
+pub const DEFAULT_MIN_HEIGHT: u32 = 550;
+
+/// Window settings applied by `patch_create_window_js`
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct WindowOptions {
+    /// Open DevTools automatically when a window is created
+    pub auto_devtools: bool,
+    /// Minimum window width in pixels; `None` keeps the stock value
+    pub min_width: Option<u32>,
+    /// Minimum window height in pixels; `None` keeps the stock value
+    pub min_height: Option<u32>,
+}
+
+impl Default for WindowOptions {
+    fn default() -> Self {
+        Self {
+            auto_devtools: false,
+            min_width: Some(DEFAULT_MIN_WIDTH),
+            min_height: Some(DEFAULT_MIN_HEIGHT),
+        }
+    }
+}
+
 /// Patch createWindow.js for devtools and window settings
-pub fn patch_create_window_js(content: &str, auto_devtools: bool) -> String {
+pub fn patch_create_window_js(content: &str, window: &WindowOptions) -> String {
     let settings_reader = generate_settings_reader_js();
 
-    let mut result = format!(
-        "{}\n{}",
-        settings_reader,
-        content
-            .replace("config_js_1.config.app.enableDevTools", "true")
-            .replace(
-                "titleBarStyle: 'hidden'",
-                "titleBarStyle: !enableSystemToolbar && 'hidden'"
-            )
-            .replace(
-                "titleBarStyle:'hidden'",
-                "titleBarStyle: !enableSystemToolbar && 'hidden'"
-            )
-            .replace("minWidth: 768", "minWidth: 360")
-            .replace("minHeight: 650", "minHeight: 550")
-            .replace("show: false", "show: true")
-    );
+    let mut patched = content
+        .replace(
+            "titleBarStyle: 'hidden'",
+            "titleBarStyle: !enableSystemToolbar && 'hidden'",
+        )
+        .replace(
+            "titleBarStyle:'hidden'",
+            "titleBarStyle: !enableSystemToolbar && 'hidden'",
+        )
+        .replace("show: false", "show: true");
 
-    if auto_devtools {
+    if let Some(min_width) = window.min_width {
+        patched = patched.replace("minWidth: 768", &format!("minWidth: {}", min_width));
+    }
+    if let Some(min_height) = window.min_height {
+        patched = patched.replace("minHeight: 650", &format!("minHeight: {}", min_height));
+    }
+
+    let mut result = format!("{}\n{}", settings_reader, patched);
+
+    if window.auto_devtools {
         result = result.replace(
             "return window",
             "window.webContents.openDevTools();\nreturn window",
@@ -184,10 +331,72 @@ pub fn patch_create_window_js(content: &str, auto_devtools: bool) -> String {
     result
 }
 
-/// Generate the analytics blocking code for main.js
-pub fn generate_analytics_blocker_js() -> String {
+/// Secure `webPreferences` of the windows of `createWindow.js` for
+/// [`ModFeature::Hardening`]: the safe values are set before the stock ones and
+/// explicit opt-outs are flipped. `sandbox` is only forced when every preload
+/// script can run without Node.js (see [`preload_needs_node`]).
+pub fn harden_create_window_js(content: &str, sandbox: bool) -> String {
+    let mut forced = vec![("contextIsolation", true), ("nodeIntegration", false)];
+    if sandbox {
+        forced.push(("sandbox", true));
+    }
+
+    let mut patched = content.to_string();
+    for (key, value) in &forced {
+        let opt_outs: &[&str] = if *value {
+            &["false", "!1"]
+        } else {
+            &["true", "!0"]
+        };
+        for opt_out in opt_outs {
+            for separator in [": ", ":"] {
+                patched = patched.replace(
+                    &format!("{}{}{}", key, separator, opt_out),
+                    &format!("{}: {}", key, value),
+                );
+            }
+        }
+    }
+
+    let defaults: String = forced
+        .iter()
+        .map(|(key, value)| format!(" {}: {},", key, value))
+        .collect();
+    patched
+        .replace(
+            "webPreferences: {",
+            &format!("webPreferences: {{{}", defaults),
+        )
+        .replace(
+            "webPreferences:{",
+            &format!("webPreferences:{{{}", defaults),
+        )
+}
+
+/// Whether `preload` requires modules other than `electron`, which sandboxed
+/// preload scripts cannot load
+pub fn preload_needs_node(preload: &str) -> bool {
+    let require = regex::Regex::new(r#"require\(\s*["']([^"']+)["']\s*\)"#).unwrap();
+    let needs_node = require
+        .captures_iter(preload)
+        .any(|captures| &captures[1] != "electron");
+    needs_node
+}
+
+/// Generate the analytics blocking code for main.js.
+/// `extra_banned_headers` are removed from API requests in addition to `BANNED_HEADERS`.
+pub fn generate_analytics_blocker_js(extra_banned_headers: &[String]) -> String {
     let urls_json = serde_json::to_string(BLOCKED_ANALYTICS_URLS).unwrap();
-    let banned_headers_json = serde_json::to_string(BANNED_HEADERS).unwrap();
+
+    // Header names are matched case-insensitively
+    let mut banned_headers: Vec<String> = BANNED_HEADERS.iter().map(|h| h.to_lowercase()).collect();
+    for header in extra_banned_headers {
+        let header = header.to_lowercase();
+        if !banned_headers.contains(&header) {
+            banned_headers.push(header);
+        }
+    }
+    let banned_headers_json = serde_json::to_string(&banned_headers).unwrap();
 
     format!(
         r#"
@@ -197,6 +406,7 @@ session.defaultSession.webRequest.onBeforeRequest(
     urls: {urls},
   }},
   (details, callback) => {{
+    if (globalThis.yandexMusicModOnBlocked) globalThis.yandexMusicModOnBlocked(details);
     callback({{ cancel: true }});
   }},
 );
@@ -207,8 +417,10 @@ session.defaultSession.webRequest.onBeforeSendHeaders(
   }},
   (details, callback) => {{
     const bannedHeaders = {headers};
-    bannedHeaders.forEach((header) => {{
-      details.requestHeaders[header] = undefined;
+    Object.keys(details.requestHeaders).forEach((name) => {{
+      if (bannedHeaders.includes(name.toLowerCase())) {{
+        delete details.requestHeaders[name];
+      }}
     }});
     callback({{ requestHeaders: details.requestHeaders }});
   }},
@@ -220,8 +432,8 @@ session.defaultSession.webRequest.onBeforeSendHeaders(
 }
 
 /// Patch main.js (index.js) with analytics blocker and mod code
-pub fn patch_main_js(content: &str) -> String {
-    let analytics_blocker = generate_analytics_blocker_js();
+pub fn patch_main_js(content: &str, extra_banned_headers: &[String]) -> String {
+    let analytics_blocker = generate_analytics_blocker_js(extra_banned_headers);
 
     content.replace(
         "createWindow)();",
@@ -238,6 +450,36 @@ pub fn patch_html(content: &str) -> String {
     )
 }
 
+/// Settings profile of `mod_settings.json`
+pub const DEFAULT_SETTINGS_PROFILE: &str = "default";
+
+/// Whether `name` can name a settings profile: 1-32 letters, digits, `_` or `-`
+pub fn is_valid_settings_profile(name: &str) -> bool {
+    (1..=32).contains(&name.len())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// Generate the `modDefaultSettings` and `modDefaultProfile` declarations
+/// `MOD_MAIN_JS` relies on
+pub fn generate_default_settings_js(
+    defaults: &BTreeMap<String, Value>,
+    profile: Option<&str>,
+) -> String {
+    format!(
+        "const modDefaultSettings = {};\nconst modDefaultProfile = {};\n",
+        serde_json::to_string(defaults).unwrap_or_else(|_| "{}".to_string()),
+        Value::from(profile.unwrap_or(DEFAULT_SETTINGS_PROFILE))
+    )
+}
+
+/// `modHtmlRoot`, the folder of the HTML pages and mod assets inside the app
+/// (`layout.html_root`), for main-process features that load those assets
+pub fn generate_html_root_js(html_root: &str) -> String {
+    format!("const modHtmlRoot = {};\n", Value::from(html_root))
+}
+
 /// The main.js mod code that handles IPC, settings, and downloads
 pub const MOD_MAIN_JS: &str = r#"
 const electron = require("electron");
@@ -246,9 +488,24 @@ const path = require("path");
 const process = require("process");
 
 const appFolder = electron.app.getPath("userData");
-const settingsFilePath = path.join(appFolder, "mod_settings.json");
 const defaultDownloadPath = path.join(appFolder, "Downloads");
 
+// Settings profiles: "default" keeps mod_settings.json, others live in
+// mod_settings.<name>.json; mod_profile.json remembers the active one
+const profileFilePath = path.join(appFolder, "mod_profile.json");
+const profileNamePattern = /^[\w-]{1,32}$/;
+const profileSettingsPath = (name) =>
+  path.join(appFolder, name === "default" ? "mod_settings.json" : `mod_settings.${name}.json`);
+const readActiveProfile = () => {
+  try {
+    const active = JSON.parse(fs.readFileSync(profileFilePath, "utf8")).active;
+    if (profileNamePattern.test(active)) return active;
+  } catch (e) {}
+  return profileNamePattern.test(modDefaultProfile) ? modDefaultProfile : "default";
+};
+let activeProfile = readActiveProfile();
+let settingsFilePath = profileSettingsPath(activeProfile);
+
 // Create settings directory
 fs.mkdir(appFolder, { recursive: true }, (err) => {
   if (err) return console.error(err);
@@ -261,27 +518,102 @@ fs.mkdir(defaultDownloadPath, { recursive: true }, (err) => {
   console.log("Default download directory created successfully!");
 });
 
-// Initialize settings file
-if (!fs.existsSync(settingsFilePath)) {
-  const initialSettings = {
-    downloadFolderPath: defaultDownloadPath,
-  };
-  fs.writeFileSync(settingsFilePath, JSON.stringify(initialSettings, null, 2));
-} else {
+// Settings only the main process may read and change. The renderers can't
+// store them and they are never copied into the settings files
+const mainOnlySettingKeys = new Set(["autoRepatch/patcher", "autoRepatch/args", "autoRepatch/pending"]);
+
+// Initialize a profile's settings file. Settings missing from it get the
+// download folder and the defaults chosen at patch time
+const initSettingsFile = (filePath) => {
+  let settings;
   try {
-    const settings = JSON.parse(fs.readFileSync(settingsFilePath, "utf8"));
-    if (!settings.downloadFolderPath) {
-      settings.downloadFolderPath = defaultDownloadPath;
-      fs.writeFileSync(settingsFilePath, JSON.stringify(settings, null, 2));
-    }
+    settings = JSON.parse(fs.readFileSync(filePath, "utf8"));
   } catch (e) {
-    const initialSettings = {
-      downloadFolderPath: defaultDownloadPath,
-    };
-    fs.writeFileSync(settingsFilePath, JSON.stringify(initialSettings, null, 2));
+    settings = {};
+  }
+  const missing = Object.keys(modDefaultSettings).filter(
+    (key) => settings[key] === undefined && !mainOnlySettingKeys.has(key),
+  );
+  missing.forEach((key) => (settings[key] = modDefaultSettings[key]));
+  if (!settings.downloadFolderPath) {
+    settings.downloadFolderPath = defaultDownloadPath;
+    missing.push("downloadFolderPath");
   }
+  if (missing.length > 0 || !fs.existsSync(filePath)) {
+    fs.writeFileSync(filePath, JSON.stringify(settings, null, 2));
+  }
+};
+initSettingsFile(settingsFilePath);
+if (!fs.existsSync(profileFilePath)) {
+  fs.writeFileSync(profileFilePath, JSON.stringify({ active: activeProfile }, null, 2));
 }
 
+// Settings helpers shared by mod features
+const readModSettings = () => {
+  try {
+    return JSON.parse(fs.readFileSync(settingsFilePath, "utf8"));
+  } catch (e) {
+    return {};
+  }
+};
+
+const downloadFolder = () => readModSettings().downloadFolderPath || defaultDownloadPath;
+
+const settingChangeHandlers = [];
+const onSettingChanged = (handler) => settingChangeHandlers.push(handler);
+
+// Tell the renderers and the mod features about a changed setting
+const notifySettingChanged = (key, value) => {
+  electron.BrowserWindow.getAllWindows().forEach((window) =>
+    window.webContents.send("yandexMusicMod.storageValueUpdated", key, value),
+  );
+  settingChangeHandlers.forEach((handler) => handler(key, value));
+};
+
+// Profile management
+const listProfiles = () => {
+  const names = new Set(["default", activeProfile]);
+  fs.readdirSync(appFolder).forEach((file) => {
+    const match = /^mod_settings\.([\w-]{1,32})\.json$/.exec(file);
+    if (match) names.add(match[1]);
+  });
+  return { active: activeProfile, profiles: [...names].sort() };
+};
+
+// Switch to the profile `name`, creating it if needed; every setting that
+// differs between the profiles is announced as changed
+const switchProfile = (name) => {
+  const previous = readModSettings();
+  activeProfile = name;
+  settingsFilePath = profileSettingsPath(name);
+  initSettingsFile(settingsFilePath);
+  fs.writeFileSync(profileFilePath, JSON.stringify({ active: name }, null, 2));
+  const settings = readModSettings();
+  new Set([...Object.keys(previous), ...Object.keys(settings)]).forEach((key) => {
+    const value = settings[key] !== undefined ? settings[key] : null;
+    if (JSON.stringify(previous[key]) !== JSON.stringify(value)) notifySettingChanged(key, value);
+  });
+};
+
+electron.ipcMain.handle("yandexMusicMod.profiles.list", () => listProfiles());
+
+electron.ipcMain.handle("yandexMusicMod.profiles.switch", (_ev, name) => {
+  if (!profileNamePattern.test(name)) return { success: false, error: "Invalid profile name" };
+  if (name !== activeProfile) switchProfile(name);
+  return { success: true, ...listProfiles() };
+});
+
+electron.ipcMain.handle("yandexMusicMod.profiles.delete", (_ev, name) => {
+  if (name === "default" || !profileNamePattern.test(name)) {
+    return { success: false, error: "Cannot delete this profile" };
+  }
+  if (name === activeProfile) switchProfile("default");
+  try {
+    fs.unlinkSync(profileSettingsPath(name));
+  } catch (e) {}
+  return { success: true, ...listProfiles() };
+});
+
 // IPC handlers for settings
 electron.ipcMain.handle("yandexMusicMod.getStorageValue", (_ev, key) => {
   const settings = fs.readFileSync(settingsFilePath, "utf8") || "{}";
@@ -290,13 +622,28 @@ electron.ipcMain.handle("yandexMusicMod.getStorageValue", (_ev, key) => {
 });
 
 electron.ipcMain.on("yandexMusicMod.setStorageValue", (_ev, key, value) => {
+  if (mainOnlySettingKeys.has(key)) {
+    console.warn("[YandexMusicMod] Refused to store a main-process setting:", key);
+    return;
+  }
   const settings = JSON.parse(fs.readFileSync(settingsFilePath, "utf8"));
   settings[key] = value;
   fs.writeFileSync(settingsFilePath, JSON.stringify(settings, null, 2));
+  notifySettingChanged(key, value);
+});
 
-  electron.BrowserWindow.getAllWindows().forEach((window) =>
-    window.webContents.send("yandexMusicMod.storageValueUpdated", key, value),
-  );
+// Always-on-top window setting
+// Windows flagged with `ymModPinned` (e.g. the mini-player) always stay on top
+const applyAlwaysOnTop = (window, enabled) =>
+  window.setAlwaysOnTop(Boolean(enabled) || window.ymModPinned === true);
+
+electron.app.on("browser-window-created", (_ev, window) => {
+  applyAlwaysOnTop(window, readModSettings()["window/alwaysOnTop"]);
+});
+
+onSettingChanged((key, value) => {
+  if (key !== "window/alwaysOnTop") return;
+  electron.BrowserWindow.getAllWindows().forEach((window) => applyAlwaysOnTop(window, value));
 });
 
 // Folder selection dialog
@@ -313,126 +660,3140 @@ electron.ipcMain.handle("yandexMusicMod.selectDownloadFolder", async (_ev) => {
   return { success: true, path: result.filePaths[0] };
 });
 
-// Open folder
+// Open folder in the file manager; the path is never handed to a shell
 electron.ipcMain.handle("yandexMusicMod.openFolder", async (_ev, folderPath) => {
-  try {
-    require("child_process").exec(`start "" "${folderPath}"`);
-    return { success: true };
-  } catch (error) {
-    console.error("Failed to open folder:", error);
-    return { success: false, error: error.message };
+  if (typeof folderPath !== "string" || !folderPath) {
+    return { success: false, error: "Invalid folder path" };
   }
+  const error = await electron.shell.openPath(folderPath);
+  if (error) console.error("Failed to open folder:", error);
+  return error ? { success: false, error } : { success: true };
 });
 
 // Open download directory
 electron.ipcMain.on("yandexMusicMod.openDownloadDirectory", (_ev) => {
-  let saveFolder;
-  try {
-    const settings = JSON.parse(fs.readFileSync(settingsFilePath, "utf8"));
-    saveFolder = settings.downloadFolderPath || process.env.USERPROFILE + "\\YandexMod Download";
-  } catch (e) {
-    saveFolder = process.env.USERPROFILE + "\\YandexMod Download";
-  }
-  require("child_process").exec('start "" "' + saveFolder + '"');
+  electron.shell.openPath(downloadFolder()).then((error) => {
+    if (error) console.error("Failed to open the download folder:", error);
+  });
 });
 
 console.log("YandexMusicMod main.js loaded successfully!");
 "#;
 
-/// The preload.js mod code
-pub const MOD_PRELOAD_JS: &str = r#"
-const { contextBridge, ipcRenderer } = require("electron");
+/// DevTools keyboard shortcut for the main process.
+/// Some builds hide the DevTools menu item even with `enableDevTools`, so
+/// Ctrl+Shift+I (Cmd+Option+I on macOS) and F12 toggle DevTools for the
+/// focused window directly.
+pub const DEVTOOLS_SHORTCUT_JS: &str = r#"
+(function () {
+  const { app, BrowserWindow } = require("electron");
 
-// Expose mod API to the renderer process
-contextBridge.exposeInMainWorld("yandexMusicMod", {
-  getStorageValue: (key) => ipcRenderer.invoke("yandexMusicMod.getStorageValue", key),
-  setStorageValue: (key, value) => ipcRenderer.send("yandexMusicMod.setStorageValue", key, value),
-  onStorageValueUpdated: (callback) => {
-    ipcRenderer.on("yandexMusicMod.storageValueUpdated", (event, key, value) => {
-      callback(key, value);
+  const isDevToolsShortcut = (input) => {
+    if (input.type !== "keyDown") return false;
+    if (input.key === "F12") return true;
+    // Cmd+Option+I on macOS, Ctrl+Shift+I elsewhere. Option turns `key` into a
+    // composed character on macOS, so match the physical key.
+    const modifiers = process.platform === "darwin" ? input.meta && input.alt : input.control && input.shift;
+    return modifiers && input.code === "KeyI";
+  };
+
+  const attach = (window) => {
+    window.webContents.on("before-input-event", (event, input) => {
+      if (!isDevToolsShortcut(input)) return;
+      event.preventDefault();
+      const focused = BrowserWindow.getFocusedWindow() || window;
+      if (focused.webContents.isDevToolsOpened()) {
+        focused.webContents.closeDevTools();
+      } else {
+        focused.webContents.openDevTools();
+      }
     });
-  },
-  selectDownloadFolder: () => ipcRenderer.invoke("yandexMusicMod.selectDownloadFolder"),
-  openFolder: (folderPath) => ipcRenderer.invoke("yandexMusicMod.openFolder", folderPath),
-  openDownloadDirectory: () => ipcRenderer.send("yandexMusicMod.openDownloadDirectory"),
-});
+  };
 
-console.log("YandexMusicMod preload.js loaded successfully!");
+  BrowserWindow.getAllWindows().forEach(attach);
+  app.on("browser-window-created", (_ev, window) => attach(window));
+})();
 "#;
 
-/// The renderer.js mod code (minimal placeholder)
-pub const MOD_RENDERER_JS: &str = r#"
-(function() {
-  console.log("YandexMusicMod renderer.js loaded!");
+/// Proxy support for the main process. Reads `network/proxy` (e.g.
+/// `socks5://127.0.0.1:1080`) and `network/proxyScope` (`all` or
+/// `music-api`) from the mod settings and applies them to the default session.
+pub const PROXY_MAIN_JS: &str = r#"
+(function () {
+  const MUSIC_API_HOSTS = ["api.music.yandex.net", "music.yandex.ru", "strm.yandex.net", "strm.yandex.ru"];
 
-  // Wait for the page to load
-  window.addEventListener("load", function() {
-    console.log("YandexMusicMod: Page loaded");
+  // "socks5://host:port" -> "SOCKS5 host:port" for PAC scripts
+  const toPacProxy = (proxy) => {
+    const match = /^(\w+):\/\/([^/]+)/.exec(proxy);
+    if (!match) return "PROXY " + proxy;
+    const kind = { http: "PROXY", https: "HTTPS", socks4: "SOCKS", socks5: "SOCKS5", socks: "SOCKS" }[match[1].toLowerCase()];
+    return (kind || "PROXY") + " " + match[2];
+  };
 
-    // Add mod indicator
-    const modIndicator = document.createElement("div");
-    modIndicator.style.cssText = "position:fixed;bottom:10px;right:10px;padding:5px 10px;background:rgba(0,0,0,0.7);color:#fff;border-radius:5px;font-size:12px;z-index:9999;";
-    modIndicator.textContent = "YandexMusicMod";
-    document.body.appendChild(modIndicator);
+  const buildConfig = (settings) => {
+    const proxy = (settings["network/proxy"] || "").trim();
+    if (!proxy) return { mode: "system" };
 
-    // Hide indicator after 5 seconds
-    setTimeout(() => {
-      modIndicator.style.opacity = "0";
-      modIndicator.style.transition = "opacity 0.5s";
-      setTimeout(() => modIndicator.remove(), 500);
-    }, 5000);
+    if (settings["network/proxyScope"] === "music-api") {
+      const pac = `function FindProxyForURL(url, host) {
+        var hosts = ${JSON.stringify(MUSIC_API_HOSTS)};
+        for (var i = 0; i < hosts.length; i++) {
+          if (host === hosts[i] || dnsDomainIs(host, "." + hosts[i])) return "${toPacProxy(proxy)}";
+        }
+        return "DIRECT";
+      }`;
+      return { mode: "pac_script", pacScript: "data:application/x-ns-proxy-autoconfig;base64," + Buffer.from(pac).toString("base64") };
+    }
+
+    return { mode: "fixed_servers", proxyRules: proxy, proxyBypassRules: "<local>" };
+  };
+
+  const applyProxy = () => {
+    const config = buildConfig(readModSettings());
+    electron.session.defaultSession
+      .setProxy(config)
+      .then(() => console.log("YandexMusicMod proxy applied:", config.mode))
+      .catch((e) => console.error("Failed to apply proxy:", e));
+  };
+
+  electron.app.whenReady().then(applyProxy);
+  onSettingChanged((key) => {
+    if (key === "network/proxy" || key === "network/proxyScope") applyProxy();
   });
 })();
 "#;
 
-/// The renderer.css mod styles
-pub const MOD_RENDERER_CSS: &str = r#"
-/* YandexMusicMod custom styles */
-
-/* Hide upgrade banners */
-.upgrade-banner,
-.plus-promo,
-.subscription-promo {
-  display: none !important;
+/// Optional mod features enabled with `patch --enable <feature>`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "kebab-case")]
+pub enum ModFeature {
+    /// Start the player minimized at login (toggle in the mod settings)
+    Autostart,
+    /// Compact always-on-top mini-player window
+    MiniPlayer,
+    /// Fetch updated renderer.js/renderer.css from GitHub releases
+    AssetUpdates,
+    /// Log requests cancelled by the analytics blocker
+    BlockedLog,
+    /// Cache streamed audio to disk and serve it when offline
+    OfflineCache,
+    /// Repatch new Yandex Music releases from inside the app and switch on restart
+    AutoRepatch,
+    /// Track info in the Windows media overlay and media keys through SMTC
+    Smtc,
+    /// Load community mods dropped into `mods/` under userData at startup
+    ExternalMods,
+    /// DNS-over-HTTPS and host mapping for networks that interfere with Yandex
+    AntiDpi,
+    /// Pause playback or quit after a set time or at the end of the track
+    SleepTimer,
+    /// Isolated, sandboxed windows and validated arguments of the mod IPC
+    Hardening,
 }
 
-/* Custom scrollbar */
-::-webkit-scrollbar {
-  width: 8px;
+impl ModFeature {
+    /// Main-process code appended after `MOD_MAIN_JS`
+    pub fn main_js(self) -> &'static str {
+        match self {
+            ModFeature::Autostart => AUTOSTART_MAIN_JS,
+            ModFeature::MiniPlayer => MINI_PLAYER_MAIN_JS,
+            ModFeature::AssetUpdates => ASSET_UPDATES_MAIN_JS,
+            ModFeature::BlockedLog => BLOCKED_LOG_MAIN_JS,
+            ModFeature::OfflineCache => OFFLINE_CACHE_MAIN_JS,
+            ModFeature::AutoRepatch => AUTO_REPATCH_MAIN_JS,
+            ModFeature::Smtc => SMTC_MAIN_JS,
+            ModFeature::ExternalMods => EXTERNAL_MODS_MAIN_JS,
+            ModFeature::AntiDpi => ANTI_DPI_MAIN_JS,
+            ModFeature::SleepTimer => SLEEP_TIMER_MAIN_JS,
+            ModFeature::Hardening => HARDENING_MAIN_JS,
+        }
+    }
+
+    /// Preload code appended after `MOD_PRELOAD_JS`
+    pub fn preload_js(self) -> &'static str {
+        match self {
+            ModFeature::MiniPlayer => MINI_PLAYER_PRELOAD_JS,
+            ModFeature::AssetUpdates => ASSET_UPDATES_PRELOAD_JS,
+            ModFeature::BlockedLog => BLOCKED_LOG_PRELOAD_JS,
+            ModFeature::ExternalMods => EXTERNAL_MODS_PRELOAD_JS,
+            ModFeature::SleepTimer => SLEEP_TIMER_PRELOAD_JS,
+            _ => "",
+        }
+    }
+
+    /// Renderer code appended after `MOD_RENDERER_JS`
+    pub fn renderer_js(self) -> &'static str {
+        match self {
+            ModFeature::Autostart => AUTOSTART_RENDERER_JS,
+            ModFeature::MiniPlayer => MINI_PLAYER_RENDERER_JS,
+            ModFeature::AssetUpdates => "",
+            ModFeature::BlockedLog => BLOCKED_LOG_RENDERER_JS,
+            ModFeature::OfflineCache => OFFLINE_CACHE_RENDERER_JS,
+            ModFeature::AutoRepatch => AUTO_REPATCH_RENDERER_JS,
+            ModFeature::Smtc => SMTC_RENDERER_JS,
+            ModFeature::ExternalMods => EXTERNAL_MODS_RENDERER_JS,
+            ModFeature::AntiDpi => ANTI_DPI_RENDERER_JS,
+            ModFeature::SleepTimer => SLEEP_TIMER_RENDERER_JS,
+            ModFeature::Hardening => "",
+        }
+    }
+
+    /// Extra files written to the mod assets directory, as (name, content)
+    pub fn assets(self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            ModFeature::MiniPlayer => &[
+                ("mini-player.html", MINI_PLAYER_HTML),
+                ("mini-player-preload.js", MINI_PLAYER_WINDOW_PRELOAD_JS),
+            ],
+            _ => &[],
+        }
+    }
 }
 
-::-webkit-scrollbar-track {
-  background: rgba(0, 0, 0, 0.1);
+/// Generate renderer.js: the base mod renderer plus enabled feature code.
+/// With `AssetUpdates` the bundled base only runs when no downloaded
+/// renderer.js replaced it; feature code always runs.
+pub fn generate_renderer_js(features: &[ModFeature]) -> String {
+    let mut js = generate_strings_js();
+    if features.contains(&ModFeature::AssetUpdates) {
+        js.push_str(ASSET_UPDATES_RENDERER_PRELUDE_JS);
+        js.push_str(&format!(
+            "\n(function () {{\n  if (window.__yandexMusicModAssetOverride) return;\n{}\n}})();\n",
+            MOD_RENDERER_JS
+        ));
+    } else {
+        js.push_str(MOD_RENDERER_JS);
+    }
+    for feature in features {
+        js.push_str(feature.renderer_js());
+    }
+    js
 }
 
-::-webkit-scrollbar-thumb {
-  background: rgba(255, 255, 255, 0.3);
-  border-radius: 4px;
+/// `assets.json` of a release: the SHA-256 of the base renderer assets that
+/// `asset-updates` installs once the manifest's signature checks out
+pub fn generate_assets_manifest(assets: &[(&str, &str)]) -> String {
+    let files: BTreeMap<&str, String> = assets
+        .iter()
+        .map(|(name, content)| (*name, hex::encode(Sha256::digest(content.as_bytes()))))
+        .collect();
+    serde_json::to_string_pretty(&json!({ "files": files })).unwrap_or_default()
 }
 
-::-webkit-scrollbar-thumb:hover {
-  background: rgba(255, 255, 255, 0.5);
+/// UI string tables of the renderer (`locales/<lang>.json`, message id to
+/// text); `en` is the fallback for ids missing in another language
+pub const RENDERER_LOCALES: &[(&str, &str)] = &[
+    ("en", include_str!("../locales/en.json")),
+    ("ru", include_str!("../locales/ru.json")),
+];
+
+/// `const modStrings = { en: {...}, ... };` with the string tables, read by
+/// `MOD_RENDERER_JS`
+pub fn generate_strings_js() -> String {
+    let tables: Vec<String> = RENDERER_LOCALES
+        .iter()
+        .map(|(lang, table)| format!("  {}: {},\n", lang, table.trim()))
+        .collect();
+    format!("const modStrings = {{\n{}}};\n", tables.concat())
 }
+
+/// Autostart with the OS: login items on Windows/macOS, an XDG autostart
+/// entry on Linux. Controlled by the `system/autostart` mod setting.
+pub const AUTOSTART_MAIN_JS: &str = r#"
+(function () {
+  const os = require("os");
+  const desktopFile = path.join(
+    process.env.XDG_CONFIG_HOME || path.join(os.homedir(), ".config"),
+    "autostart",
+    "yandex-music-mod.desktop",
+  );
+
+  const applyAutostart = (enabled) => {
+    enabled = Boolean(enabled);
+    if (process.platform === "win32" || process.platform === "darwin") {
+      electron.app.setLoginItemSettings({
+        openAtLogin: enabled,
+        openAsHidden: true,
+        args: ["--hidden"],
+      });
+      return;
+    }
+
+    try {
+      if (enabled) {
+        const exec = process.env.APPIMAGE || process.execPath;
+        fs.mkdirSync(path.dirname(desktopFile), { recursive: true });
+        fs.writeFileSync(
+          desktopFile,
+          [
+            "[Desktop Entry]",
+            "Type=Application",
+            "Name=Yandex Music Mod",
+            `Exec="${exec}" --hidden`,
+            "X-GNOME-Autostart-enabled=true",
+            "",
+          ].join("\n"),
+        );
+      } else if (fs.existsSync(desktopFile)) {
+        fs.unlinkSync(desktopFile);
+      }
+    } catch (e) {
+      console.error("Failed to update autostart entry:", e);
+    }
+  };
+
+  applyAutostart(readModSettings()["system/autostart"]);
+  onSettingChanged((key, value) => {
+    if (key === "system/autostart") applyAutostart(value);
+  });
+
+  // Start minimized when launched at login
+  const launchedHidden =
+    process.argv.includes("--hidden") ||
+    (process.platform === "darwin" && electron.app.getLoginItemSettings().wasOpenedAsHidden);
+  if (launchedHidden) {
+    electron.app.once("browser-window-created", (_ev, window) => {
+      setImmediate(() => window.minimize());
+    });
+  }
+})();
 "#;
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Mod panel entry for the autostart feature
+pub const AUTOSTART_RENDERER_JS: &str = r#"
+window.yandexMusicModPanel.register({
+  key: "system/autostart",
+  label: "autostart.enabled",
+  type: "toggle",
+});
+"#;
 
-    #[test]
-    fn test_patch_config_js() {
-        let input = "enableDevTools: false, enableAutoUpdate: true";
-        let output = patch_config_js(input);
-        assert!(output.contains("enableDevTools: true"));
-        assert!(output.contains("enableAutoUpdate: false"));
+/// Mini-player main process: a frameless always-on-top window showing the
+/// current track, fed with state from the renderer and relaying transport
+/// commands back to the main window.
+pub const MINI_PLAYER_MAIN_JS: &str = r#"
+(function () {
+  const assetsDir = path.join(electron.app.getAppPath(), modHtmlRoot, "yandexMusicMod");
+  let miniWindow = null;
+  let lastState = null;
+
+  const sendToMainWindows = (channel, ...args) => {
+    electron.BrowserWindow.getAllWindows()
+      .filter((window) => window !== miniWindow)
+      .forEach((window) => window.webContents.send(channel, ...args));
+  };
+
+  const openMiniPlayer = () => {
+    if (miniWindow) {
+      miniWindow.show();
+      return;
     }
+    miniWindow = new electron.BrowserWindow({
+      width: 340,
+      height: 110,
+      resizable: false,
+      frame: false,
+      alwaysOnTop: true,
+      skipTaskbar: true,
+      webPreferences: {
+        preload: path.join(assetsDir, "mini-player-preload.js"),
+        contextIsolation: true,
+      },
+    });
+    miniWindow.ymModPinned = true;
+    miniWindow.setAlwaysOnTop(true);
+    miniWindow.loadFile(path.join(assetsDir, "mini-player.html"));
+    miniWindow.webContents.on("did-finish-load", () => {
+      if (lastState) miniWindow.webContents.send("yandexMusicMod.miniPlayer.state", lastState);
+    });
+    miniWindow.on("closed", () => {
+      miniWindow = null;
+    });
+  };
 
-    #[test]
-    fn test_patch_html() {
-        let input = "<html><head><title>Test</title></head></html>";
-        let output = patch_html(input);
-        assert!(output.contains("yandexMusicMod/renderer.js"));
-        assert!(output.contains("yandexMusicMod/renderer.css"));
+  electron.ipcMain.on("yandexMusicMod.miniPlayer.toggle", () => {
+    if (miniWindow) miniWindow.close();
+    else openMiniPlayer();
+  });
+
+  electron.ipcMain.on("yandexMusicMod.miniPlayer.state", (_ev, state) => {
+    lastState = state;
+    if (miniWindow) miniWindow.webContents.send("yandexMusicMod.miniPlayer.state", state);
+  });
+
+  electron.ipcMain.on("yandexMusicMod.miniPlayer.command", (_ev, command) => {
+    if (command === "close") {
+      if (miniWindow) miniWindow.close();
+      return;
+    }
+    sendToMainWindows("yandexMusicMod.miniPlayer.command", command);
+  });
+})();
+"#;
+
+/// Mini-player API exposed to the main renderer
+pub const MINI_PLAYER_PRELOAD_JS: &str = r#"
+(function () {
+  const { contextBridge, ipcRenderer } = require("electron");
+  contextBridge.exposeInMainWorld("yandexMusicModMiniPlayer", {
+    toggle: () => ipcRenderer.send("yandexMusicMod.miniPlayer.toggle"),
+    sendState: (state) => ipcRenderer.send("yandexMusicMod.miniPlayer.state", state),
+    onCommand: (callback) => {
+      ipcRenderer.on("yandexMusicMod.miniPlayer.command", (_event, command) => callback(command));
+    },
+  });
+})();
+"#;
+
+/// Mini-player renderer side: captures the page's media session handlers,
+/// publishes track state and executes commands from the mini window
+pub const MINI_PLAYER_RENDERER_JS: &str = r#"
+(function () {
+  const api = window.yandexMusicModMiniPlayer;
+  if (!api || !navigator.mediaSession) return;
+
+  // Remember handlers registered by the app so commands can invoke them
+  const handlers = {};
+  const setActionHandler = navigator.mediaSession.setActionHandler.bind(navigator.mediaSession);
+  navigator.mediaSession.setActionHandler = (action, handler) => {
+    handlers[action] = handler;
+    return setActionHandler(action, handler);
+  };
+
+  const run = (action) => {
+    if (handlers[action]) handlers[action]({ action });
+  };
+
+  api.onCommand((command) => {
+    if (command === "play-pause") {
+      run(navigator.mediaSession.playbackState === "playing" ? "pause" : "play");
+    } else if (command === "next") {
+      run("nexttrack");
+    } else if (command === "previous") {
+      run("previoustrack");
+    }
+  });
+
+  let lastJson = "";
+  setInterval(() => {
+    const metadata = navigator.mediaSession.metadata;
+    const artwork = metadata && metadata.artwork && metadata.artwork.length
+      ? metadata.artwork[metadata.artwork.length - 1].src
+      : null;
+    const state = {
+      title: metadata ? metadata.title : "",
+      artist: metadata ? metadata.artist : "",
+      artwork,
+      playing: navigator.mediaSession.playbackState === "playing",
+    };
+    const json = JSON.stringify(state);
+    if (json !== lastJson) {
+      lastJson = json;
+      api.sendState(state);
+    }
+  }, 1000);
+
+  window.yandexMusicModPanel.register({
+    label: "miniPlayer.title",
+    type: "button",
+    action: () => api.toggle(),
+  });
+})();
+"#;
+
+/// Preload of the mini-player window
+pub const MINI_PLAYER_WINDOW_PRELOAD_JS: &str = r#"
+const { contextBridge, ipcRenderer } = require("electron");
+contextBridge.exposeInMainWorld("miniPlayer", {
+  onState: (callback) => {
+    ipcRenderer.on("yandexMusicMod.miniPlayer.state", (_event, state) => callback(state));
+  },
+  command: (command) => ipcRenderer.send("yandexMusicMod.miniPlayer.command", command),
+});
+"#;
+
+/// Mini-player window markup
+pub const MINI_PLAYER_HTML: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta http-equiv="Content-Security-Policy" content="default-src 'self' 'unsafe-inline'; img-src * data:">
+<style>
+  body { margin: 0; display: flex; align-items: center; gap: 10px; height: 100vh; padding: 0 10px;
+         box-sizing: border-box; background: #141414; color: #fff; font: 13px sans-serif;
+         -webkit-app-region: drag; user-select: none; }
+  img { width: 80px; height: 80px; border-radius: 4px; object-fit: cover; background: #333; }
+  .info { flex: 1; min-width: 0; }
+  .title, .artist { white-space: nowrap; overflow: hidden; text-overflow: ellipsis; }
+  .artist { color: #aaa; margin-top: 4px; }
+  .controls { margin-top: 8px; }
+  button { -webkit-app-region: no-drag; background: none; border: none; color: #fff; font-size: 18px; cursor: pointer; }
+  .close { position: absolute; top: 2px; right: 4px; font-size: 12px; color: #888; }
+</style>
+</head>
+<body>
+  <img id="artwork" alt="">
+  <div class="info">
+    <div class="title" id="title">—</div>
+    <div class="artist" id="artist"></div>
+    <div class="controls">
+      <button data-command="previous">⏮</button>
+      <button data-command="play-pause" id="play">▶</button>
+      <button data-command="next">⏭</button>
+    </div>
+  </div>
+  <button class="close" data-command="close">✕</button>
+<script>
+  document.querySelectorAll("[data-command]").forEach((button) =>
+    button.addEventListener("click", () => window.miniPlayer.command(button.dataset.command)),
+  );
+  window.miniPlayer.onState((state) => {
+    document.getElementById("title").textContent = state.title || "—";
+    document.getElementById("artist").textContent = state.artist || "";
+    document.getElementById("play").textContent = state.playing ? "⏸" : "▶";
+    const artwork = document.getElementById("artwork");
+    if (state.artwork) artwork.src = state.artwork;
+    else artwork.removeAttribute("src");
+  });
+</script>
+</body>
+</html>
+"#;
+
+/// Public ed25519 key (PEM) the release signatures of renderer assets and
+/// rule indexes are checked with. Release builds get it from CI, which derives
+/// it from the signing key (`YMM_RELEASE_PUBLIC_KEY`); other builds trust no
+/// signed updates.
+pub const RELEASE_PUBLIC_KEY_PEM: Option<&str> = option_env!("YMM_RELEASE_PUBLIC_KEY");
+
+/// `modReleasePublicKey`, the release key for main-process features that check
+/// release signatures; `null` without one
+pub fn generate_release_key_js(key: Option<&str>) -> String {
+    format!("const modReleasePublicKey = {};\n", Value::from(key))
+}
+
+/// Asset updates main process: checks this project's latest GitHub release
+/// every 6 hours and stores newer renderer.js/renderer.css under userData.
+/// The release's `assets.json` lists their SHA-256 and must carry a valid
+/// signature (`assets.json.sig`) of `modReleasePublicKey`.
+pub const ASSET_UPDATES_MAIN_JS: &str = r#"
+(function () {
+  const crypto = require("crypto");
+  const RELEASES_URL = "https://api.github.com/repos/Jhon-Crow/YandexMusicBetaModeFastLP/releases/latest";
+  const ASSETS = ["renderer.js", "renderer.css"];
+  const MANIFEST = "assets.json";
+  const CHECK_INTERVAL_MS = 6 * 60 * 60 * 1000;
+  const updatesDir = path.join(appFolder, "yandexMusicMod-assets");
+  const metaPath = path.join(updatesDir, "meta.json");
+
+  const fetchImpl = (url) => (electron.net.fetch ? electron.net.fetch(url) : fetch(url));
+  const sha256 = (data) => crypto.createHash("sha256").update(data).digest("hex");
+
+  const readMeta = () => {
+    try {
+      return JSON.parse(fs.readFileSync(metaPath, "utf8"));
+    } catch (e) {
+      return {};
+    }
+  };
+
+  const download = async (asset) => {
+    const response = await fetchImpl(asset.browser_download_url);
+    if (!response.ok) throw new Error(`HTTP ${response.status} for ${asset.name}`);
+    return Buffer.from(await response.arrayBuffer());
+  };
+
+  const checkForUpdates = async () => {
+    try {
+      const response = await fetchImpl(RELEASES_URL);
+      if (!response.ok) throw new Error(`HTTP ${response.status}`);
+      const release = await response.json();
+      if (readMeta().tag === release.tag_name) return;
+
+      const byName = (name) => (release.assets || []).find((asset) => asset.name === name);
+      const assets = ASSETS.map(byName).filter(Boolean);
+      if (!assets.length || !byName(MANIFEST) || !byName(MANIFEST + ".sig")) return;
+
+      const manifest = await download(byName(MANIFEST));
+      const signature = await download(byName(MANIFEST + ".sig"));
+      if (!crypto.verify(null, manifest, modReleasePublicKey, signature)) {
+        throw new Error(`Invalid signature of ${MANIFEST} in ${release.tag_name}`);
+      }
+      const hashes = JSON.parse(manifest.toString("utf8")).files || {};
+
+      fs.mkdirSync(updatesDir, { recursive: true });
+      for (const asset of assets) {
+        const body = await download(asset);
+        if (String(hashes[asset.name]).toLowerCase() !== sha256(body)) {
+          throw new Error(`Checksum mismatch for ${asset.name}`);
+        }
+        fs.writeFileSync(path.join(updatesDir, asset.name + ".tmp"), body);
+      }
+      // Swap in all files only after every download succeeded
+      for (const name of ASSETS) fs.rmSync(path.join(updatesDir, name), { force: true });
+      assets.forEach((asset) =>
+        fs.renameSync(path.join(updatesDir, asset.name + ".tmp"), path.join(updatesDir, asset.name)),
+      );
+      fs.writeFileSync(metaPath, JSON.stringify({ tag: release.tag_name, files: hashes, checkedAt: Date.now() }, null, 2));
+      console.log("YandexMusicMod renderer assets updated to", release.tag_name);
+    } catch (e) {
+      console.error("YandexMusicMod asset update check failed:", e);
+    }
+  };
+
+  electron.ipcMain.on("yandexMusicMod.assets.getOverride", (ev, name) => {
+    ev.returnValue = null;
+    if (!ASSETS.includes(name)) return;
+    try {
+      const content = fs.readFileSync(path.join(updatesDir, name));
+      const expected = (readMeta().files || {})[name];
+      if (expected && String(expected).toLowerCase() === sha256(content)) {
+        ev.returnValue = content.toString("utf8");
+      }
+    } catch (e) {}
+  });
+
+  // Without a release key no update could be trusted
+  if (!modReleasePublicKey) {
+    console.warn("YandexMusicMod asset updates are off: this build has no release key");
+    return;
+  }
+  electron.app.whenReady().then(checkForUpdates);
+  setInterval(checkForUpdates, CHECK_INTERVAL_MS);
+})();
+"#;
+
+/// Asset updates preload: synchronous access to downloaded assets so the
+/// renderer can prefer them before the bundled code runs
+pub const ASSET_UPDATES_PRELOAD_JS: &str = r#"
+(function () {
+  const { contextBridge, ipcRenderer } = require("electron");
+  contextBridge.exposeInMainWorld("yandexMusicModAssets", {
+    getOverride: (name) => ipcRenderer.sendSync("yandexMusicMod.assets.getOverride", name),
+  });
+})();
+"#;
+
+/// Runs a downloaded renderer.js/renderer.css instead of the bundled base
+/// (`MOD_RENDERER_JS`, `MOD_RENDERER_CSS`). Feature code and the promo rule,
+/// which `generate_renderer_css` puts last, stay active.
+pub const ASSET_UPDATES_RENDERER_PRELUDE_JS: &str = r#"
+(function () {
+  const api = window.yandexMusicModAssets;
+  if (!api || window.__yandexMusicModAssetOverride) return;
+
+  const css = api.getOverride("renderer.css");
+  if (css) {
+    const style = document.createElement("style");
+    style.textContent = css;
+    document.head.appendChild(style);
+    const dropBaseStyles = (link) => {
+      if (!link.sheet) {
+        link.addEventListener("load", () => dropBaseStyles(link), { once: true });
+        return;
+      }
+      while (link.sheet.cssRules.length > 1) link.sheet.deleteRule(0);
+    };
+    document.addEventListener("DOMContentLoaded", () => {
+      const link = document.querySelector('link[href="/yandexMusicMod/renderer.css"]');
+      if (link) dropBaseStyles(link);
+    });
+  }
+
+  const js = api.getOverride("renderer.js");
+  if (js) {
+    window.__yandexMusicModAssetOverride = true;
+    try {
+      (0, eval)(js);
+    } catch (e) {
+      console.error("YandexMusicMod: downloaded renderer.js failed, using bundled copy", e);
+      window.__yandexMusicModAssetOverride = false;
+    }
+  }
+})();
+"#;
+
+/// Blocked-request log: every request cancelled by the analytics blocker is
+/// appended to `logs/blocked-requests.log` under userData (rotated at 1 MB,
+/// 3 files kept) and counted for the mod panel
+pub const BLOCKED_LOG_MAIN_JS: &str = r#"
+(function () {
+  const MAX_LOG_SIZE = 1024 * 1024;
+  const MAX_LOG_FILES = 3;
+  const logDir = path.join(appFolder, "logs");
+  const logPath = path.join(logDir, "blocked-requests.log");
+  let blockedCount = 0;
+  let pending = Promise.resolve();
+
+  fs.mkdirSync(logDir, { recursive: true });
+
+  const rotate = () => {
+    try {
+      if (fs.statSync(logPath).size < MAX_LOG_SIZE) return;
+    } catch (e) {
+      return;
+    }
+    for (let i = MAX_LOG_FILES - 1; i >= 1; i--) {
+      const from = i === 1 ? logPath : `${logPath}.${i - 1}`;
+      if (fs.existsSync(from)) fs.renameSync(from, `${logPath}.${i}`);
+    }
+  };
+
+  globalThis.yandexMusicModOnBlocked = (details) => {
+    blockedCount++;
+    const line = `${new Date().toISOString()}\t${details.method || "GET"}\t${details.url}\n`;
+    // Serialize writes so rotation never races with an append
+    pending = pending
+      .then(() => {
+        rotate();
+        return fs.promises.appendFile(logPath, line);
+      })
+      .catch((e) => console.error("Failed to write blocked-request log:", e));
+  };
+
+  electron.ipcMain.handle("yandexMusicMod.blockedLog.getCount", () => blockedCount);
+})();
+"#;
+
+/// Blocked-request log API exposed to the renderer
+pub const BLOCKED_LOG_PRELOAD_JS: &str = r#"
+(function () {
+  const { contextBridge, ipcRenderer } = require("electron");
+  contextBridge.exposeInMainWorld("yandexMusicModBlockedLog", {
+    getCount: () => ipcRenderer.invoke("yandexMusicMod.blockedLog.getCount"),
+  });
+})();
+"#;
+
+/// Mod panel entry showing the number of blocked requests
+pub const BLOCKED_LOG_RENDERER_JS: &str = r#"
+if (window.yandexMusicModBlockedLog) {
+  window.yandexMusicModPanel.register({
+    label: "blockedLog.count",
+    type: "info",
+    value: () => window.yandexMusicModBlockedLog.getCount(),
+  });
+}
+"#;
+
+/// Offline track cache: audio responses from Yandex storage/streaming hosts
+/// are streamed to `<download folder>/.offline-cache` and served from disk,
+/// ranges included, when the network request fails. The player asks for
+/// ranges (`206`), so for those the whole track is fetched once in the
+/// background. The `https` handler is only installed while
+/// `offline/cacheEnabled` is on and hands every other host straight to the
+/// network; the cache is pruned (oldest first) above `offline/cacheLimitMb` in
+/// the background, at most once per minute.
+pub const OFFLINE_CACHE_MAIN_JS: &str = r#"
+(function () {
+  const crypto = require("crypto");
+  const { Readable } = require("stream");
+  const AUDIO_HOST = /(^|\.)(strm\.yandex\.net|storage\.yandex\.net)$/;
+  const DEFAULT_LIMIT_MB = 2048;
+  const PRUNE_DELAY_MS = 60 * 1000;
+
+  const cacheDir = () => {
+    const settings = readModSettings();
+    return path.join(settings.downloadFolderPath || defaultDownloadPath, ".offline-cache");
+  };
+
+  // Signed query strings and CDN nodes change between plays; key on the path
+  const cacheKey = (url) => crypto.createHash("sha1").update(url.pathname).digest("hex");
+
+  const prune = async (dir) => {
+    const limit = (Number(readModSettings()["offline/cacheLimitMb"]) || DEFAULT_LIMIT_MB) * 1024 * 1024;
+    const names = (await fs.promises.readdir(dir)).filter((name) => !name.endsWith(".json") && !name.endsWith(".tmp"));
+    const files = [];
+    for (const name of names) {
+      const stat = await fs.promises.stat(path.join(dir, name)).catch(() => null);
+      if (stat) files.push({ name, size: stat.size, mtime: stat.mtimeMs });
+    }
+    files.sort((a, b) => a.mtime - b.mtime);
+    let total = files.reduce((sum, f) => sum + f.size, 0);
+    for (const file of files) {
+      if (total <= limit) break;
+      await fs.promises.rm(path.join(dir, file.name), { force: true });
+      await fs.promises.rm(path.join(dir, file.name + ".json"), { force: true });
+      total -= file.size;
+    }
+  };
+
+  let pruneTimer = null;
+  const schedulePrune = (dir) => {
+    if (pruneTimer) return;
+    pruneTimer = setTimeout(() => {
+      pruneTimer = null;
+      prune(dir).catch((e) => console.error("YandexMusicMod: pruning the offline cache failed:", e));
+    }, PRUNE_DELAY_MS);
+  };
+
+  // Write a whole track to the cache; resolves once it is in place
+  const saveToCache = (body, contentType, dir, file) =>
+    new Promise((resolve) => {
+      fs.mkdirSync(dir, { recursive: true });
+      const tmp = `${file}.${process.pid}.tmp`;
+      const out = fs.createWriteStream(tmp);
+      const discard = () => fs.promises.rm(tmp, { force: true }).catch(() => {}).then(resolve);
+      Readable.fromWeb(body)
+        .on("error", () => out.destroy())
+        .pipe(out)
+        .on("error", discard)
+        .on("close", async () => {
+          if (!out.writableFinished) return discard();
+          try {
+            await fs.promises.rename(tmp, file);
+            await fs.promises.writeFile(file + ".json", JSON.stringify({ contentType }));
+            schedulePrune(dir);
+            resolve();
+          } catch (e) {
+            discard();
+          }
+        });
+    });
+
+  const passthrough = (request) => electron.net.fetch(request, { bypassCustomProtocolHandlers: true });
+
+  // The page asked for a part of the track; fetch all of it once
+  const fetching = new Set();
+  const cacheInBackground = (request, dir, file) => {
+    if (fetching.has(file) || fs.existsSync(file)) return;
+    fetching.add(file);
+    const headers = new Headers(request.headers);
+    headers.delete("range");
+    electron.net
+      .fetch(request.url, { headers, bypassCustomProtocolHandlers: true })
+      .then((response) => {
+        if (response.status !== 200 || !response.body) return;
+        return saveToCache(response.body, response.headers.get("content-type"), dir, file);
+      })
+      .catch((e) => console.error("YandexMusicMod: caching a track failed:", e))
+      .finally(() => fetching.delete(file));
+  };
+
+  // A cached track, or the part of it the `Range` header asks for
+  const serveCached = (request, file) => {
+    let contentType = "audio/mpeg";
+    try {
+      contentType = JSON.parse(fs.readFileSync(file + ".json", "utf8")).contentType || contentType;
+    } catch (err) {}
+    fs.utimesSync(file, new Date(), new Date());
+    const size = fs.statSync(file).size;
+    const range = /^bytes=(\d*)-(\d*)$/.exec(request.headers.get("range") || "");
+    if (!range || (!range[1] && !range[2])) {
+      return new Response(Readable.toWeb(fs.createReadStream(file)), {
+        status: 200,
+        headers: { "content-type": contentType, "content-length": String(size), "accept-ranges": "bytes" },
+      });
+    }
+    const start = range[1] ? Number(range[1]) : Math.max(0, size - Number(range[2]));
+    const end = range[1] && range[2] ? Math.min(Number(range[2]), size - 1) : size - 1;
+    if (start > end) {
+      return new Response(null, { status: 416, headers: { "content-range": `bytes */${size}` } });
+    }
+    return new Response(Readable.toWeb(fs.createReadStream(file, { start, end })), {
+      status: 206,
+      headers: {
+        "content-type": contentType,
+        "content-length": String(end - start + 1),
+        "content-range": `bytes ${start}-${end}/${size}`,
+        "accept-ranges": "bytes",
+      },
+    });
+  };
+
+  const handle = async (request) => {
+    const url = new URL(request.url);
+    if (request.method !== "GET" || !AUDIO_HOST.test(url.hostname)) return passthrough(request);
+
+    const dir = cacheDir();
+    const file = path.join(dir, cacheKey(url));
+    try {
+      const response = await passthrough(request);
+      if (response.status === 206) cacheInBackground(request, dir, file);
+      if (response.status !== 200 || !response.body) return response;
+      // Copy the body to the cache while the page reads it
+      const [toPage, toDisk] = response.body.tee();
+      saveToCache(toDisk, response.headers.get("content-type"), dir, file);
+      return new Response(toPage, { status: 200, headers: response.headers });
+    } catch (e) {
+      if (!fs.existsSync(file)) throw e;
+      console.log("YandexMusicMod: serving cached audio for", url.pathname);
+      return serveCached(request, file);
+    }
+  };
+
+  const setEnabled = (enabled) => {
+    const installed = electron.protocol.isProtocolHandled("https");
+    if (enabled && !installed) electron.protocol.handle("https", handle);
+    if (!enabled && installed) electron.protocol.unhandle("https");
+  };
+
+  electron.app.whenReady().then(() => {
+    setEnabled(Boolean(readModSettings()["offline/cacheEnabled"]));
+    onSettingChanged((key, value) => {
+      if (key === "offline/cacheEnabled") setEnabled(Boolean(value));
+    });
+  });
+})();
+"#;
+
+/// Mod panel entries for the offline cache
+pub const OFFLINE_CACHE_RENDERER_JS: &str = r#"
+window.yandexMusicModPanel.register({
+  key: "offline/cacheEnabled",
+  label: "offline.enabled",
+  type: "toggle",
+});
+window.yandexMusicModPanel.register({
+  key: "offline/cacheLimitMb",
+  label: "offline.limit",
+  type: "text",
+  placeholder: "2048",
+});
+"#;
+
+/// Settings telling [`AUTO_REPATCH_MAIN_JS`] how to run the patcher: its path
+/// (`None` to download the latest release) and the `patch` arguments, which
+/// keep `output_dir` and `features`
+pub fn auto_repatch_settings(
+    patcher: Option<&std::path::Path>,
+    output_dir: &std::path::Path,
+    features: &[ModFeature],
+) -> BTreeMap<String, Value> {
+    let mut args = vec![
+        "--json".to_string(),
+        "--yes".to_string(),
+        "patch".to_string(),
+        "--output".to_string(),
+        output_dir.to_string_lossy().into_owned(),
+    ];
+    for feature in features {
+        if let Ok(Value::String(name)) = serde_json::to_value(feature) {
+            args.push("--enable".to_string());
+            args.push(name);
+        }
+    }
+    BTreeMap::from([
+        (
+            "autoRepatch/patcher".to_string(),
+            patcher.map_or(Value::Null, |p| p.to_string_lossy().into()),
+        ),
+        ("autoRepatch/args".to_string(), json!(args)),
+        ("autoRepatch/enabled".to_string(), Value::Bool(true)),
+    ])
+}
+
+/// Auto-repatch: every 6 hours the main process compares the app version with
+/// the stable update manifest. On a new release it runs the patcher (the one
+/// that made this build, else the latest GitHub release, checked against its
+/// `SHA256SUMS`) and remembers the new build. On the next start an app run as
+/// `electron <app dir>` relaunches itself on that build; packaged runtimes only
+/// get a notification with its location. Controlled by `autoRepatch/enabled`.
+/// The patcher path and arguments come only from the patch-time defaults and
+/// the pending build lives in its own file, so a renderer can't change what
+/// runs or which build is launched.
+pub const AUTO_REPATCH_MAIN_JS: &str = r#"
+(function () {
+  const crypto = require("crypto");
+  const { execFile } = require("child_process");
+  const MANIFEST_URL = "https://music-desktop-application.s3.yandex.net/stable/latest.yml";
+  const RELEASES_URL = "https://api.github.com/repos/Jhon-Crow/YandexMusicBetaModeFastLP/releases/latest";
+  const CHECK_INTERVAL_MS = 6 * 60 * 60 * 1000;
+  const patcherDir = path.join(appFolder, "yandexMusicMod-patcher");
+  const pendingFilePath = path.join(appFolder, "yandexMusicMod-autoRepatch.json");
+  const platformAsset =
+    process.platform === "win32"
+      ? "yandex-music-mod-windows-x64.exe"
+      : process.platform === "darwin"
+        ? "yandex-music-mod-macos-x64"
+        : "yandex-music-mod-linux-x64";
+
+  const fetchImpl = (url) => (electron.net.fetch ? electron.net.fetch(url) : fetch(url));
+  const readPending = () => {
+    try {
+      return JSON.parse(fs.readFileSync(pendingFilePath, "utf8")).pending || null;
+    } catch (e) {
+      return null;
+    }
+  };
+  const writePending = (pending) => fs.writeFileSync(pendingFilePath, JSON.stringify({ pending }, null, 2));
+  const isNewer = (a, b) => {
+    const pa = String(a).split(/[.-]/).map(Number);
+    const pb = String(b).split(/[.-]/).map(Number);
+    for (let i = 0; i < Math.max(pa.length, pb.length); i++) {
+      if ((pa[i] || 0) !== (pb[i] || 0)) return (pa[i] || 0) > (pb[i] || 0);
+    }
+    return false;
+  };
+  const notify = (body) => {
+    if (electron.Notification.isSupported()) {
+      new electron.Notification({ title: "Yandex Music Mod", body }).show();
+    }
+  };
+
+  // Switch to a build repatched by an earlier run
+  const pending = readPending();
+  if (pending && pending.modDir && fs.existsSync(pending.modDir)) {
+    if (process.defaultApp && path.resolve(electron.app.getAppPath()) !== path.resolve(pending.modDir)) {
+      writePending(null);
+      electron.app.relaunch({ args: [pending.modDir, ...process.argv.slice(2)] });
+      electron.app.exit(0);
+      return;
+    }
+    if (!process.defaultApp) {
+      electron.app.whenReady().then(() =>
+        notify(`Yandex Music ${pending.version} is patched in ${pending.modDir}`),
+      );
+    }
+  }
+
+  const downloadPatcher = async () => {
+    const target = path.join(patcherDir, platformAsset);
+    if (fs.existsSync(target)) return target;
+
+    const response = await fetchImpl(RELEASES_URL);
+    if (!response.ok) throw new Error(`HTTP ${response.status}`);
+    const release = await response.json();
+    const assetUrl = (name) => ((release.assets || []).find((a) => a.name === name) || {}).browser_download_url;
+    if (!assetUrl(platformAsset) || !assetUrl("SHA256SUMS")) {
+      throw new Error(`Release ${release.tag_name} has no ${platformAsset}`);
+    }
+
+    const sums = await (await fetchImpl(assetUrl("SHA256SUMS"))).text();
+    const line = sums.split(/\r?\n/).find((l) => l.trim().endsWith(platformAsset));
+    const body = Buffer.from(await (await fetchImpl(assetUrl(platformAsset))).arrayBuffer());
+    const sha256 = crypto.createHash("sha256").update(body).digest("hex");
+    if (!line || line.trim().split(/\s+/)[0].toLowerCase() !== sha256) {
+      throw new Error(`Checksum mismatch for ${platformAsset}`);
+    }
+
+    fs.mkdirSync(patcherDir, { recursive: true });
+    fs.writeFileSync(target + ".tmp", body, { mode: 0o755 });
+    fs.renameSync(target + ".tmp", target);
+    return target;
+  };
+
+  let running = false;
+  const checkForUpdate = async () => {
+    const settings = readModSettings();
+    if (running || settings["autoRepatch/enabled"] === false) return;
+    running = true;
+    try {
+      const response = await fetchImpl(MANIFEST_URL);
+      if (!response.ok) throw new Error(`HTTP ${response.status}`);
+      const match = /^version:\s*['"]?([^'"\s]+)/m.exec(await response.text());
+      if (!match) throw new Error("No version in the update manifest");
+      const latest = match[1];
+      const pending = readPending();
+      const current = pending ? pending.version : electron.app.getVersion();
+      if (!isNewer(latest, current)) return;
+
+      const configured = modDefaultSettings["autoRepatch/patcher"];
+      const patcher = configured && fs.existsSync(configured) ? configured : await downloadPatcher();
+      console.log("YandexMusicMod repatching Yandex Music", latest, "with", patcher);
+      const stdout = await new Promise((resolve, reject) =>
+        execFile(patcher, modDefaultSettings["autoRepatch/args"] || ["--json", "--yes", "patch"], { maxBuffer: 64 * 1024 * 1024 }, (e, out) =>
+          e ? reject(e) : resolve(out),
+        ),
+      );
+      const report = JSON.parse(stdout);
+      const modDir = path.join(report.output, "mod");
+      writePending({ version: report.version, modDir });
+      notify(`Yandex Music ${report.version} is patched. Restart the app to switch to it.`);
+    } catch (e) {
+      console.error("YandexMusicMod auto-repatch failed:", e);
+    } finally {
+      running = false;
+    }
+  };
+
+  electron.app.whenReady().then(checkForUpdate);
+  setInterval(checkForUpdate, CHECK_INTERVAL_MS);
+})();
+"#;
+
+/// Mod panel entry for auto-repatching
+pub const AUTO_REPATCH_RENDERER_JS: &str = r#"
+window.yandexMusicModPanel.register({
+  key: "autoRepatch/enabled",
+  label: "autoRepatch.enabled",
+  type: "toggle",
+});
+"#;
+
+/// System media controls main process: turns on Chromium's media session
+/// service, which feeds the page's `navigator.mediaSession` to SMTC on Windows
+/// (MPRIS on Linux), and keeps the media keys away from `globalShortcut` so
+/// they reach the app through SMTC. Runs before `ready`, as switches must.
+pub const SMTC_MAIN_JS: &str = r#"
+(function () {
+  const FEATURES = ["HardwareMediaKeyHandling", "MediaSessionService"];
+  const MEDIA_KEYS = ["MediaPlayPause", "MediaNextTrack", "MediaPreviousTrack", "MediaStop"];
+  const { app, globalShortcut } = electron;
+
+  const switchList = (name) => (app.commandLine.getSwitchValue(name) || "").split(",").filter(Boolean);
+  app.commandLine.appendSwitch(
+    "enable-features",
+    [...new Set(switchList("enable-features").concat(FEATURES))].join(","),
+  );
+  const disabled = switchList("disable-features").filter((feature) => !FEATURES.includes(feature));
+  if (app.commandLine.hasSwitch("disable-features")) {
+    app.commandLine.removeSwitch("disable-features");
+    if (disabled.length) app.commandLine.appendSwitch("disable-features", disabled.join(","));
+  }
+
+  // Media keys registered as global shortcuts would never reach SMTC
+  const isMediaKey = (accelerator) => MEDIA_KEYS.includes(String(accelerator));
+  const register = globalShortcut.register.bind(globalShortcut);
+  const registerAll = globalShortcut.registerAll.bind(globalShortcut);
+  globalShortcut.register = (accelerator, callback) =>
+    isMediaKey(accelerator) ? false : register(accelerator, callback);
+  globalShortcut.registerAll = (accelerators, callback) =>
+    registerAll(accelerators.filter((accelerator) => !isMediaKey(accelerator)), callback);
+
+  app.whenReady().then(() => {
+    MEDIA_KEYS.filter((key) => globalShortcut.isRegistered(key)).forEach((key) => globalShortcut.unregister(key));
+  });
+})();
+"#;
+
+/// System media controls renderer side: completes the page's media session
+/// with absolute artwork URLs in several sizes, the playback state and
+/// position of the playing element, and seeking for the overlay timeline
+pub const SMTC_RENDERER_JS: &str = r#"
+(function () {
+  const session = navigator.mediaSession;
+  if (!session || window.__yandexMusicModSmtc) return;
+  window.__yandexMusicModSmtc = true;
+
+  const ARTWORK_SIZES = ["200x200", "400x400", "1000x1000"];
+  const artworkUrl = (src) => {
+    const url = String(src || "");
+    return url.startsWith("//") ? "https:" + url : /^https?:/.test(url) ? url : "https://" + url;
+  };
+  const completeArtwork = (artwork) => {
+    const result = [];
+    (artwork || []).forEach((image) => {
+      // Yandex cover URIs end in a %% size placeholder
+      if (String(image.src).includes("%%")) {
+        ARTWORK_SIZES.forEach((size) =>
+          result.push({ src: artworkUrl(image.src.replace("%%", size)), sizes: size, type: "image/jpeg" }),
+        );
+      } else {
+        result.push(Object.assign({}, image, { src: artworkUrl(image.src) }));
+      }
+    });
+    return result;
+  };
+
+  const metadata = Object.getOwnPropertyDescriptor(MediaSession.prototype, "metadata");
+  Object.defineProperty(session, "metadata", {
+    configurable: true,
+    get: () => metadata.get.call(session),
+    set: (value) => {
+      if (value && value.artwork) {
+        value = new MediaMetadata({
+          title: value.title,
+          artist: value.artist,
+          album: value.album,
+          artwork: completeArtwork(value.artwork),
+        });
+      }
+      metadata.set.call(session, value);
+    },
+  });
+  if (session.metadata) session.metadata = session.metadata;
+
+  // The player's audio elements are often not in the DOM
+  let media = null;
+  const play = HTMLMediaElement.prototype.play;
+  HTMLMediaElement.prototype.play = function () {
+    media = this;
+    return play.apply(this, arguments);
+  };
+  ["playing", "pause", "ended"].forEach((type) =>
+    document.addEventListener(type, (event) => {
+      media = event.target;
+      session.playbackState = event.target.paused ? "paused" : "playing";
+    }, true),
+  );
+
+  const handlers = {};
+  const setActionHandler = session.setActionHandler.bind(session);
+  session.setActionHandler = (action, handler) => {
+    handlers[action] = handler;
+    return setActionHandler(action, handler);
+  };
+  setActionHandler("seekto", (details) => {
+    if (handlers.seekto) return handlers.seekto(details);
+    if (media && Number.isFinite(details.seekTime)) media.currentTime = details.seekTime;
+  });
+
+  setInterval(() => {
+    if (!media) return;
+    session.playbackState = media.paused ? "paused" : "playing";
+    if (!Number.isFinite(media.duration) || media.duration <= 0) return;
+    try {
+      session.setPositionState({
+        duration: media.duration,
+        playbackRate: media.playbackRate || 1,
+        position: Math.min(media.currentTime, media.duration),
+      });
+    } catch (e) {}
+  }, 1000);
+})();
+"#;
+
+/// Folder of the external mods in the app data folder (userData)
+pub const EXTERNAL_MODS_DIR: &str = "mods";
+
+/// External mods: `*.main.js` files in `mods/` under userData are required
+/// at startup with `global.yandexMusicMod` (settings helpers) set, and the
+/// `*.preload.js`, `*.renderer.js` and `*.css` files are handed to every
+/// window's preload. The `mods/enabled` setting turns them all off.
+pub const EXTERNAL_MODS_MAIN_JS: &str = r#"
+(function () {
+  const modsDir = path.join(appFolder, "mods");
+  const KINDS = { preload: ".preload.js", renderer: ".renderer.js", css: ".css" };
+  fs.mkdirSync(modsDir, { recursive: true });
+
+  const enabled = () => readModSettings()["mods/enabled"] !== false;
+  const listMods = (suffix) => {
+    try {
+      return fs.readdirSync(modsDir).filter((name) => name.endsWith(suffix)).sort();
+    } catch (e) {
+      return [];
+    }
+  };
+  const readMods = (suffix) =>
+    listMods(suffix)
+      .map((name) => {
+        try {
+          return { name, code: fs.readFileSync(path.join(modsDir, name), "utf8") };
+        } catch (e) {
+          return null;
+        }
+      })
+      .filter(Boolean);
+
+  global.yandexMusicMod = { appFolder, modsDir, readModSettings, onSettingChanged };
+  const loaded = [];
+  if (enabled()) {
+    listMods(".main.js").forEach((name) => {
+      try {
+        require(path.join(modsDir, name));
+        loaded.push(name);
+        console.log("YandexMusicMod: loaded mod", name);
+      } catch (e) {
+        console.error("YandexMusicMod: mod " + name + " failed to load:", e);
+      }
+    });
+  }
+
+  electron.ipcMain.on("yandexMusicMod.mods.get", (ev, kind) => {
+    ev.returnValue = KINDS[kind] && enabled() ? readMods(KINDS[kind]) : [];
+  });
+  electron.ipcMain.handle("yandexMusicMod.mods.list", () => ({
+    dir: modsDir,
+    main: loaded,
+    preload: listMods(KINDS.preload),
+    renderer: listMods(KINDS.renderer),
+    css: listMods(KINDS.css),
+  }));
+  electron.ipcMain.handle("yandexMusicMod.mods.openFolder", () => electron.shell.openPath(modsDir));
+})();
+"#;
+
+/// External mods preload: runs the `*.preload.js` mods, inserts the `*.css`
+/// mods and runs the `*.renderer.js` mods in the page once it is parsed
+pub const EXTERNAL_MODS_PRELOAD_JS: &str = r#"
+(function () {
+  const { contextBridge, ipcRenderer, webFrame } = require("electron");
+  const mods = (kind) => ipcRenderer.sendSync("yandexMusicMod.mods.get", kind) || [];
+
+  mods("preload").forEach(({ name, code }) => {
+    try {
+      new Function("require", "ipcRenderer", code)(require, ipcRenderer);
+    } catch (e) {
+      console.error("YandexMusicMod: preload mod " + name + " failed:", e);
+    }
+  });
+  mods("css").forEach(({ code }) => webFrame.insertCSS(code));
+
+  const renderers = mods("renderer");
+  window.addEventListener("DOMContentLoaded", () =>
+    renderers.forEach(({ name, code }) =>
+      webFrame
+        .executeJavaScript(code + "\n//# sourceURL=mods/" + name)
+        .catch((e) => console.error("YandexMusicMod: renderer mod " + name + " failed:", e)),
+    ),
+  );
+
+  contextBridge.exposeInMainWorld("yandexMusicModMods", {
+    list: () => ipcRenderer.invoke("yandexMusicMod.mods.list"),
+    openFolder: () => ipcRenderer.invoke("yandexMusicMod.mods.openFolder"),
+  });
+})();
+"#;
+
+/// External mods panel entries
+pub const EXTERNAL_MODS_RENDERER_JS: &str = r#"
+if (window.yandexMusicModMods) {
+  window.yandexMusicModPanel.register({
+    key: "mods/enabled",
+    label: "mods.enabled",
+    type: "toggle",
+  });
+  window.yandexMusicModPanel.register({
+    label: "mods.installed",
+    type: "info",
+    value: () =>
+      window.yandexMusicModMods
+        .list()
+        .then((mods) => mods.main.length + mods.preload.length + mods.renderer.length + mods.css.length),
+  });
+  window.yandexMusicModPanel.register({
+    label: "mods.folder",
+    type: "button",
+    action: () => window.yandexMusicModMods.openFolder(),
+  });
+}
+"#;
+
+/// Anti-DPI main process: resolves names through DNS-over-HTTPS
+/// (`network/dohMode`, `network/dohServers`), so ISP DNS cannot spoof Yandex
+/// hosts, and sets `--host-resolver-rules` before `ready`: the Yandex media
+/// hosts can be sent to another address or CDN edge (`network/frontHost`, the
+/// TLS name stays the original) and `network/hostRules` takes raw Chromium
+/// rules. QUIC is turned off unless `network/quic` is set, as DPI boxes
+/// throttle it first.
+pub const ANTI_DPI_MAIN_JS: &str = r#"
+(function () {
+  const MEDIA_HOSTS = ["*.strm.yandex.net", "*.strm.yandex.ru", "strm.yandex.net", "strm.yandex.ru", "*.music.yandex.net"];
+  const DEFAULT_DOH = ["https://cloudflare-dns.com/dns-query", "https://dns.google/dns-query"];
+  const { app } = electron;
+  const settings = readModSettings();
+
+  const rules = [];
+  const frontHost = (settings["network/frontHost"] || "").trim();
+  if (frontHost) MEDIA_HOSTS.forEach((host) => rules.push("MAP " + host + " " + frontHost));
+  (settings["network/hostRules"] || "")
+    .split(/[,\n]/)
+    .map((rule) => rule.trim())
+    .filter(Boolean)
+    .forEach((rule) => rules.push(rule));
+  if (rules.length) {
+    const existing = app.commandLine.getSwitchValue("host-resolver-rules");
+    app.commandLine.appendSwitch("host-resolver-rules", (existing ? existing + "," : "") + rules.join(","));
+  }
+  if (settings["network/quic"] !== true) app.commandLine.appendSwitch("disable-quic");
+
+  const applyDoh = () => {
+    const current = readModSettings();
+    // "automatic" falls back to plain DNS where the DoH servers are blocked
+    const mode = current["network/dohMode"] || "automatic";
+    const servers = String(current["network/dohServers"] || "")
+      .split(/[\s,]+/)
+      .filter((server) => /^https:\/\//.test(server));
+    try {
+      app.configureHostResolver({
+        enableBuiltInResolver: true,
+        secureDnsMode: mode,
+        secureDnsServers: mode === "off" ? [] : servers.length ? servers : DEFAULT_DOH,
+      });
+      console.log("YandexMusicMod DNS-over-HTTPS:", mode);
+    } catch (e) {
+      console.error("Failed to configure DNS-over-HTTPS:", e);
+    }
+  };
+
+  app.whenReady().then(applyDoh);
+  onSettingChanged((key) => {
+    if (key === "network/dohMode" || key === "network/dohServers") applyDoh();
+  });
+})();
+"#;
+
+/// Anti-DPI panel entries
+pub const ANTI_DPI_RENDERER_JS: &str = r#"
+window.yandexMusicModPanel.register({
+  key: "network/dohMode",
+  label: "antiDpi.doh",
+  type: "select",
+  options: [
+    { value: "automatic", label: "antiDpi.doh.automatic" },
+    { value: "secure", label: "antiDpi.doh.secure" },
+    { value: "off", label: "antiDpi.doh.off" },
+  ],
+});
+window.yandexMusicModPanel.register({
+  key: "network/dohServers",
+  label: "antiDpi.dohServers",
+  type: "text",
+  placeholder: "https://cloudflare-dns.com/dns-query",
+});
+window.yandexMusicModPanel.register({
+  key: "network/frontHost",
+  label: "antiDpi.frontHost",
+  type: "text",
+  placeholder: "203.0.113.10",
+});
+window.yandexMusicModPanel.register({
+  key: "network/hostRules",
+  label: "antiDpi.hostRules",
+  type: "text",
+  placeholder: "MAP *.example.com 127.0.0.1",
+});
+window.yandexMusicModPanel.register({
+  key: "network/quic",
+  label: "antiDpi.quic",
+  type: "toggle",
+});
+"#;
+
+/// Sleep timer main process: choosing `sleepTimer/mode` in the panel stores
+/// the deadline in `sleepTimer/endsAt`, so a timer survives a reload or
+/// restart. When it fires, the windows are told to pause, or the app quits if
+/// `sleepTimer/action` is `exit`, and the mode goes back to `off`.
+pub const SLEEP_TIMER_MAIN_JS: &str = r#"
+(function () {
+  const MINUTES = { 15: 15, 30: 30, 60: 60 };
+  let timer = null;
+
+  const setSetting = (key, value) => {
+    const settings = readModSettings();
+    settings[key] = value;
+    fs.writeFileSync(settingsFilePath, JSON.stringify(settings, null, 2));
+    notifySettingChanged(key, value);
+  };
+
+  const fire = () => {
+    clearTimeout(timer);
+    timer = null;
+    const exit = readModSettings()["sleepTimer/action"] === "exit";
+    setSetting("sleepTimer/endsAt", null);
+    setSetting("sleepTimer/mode", "off");
+    console.log("YandexMusicMod sleep timer:", exit ? "quitting" : "pausing");
+    if (exit) {
+      electron.app.quit();
+    } else {
+      electron.BrowserWindow.getAllWindows().forEach((window) =>
+        window.webContents.send("yandexMusicMod.sleepTimer.pause"),
+      );
+    }
+  };
+
+  const schedule = (endsAt) => {
+    clearTimeout(timer);
+    timer = endsAt ? setTimeout(fire, Math.max(0, endsAt - Date.now())) : null;
+  };
+
+  const start = (mode) => {
+    const endsAt = MINUTES[mode] ? Date.now() + MINUTES[mode] * 60 * 1000 : null;
+    setSetting("sleepTimer/endsAt", endsAt);
+    schedule(endsAt);
+  };
+
+  // Resume a running timer; one that ran out while the app was closed is reset
+  const settings = readModSettings();
+  if (settings["sleepTimer/endsAt"] > Date.now()) {
+    schedule(settings["sleepTimer/endsAt"]);
+  } else if (MINUTES[settings["sleepTimer/mode"]]) {
+    setSetting("sleepTimer/endsAt", null);
+    setSetting("sleepTimer/mode", "off");
+  }
+
+  onSettingChanged((key, value) => {
+    if (key === "sleepTimer/mode") start(value);
+  });
+  electron.ipcMain.on("yandexMusicMod.sleepTimer.trackEnded", () => {
+    if (readModSettings()["sleepTimer/mode"] === "end-of-track") fire();
+  });
+  electron.ipcMain.handle("yandexMusicMod.sleepTimer.remaining", () => {
+    const current = readModSettings();
+    if (current["sleepTimer/mode"] === "end-of-track") return -1;
+    return current["sleepTimer/endsAt"] ? Math.max(0, current["sleepTimer/endsAt"] - Date.now()) : null;
+  });
+})();
+"#;
+
+/// Sleep timer preload bridge
+pub const SLEEP_TIMER_PRELOAD_JS: &str = r#"
+(function () {
+  const { contextBridge, ipcRenderer } = require("electron");
+  contextBridge.exposeInMainWorld("yandexMusicModSleepTimer", {
+    remaining: () => ipcRenderer.invoke("yandexMusicMod.sleepTimer.remaining"),
+    trackEnded: () => ipcRenderer.send("yandexMusicMod.sleepTimer.trackEnded"),
+    onPause: (callback) => ipcRenderer.on("yandexMusicMod.sleepTimer.pause", () => callback()),
+  });
+})();
+"#;
+
+/// Sleep timer panel entries; pauses the audio element that played last and
+/// reports finished tracks for the end-of-track mode
+pub const SLEEP_TIMER_RENDERER_JS: &str = r#"
+(function () {
+  const api = window.yandexMusicModSleepTimer;
+  if (!api || window.__yandexMusicModSleepTimer) return;
+  window.__yandexMusicModSleepTimer = true;
+
+  // The player's audio elements are often not in the DOM
+  let media = null;
+  const watched = new WeakSet();
+  const play = HTMLMediaElement.prototype.play;
+  HTMLMediaElement.prototype.play = function () {
+    media = this;
+    if (!watched.has(this)) {
+      watched.add(this);
+      this.addEventListener("ended", () => api.trackEnded());
+    }
+    return play.apply(this, arguments);
+  };
+
+  // The player may already have moved on to the next track; pause it again
+  // once it has started
+  api.onPause(() => {
+    if (!media) return;
+    media.pause();
+    setTimeout(() => media.pause(), 1000);
+  });
+
+  window.yandexMusicModPanel.register({
+    key: "sleepTimer/mode",
+    label: "sleepTimer.mode",
+    type: "select",
+    options: [
+      { value: "off", label: "sleepTimer.off" },
+      { value: "15", label: "sleepTimer.minutes15" },
+      { value: "30", label: "sleepTimer.minutes30" },
+      { value: "60", label: "sleepTimer.minutes60" },
+      { value: "end-of-track", label: "sleepTimer.endOfTrack" },
+    ],
+  });
+  window.yandexMusicModPanel.register({
+    key: "sleepTimer/action",
+    label: "sleepTimer.action",
+    type: "select",
+    options: [
+      { value: "pause", label: "sleepTimer.pause" },
+      { value: "exit", label: "sleepTimer.exit" },
+    ],
+  });
+  window.yandexMusicModPanel.register({
+    label: "sleepTimer.left",
+    type: "info",
+    value: () =>
+      api.remaining().then((ms) => {
+        const t = window.yandexMusicModPanel.t;
+        if (ms === -1) return t("sleepTimer.left.endOfTrack");
+        if (ms == null) return t("sleepTimer.left.off");
+        const seconds = Math.ceil(ms / 1000);
+        return Math.floor(seconds / 60) + ":" + String(seconds % 60).padStart(2, "0");
+      }),
+  });
+})();
+"#;
+
+/// Hardening: replaces the handlers of the mod IPC that take paths or settings
+/// with validating ones. Only folders picked in the folder dialog can become
+/// the download folder, and only folders inside it can be opened, so a
+/// compromised page cannot open arbitrary paths. `<webview>` tags get neither
+/// Node.js nor a preload script. `createWindow.js` is patched at patch time to
+/// isolate the windows (see `harden_create_window_js`).
+pub const HARDENING_MAIN_JS: &str = r#"
+(function () {
+  const approvedFolders = new Set([path.resolve(defaultDownloadPath)]);
+  const isInside = (base, target) => {
+    const relative = path.relative(path.resolve(base), path.resolve(target));
+    return relative !== ".." && !relative.startsWith(".." + path.sep) && !path.isAbsolute(relative);
+  };
+
+  electron.ipcMain.removeHandler("yandexMusicMod.selectDownloadFolder");
+  electron.ipcMain.handle("yandexMusicMod.selectDownloadFolder", async () => {
+    const result = await electron.dialog.showOpenDialog({
+      properties: ["openDirectory"],
+      title: "Select download folder",
+    });
+    if (result.canceled || !result.filePaths.length) {
+      return { success: false, path: null };
+    }
+    approvedFolders.add(path.resolve(result.filePaths[0]));
+    return { success: true, path: result.filePaths[0] };
+  });
+
+  electron.ipcMain.removeHandler("yandexMusicMod.openFolder");
+  electron.ipcMain.handle("yandexMusicMod.openFolder", async (_ev, folderPath) => {
+    if (typeof folderPath !== "string" || !folderPath || !isInside(downloadFolder(), folderPath)) {
+      console.warn("[YandexMusicMod] Refused to open a folder outside the download folder:", folderPath);
+      return { success: false, error: "Only folders inside the download folder can be opened" };
+    }
+    const error = await electron.shell.openPath(path.resolve(folderPath));
+    return error ? { success: false, error } : { success: true };
+  });
+
+  electron.ipcMain.removeAllListeners("yandexMusicMod.setStorageValue");
+  electron.ipcMain.on("yandexMusicMod.setStorageValue", (_ev, key, value) => {
+    if (typeof key !== "string" || key === "__proto__" || !/^[\w-]{1,32}(\/[\w.-]{1,64})*$/.test(key)) {
+      console.warn("[YandexMusicMod] Refused to store an invalid setting key:", key);
+      return;
+    }
+    if (mainOnlySettingKeys.has(key)) {
+      console.warn("[YandexMusicMod] Refused to store a main-process setting:", key);
+      return;
+    }
+    if (
+      key === "downloadFolderPath" &&
+      !(typeof value === "string" && path.isAbsolute(value) && approvedFolders.has(path.resolve(value)))
+    ) {
+      console.warn("[YandexMusicMod] Refused a download folder not picked in the dialog:", value);
+      return;
+    }
+    const settings = readModSettings();
+    settings[key] = value;
+    fs.writeFileSync(settingsFilePath, JSON.stringify(settings, null, 2));
+    notifySettingChanged(key, value);
+  });
+
+  electron.app.on("web-contents-created", (_ev, contents) => {
+    contents.on("will-attach-webview", (_attachEv, webPreferences) => {
+      delete webPreferences.preload;
+      webPreferences.nodeIntegration = false;
+      webPreferences.contextIsolation = true;
+    });
+  });
+})();
+"#;
+
+/// The preload.js mod code
+pub const MOD_PRELOAD_JS: &str = r#"
+const { contextBridge, ipcRenderer } = require("electron");
+
+// Expose mod API to the renderer process
+contextBridge.exposeInMainWorld("yandexMusicMod", {
+  getStorageValue: (key) => ipcRenderer.invoke("yandexMusicMod.getStorageValue", key),
+  setStorageValue: (key, value) => ipcRenderer.send("yandexMusicMod.setStorageValue", key, value),
+  onStorageValueUpdated: (callback) => {
+    ipcRenderer.on("yandexMusicMod.storageValueUpdated", (event, key, value) => {
+      callback(key, value);
+    });
+  },
+  selectDownloadFolder: () => ipcRenderer.invoke("yandexMusicMod.selectDownloadFolder"),
+  openFolder: (folderPath) => ipcRenderer.invoke("yandexMusicMod.openFolder", folderPath),
+  openDownloadDirectory: () => ipcRenderer.send("yandexMusicMod.openDownloadDirectory"),
+  listProfiles: () => ipcRenderer.invoke("yandexMusicMod.profiles.list"),
+  switchProfile: (name) => ipcRenderer.invoke("yandexMusicMod.profiles.switch", name),
+  deleteProfile: (name) => ipcRenderer.invoke("yandexMusicMod.profiles.delete", name),
+});
+
+console.log("YandexMusicMod preload.js loaded successfully!");
+"#;
+
+/// The renderer.js mod code (minimal placeholder)
+pub const MOD_RENDERER_JS: &str = r#"
+(function() {
+  console.log("YandexMusicMod renderer.js loaded!");
+
+  const modApi = window.yandexMusicMod;
+
+  // Settings shown in the mod panel. Features may add entries through
+  // window.yandexMusicModPanel.register() before or after the panel opens.
+  const panelItems = [
+    { key: "window/alwaysOnTop", label: "panel.alwaysOnTop", type: "toggle" },
+    { key: "devtools/systemToolbar", label: "panel.systemToolbar", type: "toggle" },
+    { key: "network/proxy", label: "panel.proxy", type: "text", placeholder: "socks5://127.0.0.1:1080" },
+    {
+      key: "network/proxyScope",
+      label: "panel.proxyScope",
+      type: "select",
+      options: [
+        { value: "all", label: "panel.proxyScope.all" },
+        { value: "music-api", label: "panel.proxyScope.musicApi" },
+      ],
+    },
+    {
+      key: "mod/theme",
+      label: "panel.theme",
+      type: "select",
+      options: [
+        { value: "dark", label: "panel.theme.dark" },
+        { value: "light", label: "panel.theme.light" },
+      ],
+    },
+    {
+      key: "mod/language",
+      label: "panel.language",
+      type: "select",
+      options: [
+        { value: "auto", label: "panel.language.auto" },
+        { value: "en", label: "English" },
+        { value: "ru", label: "Русский" },
+      ],
+    },
+  ];
+
+  // UI strings of the languages the patcher embedded as `modStrings`; labels
+  // are looked up by id, and text that is not an id is shown as it is
+  const strings = typeof modStrings === "object" ? modStrings : { en: {} };
+  let languageSetting = "auto";
+  let lang = "en";
+  const detectLanguage = () => {
+    const locale =
+      languageSetting && languageSetting !== "auto"
+        ? languageSetting
+        : document.documentElement.lang || navigator.language || "en";
+    const code = String(locale).toLowerCase().split(/[-_]/)[0];
+    lang = strings[code] ? code : "en";
+  };
+  const t = (id) => (strings[lang] && strings[lang][id]) || strings.en[id] || id;
+
+  let panel = null;
+
+  function renderItem(item) {
+    const row = document.createElement("label");
+    row.className = "ym-mod-panel__row";
+
+    const title = document.createElement("span");
+    title.textContent = t(item.label);
+    row.appendChild(title);
+
+    if (item.type === "button") {
+      const button = document.createElement("button");
+      button.textContent = t(item.buttonLabel || "panel.open");
+      button.addEventListener("click", () => item.action());
+      row.appendChild(button);
+      return row;
+    }
+
+    if (item.type === "info") {
+      const value = document.createElement("span");
+      value.className = "ym-mod-panel__value";
+      Promise.resolve(item.value()).then((v) => (value.textContent = String(v)));
+      row.appendChild(value);
+      return row;
+    }
+
+    let input;
+    if (item.type === "toggle") {
+      input = document.createElement("input");
+      input.type = "checkbox";
+      input.addEventListener("change", () => modApi.setStorageValue(item.key, input.checked));
+    } else if (item.type === "select") {
+      input = document.createElement("select");
+      item.options.forEach((option) => {
+        const el = document.createElement("option");
+        el.value = option.value;
+        el.textContent = t(option.label);
+        input.appendChild(el);
+      });
+      input.addEventListener("change", () => modApi.setStorageValue(item.key, input.value));
+    } else {
+      input = document.createElement("input");
+      input.type = "text";
+      input.placeholder = item.placeholder || "";
+      input.addEventListener("change", () => modApi.setStorageValue(item.key, input.value.trim()));
+    }
+    input.dataset.key = item.key;
+    row.appendChild(input);
+
+    modApi.getStorageValue(item.key).then((value) => {
+      if (item.type === "toggle") input.checked = Boolean(value);
+      else input.value = value == null ? "" : value;
+    });
+    return row;
+  }
+
+  // Settings profile picker; each profile has its own mod_settings.json
+  function renderProfiles() {
+    const row = document.createElement("div");
+    row.className = "ym-mod-panel__row";
+
+    const title = document.createElement("span");
+    title.textContent = t("panel.profile");
+    row.appendChild(title);
+
+    const select = document.createElement("select");
+    const name = document.createElement("input");
+    name.type = "text";
+    name.placeholder = t("panel.newProfile");
+    const add = document.createElement("button");
+    add.textContent = t("panel.add");
+    const remove = document.createElement("button");
+    remove.textContent = t("panel.delete");
+
+    const update = (state) => {
+      if (!state || state.success === false) return;
+      select.replaceChildren(
+        ...state.profiles.map((profile) => {
+          const el = document.createElement("option");
+          el.value = profile;
+          el.textContent = profile;
+          return el;
+        }),
+      );
+      select.value = state.active;
+      remove.disabled = state.active === "default";
+    };
+
+    select.addEventListener("change", () => modApi.switchProfile(select.value).then(update));
+    add.addEventListener("click", () => {
+      const profile = name.value.trim();
+      if (!profile) return;
+      name.value = "";
+      modApi.switchProfile(profile).then(update);
+    });
+    remove.addEventListener("click", () => modApi.deleteProfile(select.value).then(update));
+
+    row.append(select, name, add, remove);
+    modApi.listProfiles().then(update);
+    return row;
+  }
+
+  function buildPanel() {
+    detectLanguage();
+    panel = document.createElement("div");
+    panel.className = "ym-mod-panel";
+
+    const header = document.createElement("div");
+    header.className = "ym-mod-panel__header";
+    header.textContent = "YandexMusicMod";
+    const close = document.createElement("button");
+    close.textContent = "×";
+    close.addEventListener("click", () => togglePanel(false));
+    header.appendChild(close);
+    panel.appendChild(header);
+
+    if (modApi.listProfiles) panel.appendChild(renderProfiles());
+    panelItems.forEach((item) => panel.appendChild(renderItem(item)));
+    document.body.appendChild(panel);
+    modApi.getStorageValue("mod/theme").then(applyTheme);
+  }
+
+  function applyTheme(theme) {
+    if (panel) panel.classList.toggle("ym-mod-panel--light", theme === "light");
+  }
+
+  function togglePanel(show) {
+    if (!modApi) return;
+    const visible = panel && panel.isConnected;
+    if (show === undefined) show = !visible;
+    if (visible) panel.remove();
+    if (show) buildPanel();
+  }
+
+  window.yandexMusicModPanel = {
+    register: (item) => panelItems.push(item),
+    toggle: togglePanel,
+    t,
+  };
+
+  if (modApi) {
+    modApi.getStorageValue("mod/language").then((value) => (languageSetting = value));
+    modApi.onStorageValueUpdated((key, value) => {
+      if (key === "mod/language") {
+        languageSetting = value;
+        if (panel && panel.isConnected) togglePanel(true);
+      }
+      if (!panel) return;
+      if (key === "mod/theme") applyTheme(value);
+      const input = panel.querySelector(`[data-key="${key}"]`);
+      if (!input) return;
+      if (input.type === "checkbox") input.checked = Boolean(value);
+      else input.value = value == null ? "" : value;
+    });
+  }
+
+  // Ctrl+Shift+M opens the mod settings panel
+  window.addEventListener("keydown", (event) => {
+    if (event.ctrlKey && event.shiftKey && event.code === "KeyM") {
+      event.preventDefault();
+      togglePanel();
+    }
+  });
+
+  // Wait for the page to load
+  window.addEventListener("load", function() {
+    console.log("YandexMusicMod: Page loaded");
+
+    // Add mod indicator; clicking it opens the settings panel
+    const modIndicator = document.createElement("div");
+    modIndicator.style.cssText = "position:fixed;bottom:10px;right:10px;padding:5px 10px;background:rgba(0,0,0,0.7);color:#fff;border-radius:5px;font-size:12px;z-index:9999;cursor:pointer;";
+    detectLanguage();
+    modIndicator.textContent = t("indicator");
+    modIndicator.addEventListener("click", () => togglePanel(true));
+    document.body.appendChild(modIndicator);
+
+    // Hide indicator after 5 seconds
+    setTimeout(() => {
+      modIndicator.style.opacity = "0";
+      modIndicator.style.transition = "opacity 0.5s";
+      setTimeout(() => modIndicator.remove(), 500);
+    }, 5000);
+  });
+})();
+"#;
+
+/// Built-in promo/upgrade selectors hidden by the renderer CSS
+pub const PROMO_SELECTORS_TXT: &str = include_str!("../rules/promo-selectors.txt");
+
+/// Parse a line list (selectors, header names): one entry per line,
+/// `#` starts a comment line
+pub fn parse_line_list(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Generate renderer.css: the static mod styles plus a rule hiding the
+/// built-in and `extra_selectors` promo elements. The promo rule comes last,
+/// downloaded base styles of `asset-updates` replace only the rules before it.
+pub fn generate_renderer_css(extra_selectors: &[String]) -> String {
+    let mut selectors = parse_line_list(PROMO_SELECTORS_TXT);
+    for selector in extra_selectors {
+        if !selectors.contains(selector) {
+            selectors.push(selector.clone());
+        }
+    }
+
+    format!(
+        "{}\n/* Hide promo and upgrade banners */\n{} {{\n  display: none !important;\n}}\n",
+        MOD_RENDERER_CSS,
+        selectors.join(",\n")
+    )
+}
+
+/// The renderer.css mod styles
+pub const MOD_RENDERER_CSS: &str = r#"
+/* YandexMusicMod custom styles */
+
+/* Custom scrollbar */
+::-webkit-scrollbar {
+  width: 8px;
+}
+
+::-webkit-scrollbar-track {
+  background: rgba(0, 0, 0, 0.1);
+}
+
+::-webkit-scrollbar-thumb {
+  background: rgba(255, 255, 255, 0.3);
+  border-radius: 4px;
+}
+
+::-webkit-scrollbar-thumb:hover {
+  background: rgba(255, 255, 255, 0.5);
+}
+
+/* Mod settings panel */
+.ym-mod-panel {
+  position: fixed;
+  top: 60px;
+  right: 20px;
+  width: 320px;
+  max-height: 70vh;
+  overflow-y: auto;
+  padding: 12px;
+  background: rgba(20, 20, 20, 0.95);
+  color: #fff;
+  border-radius: 8px;
+  font-size: 13px;
+  z-index: 10000;
+  box-shadow: 0 4px 20px rgba(0, 0, 0, 0.5);
+}
+
+.ym-mod-panel--light {
+  background: rgba(250, 250, 250, 0.97);
+  color: #111;
+  box-shadow: 0 4px 20px rgba(0, 0, 0, 0.2);
+}
+
+.ym-mod-panel__header {
+  display: flex;
+  justify-content: space-between;
+  align-items: center;
+  margin-bottom: 8px;
+  font-weight: bold;
+}
+
+.ym-mod-panel__header button {
+  background: none;
+  border: none;
+  color: inherit;
+  font-size: 18px;
+  cursor: pointer;
+}
+
+.ym-mod-panel__row {
+  display: flex;
+  justify-content: space-between;
+  align-items: center;
+  gap: 8px;
+  padding: 6px 0;
+}
+
+.ym-mod-panel__row input[type="text"],
+.ym-mod-panel__row select {
+  width: 150px;
+}
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_patch_config_js() {
+        let input = "enableDevTools: false, enableAutoUpdate: true";
+        let output = patch_config_js(input);
+        assert!(output.contains("enableDevTools: true"));
+        assert!(output.contains("enableAutoUpdate: false"));
+    }
+
+    #[test]
+    fn test_patch_html() {
+        let input = "<html><head><title>Test</title></head></html>";
+        let output = patch_html(input);
+        assert!(output.contains("yandexMusicMod/renderer.js"));
+        assert!(output.contains("yandexMusicMod/renderer.css"));
+    }
+
+    #[test]
+    fn test_check_compatibility() {
+        assert_eq!(
+            check_compatibility(latest_known_version()),
+            Compatibility::Known
+        );
+        assert_eq!(check_compatibility("5.0.0"), Compatibility::Untested);
+        assert_eq!(check_compatibility("99.0.0"), Compatibility::Newer);
+        assert_eq!(check_compatibility("garbage"), Compatibility::Newer);
+    }
+
+    #[test]
+    fn test_generate_renderer_css() {
+        let extra = parse_line_list("# comment\n\n.new-promo\n.plus-promo\n");
+        assert_eq!(extra, vec![".new-promo", ".plus-promo"]);
+
+        let css = generate_renderer_css(&extra);
+        assert!(css.contains(".upgrade-banner,\n"));
+        assert!(css.contains(".new-promo {"));
+        assert_eq!(css.matches(".plus-promo").count(), 1);
+        assert!(css.contains("::-webkit-scrollbar"));
+    }
+
+    #[test]
+    fn test_patch_create_window_js_min_size() {
+        let input = "new BrowserWindow({ minWidth: 768, minHeight: 650, show: false });";
+
+        let output = patch_create_window_js(input, &WindowOptions::default());
+        assert!(output.contains("minWidth: 360"));
+        assert!(output.contains("minHeight: 550"));
+
+        let window = WindowOptions {
+            min_width: Some(1024),
+            min_height: None,
+            ..Default::default()
+        };
+        let output = patch_create_window_js(input, &window);
+        assert!(output.contains("minWidth: 1024"));
+        assert!(output.contains("minHeight: 650"));
+    }
+
+    #[test]
+    fn test_always_on_top_setting() {
+        assert!(MOD_MAIN_JS.contains("setAlwaysOnTop"));
+        assert!(MOD_RENDERER_JS.contains("\"window/alwaysOnTop\""));
+    }
+
+    #[test]
+    fn test_generate_renderer_js_features() {
+        let strings = generate_strings_js();
+        assert_eq!(
+            generate_renderer_js(&[]),
+            format!("{}{}", strings, MOD_RENDERER_JS)
+        );
+
+        let js = generate_renderer_js(&[ModFeature::Autostart]);
+        assert!(js.starts_with(&strings));
+        assert!(js.contains("\"system/autostart\""));
+    }
+
+    #[test]
+    fn test_renderer_locales() {
+        let table =
+            |json: &str| -> serde_json::Map<String, Value> { serde_json::from_str(json).unwrap() };
+        let en = table(RENDERER_LOCALES[0].1);
+        for (lang, json) in RENDERER_LOCALES {
+            let strings = table(json);
+            let mut ids: Vec<_> = strings.keys().collect();
+            ids.retain(|id| !en.contains_key(*id));
+            assert!(ids.is_empty(), "{} has unknown ids {:?}", lang, ids);
+            assert_eq!(strings.len(), en.len(), "{} misses strings", lang);
+        }
+
+        // Every label id used by the panel and the features has a string
+        let features = [
+            ModFeature::Autostart,
+            ModFeature::MiniPlayer,
+            ModFeature::BlockedLog,
+            ModFeature::OfflineCache,
+            ModFeature::AutoRepatch,
+            ModFeature::ExternalMods,
+            ModFeature::AntiDpi,
+            ModFeature::SleepTimer,
+        ];
+        let js = generate_renderer_js(&features);
+        let id = regex::Regex::new(r#"label: "([a-zA-Z]+\.[\w.]+)""#).unwrap();
+        for captures in id.captures_iter(&js) {
+            assert!(
+                en.contains_key(&captures[1]),
+                "no string for {}",
+                &captures[1]
+            );
+        }
+        assert!(js.starts_with("const modStrings = {\n  en: {"));
+    }
+
+    #[test]
+    fn test_proxy_main_js() {
+        assert!(PROXY_MAIN_JS.contains("setProxy(config)"));
+        assert!(PROXY_MAIN_JS.contains("\"network/proxy\""));
+        assert!(PROXY_MAIN_JS.contains("api.music.yandex.net"));
+    }
+
+    #[test]
+    fn test_analytics_blocker_banned_headers() {
+        let js = generate_analytics_blocker_js(&[
+            "X-Custom-Header".to_string(),
+            "X-Request-Id".to_string(),
+        ]);
+        assert!(js.contains(r#"["x-yandex-music-device","x-request-id","x-custom-header"]"#));
+        assert!(js.contains("delete details.requestHeaders[name];"));
+        assert!(js.contains("globalThis.yandexMusicModOnBlocked(details)"));
+        assert!(!js.contains("= undefined"));
+    }
+
+    #[test]
+    fn test_patch_main_js() {
+        let input = "(0, createWindow_js_1.createWindow)();\nstart();";
+        let output = patch_main_js(input, &[]);
+        assert!(output.contains("onBeforeRequest"));
+        assert!(output.contains("onBeforeSendHeaders"));
+        assert!(output.ends_with("start();"));
+    }
+
+    #[test]
+    fn test_mini_player_feature() {
+        let feature = ModFeature::MiniPlayer;
+        assert!(feature
+            .main_js()
+            .contains("yandexMusicMod.miniPlayer.toggle"));
+        assert!(feature.preload_js().contains("yandexMusicModMiniPlayer"));
+        let names: Vec<&str> = feature.assets().iter().map(|(name, _)| *name).collect();
+        assert_eq!(names, vec!["mini-player.html", "mini-player-preload.js"]);
+        assert!(MINI_PLAYER_MAIN_JS.contains("mini-player-preload.js"));
+        assert!(MINI_PLAYER_MAIN_JS.contains("modHtmlRoot, \"yandexMusicMod\""));
+        assert_eq!(
+            generate_html_root_js("resources/app"),
+            "const modHtmlRoot = \"resources/app\";\n"
+        );
+    }
+
+    #[test]
+    fn test_generate_renderer_js_asset_updates() {
+        let js = generate_renderer_js(&[ModFeature::AssetUpdates, ModFeature::Autostart]);
+        let prelude = format!(
+            "{}{}",
+            generate_strings_js(),
+            ASSET_UPDATES_RENDERER_PRELUDE_JS
+        );
+        assert!(js.starts_with(&prelude));
+        let guard = js
+            .find("if (window.__yandexMusicModAssetOverride) return;")
+            .unwrap();
+        assert!(js.find(MOD_RENDERER_JS).unwrap() > guard);
+        // Feature code runs whether or not the base was replaced
+        assert!(js.ends_with(AUTOSTART_RENDERER_JS));
+        assert_eq!(
+            generate_release_key_js(Some("KEY")),
+            "const modReleasePublicKey = \"KEY\";\n"
+        );
+        assert_eq!(
+            generate_release_key_js(None),
+            "const modReleasePublicKey = null;\n"
+        );
+
+        let manifest = generate_assets_manifest(&[("renderer.js", "hi\n")]);
+        let manifest: Value = serde_json::from_str(&manifest).unwrap();
+        assert_eq!(
+            manifest["files"]["renderer.js"],
+            "98ea6e4f216f2fb4b69fff9b3a44842c38686ca685f3f55dc48c5d3fb1107be4"
+        );
+    }
+
+    #[test]
+    fn test_asset_updates_check_the_signature() {
+        use ed25519_dalek::{Signer, SigningKey};
+
+        let release = SigningKey::from_bytes(&[7; 32]);
+        let pem = crate::remote_rules::public_key_pem(&release.verifying_key());
+        let manifest = generate_assets_manifest(&[("renderer.js", "updated();\n")]);
+        let script = r#"
+const releaseDir = path.join(appFolder, "release");
+let signatureFetchedAt = null;
+__ym.fetch = async (url) => {
+  if (url.includes("api.github.com")) {
+    const assets = fs.readdirSync(releaseDir).map((name) => ({ name, browser_download_url: "https://assets/" + name }));
+    return new Response(JSON.stringify({ tag_name: "v2", assets }));
+  }
+  if (url.endsWith(".sig")) signatureFetchedAt = Date.now();
+  return new Response(fs.readFileSync(path.join(releaseDir, path.basename(url))));
+};
+const metaPath = path.join(appFolder, "yandexMusicMod-assets", "meta.json");
+while (!fs.existsSync(metaPath) && !(signatureFetchedAt && Date.now() - signatureFetchedAt > 300)) {
+  await new Promise((resolve) => setTimeout(resolve, 20));
+  if (!signatureFetchedAt && !modReleasePublicKey) break;
+}
+const ev = {};
+__ym.ipc["yandexMusicMod.assets.getOverride"](ev, "renderer.js");
+return ev.returnValue;
+"#;
+        let signers = [
+            (Some(&pem), &release, json!("updated();\n")),
+            (Some(&pem), &SigningKey::from_bytes(&[8; 32]), Value::Null),
+            (None, &release, Value::Null),
+        ];
+        for (key, signer, expected) in signers {
+            let dir = tempfile::tempdir().unwrap();
+            let release_dir = dir.path().join("release");
+            std::fs::create_dir(&release_dir).unwrap();
+            std::fs::write(release_dir.join("renderer.js"), "updated();\n").unwrap();
+            std::fs::write(release_dir.join("assets.json"), &manifest).unwrap();
+            let signature = signer.sign(manifest.as_bytes()).to_bytes();
+            std::fs::write(release_dir.join("assets.json.sig"), signature).unwrap();
+
+            let bundle = main_bundle(
+                &BTreeMap::new(),
+                key.map(String::as_str),
+                &[ModFeature::AssetUpdates],
+            );
+            let Some(result) = run_main_js(dir.path(), &bundle, script) else {
+                return;
+            };
+            assert_eq!(result, expected);
+        }
+    }
+
+    #[test]
+    fn test_offline_cache_feature() {
+        let script = r#"
+const track = "0123456789".repeat(100);
+let online = true;
+const requests = [];
+__ym.fetch = async (input, init) => {
+  const request = new Request(input, init);
+  requests.push(request.headers.get("range"));
+  if (!online) throw new TypeError("net::ERR_INTERNET_DISCONNECTED");
+  const range = /^bytes=(\d+)-(\d+)$/.exec(request.headers.get("range") || "");
+  if (!range) return new Response(track, { status: 200, headers: { "content-type": "audio/mp4" } });
+  return new Response(track.slice(Number(range[1]), Number(range[2]) + 1), {
+    status: 206,
+    headers: { "content-range": `bytes ${range[1]}-${range[2]}/${track.length}` },
+  });
+};
+const get = async (url, range) => {
+  const response = await __ym.protocol(new Request(url, { headers: range ? { range } : {} }));
+  return { status: response.status, body: await response.text(), range: response.headers.get("content-range") };
+};
+const cached = async (count) => {
+  const dir = path.join(downloadFolder(), ".offline-cache");
+  for (let i = 0; i < 500; i++) {
+    const names = fs.existsSync(dir) ? fs.readdirSync(dir) : [];
+    if (names.filter((name) => name.endsWith(".json")).length >= count) return;
+    await new Promise((resolve) => setTimeout(resolve, 20));
+  }
+  throw new Error("the tracks were never cached");
+};
+
+await electron.app.whenReady();
+const played = [
+  await get("https://s1.strm.yandex.net/get-mp3/a/1?sign=x", "bytes=0-3"),
+  await get("https://s2.storage.yandex.net/get-mp3/b/2?sign=x"),
+];
+await cached(2);
+online = false;
+return {
+  played,
+  requests: [...requests],
+  offline: [
+    await get("https://s3.strm.yandex.net/get-mp3/a/1?sign=y", "bytes=10-14"),
+    await get("https://s3.strm.yandex.net/get-mp3/a/1?sign=y", "bytes=995-"),
+    (await get("https://s3.strm.yandex.net/get-mp3/b/2?sign=y")).body.length,
+    await get("https://s3.strm.yandex.net/get-mp3/a/1?sign=y", "bytes=2000-"),
+  ],
+};
+"#;
+        let dir = tempfile::tempdir().unwrap();
+        let defaults = BTreeMap::from([("offline/cacheEnabled".to_string(), json!(true))]);
+        let bundle = main_bundle(&defaults, None, &[ModFeature::OfflineCache]);
+        let Some(result) = run_main_js(dir.path(), &bundle, script) else {
+            return;
+        };
+        assert_eq!(
+            result["played"],
+            json!([
+                { "status": 206, "body": "0123", "range": "bytes 0-3/1000" },
+                { "status": 200, "body": "0123456789".repeat(100), "range": null },
+            ])
+        );
+        // The ranged play fetched the whole track once in the background
+        assert_eq!(result["requests"], json!(["bytes=0-3", null, null]));
+        assert_eq!(
+            result["offline"],
+            json!([
+                { "status": 206, "body": "01234", "range": "bytes 10-14/1000" },
+                { "status": 206, "body": "56789", "range": "bytes 995-999/1000" },
+                1000,
+                { "status": 416, "body": "", "range": "bytes */1000" },
+            ])
+        );
+    }
+
+    #[test]
+    fn test_smtc_feature() {
+        // Switches and a media key left by the app or the command line
+        let bundle = format!(
+            "__ym.switches[\"enable-features\"] = \"Foo\";\n\
+             __ym.switches[\"disable-features\"] = \"HardwareMediaKeyHandling,Bar\";\n\
+             __ymElectron.globalShortcut.register(\"MediaPlayPause\");\n{}",
+            main_bundle(&BTreeMap::new(), None, &[ModFeature::Smtc])
+        );
+        let script = r#"
+await electron.app.whenReady();
+const { globalShortcut } = electron;
+const registered = [globalShortcut.register("MediaNextTrack", () => {}), globalShortcut.register("F1", () => {})];
+globalShortcut.registerAll(["MediaStop", "F2"], () => {});
+return { switches: __ym.switches, registered, shortcuts: [...globalShortcut.registered] };
+"#;
+        let dir = tempfile::tempdir().unwrap();
+        let Some(result) = run_main_js(dir.path(), &bundle, script) else {
+            return;
+        };
+        assert_eq!(
+            result,
+            json!({
+                "switches": {
+                    "enable-features": "Foo,HardwareMediaKeyHandling,MediaSessionService",
+                    "disable-features": "Bar",
+                },
+                "registered": [false, true],
+                "shortcuts": ["F1", "F2"],
+            })
+        );
+
+        let script = r#"
+const session = navigator.mediaSession;
+session.metadata = new MediaMetadata({
+  title: "Song",
+  artist: "Artist",
+  album: "Album",
+  artwork: [{ src: "avatars.yandex.net/get-music-content/1/%%" }, { src: "//example.com/c.jpg", sizes: "50x50" }],
+});
+const media = new HTMLMediaElement();
+media.duration = 200;
+media.currentTime = 250;
+media.play();
+media.dispatch("playing");
+__ym.intervals.forEach((tick) => tick());
+const playing = { state: session.playbackState, position: session.positionState };
+session.actions.seekto({ seekTime: 42 });
+media.pause();
+media.dispatch("pause");
+return {
+  title: session.metadata.title,
+  artwork: session.metadata.artwork.map((image) => [image.src, image.sizes]),
+  playing,
+  seeked: media.currentTime,
+  paused: session.playbackState,
+};
+"#;
+        let result = run_renderer_js(dir.path(), SMTC_RENDERER_JS, script).unwrap();
+        let cover = "https://avatars.yandex.net/get-music-content/1";
+        assert_eq!(
+            result,
+            json!({
+                "title": "Song",
+                "artwork": [
+                    [format!("{}/200x200", cover), "200x200"],
+                    [format!("{}/400x400", cover), "400x400"],
+                    [format!("{}/1000x1000", cover), "1000x1000"],
+                    ["https://example.com/c.jpg", "50x50"],
+                ],
+                "playing": {
+                    "state": "playing",
+                    "position": { "duration": 200, "playbackRate": 1, "position": 200 },
+                },
+                "seeked": 42,
+                "paused": "paused",
+            })
+        );
+    }
+
+    #[test]
+    fn test_external_mods_feature() {
+        let script = r#"
+const get = (kind) => {
+  const ev = {};
+  __ym.ipc["yandexMusicMod.mods.get"](ev, kind);
+  return ev.returnValue;
+};
+const list = await __ym.ipc["yandexMusicMod.mods.list"]();
+const result = { list: { ...list, dir: path.relative(appFolder, list.dir) }, api: global.modApi || null };
+result.css = get("css");
+result.unknown = get("main");
+__ym.ipc["yandexMusicMod.setStorageValue"]({}, "mods/enabled", false);
+result.disabled = get("renderer");
+return result;
+"#;
+        let run = |defaults: BTreeMap<String, Value>| {
+            let dir = tempfile::tempdir().unwrap();
+            let mods = dir.path().join(EXTERNAL_MODS_DIR);
+            std::fs::create_dir(&mods).unwrap();
+            let files = [
+                (
+                    "a.main.js",
+                    "global.modApi = Object.keys(global.yandexMusicMod).sort();",
+                ),
+                ("b.main.js", "throw new Error(\"broken mod\");"),
+                ("c.preload.js", ""),
+                ("d.renderer.js", ""),
+                ("e.css", "body { color: red; }"),
+            ];
+            for (name, code) in files {
+                std::fs::write(mods.join(name), code).unwrap();
+            }
+            let bundle = main_bundle(&defaults, None, &[ModFeature::ExternalMods]);
+            run_main_js(dir.path(), &bundle, script)
+        };
+
+        let Some(result) = run(BTreeMap::new()) else {
+            return;
+        };
+        assert_eq!(
+            result,
+            json!({
+                // The broken mod is skipped
+                "list": {
+                    "dir": "mods",
+                    "main": ["a.main.js"],
+                    "preload": ["c.preload.js"],
+                    "renderer": ["d.renderer.js"],
+                    "css": ["e.css"],
+                },
+                "api": ["appFolder", "modsDir", "onSettingChanged", "readModSettings"],
+                "css": [{ "name": "e.css", "code": "body { color: red; }" }],
+                "unknown": [],
+                "disabled": [],
+            })
+        );
+
+        let disabled = BTreeMap::from([("mods/enabled".to_string(), json!(false))]);
+        let result = run(disabled).unwrap();
+        assert_eq!(result["list"]["main"], json!([]));
+        assert_eq!(result["api"], Value::Null);
+        assert_eq!(result["css"], json!([]));
+    }
+
+    #[test]
+    fn test_anti_dpi_feature() {
+        let script = r#"
+await electron.app.whenReady();
+const set = __ym.ipc["yandexMusicMod.setStorageValue"];
+set({}, "network/dohServers", "https://dns.example/dns-query, http://plain.example");
+set({}, "network/dohMode", "off");
+return { switches: __ym.switches, resolver: __ym.resolver };
+"#;
+        let default_doh = json!([
+            "https://cloudflare-dns.com/dns-query",
+            "https://dns.google/dns-query"
+        ]);
+        let resolver = |mode: &str, servers: Value| json!({ "enableBuiltInResolver": true, "secureDnsMode": mode, "secureDnsServers": servers });
+
+        // Automatic DoH falls back to the system DNS where the servers are blocked
+        let dir = tempfile::tempdir().unwrap();
+        let bundle = main_bundle(&BTreeMap::new(), None, &[ModFeature::AntiDpi]);
+        let Some(result) = run_main_js(dir.path(), &bundle, script) else {
+            return;
+        };
+        assert_eq!(result["switches"], json!({ "disable-quic": "" }));
+        assert_eq!(
+            result["resolver"],
+            json!([
+                resolver("automatic", default_doh.clone()),
+                resolver("automatic", json!(["https://dns.example/dns-query"])),
+                resolver("off", json!([])),
+            ])
+        );
+
+        let defaults = BTreeMap::from([
+            ("network/dohMode".to_string(), json!("secure")),
+            ("network/frontHost".to_string(), json!("203.0.113.10")),
+            (
+                "network/hostRules".to_string(),
+                json!("MAP a.example 127.0.0.1\n"),
+            ),
+            ("network/quic".to_string(), json!(true)),
+        ]);
+        let dir = tempfile::tempdir().unwrap();
+        let bundle = main_bundle(&defaults, None, &[ModFeature::AntiDpi]);
+        let result = run_main_js(dir.path(), &bundle, script).unwrap();
+        let rules = [
+            "*.strm.yandex.net",
+            "*.strm.yandex.ru",
+            "strm.yandex.net",
+            "strm.yandex.ru",
+            "*.music.yandex.net",
+        ]
+        .map(|host| format!("MAP {} 203.0.113.10", host))
+        .join(",");
+        assert_eq!(
+            result["switches"],
+            json!({ "host-resolver-rules": format!("{},MAP a.example 127.0.0.1", rules) })
+        );
+        assert_eq!(result["resolver"][0], resolver("secure", default_doh));
+    }
+
+    #[test]
+    fn test_sleep_timer_feature() {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as u64;
+        let script = r#"
+await electron.app.whenReady();
+const remaining = () => __ym.ipc["yandexMusicMod.sleepTimer.remaining"]();
+const state = () => {
+  const settings = readModSettings();
+  return { mode: settings["sleepTimer/mode"], endsAt: settings["sleepTimer/endsAt"], sent: [...__ym.sent], quit: __ym.quit };
+};
+const before = { remaining: remaining(), ...state() };
+for (let i = 0; i < 250 && readModSettings()["sleepTimer/endsAt"]; i++) {
+  await new Promise((resolve) => setTimeout(resolve, 20));
+}
+const after = state();
+__ym.ipc["yandexMusicMod.setStorageValue"]({}, "sleepTimer/mode", "end-of-track");
+const endOfTrack = remaining();
+__ym.ipc["yandexMusicMod.sleepTimer.trackEnded"]();
+return { before, after, endOfTrack, ended: state() };
+"#;
+        let run = |defaults: Value| {
+            let dir = tempfile::tempdir().unwrap();
+            let defaults = serde_json::from_value(defaults).unwrap();
+            run_main_js(
+                dir.path(),
+                &main_bundle(&defaults, None, &[ModFeature::SleepTimer]),
+                script,
+            )
+        };
+
+        // A running timer is resumed on start and pauses the player
+        let Some(result) = run(json!({ "sleepTimer/mode": "15", "sleepTimer/endsAt": now + 2000 }))
+        else {
+            return;
+        };
+        let left = result["before"]["remaining"].as_u64().unwrap();
+        assert!(left > 0 && left <= 2000, "{} ms left", left);
+        assert_eq!(result["before"]["mode"], "15");
+        let sent = result["after"]["sent"].as_array().unwrap();
+        assert!(sent.contains(&json!("yandexMusicMod.sleepTimer.pause")));
+        assert_eq!(result["after"]["mode"], "off");
+        assert_eq!(result["after"]["endsAt"], Value::Null);
+        assert_eq!(result["after"]["quit"], false);
+        assert_eq!(result["endOfTrack"], -1);
+
+        // The end of the track quits with the exit action
+        let result = run(json!({ "sleepTimer/action": "exit" })).unwrap();
+        assert_eq!(result["before"]["remaining"], Value::Null);
+        assert_eq!(result["ended"]["quit"], true);
+        assert_eq!(result["ended"]["mode"], "off");
+
+        // A timer that ran out while the app was closed is reset
+        let result =
+            run(json!({ "sleepTimer/mode": "30", "sleepTimer/endsAt": now - 1000 })).unwrap();
+        assert_eq!(result["before"]["mode"], "off");
+        assert_eq!(result["before"]["remaining"], Value::Null);
+
+        // The preload bridge
+        let bridge = r#"
+let remaining = 61000;
+let ended = 0;
+let pause = null;
+window.yandexMusicModSleepTimer = {
+  remaining: () => Promise.resolve(remaining),
+  trackEnded: () => ended++,
+  onPause: (callback) => (pause = callback),
+};
+"#;
+        let script = r#"
+const media = new HTMLMediaElement();
+media.play();
+media.play();
+media.dispatch("ended");
+pause();
+const info = __ym.panel.find((entry) => entry.type === "info");
+const left = [await info.value()];
+remaining = -1;
+left.push(await info.value());
+remaining = null;
+left.push(await info.value());
+return { ended, paused: media.paused, left, entries: __ym.panel.map((entry) => entry.key || entry.label) };
+"#;
+        let dir = tempfile::tempdir().unwrap();
+        let code = format!("{}{}", bridge, SLEEP_TIMER_RENDERER_JS);
+        let result = run_renderer_js(dir.path(), &code, script).unwrap();
+        assert_eq!(
+            result,
+            json!({
+                "ended": 1,
+                "paused": true,
+                "left": ["1:01", "sleepTimer.left.endOfTrack", "sleepTimer.left.off"],
+                "entries": ["sleepTimer/mode", "sleepTimer/action", "sleepTimer.left"],
+            })
+        );
+    }
+
+    #[test]
+    fn test_hardening_feature() {
+        let create_window = "new electron_1.BrowserWindow({\n    webPreferences: {\n        devTools: true,\n        contextIsolation: false,\n    },\n});";
+        let hardened = harden_create_window_js(create_window, true);
+        assert!(hardened.contains(
+            "webPreferences: { contextIsolation: true, nodeIntegration: false, sandbox: true,"
+        ));
+        assert!(!hardened.contains("contextIsolation: false"));
+        assert!(
+            !harden_create_window_js("webPreferences:{sandbox:!1}", false)
+                .contains("sandbox: true")
+        );
+        assert!(
+            harden_create_window_js("webPreferences:{nodeIntegration:!0}", false)
+                .contains("nodeIntegration: false")
+        );
+
+        assert!(!preload_needs_node(MOD_PRELOAD_JS));
+        assert!(!preload_needs_node("const e = require('electron');"));
+        assert!(preload_needs_node("const fs = require(\"fs\");"));
+    }
+
+    #[test]
+    fn test_hardening_ipc() {
+        let script = r#"
+const set = __ym.ipc["yandexMusicMod.setStorageValue"];
+const open = (folder) => __ym.ipc["yandexMusicMod.openFolder"]({}, folder);
+const picked = path.join(appFolder, "Music");
+set({}, "downloadFolderPath", picked);
+set({}, "../escape", 1);
+set({}, "__proto__", { polluted: true });
+set({}, "autoRepatch/patcher", "/tmp/patcher");
+const refused = readModSettings().downloadFolderPath;
+__ym.dialog = { canceled: false, filePaths: [picked] };
+await __ym.ipc["yandexMusicMod.selectDownloadFolder"]();
+set({}, "downloadFolderPath", picked);
+const opens = [await open(path.join(picked, "Album")), await open(appFolder), await open(path.join(picked, ".."))];
+
+let attach = null;
+__ym.appEvents["web-contents-created"].forEach((handler) => handler({}, { on: (_event, listener) => (attach = listener) }));
+const webPreferences = { preload: "/tmp/evil.js", nodeIntegration: true, contextIsolation: false };
+attach({}, webPreferences);
+const relative = (folder) => path.relative(appFolder, folder);
+return {
+  refused: relative(refused),
+  stored: relative(readModSettings().downloadFolderPath),
+  keys: Object.keys(readModSettings()).sort(),
+  opens: opens.map((result) => result.success),
+  opened: __ym.opened.map(relative),
+  webPreferences,
+};
+"#;
+        let dir = tempfile::tempdir().unwrap();
+        let bundle = main_bundle(&BTreeMap::new(), None, &[ModFeature::Hardening]);
+        let Some(result) = run_main_js(dir.path(), &bundle, script) else {
+            return;
+        };
+        assert_eq!(
+            result,
+            json!({
+                // Only a folder picked in the dialog becomes the download folder
+                "refused": "Downloads",
+                "stored": "Music",
+                "keys": ["downloadFolderPath"],
+                "opens": [true, false, false],
+                "opened": [std::path::Path::new("Music").join("Album")],
+                "webPreferences": { "nodeIntegration": false, "contextIsolation": true },
+            })
+        );
+    }
+
+    #[test]
+    fn test_auto_repatch_settings() {
+        let settings = auto_repatch_settings(
+            Some(std::path::Path::new("/opt/yandex-music-mod")),
+            std::path::Path::new("/data/versions"),
+            &[ModFeature::AutoRepatch, ModFeature::MiniPlayer],
+        );
+        assert_eq!(settings["autoRepatch/patcher"], "/opt/yandex-music-mod");
+        assert_eq!(
+            settings["autoRepatch/args"],
+            json!([
+                "--json",
+                "--yes",
+                "patch",
+                "--output",
+                "/data/versions",
+                "--enable",
+                "auto-repatch",
+                "--enable",
+                "mini-player"
+            ])
+        );
+        assert!(ModFeature::AutoRepatch
+            .main_js()
+            .contains("electron.app.relaunch"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_auto_repatch_ignores_renderer_settings() {
+        use std::os::unix::fs::PermissionsExt;
+
+        for features in [
+            vec![ModFeature::AutoRepatch],
+            vec![ModFeature::Hardening, ModFeature::AutoRepatch],
+        ] {
+            let dir = tempfile::tempdir().unwrap();
+            let patcher = dir.path().join("patcher.sh");
+            let output = dir.path().join("versions");
+            std::fs::write(
+                &patcher,
+                format!(
+                    "#!/bin/sh\necho \"$@\" > {0}/args\necho '{{\"version\":\"9.9.9\",\"output\":\"{0}/versions/9.9.9\"}}'\n",
+                    dir.path().display()
+                ),
+            )
+            .unwrap();
+            std::fs::set_permissions(&patcher, std::fs::Permissions::from_mode(0o755)).unwrap();
+            let defaults =
+                auto_repatch_settings(Some(&patcher), &output, &[ModFeature::AutoRepatch]);
+
+            let script = r#"
+__ym.fetch = async () => new Response("version: 9.9.9\n");
+const set = __ym.ipc["yandexMusicMod.setStorageValue"];
+set({}, "autoRepatch/patcher", "/bin/false");
+set({}, "autoRepatch/args", ["--evil"]);
+set({}, "autoRepatch/pending", { version: "9.9.9", modDir: "/evil" });
+set({}, "autoRepatch/enabled", true);
+const pendingFile = path.join(appFolder, "yandexMusicMod-autoRepatch.json");
+for (let i = 0; !fs.existsSync(pendingFile); i++) {
+  if (i > 500) throw new Error("the patcher never ran");
+  await new Promise((resolve) => setTimeout(resolve, 20));
+}
+return {
+  settings: readModSettings(),
+  pending: JSON.parse(fs.readFileSync(pendingFile, "utf8")).pending,
+  args: fs.readFileSync(path.join(appFolder, "args"), "utf8"),
+};
+"#;
+            let Some(result) =
+                run_main_js(dir.path(), &main_bundle(&defaults, None, &features), script)
+            else {
+                return;
+            };
+            let settings = result["settings"].as_object().unwrap();
+            assert_eq!(settings["autoRepatch/enabled"], true);
+            for key in [
+                "autoRepatch/patcher",
+                "autoRepatch/args",
+                "autoRepatch/pending",
+            ] {
+                assert!(!settings.contains_key(key), "{} reached the settings", key);
+            }
+            assert_eq!(
+                result["args"],
+                format!(
+                    "--json --yes patch --output {} --enable auto-repatch\n",
+                    output.display()
+                )
+            );
+            assert_eq!(
+                result["pending"],
+                json!({
+                    "version": "9.9.9",
+                    "modDir": format!("{}/versions/9.9.9/mod", dir.path().display()),
+                })
+            );
+        }
+    }
+
+    /// `node --check` on a script; `None` when node is not installed
+    fn node_check(name: &str, js: &str) -> Option<Result<(), String>> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(format!("{}.js", name));
+        std::fs::write(&path, js).unwrap();
+        let output = std::process::Command::new("node")
+            .arg("--check")
+            .arg(&path)
+            .output()
+            .ok()?;
+        Some(if output.status.success() {
+            Ok(())
+        } else {
+            Err(String::from_utf8_lossy(&output.stderr).into_owned())
+        })
+    }
+
+    /// Stub `electron` module for running main-process bundles in Node. It
+    /// records IPC handlers, app events, switches and other calls in `__ym`;
+    /// a test sets `__ym.fetch` to answer `net.fetch` and `__ym.dialog` for
+    /// the folder dialog
+    const FAKE_ELECTRON_JS: &str = r#"
+const __ym = {
+  ipc: {},
+  appEvents: {},
+  switches: {},
+  resolver: [],
+  sent: [],
+  opened: [],
+  dialog: null,
+  protocol: null,
+  relaunch: null,
+  quit: false,
+};
+const __ymElectron = {
+  app: {
+    getPath: () => process.env.YM_USER_DATA,
+    getAppPath: () => process.env.YM_USER_DATA,
+    getVersion: () => "1.0.0",
+    whenReady: () => Promise.resolve(),
+    on: (event, handler) => (__ym.appEvents[event] = __ym.appEvents[event] || []).push(handler),
+    once() {},
+    relaunch: (options) => (__ym.relaunch = options),
+    exit() {},
+    quit: () => (__ym.quit = true),
+    configureHostResolver: (options) => __ym.resolver.push(options),
+    commandLine: {
+      appendSwitch: (name, value) => (__ym.switches[name] = value === undefined ? "" : value),
+      getSwitchValue: (name) => __ym.switches[name] || "",
+      hasSwitch: (name) => name in __ym.switches,
+      removeSwitch: (name) => delete __ym.switches[name],
+    },
+  },
+  ipcMain: {
+    on: (channel, handler) => (__ym.ipc[channel] = handler),
+    handle: (channel, handler) => (__ym.ipc[channel] = handler),
+    removeHandler: (channel) => delete __ym.ipc[channel],
+    removeAllListeners: (channel) => delete __ym.ipc[channel],
+  },
+  BrowserWindow: {
+    getAllWindows: () => [{ setAlwaysOnTop() {}, webContents: { send: (channel) => __ym.sent.push(channel) } }],
+  },
+  Notification: Object.assign(function () {}, { isSupported: () => false }),
+  net: { fetch: (...args) => __ym.fetch(...args) },
+  dialog: { showOpenDialog: async () => __ym.dialog },
+  shell: {
+    openPath: async (folder) => {
+      __ym.opened.push(folder);
+      return "";
+    },
+  },
+  globalShortcut: {
+    registered: new Set(),
+    register(accelerator) {
+      this.registered.add(accelerator);
+      return true;
+    },
+    registerAll(accelerators) {
+      accelerators.forEach((accelerator) => this.registered.add(accelerator));
+    },
+    isRegistered(accelerator) {
+      return this.registered.has(accelerator);
+    },
+    unregister(accelerator) {
+      this.registered.delete(accelerator);
+    },
+  },
+  protocol: {
+    handle: (_scheme, handler) => (__ym.protocol = handler),
+    unhandle: () => (__ym.protocol = null),
+    isProtocolHandled: () => __ym.protocol !== null,
+  },
+};
+const __ymModule = require("module");
+const __ymLoad = __ymModule._load;
+__ymModule._load = function (request, ...rest) {
+  return request === "electron" ? __ymElectron : __ymLoad.call(this, request, ...rest);
+};
+"#;
+
+    /// Stub browser globals for running renderer code in Node: a media
+    /// session, media elements and the mod panel. Document listeners, panel
+    /// entries and `setInterval` callbacks are kept in `__ym`
+    const FAKE_RENDERER_JS: &str = r#"
+const __ym = { listeners: {}, panel: [], intervals: [] };
+globalThis.window = globalThis;
+globalThis.setInterval = (callback) => __ym.intervals.push(callback);
+class MediaMetadata {
+  constructor(init) {
+    Object.assign(this, init);
+  }
+}
+class MediaSession {
+  constructor() {
+    this.actions = {};
+    this.playbackState = "none";
+    this.positionState = null;
+    this.current = null;
+  }
+  get metadata() {
+    return this.current;
+  }
+  set metadata(value) {
+    this.current = value;
+  }
+  setActionHandler(action, handler) {
+    this.actions[action] = handler;
+  }
+  setPositionState(state) {
+    this.positionState = state;
+  }
+}
+class HTMLMediaElement {
+  constructor() {
+    Object.assign(this, { paused: true, currentTime: 0, duration: NaN, playbackRate: 1, listeners: {} });
+  }
+  play() {
+    this.paused = false;
+    return Promise.resolve();
+  }
+  pause() {
+    this.paused = true;
+  }
+  addEventListener(type, listener) {
+    (this.listeners[type] = this.listeners[type] || []).push(listener);
+  }
+  // Events reach the element and then the document's capturing listeners
+  dispatch(type) {
+    const event = { type, target: this };
+    (this.listeners[type] || []).forEach((listener) => listener(event));
+    (__ym.listeners[type] || []).forEach((listener) => listener(event));
+  }
+}
+Object.defineProperty(globalThis, "navigator", { value: { mediaSession: new MediaSession() }, configurable: true });
+globalThis.document = {
+  addEventListener: (type, listener) => (__ym.listeners[type] = __ym.listeners[type] || []).push(listener),
+};
+window.yandexMusicModPanel = { register: (entry) => __ym.panel.push(entry), t: (key) => key };
+"#;
+
+    /// Runs `bundle` in Node under [`FAKE_ELECTRON_JS`] with userData in
+    /// `user_data`, then the async function body `script`, and returns what
+    /// it returned. `None` when node is not installed
+    fn run_main_js(user_data: &std::path::Path, bundle: &str, script: &str) -> Option<Value> {
+        run_js(
+            user_data,
+            &format!("{}{}", FAKE_ELECTRON_JS, bundle),
+            script,
+        )
+    }
+
+    /// [`run_main_js`] for renderer code, under [`FAKE_RENDERER_JS`]
+    fn run_renderer_js(dir: &std::path::Path, code: &str, script: &str) -> Option<Value> {
+        run_js(dir, &format!("{}{}", FAKE_RENDERER_JS, code), script)
+    }
+
+    fn run_js(user_data: &std::path::Path, code: &str, script: &str) -> Option<Value> {
+        const MARKER: &str = "__YM_RESULT__";
+        let js = format!(
+            "{}\n(async () => {{\n{}\n}})().then(\n  (result) => {{\n    \
+             process.stdout.write(\"\\n{}\" + JSON.stringify(result === undefined ? null : result));\n    \
+             process.exit(0);\n  }},\n  (e) => {{\n    console.error(e);\n    process.exit(1);\n  }},\n);\n",
+            code, script, MARKER
+        );
+        let path = user_data.join("test.js");
+        std::fs::write(&path, js).unwrap();
+        let output = match std::process::Command::new("node")
+            .arg(&path)
+            .env("YM_USER_DATA", user_data)
+            .output()
+        {
+            Ok(output) => output,
+            Err(_) => {
+                eprintln!("node is not installed, skipping the JavaScript behavior test");
+                return None;
+            }
+        };
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(
+            output.status.success(),
+            "node failed:\n{}\n{}",
+            stdout,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        let result = stdout.rsplit(MARKER).next().unwrap();
+        Some(serde_json::from_str(result).unwrap())
+    }
+
+    /// The main-process bundle as the patcher appends it to index.js, with
+    /// `defaults`, `release_key` and the main-process code of `features`
+    fn main_bundle(
+        defaults: &BTreeMap<String, Value>,
+        release_key: Option<&str>,
+        features: &[ModFeature],
+    ) -> String {
+        let mut js = generate_default_settings_js(defaults, None);
+        js.push_str(&generate_release_key_js(release_key));
+        js.push_str(MOD_MAIN_JS);
+        for feature in features {
+            js.push_str(feature.main_js());
+        }
+        js
+    }
+
+    #[test]
+    fn test_js_syntax() {
+        let all_features = [
+            ModFeature::Autostart,
+            ModFeature::MiniPlayer,
+            ModFeature::AssetUpdates,
+            ModFeature::BlockedLog,
+            ModFeature::OfflineCache,
+            ModFeature::AutoRepatch,
+            ModFeature::Smtc,
+            ModFeature::ExternalMods,
+            ModFeature::AntiDpi,
+            ModFeature::SleepTimer,
+            ModFeature::Hardening,
+        ];
+        // The bundles as the patcher appends them to index.js and preload.js
+        let mut main_js = generate_default_settings_js(&BTreeMap::new(), None);
+        main_js.push_str(&generate_html_root_js("app"));
+        main_js.push_str(&generate_release_key_js(None));
+        main_js.push_str(MOD_MAIN_JS);
+        main_js.push_str(DEVTOOLS_SHORTCUT_JS);
+        main_js.push_str(PROXY_MAIN_JS);
+        let mut preload_js = MOD_PRELOAD_JS.to_string();
+        for feature in all_features {
+            main_js.push_str(feature.main_js());
+            preload_js.push_str(feature.preload_js());
+        }
+
+        let scripts = [
+            ("MOD_MAIN_JS", MOD_MAIN_JS.to_string()),
+            ("DEVTOOLS_SHORTCUT_JS", DEVTOOLS_SHORTCUT_JS.to_string()),
+            ("PROXY_MAIN_JS", PROXY_MAIN_JS.to_string()),
+            ("AUTOSTART_MAIN_JS", AUTOSTART_MAIN_JS.to_string()),
+            ("AUTOSTART_RENDERER_JS", AUTOSTART_RENDERER_JS.to_string()),
+            ("MINI_PLAYER_MAIN_JS", MINI_PLAYER_MAIN_JS.to_string()),
+            ("MINI_PLAYER_PRELOAD_JS", MINI_PLAYER_PRELOAD_JS.to_string()),
+            (
+                "MINI_PLAYER_RENDERER_JS",
+                MINI_PLAYER_RENDERER_JS.to_string(),
+            ),
+            (
+                "MINI_PLAYER_WINDOW_PRELOAD_JS",
+                MINI_PLAYER_WINDOW_PRELOAD_JS.to_string(),
+            ),
+            ("ASSET_UPDATES_MAIN_JS", ASSET_UPDATES_MAIN_JS.to_string()),
+            (
+                "ASSET_UPDATES_PRELOAD_JS",
+                ASSET_UPDATES_PRELOAD_JS.to_string(),
+            ),
+            (
+                "ASSET_UPDATES_RENDERER_PRELUDE_JS",
+                ASSET_UPDATES_RENDERER_PRELUDE_JS.to_string(),
+            ),
+            ("BLOCKED_LOG_MAIN_JS", BLOCKED_LOG_MAIN_JS.to_string()),
+            ("BLOCKED_LOG_PRELOAD_JS", BLOCKED_LOG_PRELOAD_JS.to_string()),
+            (
+                "BLOCKED_LOG_RENDERER_JS",
+                BLOCKED_LOG_RENDERER_JS.to_string(),
+            ),
+            ("OFFLINE_CACHE_MAIN_JS", OFFLINE_CACHE_MAIN_JS.to_string()),
+            (
+                "OFFLINE_CACHE_RENDERER_JS",
+                OFFLINE_CACHE_RENDERER_JS.to_string(),
+            ),
+            ("AUTO_REPATCH_MAIN_JS", AUTO_REPATCH_MAIN_JS.to_string()),
+            (
+                "AUTO_REPATCH_RENDERER_JS",
+                AUTO_REPATCH_RENDERER_JS.to_string(),
+            ),
+            ("SMTC_MAIN_JS", SMTC_MAIN_JS.to_string()),
+            ("SMTC_RENDERER_JS", SMTC_RENDERER_JS.to_string()),
+            ("EXTERNAL_MODS_MAIN_JS", EXTERNAL_MODS_MAIN_JS.to_string()),
+            (
+                "EXTERNAL_MODS_PRELOAD_JS",
+                EXTERNAL_MODS_PRELOAD_JS.to_string(),
+            ),
+            (
+                "EXTERNAL_MODS_RENDERER_JS",
+                EXTERNAL_MODS_RENDERER_JS.to_string(),
+            ),
+            ("ANTI_DPI_MAIN_JS", ANTI_DPI_MAIN_JS.to_string()),
+            ("ANTI_DPI_RENDERER_JS", ANTI_DPI_RENDERER_JS.to_string()),
+            ("SLEEP_TIMER_MAIN_JS", SLEEP_TIMER_MAIN_JS.to_string()),
+            ("SLEEP_TIMER_PRELOAD_JS", SLEEP_TIMER_PRELOAD_JS.to_string()),
+            (
+                "SLEEP_TIMER_RENDERER_JS",
+                SLEEP_TIMER_RENDERER_JS.to_string(),
+            ),
+            ("HARDENING_MAIN_JS", HARDENING_MAIN_JS.to_string()),
+            ("MOD_PRELOAD_JS", MOD_PRELOAD_JS.to_string()),
+            ("MOD_RENDERER_JS", MOD_RENDERER_JS.to_string()),
+            ("settings_reader", generate_settings_reader_js()),
+            ("analytics_blocker", generate_analytics_blocker_js(&[])),
+            ("main_bundle", main_js),
+            ("preload_bundle", preload_js),
+            ("renderer_bundle", generate_renderer_js(&all_features)),
+        ];
+        for (name, js) in scripts {
+            match node_check(name, &js) {
+                None => {
+                    eprintln!("node is not installed, skipping the JavaScript syntax check");
+                    return;
+                }
+                Some(result) => {
+                    if let Err(e) = result {
+                        panic!("{} is not valid JavaScript:\n{}", name, e);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_generate_default_settings_js() {
+        let mut defaults = BTreeMap::new();
+        defaults.insert("mod/theme".to_string(), json!("light"));
+        assert_eq!(
+            generate_default_settings_js(&defaults, Some("work")),
+            "const modDefaultSettings = {\"mod/theme\":\"light\"};\nconst modDefaultProfile = \"work\";\n"
+        );
+        assert!(generate_default_settings_js(&defaults, None).ends_with("= \"default\";\n"));
+        assert!(MOD_MAIN_JS.contains("yandexMusicMod.profiles.switch"));
+        assert!(MOD_PRELOAD_JS.contains("switchProfile"));
+        assert!(is_valid_settings_profile("home_2-b"));
+        assert!(!is_valid_settings_profile("../work"));
+        assert!(!is_valid_settings_profile(""));
+        assert!(MOD_MAIN_JS.contains("modDefaultSettings[key]"));
+    }
+
+    #[test]
+    fn test_devtools_shortcut_js() {
+        assert!(DEVTOOLS_SHORTCUT_JS.contains("\"F12\""));
+        assert!(DEVTOOLS_SHORTCUT_JS.contains("before-input-event"));
+        assert!(DEVTOOLS_SHORTCUT_JS.contains("openDevTools()"));
+        assert!(DEVTOOLS_SHORTCUT_JS.contains("input.code === \"KeyI\""));
     }
 
     #[test]
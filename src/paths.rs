@@ -0,0 +1,357 @@
+//! Paths module - expands and validates user-supplied paths
+//!
+//! `--output` and `output` in `config.toml` may use `~`, environment variables
+//! (`$VAR`, `${VAR}`, `%VAR%`) and either separator style.
+//!
+//! Deletes and renames go through [`remove_dir_all`], [`remove_file`] and
+//! [`rename`], which retry on Windows while an antivirus scanner (usually
+//! Defender, right after the installer is extracted) holds a file open.
+
+use crate::i18n::tr;
+use anyhow::{Context, Result};
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::debug;
+
+/// Attempts of a delete or rename whose file is locked; only Windows locks
+/// files that are open elsewhere
+const LOCKED_ATTEMPTS: u32 = if cfg!(windows) { 8 } else { 1 };
+
+/// Delay before the first retry, growing with each attempt (7 s in total)
+const LOCKED_RETRY_DELAY: Duration = Duration::from_millis(250);
+
+/// Expand `~` and environment variables and normalize separators.
+///
+/// Unknown variables are left as written. Paths that are not valid Unicode are
+/// taken as they are; the home directory is joined as a path, never as text.
+pub fn expand(raw: impl AsRef<OsStr>) -> PathBuf {
+    let raw = raw.as_ref();
+    let Some(raw) = raw.to_str() else {
+        return PathBuf::from(raw);
+    };
+    let expanded = expand_vars(raw.trim(), |name| std::env::var(name).ok());
+
+    if let Some(rest) = expanded.strip_prefix('~') {
+        if rest.is_empty() || rest.starts_with(['/', '\\']) {
+            if let Some(dirs) = directories::BaseDirs::new() {
+                let rest = rest.trim_start_matches(['/', '\\']);
+                let home = dirs.home_dir();
+                return if rest.is_empty() {
+                    home.to_path_buf()
+                } else {
+                    home.join(normalize_separators(rest))
+                };
+            }
+        }
+    }
+
+    PathBuf::from(normalize_separators(&expanded))
+}
+
+/// Open `path` in the platform file manager
+pub fn open_folder(path: &Path) -> Result<()> {
+    let opener = if cfg!(target_os = "windows") {
+        "explorer"
+    } else if cfg!(target_os = "macos") {
+        "open"
+    } else {
+        "xdg-open"
+    };
+    std::process::Command::new(opener)
+        .arg(path)
+        .spawn()
+        .with_context(|| format!("Failed to open {:?}", path))?;
+    Ok(())
+}
+
+/// Create `dir` if needed and check that files can be written into it
+pub fn ensure_writable(dir: &Path) -> Result<()> {
+    fs::create_dir_all(dir).with_context(|| format!("Failed to create {:?}", dir))?;
+
+    let probe = dir.join(".write-test");
+    fs::write(&probe, b"ok")
+        .and_then(|_| fs::remove_file(&probe))
+        .with_context(|| format!("{:?} is not writable", dir))
+}
+
+/// Free space on the volume of `path`, which may not exist yet
+pub fn available_space(path: &Path) -> Result<u64> {
+    let existing = path
+        .ancestors()
+        .find(|p| p.exists())
+        .unwrap_or(Path::new("."));
+    fs2::available_space(existing)
+        .with_context(|| format!("Failed to determine free space of {:?}", existing))
+}
+
+/// Copy the file `src` to `dst` with its permission bits. On Unix a symlink is
+/// recreated as a symlink instead of copying what it points to.
+pub fn copy_file(src: &Path, dst: &Path) -> Result<()> {
+    #[cfg(unix)]
+    if fs::symlink_metadata(src)
+        .with_context(|| format!("Failed to read {:?}", src))?
+        .file_type()
+        .is_symlink()
+    {
+        let target = fs::read_link(src)?;
+        if fs::symlink_metadata(dst).is_ok() {
+            fs::remove_file(dst)?;
+        }
+        std::os::unix::fs::symlink(&target, dst)
+            .with_context(|| format!("Failed to link {:?} to {:?}", dst, target))?;
+        return Ok(());
+    }
+
+    // `fs::copy` carries the permission bits over
+    fs::copy(src, dst).with_context(|| format!("Failed to copy {:?}", src))?;
+    Ok(())
+}
+
+/// `fs::remove_dir_all`, retried while a file inside is locked
+pub fn remove_dir_all(path: &Path) -> Result<()> {
+    retry_locked(path, LOCKED_ATTEMPTS, LOCKED_RETRY_DELAY, || {
+        fs::remove_dir_all(path)
+    })
+    .with_context(|| format!("Failed to remove {:?}", path))
+}
+
+/// `fs::remove_file`, retried while the file is locked
+pub fn remove_file(path: &Path) -> Result<()> {
+    retry_locked(path, LOCKED_ATTEMPTS, LOCKED_RETRY_DELAY, || {
+        fs::remove_file(path)
+    })
+    .with_context(|| format!("Failed to remove {:?}", path))
+}
+
+/// `fs::rename`, retried while `from` or a file inside it is locked
+pub fn rename(from: &Path, to: &Path) -> Result<()> {
+    retry_locked(from, LOCKED_ATTEMPTS, LOCKED_RETRY_DELAY, || {
+        fs::rename(from, to)
+    })
+    .with_context(|| format!("Failed to move {:?} to {:?}", from, to))
+}
+
+/// Run `op` up to `attempts` times while it fails because a file is locked.
+/// When the lock outlasts the retries, the error suggests an antivirus exclusion.
+fn retry_locked<T>(
+    path: &Path,
+    attempts: u32,
+    delay: Duration,
+    mut op: impl FnMut() -> io::Result<T>,
+) -> Result<T> {
+    let mut attempt = 1;
+    loop {
+        match op() {
+            Ok(value) => return Ok(value),
+            Err(e) if is_locked(&e) && attempt < attempts => {
+                debug!(
+                    "{:?} is locked ({}), retry {}/{}",
+                    path,
+                    e,
+                    attempt,
+                    attempts - 1
+                );
+                std::thread::sleep(delay * attempt);
+                attempt += 1;
+            }
+            Err(e) if is_locked(&e) && attempts > 1 => {
+                let dir = if path.is_dir() {
+                    path
+                } else {
+                    path.parent().unwrap_or(path)
+                };
+                return Err(anyhow::Error::new(e).context(tr!(
+                    "{:?} stayed locked after {} attempts, probably by an antivirus scan. \
+                     Add an exclusion for {:?} (e.g. in Windows Defender) and try again",
+                    "{:?} остаётся заблокированным после {} попыток, вероятно, антивирусом. \
+                     Добавьте {:?} в исключения (например, Защитника Windows) и повторите",
+                    path,
+                    attempts,
+                    dir
+                )));
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+/// Whether `error` means another process holds the file open: access denied
+/// (`ERROR_ACCESS_DENIED` while a scanner has it) or a sharing violation
+fn is_locked(error: &io::Error) -> bool {
+    const ERROR_SHARING_VIOLATION: i32 = 32;
+    const ERROR_LOCK_VIOLATION: i32 = 33;
+    let violation = matches!(
+        error.raw_os_error(),
+        Some(ERROR_SHARING_VIOLATION | ERROR_LOCK_VIOLATION)
+    );
+    error.kind() == io::ErrorKind::PermissionDenied || (cfg!(windows) && violation)
+}
+
+/// Replace `$VAR`, `${VAR}` and `%VAR%` using `lookup`
+fn expand_vars(input: &str, lookup: impl Fn(&str) -> Option<String>) -> String {
+    let mut out = String::with_capacity(input.len());
+    let mut rest = input;
+
+    while let Some(pos) = rest.find(['$', '%']) {
+        out.push_str(&rest[..pos]);
+        let tail = &rest[pos..];
+
+        let (name, len) = if let Some(braced) = tail.strip_prefix("${") {
+            match braced.find('}') {
+                Some(end) => (&braced[..end], end + 3),
+                None => ("", 0),
+            }
+        } else if let Some(percent) = tail.strip_prefix('%') {
+            match percent.find('%') {
+                Some(end) => (&percent[..end], end + 2),
+                None => ("", 0),
+            }
+        } else {
+            let name = &tail[1..];
+            let end = name
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(name.len());
+            (&name[..end], end + 1)
+        };
+
+        match lookup(name).filter(|_| !name.is_empty()) {
+            Some(value) => {
+                out.push_str(&value);
+                rest = &tail[len..];
+            }
+            None => {
+                out.push_str(&tail[..1]);
+                rest = &tail[1..];
+            }
+        }
+    }
+
+    out.push_str(rest);
+    out
+}
+
+/// Use the platform separator throughout and drop trailing separators
+fn normalize_separators(path: &str) -> String {
+    let path = if cfg!(windows) {
+        path.replace('/', "\\")
+    } else {
+        path.to_string()
+    };
+
+    let trimmed = path.trim_end_matches(std::path::MAIN_SEPARATOR);
+    if trimmed.is_empty() || trimmed.ends_with(':') {
+        // Keep roots like `/` and `C:\` intact
+        path
+    } else {
+        trimmed.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_vars() {
+        let lookup = |name: &str| (name == "HOME_DIR").then(|| "/home/me".to_string());
+
+        assert_eq!(expand_vars("$HOME_DIR/out", lookup), "/home/me/out");
+        assert_eq!(expand_vars("${HOME_DIR}x", lookup), "/home/mex");
+        assert_eq!(expand_vars("%HOME_DIR%\\out", lookup), "/home/me\\out");
+        assert_eq!(
+            expand_vars("$MISSING/%MISSING%", lookup),
+            "$MISSING/%MISSING%"
+        );
+        assert_eq!(expand_vars("100% $", lookup), "100% $");
+    }
+
+    #[test]
+    fn test_expand() {
+        assert_eq!(expand("builds/"), PathBuf::from("builds"));
+        assert_eq!(expand("/"), PathBuf::from("/"));
+
+        let home = directories::BaseDirs::new()
+            .unwrap()
+            .home_dir()
+            .to_path_buf();
+        assert_eq!(expand("~/builds"), home.join("builds"));
+        assert_eq!(expand("~"), home);
+        assert_eq!(expand("~/Музыка/"), home.join("Музыка"));
+        assert_eq!(expand("~user"), PathBuf::from("~user"));
+        assert_eq!(
+            expand("Пользователь/Яндекс Музыка"),
+            PathBuf::from("Пользователь").join("Яндекс Музыка")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_expand_non_unicode() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let raw = OsStr::from_bytes(b"builds/\xff\xfe");
+        assert_eq!(expand(raw).as_os_str(), raw);
+    }
+
+    #[test]
+    fn test_retry_locked() {
+        let path = Path::new("extracted/Yandex Music.exe");
+        let locked = || io::Error::from(io::ErrorKind::PermissionDenied);
+
+        let mut calls = 0;
+        let value = retry_locked(path, 4, Duration::ZERO, || {
+            calls += 1;
+            if calls < 3 {
+                Err(locked())
+            } else {
+                Ok(calls)
+            }
+        })
+        .unwrap();
+        assert_eq!(value, 3);
+
+        let mut calls = 0;
+        let error = retry_locked(path, 4, Duration::ZERO, || -> io::Result<()> {
+            calls += 1;
+            Err(locked())
+        })
+        .unwrap_err();
+        assert_eq!(calls, 4);
+        assert!(format!("{:#}", error).contains("Windows"));
+        assert_eq!(crate::error::code(&error), 40);
+
+        // Other errors and single attempts fail right away, without the hint
+        let mut calls = 0;
+        let error = retry_locked(path, 4, Duration::ZERO, || -> io::Result<()> {
+            calls += 1;
+            Err(io::Error::from(io::ErrorKind::NotFound))
+        })
+        .unwrap_err();
+        assert_eq!(calls, 1);
+        assert!(!format!("{:#}", error).contains("Windows"));
+        let error = retry_locked(path, 1, Duration::ZERO, || -> io::Result<()> {
+            Err(locked())
+        })
+        .unwrap_err();
+        assert!(!format!("{:#}", error).contains("Windows"));
+    }
+
+    #[test]
+    fn test_ensure_writable() {
+        let dir = tempfile::tempdir().unwrap();
+        let nested = dir.path().join("a").join("b");
+        ensure_writable(&nested).unwrap();
+        assert!(nested.is_dir());
+
+        let cyrillic = dir.path().join("Пользователь").join("Музыка");
+        ensure_writable(&cyrillic).unwrap();
+        assert!(available_space(&cyrillic.join("5.0.0")).is_ok());
+
+        let file = dir.path().join("file");
+        fs::write(&file, b"").unwrap();
+        assert!(ensure_writable(&file.join("sub")).is_err());
+    }
+}
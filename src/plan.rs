@@ -0,0 +1,151 @@
+//! Plan module - exported patch runs that can be replayed exactly
+//!
+//! `patch --export-plan plan.json` records the installer (with its SHA-512), the
+//! selected profile, a hash of the rules it resolves to and every patch option.
+//! `patch --plan plan.json` replays it: the same installer is downloaded and
+//! verified, and the run fails if the rules no longer hash the same, so two
+//! machines produce identical patched output.
+
+use crate::api::AppBuild;
+use crate::patcher::PatchOptions;
+use crate::rules::{PatchProfile, ProfileSet};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::Path;
+use tracing::{info, warn};
+
+/// Version of the plan file format
+pub const PLAN_FORMAT: u32 = 1;
+
+/// A reproducible patch run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PatchPlan {
+    pub format: u32,
+    /// Patcher version that exported the plan; the built-in mod code depends on it
+    pub patcher_version: String,
+    /// Installer to patch, including its published SHA-512
+    pub build: AppBuild,
+    /// Patch profile selected for the build
+    pub profile: String,
+    /// SHA-256 of the profile and the rules it resolves to for the build
+    pub rules_hash: String,
+    pub options: PatchOptions,
+}
+
+impl PatchPlan {
+    /// Plan patching `build` with `options`, resolving the profile as a run would
+    pub fn new(build: &AppBuild, options: &PatchOptions) -> Result<Self> {
+        let profiles = ProfileSet::load()?;
+        let profile = profiles.select(&build.version, options.profile.as_deref())?;
+        Ok(Self {
+            format: PLAN_FORMAT,
+            patcher_version: env!("CARGO_PKG_VERSION").to_string(),
+            build: build.clone(),
+            profile: profile.name.clone(),
+            rules_hash: rules_hash(profile, &build.version)?,
+            options: PatchOptions {
+                profile: Some(profile.name.clone()),
+                ..options.clone()
+            },
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let content =
+            fs::read_to_string(path).with_context(|| format!("Failed to read plan {:?}", path))?;
+        let plan: Self = serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse plan {:?}", path))?;
+        if plan.format != PLAN_FORMAT {
+            anyhow::bail!(
+                "Unsupported plan format {} (expected {})",
+                plan.format,
+                PLAN_FORMAT
+            );
+        }
+        Ok(plan)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)? + "\n")
+            .with_context(|| format!("Failed to write plan {:?}", path))?;
+        info!("Patch plan written to {:?}", path);
+        Ok(())
+    }
+
+    /// Check that replaying the plan reproduces it and return its options
+    pub fn options(&self) -> Result<PatchOptions> {
+        let patcher_version = env!("CARGO_PKG_VERSION");
+        if self.patcher_version != patcher_version {
+            warn!(
+                "Plan was exported by patcher {}, this is {}; the mod code may differ",
+                self.patcher_version, patcher_version
+            );
+        }
+
+        let profiles = ProfileSet::load()?;
+        let profile = profiles.select(&self.build.version, Some(&self.profile))?;
+        if rules_hash(profile, &self.build.version)? != self.rules_hash {
+            anyhow::bail!(
+                "Patch rules of profile {} changed since the plan was exported",
+                self.profile
+            );
+        }
+        Ok(PatchOptions {
+            profile: Some(self.profile.clone()),
+            dry_run: false,
+            ..self.options.clone()
+        })
+    }
+}
+
+/// SHA-256 of `profile` and the rules it applies to `app_version`
+pub(crate) fn rules_hash(profile: &PatchProfile, app_version: &str) -> Result<String> {
+    let rules = profile.collect_rules(app_version)?;
+    let mut hasher = Sha256::new();
+    hasher.update(serde_json::to_vec(&(profile, rules))?);
+    Ok(hex::encode(hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::package::PackageFormat;
+
+    #[test]
+    fn test_plan_roundtrip() {
+        let build = AppBuild {
+            path: "Yandex_Music_x64_5.0.0.exe".to_string(),
+            hash: "c2hhNTEy".to_string(),
+            size: 1024,
+            release_date: None,
+            update_probability: None,
+            version: "5.0.0".to_string(),
+            deprecated_versions: None,
+            channel: "stable".to_string(),
+        };
+        let options = PatchOptions {
+            banned_headers: vec!["X-Extra".to_string()],
+            packages: vec![PackageFormat::Scoop],
+            ..Default::default()
+        };
+
+        let plan = PatchPlan::new(&build, &options).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("plan.json");
+        plan.save(&path).unwrap();
+
+        let loaded = PatchPlan::load(&path).unwrap();
+        assert_eq!(loaded.build.hash, build.hash);
+        assert_eq!(loaded.rules_hash, plan.rules_hash);
+        let replayed = loaded.options().unwrap();
+        assert_eq!(replayed.profile.as_deref(), Some(plan.profile.as_str()));
+        assert_eq!(replayed.banned_headers, options.banned_headers);
+        assert_eq!(replayed.packages, options.packages);
+
+        let mut tampered = loaded;
+        tampered.rules_hash = "0".repeat(64);
+        assert!(tampered.options().is_err());
+    }
+}
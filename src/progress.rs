@@ -0,0 +1,189 @@
+//! Progress module - events emitted while a build is patched
+//!
+//! [`patcher::process_build`](crate::patcher::process_build) reports to a
+//! [`ProgressSink`]; frontends render the events their own way (the CLI with a
+//! progress bar, the GUI and C API from a [`ProgressState`] snapshot).
+
+use crate::i18n::tr;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+
+/// Stage of the patch pipeline, in order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Stage {
+    Download,
+    ExtractInstaller,
+    ExtractNested,
+    ExtractAsar,
+    Cleanup,
+    /// Dry run only: checking anchors and rules instead of patching
+    CheckRules,
+    CopySources,
+    ApplyPatches,
+    CreateModFiles,
+    InjectHtml,
+    /// Only with `--pack-asar`
+    PackAsar,
+    /// Only with `--package`
+    Package,
+    Done,
+}
+
+impl Stage {
+    /// Overall progress in percent when the stage starts
+    pub fn percent(self) -> u64 {
+        match self {
+            Stage::Download => 5,
+            Stage::ExtractInstaller => 20,
+            Stage::ExtractNested => 30,
+            Stage::ExtractAsar => 35,
+            Stage::Cleanup => 45,
+            Stage::CheckRules => 90,
+            Stage::CopySources => 50,
+            Stage::ApplyPatches => 55,
+            Stage::CreateModFiles => 80,
+            Stage::InjectHtml => 90,
+            Stage::PackAsar => 92,
+            Stage::Package => 95,
+            Stage::Done => 100,
+        }
+    }
+
+    /// Localized description of the stage
+    pub fn message(self) -> String {
+        match self {
+            Stage::Download => tr!("Downloading build...", "Загрузка сборки..."),
+            Stage::ExtractInstaller => tr!("Extracting installer...", "Распаковка установщика..."),
+            Stage::ExtractNested => tr!(
+                "Extracting nested archives...",
+                "Распаковка вложенных архивов..."
+            ),
+            Stage::ExtractAsar => tr!(
+                "Locating and extracting app.asar...",
+                "Поиск и распаковка app.asar..."
+            ),
+            Stage::Cleanup => tr!("Cleaning up temp files...", "Удаление временных файлов..."),
+            Stage::CheckRules => tr!("Checking patch rules...", "Проверка правил патчей..."),
+            Stage::CopySources => tr!("Copying sources...", "Копирование исходников..."),
+            Stage::ApplyPatches => tr!("Applying patches...", "Применение патчей..."),
+            Stage::CreateModFiles => tr!("Creating mod files...", "Создание файлов мода..."),
+            Stage::InjectHtml => tr!("Injecting mod into HTML...", "Внедрение мода в HTML..."),
+            Stage::PackAsar => tr!("Packing app.asar...", "Упаковка app.asar..."),
+            Stage::Package => tr!("Packaging build...", "Упаковка сборки..."),
+            Stage::Done => tr!("Done!", "Готово!"),
+        }
+    }
+}
+
+/// Something that happened during a patch run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum Event {
+    /// A new stage started
+    Stage { stage: Stage },
+    /// Part of the installer was downloaded; `total` is unknown without Content-Length
+    Bytes { downloaded: u64, total: Option<u64> },
+    /// The current stage read or wrote `bytes` more, for `--bench`
+    Processed { bytes: u64 },
+}
+
+/// Receiver of progress events
+pub trait ProgressSink: Send + Sync {
+    fn event(&self, event: Event);
+}
+
+/// Latest state of a run, for frontends that poll instead of reacting to events
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Snapshot {
+    pub stage: Option<Stage>,
+    /// Overall progress in percent
+    pub percent: u64,
+    pub downloaded: u64,
+    pub total: Option<u64>,
+}
+
+impl Snapshot {
+    /// Localized description of the current stage
+    pub fn message(&self) -> String {
+        match self.stage {
+            Some(Stage::Download) if self.downloaded > 0 => {
+                format!(
+                    "{} {}",
+                    Stage::Download.message(),
+                    format_bytes(self.downloaded, self.total)
+                )
+            }
+            Some(stage) => stage.message(),
+            None => tr!("Starting...", "Запуск..."),
+        }
+    }
+}
+
+/// A [`ProgressSink`] that keeps the latest [`Snapshot`]
+#[derive(Debug, Default)]
+pub struct ProgressState(Mutex<Snapshot>);
+
+impl ProgressState {
+    pub fn snapshot(&self) -> Snapshot {
+        self.0.lock().unwrap().clone()
+    }
+
+    /// Forget the previous run
+    pub fn reset(&self) {
+        *self.0.lock().unwrap() = Snapshot::default();
+    }
+}
+
+impl ProgressSink for ProgressState {
+    fn event(&self, event: Event) {
+        let mut snapshot = self.0.lock().unwrap();
+        match event {
+            Event::Stage { stage } => {
+                snapshot.stage = Some(stage);
+                snapshot.percent = stage.percent();
+            }
+            Event::Bytes { downloaded, total } => {
+                snapshot.downloaded = downloaded;
+                snapshot.total = total;
+            }
+            Event::Processed { .. } => {}
+        }
+    }
+}
+
+/// `12.3/95.0 MB`, or `12.3 MB` without a total
+pub fn format_bytes(downloaded: u64, total: Option<u64>) -> String {
+    let mb = |bytes: u64| bytes as f64 / (1024.0 * 1024.0);
+    match total {
+        Some(total) => format!("{:.1}/{:.1} MB", mb(downloaded), mb(total)),
+        None => format!("{:.1} MB", mb(downloaded)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_progress_state() {
+        let state = ProgressState::default();
+        state.event(Event::Stage {
+            stage: Stage::Download,
+        });
+        state.event(Event::Bytes {
+            downloaded: 1024 * 1024,
+            total: Some(4 * 1024 * 1024),
+        });
+
+        let snapshot = state.snapshot();
+        assert_eq!(snapshot.percent, 5);
+        assert!(snapshot.message().ends_with("1.0/4.0 MB"));
+
+        state.event(Event::Stage { stage: Stage::Done });
+        assert_eq!(state.snapshot().percent, 100);
+
+        state.reset();
+        assert_eq!(state.snapshot(), Snapshot::default());
+    }
+}
@@ -0,0 +1,43 @@
+//! Interactive console behaviour, disabled in non-interactive mode
+//!
+//! Non-interactive mode (`--yes`, or the `CI` environment variable) answers every
+//! prompt with its default and never reads stdin.
+
+use std::io::{self, IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static NON_INTERACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Enable non-interactive mode for the rest of the run
+pub fn set_non_interactive(enabled: bool) {
+    NON_INTERACTIVE.store(enabled, Ordering::Relaxed);
+}
+
+/// Whether non-interactive mode was requested with `--yes` or the `CI` variable
+pub fn is_non_interactive() -> bool {
+    NON_INTERACTIVE.load(Ordering::Relaxed) || std::env::var_os("CI").is_some()
+}
+
+/// Whether the user can be asked anything: not disabled, not on CI, stdin is a terminal
+pub fn is_interactive() -> bool {
+    !is_non_interactive() && io::stdin().is_terminal()
+}
+
+/// Ask a yes/no question; returns `default` without asking when non-interactive
+pub fn confirm(question: &str, default: bool) -> bool {
+    if !is_interactive() {
+        return default;
+    }
+
+    print!("{} [{}] ", question, if default { "Y/n" } else { "y/N" });
+    let _ = io::stdout().flush();
+    let mut answer = String::new();
+    if io::stdin().read_line(&mut answer).is_err() {
+        return default;
+    }
+    match answer.trim().to_lowercase().as_str() {
+        "" => default,
+        "y" | "yes" | "д" | "да" => true,
+        _ => false,
+    }
+}
@@ -0,0 +1,331 @@
+//! Remote rules module - the latest rule sets from the project repository
+//!
+//! The rule sets in `rules/` change far more often than the patcher itself:
+//! a renamed string in a new Yandex Music release only needs a rule update.
+//! With `--remote-rules` the patcher fetches `rules/index.json` from the
+//! latest release, which lists the rule files with their SHA-256, and its
+//! ed25519 signature `index.json.sig` made by the release job. Only an index
+//! signed with the release key
+//! compiled into the binary is trusted; the patcher then downloads the files,
+//! verifies every hash and checks that they parse before replacing the
+//! cached copy. The cached rules are used instead of the built-in ones unless
+//! they are older than the binary's; any failure falls back to the last
+//! verified copy or the built-in rules.
+
+use crate::api;
+use crate::config;
+use crate::patches::RELEASE_PUBLIC_KEY_PEM;
+use crate::rules::{self, ProfileSet, RuleSet};
+use anyhow::{Context, Result};
+use base64::Engine;
+use ed25519_dalek::{Signature, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use tracing::{debug, info, warn};
+
+/// Where the rule files are served from: the assets of the latest release,
+/// published from the main branch with a signed index
+pub const RULES_BASE_URL: &str =
+    "https://github.com/Jhon-Crow/YandexMusicBetaModeFastLP/releases/latest/download/";
+
+/// Index of the rule files, next to them
+pub const INDEX_FILE: &str = "index.json";
+
+/// Raw ed25519 signature of the index by the release key, made by the release
+/// job with `openssl pkeyutl -sign -rawin`
+pub const SIGNATURE_FILE: &str = "index.json.sig";
+
+/// DER prefix of an ed25519 SubjectPublicKeyInfo, followed by the 32 key bytes
+const ED25519_SPKI_PREFIX: [u8; 12] = [
+    0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00,
+];
+
+/// Index format this patcher understands
+const INDEX_FORMAT: u32 = 1;
+
+/// Index of the built-in rules
+const BUILTIN_INDEX_JSON: &str = include_str!("../rules/index.json");
+
+/// Set once the rules were refreshed in this process
+static REFRESHED: OnceLock<()> = OnceLock::new();
+
+/// `rules/index.json`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RulesIndex {
+    pub format: u32,
+    /// Bumped with every change of the rule files
+    pub revision: u64,
+    /// Rule file names with the SHA-256 of their content
+    pub files: BTreeMap<String, String>,
+}
+
+impl RulesIndex {
+    /// Parse an index, rejecting unknown formats and file names that are not
+    /// plain names
+    pub fn from_json(content: &str) -> Result<Self> {
+        let index: Self = serde_json::from_str(content).context("Failed to parse rules index")?;
+        if index.format != INDEX_FORMAT {
+            anyhow::bail!("Unsupported rules index format {}", index.format);
+        }
+        for name in index.files.keys() {
+            let plain = Path::new(name).file_name().and_then(|n| n.to_str()) == Some(name);
+            if !plain || !name.ends_with(".toml") {
+                anyhow::bail!("Invalid rule file name {:?} in the rules index", name);
+            }
+        }
+        Ok(index)
+    }
+
+    /// The index of the rules compiled into this binary
+    pub fn builtin() -> Result<Self> {
+        Self::from_json(BUILTIN_INDEX_JSON)
+    }
+}
+
+/// Directory the verified remote rules are cached in
+pub fn cache_dir() -> Option<PathBuf> {
+    config::cache_dir().map(|dir| dir.join("rules"))
+}
+
+/// SHA-256 of a rule file; line endings are normalized so a checkout with
+/// CRLF hashes the same
+pub fn content_hash(content: &str) -> String {
+    hex::encode(Sha256::digest(content.replace("\r\n", "\n").as_bytes()))
+}
+
+/// The release key compiled into this binary
+fn release_key() -> Result<VerifyingKey> {
+    public_key(RELEASE_PUBLIC_KEY_PEM.context("This build has no release key")?)
+}
+
+/// An ed25519 public key from its PEM form
+fn public_key(pem: &str) -> Result<VerifyingKey> {
+    let body: String = pem
+        .lines()
+        .filter(|line| !line.starts_with("-----"))
+        .collect();
+    let der = base64::engine::general_purpose::STANDARD.decode(body.trim())?;
+    let key = der
+        .strip_prefix(&ED25519_SPKI_PREFIX[..])
+        .and_then(|key| <[u8; 32]>::try_from(key).ok())
+        .context("The release key is not an ed25519 public key")?;
+    Ok(VerifyingKey::from_bytes(&key)?)
+}
+
+/// PEM form of `key`, as [`public_key`] reads it
+#[cfg(test)]
+pub(crate) fn public_key_pem(key: &VerifyingKey) -> String {
+    let mut der = ED25519_SPKI_PREFIX.to_vec();
+    der.extend_from_slice(key.as_bytes());
+    format!(
+        "-----BEGIN PUBLIC KEY-----\n{}\n-----END PUBLIC KEY-----\n",
+        base64::engine::general_purpose::STANDARD.encode(der)
+    )
+}
+
+/// Check that `signature` is `key`'s signature of the index `content`; line
+/// endings are normalized as in [`content_hash`]
+pub fn verify_signature(key: &VerifyingKey, content: &str, signature: &[u8]) -> Result<()> {
+    let signature = Signature::from_slice(signature).context("Malformed rules index signature")?;
+    key.verify_strict(content.replace("\r\n", "\n").as_bytes(), &signature)
+        .context("The rules index is not signed with the release key")
+}
+
+/// Check `content` of the rule file `name` against `index` and make sure it
+/// parses
+pub fn verify(index: &RulesIndex, name: &str, content: &str) -> Result<()> {
+    let expected = index
+        .files
+        .get(name)
+        .with_context(|| format!("{} is not listed in the rules index", name))?;
+    let actual = content_hash(content);
+    if !actual.eq_ignore_ascii_case(expected) {
+        anyhow::bail!(
+            "Hash mismatch for {}: expected {}, got {}",
+            name,
+            expected,
+            actual
+        );
+    }
+    match name {
+        "profiles.toml" => {
+            ProfileSet::from_toml(content)?;
+        }
+        _ => {
+            RuleSet::from_toml(content)?;
+        }
+    }
+    Ok(())
+}
+
+/// Fetch the rules below `base_url` into `dir`, replacing its content only
+/// once the index is signed by `key` and every file is verified. Returns the
+/// index fetched.
+pub async fn update(base_url: &str, dir: &Path, key: &VerifyingKey) -> Result<RulesIndex> {
+    let base_url = format!("{}/", base_url.trim_end_matches('/'));
+    let index_json = api::fetch_text(&format!("{}{}", base_url, INDEX_FILE)).await?;
+    let signature = api::fetch_bytes(&format!("{}{}", base_url, SIGNATURE_FILE)).await?;
+    verify_signature(key, &index_json, &signature)?;
+    let index = RulesIndex::from_json(&index_json)?;
+
+    let parent = dir.parent().unwrap_or(Path::new("."));
+    fs::create_dir_all(parent)?;
+    let staging = tempfile::tempdir_in(parent)?;
+    for name in index.files.keys() {
+        let content = api::fetch_text(&format!("{}{}", base_url, name)).await?;
+        verify(&index, name, &content)?;
+        fs::write(staging.path().join(name), content)?;
+    }
+    fs::write(staging.path().join(INDEX_FILE), index_json)?;
+    fs::write(staging.path().join(SIGNATURE_FILE), signature)?;
+
+    if dir.exists() {
+        fs::remove_dir_all(dir)?;
+    }
+    fs::rename(staging.keep(), dir)
+        .with_context(|| format!("Failed to store the rules in {:?}", dir))?;
+    info!(
+        "Fetched rules revision {} ({} file(s)) from {}",
+        index.revision,
+        index.files.len(),
+        base_url
+    );
+    Ok(index)
+}
+
+/// Rules of `dir` if they are signed by `key`, complete, verified and not
+/// older than the built-in ones
+pub fn cached(dir: &Path, key: &VerifyingKey) -> Result<Option<RulesIndex>> {
+    let Ok(index_json) = fs::read_to_string(dir.join(INDEX_FILE)) else {
+        return Ok(None);
+    };
+    let signature = fs::read(dir.join(SIGNATURE_FILE))
+        .with_context(|| format!("Failed to read {:?}", dir.join(SIGNATURE_FILE)))?;
+    verify_signature(key, &index_json, &signature)?;
+    let index = RulesIndex::from_json(&index_json)?;
+    for name in index.files.keys() {
+        let content = fs::read_to_string(dir.join(name))
+            .with_context(|| format!("Failed to read {:?}", dir.join(name)))?;
+        verify(&index, name, &content)?;
+    }
+    let builtin = RulesIndex::builtin()?;
+    if index.revision < builtin.revision {
+        debug!(
+            "Cached rules revision {} is older than the built-in revision {}",
+            index.revision, builtin.revision
+        );
+        return Ok(None);
+    }
+    Ok(Some(index))
+}
+
+/// Update the cached rules from `base_url` and use them for this run; on
+/// failure the last verified copy or the built-in rules are used. Only the
+/// first call of a process fetches.
+pub async fn refresh(base_url: &str) -> Result<()> {
+    if REFRESHED.set(()).is_err() {
+        return Ok(());
+    }
+    let key = match release_key() {
+        Ok(key) => key,
+        Err(e) => {
+            warn!("Using the built-in rules: {:#}", e);
+            return Ok(());
+        }
+    };
+    let dir = cache_dir().context("No cache directory for the remote rules")?;
+    if let Err(e) = update(base_url, &dir, &key).await {
+        warn!("Failed to fetch the latest rules: {:#}", e);
+    }
+    match cached(&dir, &key) {
+        Ok(Some(index)) => {
+            info!("Using rules revision {} from {:?}", index.revision, dir);
+            rules::use_remote_rules(dir);
+        }
+        Ok(None) => info!("Using the built-in rules"),
+        Err(e) => warn!("Ignoring the cached rules: {:#}", e),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ed25519_dalek::{Signer, SigningKey};
+
+    /// A key pair made up for the tests, and its signature of
+    /// `rules/index.json`
+    fn test_key() -> (VerifyingKey, Vec<u8>) {
+        let signing = SigningKey::from_bytes(&[7; 32]);
+        let signature = signing.sign(BUILTIN_INDEX_JSON.as_bytes()).to_vec();
+        (signing.verifying_key(), signature)
+    }
+
+    #[test]
+    fn test_verify_signature() {
+        let (key, signature) = test_key();
+        verify_signature(&key, BUILTIN_INDEX_JSON, &signature).unwrap();
+        let crlf = BUILTIN_INDEX_JSON.replace('\n', "\r\n");
+        verify_signature(&key, &crlf, &signature).unwrap();
+
+        let raised = BUILTIN_INDEX_JSON.replace("\"revision\": 1", "\"revision\": 9");
+        assert!(verify_signature(&key, &raised, &signature).is_err());
+        assert!(verify_signature(&key, BUILTIN_INDEX_JSON, &signature[1..]).is_err());
+        let other = SigningKey::from_bytes(&[8; 32]).verifying_key();
+        assert!(verify_signature(&other, BUILTIN_INDEX_JSON, &signature).is_err());
+    }
+
+    #[test]
+    fn test_public_key() {
+        let (key, _) = test_key();
+        assert_eq!(public_key(&public_key_pem(&key)).unwrap(), key);
+        assert!(
+            public_key("-----BEGIN PUBLIC KEY-----\nAAAA\n-----END PUBLIC KEY-----\n").is_err()
+        );
+    }
+
+    #[test]
+    fn test_verify_rules() {
+        let index = RulesIndex::builtin().unwrap();
+        assert_eq!(index.files.len(), 2);
+        verify(&index, "profiles.toml", rules::PROFILES_TOML).unwrap();
+        verify(&index, "premium.toml", rules::PREMIUM_RULES_TOML).unwrap();
+        verify(
+            &index,
+            "premium.toml",
+            &rules::PREMIUM_RULES_TOML.replace('\n', "\r\n"),
+        )
+        .unwrap();
+
+        let tampered = rules::PREMIUM_RULES_TOML.replace("premium", "prernium");
+        assert!(verify(&index, "premium.toml", &tampered).is_err());
+        assert!(verify(&index, "other.toml", "").is_err());
+
+        let traversal = r#"{"format": 1, "revision": 2, "files": {"../x.toml": "00"}}"#;
+        assert!(RulesIndex::from_json(traversal).is_err());
+        let future = r#"{"format": 2, "revision": 2, "files": {}}"#;
+        assert!(RulesIndex::from_json(future).is_err());
+
+        let (key, signature) = test_key();
+        let dir = tempfile::tempdir().unwrap();
+        assert!(cached(dir.path(), &key).unwrap().is_none());
+        fs::write(dir.path().join(INDEX_FILE), BUILTIN_INDEX_JSON).unwrap();
+        assert!(cached(dir.path(), &key).is_err());
+        fs::write(dir.path().join(SIGNATURE_FILE), &signature).unwrap();
+        fs::write(dir.path().join("profiles.toml"), rules::PROFILES_TOML).unwrap();
+        fs::write(dir.path().join("premium.toml"), tampered).unwrap();
+        assert!(cached(dir.path(), &key).is_err());
+        fs::write(dir.path().join("premium.toml"), rules::PREMIUM_RULES_TOML).unwrap();
+        assert_eq!(
+            cached(dir.path(), &key).unwrap().unwrap().revision,
+            index.revision
+        );
+        let other = SigningKey::from_bytes(&[8; 32]).verifying_key();
+        assert!(cached(dir.path(), &other).is_err());
+    }
+}
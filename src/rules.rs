@@ -0,0 +1,531 @@
+//! Rules module - declarative find/replace patch rules
+//!
+//! Rule sets are TOML documents (see `rules/`) describing text replacements
+//! applied to files of the extracted app. Each rule may be limited to a
+//! semver range of the Yandex Music app, so rules can be maintained per
+//! app version without touching the patcher code.
+//!
+//! Patch profiles (`rules/profiles.toml`) map app version ranges to a file
+//! layout and the rules to apply, and are selected from the build version.
+//! Rule files fetched with `--remote-rules` take the place of the built-in
+//! ones for the run (see `remote_rules`).
+
+use crate::config;
+use anyhow::{Context, Result};
+use globset::{Glob, GlobMatcher};
+use regex::Regex;
+use semver::{Version, VersionReq};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use tracing::{debug, info, warn};
+use walkdir::WalkDir;
+
+/// Built-in premium capability rule set
+pub const PREMIUM_RULES_TOML: &str = include_str!("../rules/premium.toml");
+
+/// Built-in versioned patch profiles
+pub const PROFILES_TOML: &str = include_str!("../rules/profiles.toml");
+
+/// Directory of verified remote rule files, once enabled
+static REMOTE_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Use the rule files in `dir` instead of the built-in ones from now on
+pub fn use_remote_rules(dir: PathBuf) {
+    let _ = REMOTE_DIR.set(dir);
+}
+
+/// Content of the remote rule file `name`, if remote rules are in use
+fn remote_file(name: &str) -> Result<Option<String>> {
+    let Some(dir) = REMOTE_DIR.get() else {
+        return Ok(None);
+    };
+    let path = dir.join(name);
+    if !path.exists() {
+        return Ok(None);
+    }
+    fs::read_to_string(&path)
+        .map(Some)
+        .with_context(|| format!("Failed to read {:?}", path))
+}
+
+/// A single find/replace rule
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PatchRule {
+    /// Short identifier used in logs and reports
+    pub name: String,
+    /// Glob of target files, relative to the app root (e.g. `app/**/*.js`)
+    pub files: String,
+    /// Literal text (or regex when `regex` is set) to look for
+    pub find: String,
+    /// Replacement text; regex rules may use `${1}` style captures
+    pub replace: String,
+    /// Treat `find` as a regular expression
+    #[serde(default)]
+    pub regex: bool,
+    /// Semver range of app versions this rule applies to (all if unset)
+    #[serde(default)]
+    pub versions: Option<String>,
+}
+
+/// A named collection of rules
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RuleSet {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub rules: Vec<PatchRule>,
+}
+
+/// Result of applying a single rule
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RuleOutcome {
+    pub rule: String,
+    pub matches: usize,
+    pub files: Vec<String>,
+}
+
+impl RuleSet {
+    /// Parse a rule set from TOML
+    pub fn from_toml(content: &str) -> Result<Self> {
+        toml::from_str(content).context("Failed to parse rule set")
+    }
+
+    /// The built-in premium rule set
+    pub fn premium() -> Result<Self> {
+        Self::from_toml(PREMIUM_RULES_TOML)
+    }
+
+    /// Built-in rule set by name, as referenced from profiles; the remote
+    /// `<name>.toml` takes precedence when remote rules are in use
+    pub fn builtin(name: &str) -> Result<Self> {
+        if let Some(content) = remote_file(&format!("{}.toml", name))? {
+            return Self::from_toml(&content);
+        }
+        match name {
+            "premium" => Self::premium(),
+            other => anyhow::bail!("Unknown rule set: {}", other),
+        }
+    }
+
+    /// Rules whose version range includes `app_version`.
+    /// If the app version is not valid semver, all rules are kept.
+    pub fn rules_for_version(&self, app_version: &str) -> Vec<&PatchRule> {
+        for_version(&self.rules, app_version)
+    }
+}
+
+/// The `rules` whose version range includes `app_version`, all of them if it
+/// is not valid semver
+pub fn for_version<'a>(rules: &'a [PatchRule], app_version: &str) -> Vec<&'a PatchRule> {
+    let version = parse_app_version(app_version);
+    rules
+        .iter()
+        .filter(|rule| rule_matches_version(rule, version.as_ref()))
+        .collect()
+}
+
+/// Paths of the patched files, relative to the app root
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct FileLayout {
+    pub package_json: String,
+    pub config_js: String,
+    pub main_js: String,
+    pub preload_js: String,
+    pub create_window_js: String,
+    pub system_menu_js: String,
+    /// Directory holding the renderer HTML and mod assets
+    pub html_root: String,
+}
+
+impl Default for FileLayout {
+    fn default() -> Self {
+        Self {
+            package_json: "package.json".to_string(),
+            config_js: "main/config.js".to_string(),
+            main_js: "main/index.js".to_string(),
+            preload_js: "main/lib/preload.js".to_string(),
+            create_window_js: "main/lib/createWindow.js".to_string(),
+            system_menu_js: "main/lib/systemMenu.js".to_string(),
+            html_root: "app".to_string(),
+        }
+    }
+}
+
+/// A patch profile for a range of app versions
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PatchProfile {
+    pub name: String,
+    /// Semver range of app versions this profile targets
+    pub versions: String,
+    #[serde(default)]
+    pub layout: FileLayout,
+    /// Names of built-in rule sets to apply (e.g. "premium")
+    #[serde(default)]
+    pub rule_sets: Vec<String>,
+    /// Profile-specific rules, applied before the rule sets
+    #[serde(default)]
+    pub rules: Vec<PatchRule>,
+}
+
+impl PatchProfile {
+    /// Whether this profile targets `app_version`
+    pub fn matches(&self, app_version: &str) -> bool {
+        match (
+            VersionReq::parse(&self.versions),
+            Version::parse(app_version),
+        ) {
+            (Ok(req), Ok(version)) => req.matches(&version),
+            _ => false,
+        }
+    }
+
+    /// All rules of this profile applicable to `app_version`
+    pub fn collect_rules(&self, app_version: &str) -> Result<Vec<PatchRule>> {
+        let version = parse_app_version(app_version);
+        let mut rules: Vec<PatchRule> = self
+            .rules
+            .iter()
+            .filter(|rule| rule_matches_version(rule, version.as_ref()))
+            .cloned()
+            .collect();
+
+        for name in &self.rule_sets {
+            let set = RuleSet::builtin(name)?;
+            rules.extend(set.rules_for_version(app_version).into_iter().cloned());
+        }
+
+        Ok(rules)
+    }
+}
+
+/// The list of available patch profiles
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ProfileSet {
+    pub profiles: Vec<PatchProfile>,
+}
+
+impl ProfileSet {
+    /// Parse profiles from TOML
+    pub fn from_toml(content: &str) -> Result<Self> {
+        toml::from_str(content).context("Failed to parse patch profiles")
+    }
+
+    /// The built-in profiles
+    pub fn builtin() -> Result<Self> {
+        Self::from_toml(PROFILES_TOML)
+    }
+
+    /// User `profiles.toml` from the config directory, else the remote
+    /// profiles if in use, else the built-in profiles
+    pub fn load() -> Result<Self> {
+        if let Some(content) = config::read_config_file(config::USER_PROFILES_FILE)? {
+            return Self::from_toml(&content);
+        }
+        match remote_file(config::USER_PROFILES_FILE)? {
+            Some(content) => Self::from_toml(&content),
+            None => Self::builtin(),
+        }
+    }
+
+    /// Select a profile by name, or the first one matching `app_version`.
+    /// Falls back to the last profile when no range matches.
+    pub fn select(&self, app_version: &str, name: Option<&str>) -> Result<&PatchProfile> {
+        if let Some(name) = name {
+            return self
+                .profiles
+                .iter()
+                .find(|p| p.name == name)
+                .with_context(|| format!("Patch profile {:?} not found", name));
+        }
+
+        if let Some(profile) = self.profiles.iter().find(|p| p.matches(app_version)) {
+            info!(
+                "Selected patch profile {} for version {}",
+                profile.name, app_version
+            );
+            return Ok(profile);
+        }
+
+        let fallback = self.profiles.last().context("No patch profiles defined")?;
+        warn!(
+            "No patch profile targets version {}, falling back to {}",
+            app_version, fallback.name
+        );
+        Ok(fallback)
+    }
+}
+
+fn parse_app_version(app_version: &str) -> Option<Version> {
+    match Version::parse(app_version) {
+        Ok(v) => Some(v),
+        Err(e) => {
+            warn!(
+                "Cannot parse app version {:?} ({}), ignoring rule version ranges",
+                app_version, e
+            );
+            None
+        }
+    }
+}
+
+fn rule_matches_version(rule: &PatchRule, version: Option<&Version>) -> bool {
+    match (&rule.versions, version) {
+        (Some(range), Some(version)) => match VersionReq::parse(range) {
+            Ok(req) => req.matches(version),
+            Err(e) => {
+                warn!(
+                    "Invalid version range {:?} in rule {}: {}",
+                    range, rule.name, e
+                );
+                false
+            }
+        },
+        _ => true,
+    }
+}
+
+/// A rule compiled for matching
+struct CompiledRule<'a> {
+    rule: &'a PatchRule,
+    files: GlobMatcher,
+    find: Option<Regex>,
+}
+
+impl<'a> CompiledRule<'a> {
+    fn new(rule: &'a PatchRule) -> Result<Self> {
+        let files = Glob::new(&rule.files)
+            .with_context(|| format!("Invalid file glob in rule {}", rule.name))?
+            .compile_matcher();
+        let find = if rule.regex {
+            Some(
+                Regex::new(&rule.find)
+                    .with_context(|| format!("Invalid regex in rule {}", rule.name))?,
+            )
+        } else {
+            None
+        };
+        Ok(Self { rule, files, find })
+    }
+
+    /// Apply the rule to `content`, returning the number of replacements
+    fn apply(&self, content: &mut String) -> usize {
+        match &self.find {
+            Some(re) => {
+                let count = re.find_iter(content).count();
+                if count > 0 {
+                    *content = re
+                        .replace_all(content, self.rule.replace.as_str())
+                        .into_owned();
+                }
+                count
+            }
+            None => {
+                let count = content.matches(self.rule.find.as_str()).count();
+                if count > 0 {
+                    *content = content.replace(&self.rule.find, &self.rule.replace);
+                }
+                count
+            }
+        }
+    }
+}
+
+/// Apply rules to every matching file under `root`.
+/// With `dry_run` set, files are only scanned and never written.
+pub fn apply_rules(root: &Path, rules: &[&PatchRule], dry_run: bool) -> Result<Vec<RuleOutcome>> {
+    let compiled = rules
+        .iter()
+        .map(|rule| CompiledRule::new(rule))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut outcomes: Vec<RuleOutcome> = rules
+        .iter()
+        .map(|rule| RuleOutcome {
+            rule: rule.name.clone(),
+            matches: 0,
+            files: Vec::new(),
+        })
+        .collect();
+
+    for entry in WalkDir::new(root)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+    {
+        let relative = match entry.path().strip_prefix(root) {
+            Ok(rel) => rel.to_string_lossy().replace('\\', "/"),
+            Err(_) => continue,
+        };
+
+        let targets: Vec<usize> = compiled
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| c.files.is_match(&relative))
+            .map(|(i, _)| i)
+            .collect();
+        if targets.is_empty() {
+            continue;
+        }
+
+        // Skip binary files
+        let Ok(mut content) = String::from_utf8(fs::read(entry.path())?) else {
+            continue;
+        };
+
+        let mut changed = false;
+        for i in targets {
+            let count = compiled[i].apply(&mut content);
+            if count > 0 {
+                debug!(
+                    "Rule {} matched {} time(s) in {}",
+                    rules[i].name, count, relative
+                );
+                outcomes[i].matches += count;
+                outcomes[i].files.push(relative.clone());
+                changed = true;
+            }
+        }
+
+        if changed && !dry_run {
+            fs::write(entry.path(), content)?;
+        }
+    }
+
+    for outcome in &outcomes {
+        if outcome.matches == 0 {
+            warn!("Rule {} did not match any file", outcome.rule);
+        } else {
+            info!(
+                "Rule {} applied {} time(s) in {} file(s)",
+                outcome.rule,
+                outcome.matches,
+                outcome.files.len()
+            );
+        }
+    }
+
+    Ok(outcomes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(name: &str, find: &str, replace: &str, regex: bool) -> PatchRule {
+        PatchRule {
+            name: name.to_string(),
+            files: "app/**/*.js".to_string(),
+            find: find.to_string(),
+            replace: replace.to_string(),
+            regex,
+            versions: None,
+        }
+    }
+
+    #[test]
+    fn test_premium_rules_parse() {
+        let set = RuleSet::premium().unwrap();
+        assert_eq!(set.name, "premium");
+        assert!(!set.rules.is_empty());
+        for rule in &set.rules {
+            CompiledRule::new(rule).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_rules_for_version() {
+        let mut set = RuleSet {
+            name: "test".to_string(),
+            description: None,
+            rules: vec![rule("any", "a", "b", false), rule("new", "c", "d", false)],
+        };
+        set.rules[1].versions = Some(">=5.20.0".to_string());
+
+        assert_eq!(set.rules_for_version("5.10.0").len(), 1);
+        assert_eq!(set.rules_for_version("5.40.1").len(), 2);
+        assert_eq!(set.rules_for_version("not-a-version").len(), 2);
+    }
+
+    #[test]
+    fn test_builtin_profiles() {
+        let set = ProfileSet::builtin().unwrap();
+        let profile = set.select("5.40.1", None).unwrap();
+        assert_eq!(profile.layout.main_js, "main/index.js");
+
+        let rules = profile.collect_rules("5.40.1").unwrap();
+        assert!(rules.iter().any(|r| r.name == "create-window-devtools"));
+        assert!(rules.iter().any(|r| r.name == "account-has-plus"));
+    }
+
+    #[test]
+    fn test_select_profile() {
+        let set = ProfileSet::from_toml(
+            r#"
+            [[profiles]]
+            name = "new"
+            versions = ">=6.0.0"
+            [profiles.layout]
+            main_js = "dist/main.js"
+
+            [[profiles]]
+            name = "old"
+            versions = "<6.0.0"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(set.select("6.1.0", None).unwrap().name, "new");
+        assert_eq!(
+            set.select("6.1.0", None).unwrap().layout.main_js,
+            "dist/main.js"
+        );
+        assert_eq!(set.select("5.40.1", None).unwrap().name, "old");
+        assert_eq!(
+            set.select("5.40.1", None).unwrap().layout.main_js,
+            "main/index.js"
+        );
+        assert_eq!(set.select("6.1.0", Some("old")).unwrap().name, "old");
+        assert!(set.select("6.1.0", Some("missing")).is_err());
+        assert_eq!(set.select("garbage", None).unwrap().name, "old");
+    }
+
+    #[test]
+    fn test_apply_rules() {
+        let temp = tempfile::tempdir().unwrap();
+        let chunk_dir = temp.path().join("app").join("_next");
+        fs::create_dir_all(&chunk_dir).unwrap();
+        fs::write(chunk_dir.join("chunk.js"), "a={hasPlus:!1,canUseHq:!1}").unwrap();
+        fs::write(temp.path().join("app").join("page.css"), "hasPlus:!1").unwrap();
+
+        let rules = [
+            rule("plus", "hasPlus:!1", "hasPlus:!0", false),
+            rule("hq", r"(canUseHq):!1", "${1}:!0", true),
+            rule("missing", "nothing-here", "x", false),
+        ];
+        let refs: Vec<&PatchRule> = rules.iter().collect();
+
+        let dry = apply_rules(temp.path(), &refs, true).unwrap();
+        assert_eq!(dry[0].matches, 1);
+        assert_eq!(
+            fs::read_to_string(chunk_dir.join("chunk.js")).unwrap(),
+            "a={hasPlus:!1,canUseHq:!1}"
+        );
+
+        let outcomes = apply_rules(temp.path(), &refs, false).unwrap();
+        assert_eq!(outcomes[0].files, vec!["app/_next/chunk.js".to_string()]);
+        assert_eq!(outcomes[1].matches, 1);
+        assert_eq!(outcomes[2].matches, 0);
+        assert_eq!(
+            fs::read_to_string(chunk_dir.join("chunk.js")).unwrap(),
+            "a={hasPlus:!0,canUseHq:!0}"
+        );
+        assert_eq!(
+            fs::read_to_string(temp.path().join("app").join("page.css")).unwrap(),
+            "hasPlus:!1"
+        );
+    }
+}
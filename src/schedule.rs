@@ -0,0 +1,386 @@
+//! Schedule module - periodic update checks without a running patcher
+//!
+//! `schedule install` registers a task that runs `check-update --json --patch`
+//! every interval, so new releases are patched in the background without
+//! `watch` or the tray staying up: a Task Scheduler task on Windows, a systemd
+//! user timer on Linux. The task starts this executable where it is now, with
+//! the config file, channel and output directory given at install time.
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+use tracing::info;
+
+/// Name of the scheduled task and of the systemd units
+pub const TASK_NAME: &str = "yandex-music-mod-update";
+
+/// Shortest interval Task Scheduler repeats a task at
+const MIN_INTERVAL: Duration = Duration::from_secs(60);
+
+/// A registered update check
+#[derive(Debug, Clone, Serialize)]
+pub struct Schedule {
+    pub name: String,
+    #[serde(serialize_with = "serialize_duration")]
+    pub interval: Duration,
+    /// Program and arguments run on every check
+    pub command: Vec<String>,
+    /// Task definition or unit files written
+    pub files: Vec<PathBuf>,
+}
+
+fn serialize_duration<S: serde::Serializer>(
+    duration: &Duration,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&humantime::format_duration(*duration).to_string())
+}
+
+/// Command line of a scheduled check: this executable running `check-update`
+/// non-interactively with the given config file, channel and output directory
+pub fn check_command(config: Option<&Path>, channel: &str, output: &Path) -> Result<Vec<String>> {
+    let exe = std::env::current_exe().context("Cannot locate the patcher executable")?;
+    let mut command = vec![exe.to_string_lossy().into_owned()];
+    if crate::config::portable_dir().is_some() {
+        command.push("--portable".to_string());
+    }
+    if let Some(config) = config {
+        let config = std::path::absolute(config)?;
+        command.extend([
+            "--config".to_string(),
+            config.to_string_lossy().into_owned(),
+        ]);
+    }
+    let output = std::path::absolute(output)?;
+    command.extend(
+        [
+            "--yes",
+            "--channel",
+            channel,
+            "check-update",
+            "--json",
+            "--patch",
+            "--output",
+        ]
+        .map(String::from),
+    );
+    command.push(output.to_string_lossy().into_owned());
+    Ok(command)
+}
+
+/// Register `command` to run every `interval`, replacing an earlier schedule
+pub fn install(command: Vec<String>, interval: Duration) -> Result<Schedule> {
+    anyhow::ensure!(
+        interval >= MIN_INTERVAL,
+        "The check interval must be at least {}",
+        humantime::format_duration(MIN_INTERVAL)
+    );
+    let files = register(&command, interval)?;
+    info!(
+        "Scheduled an update check every {}",
+        humantime::format_duration(interval)
+    );
+    Ok(Schedule {
+        name: TASK_NAME.to_string(),
+        interval,
+        command,
+        files,
+    })
+}
+
+/// Unregister the update check; returns the files removed
+pub fn remove() -> Result<Vec<PathBuf>> {
+    let files = unregister()?;
+    info!("Removed the scheduled update check");
+    Ok(files)
+}
+
+/// Run a scheduler tool, failing with its output
+fn run(program: &str, args: &[&str]) -> Result<()> {
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .with_context(|| format!("Failed to run {}", program))?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "{} {} failed: {}",
+            program,
+            args.first().copied().unwrap_or_default(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+fn register(command: &[String], interval: Duration) -> Result<Vec<PathBuf>> {
+    // A task definition sets any interval and has no length limit on the command
+    let path = std::env::temp_dir().join(format!("{}.xml", TASK_NAME));
+    let xml: Vec<u8> = std::iter::once(0xFEFF)
+        .chain(task_xml(command, interval).encode_utf16())
+        .flat_map(u16::to_le_bytes)
+        .collect();
+    std::fs::write(&path, xml)?;
+    let result = run(
+        "schtasks",
+        &[
+            "/Create",
+            "/TN",
+            TASK_NAME,
+            "/XML",
+            &path.to_string_lossy(),
+            "/F",
+        ],
+    );
+    let _ = std::fs::remove_file(&path);
+    result?;
+    Ok(Vec::new())
+}
+
+#[cfg(windows)]
+fn unregister() -> Result<Vec<PathBuf>> {
+    run("schtasks", &["/Delete", "/TN", TASK_NAME, "/F"])?;
+    Ok(Vec::new())
+}
+
+/// Directory of the systemd user units
+#[cfg(all(unix, not(target_os = "macos")))]
+fn unit_dir() -> Result<PathBuf> {
+    directories::BaseDirs::new()
+        .map(|dirs| dirs.config_dir().join("systemd/user"))
+        .context("Cannot locate the systemd user unit directory")
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn register(command: &[String], interval: Duration) -> Result<Vec<PathBuf>> {
+    let dir = unit_dir()?;
+    std::fs::create_dir_all(&dir)?;
+    let service = dir.join(format!("{}.service", TASK_NAME));
+    let timer = dir.join(format!("{}.timer", TASK_NAME));
+    std::fs::write(&service, service_unit(command))?;
+    std::fs::write(&timer, timer_unit(interval))?;
+
+    run("systemctl", &["--user", "daemon-reload"])?;
+    run(
+        "systemctl",
+        &["--user", "enable", "--now", &format!("{}.timer", TASK_NAME)],
+    )?;
+    Ok(vec![service, timer])
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn unregister() -> Result<Vec<PathBuf>> {
+    let dir = unit_dir()?;
+    let files: Vec<PathBuf> = ["service", "timer"]
+        .iter()
+        .map(|kind| dir.join(format!("{}.{}", TASK_NAME, kind)))
+        .filter(|path| path.exists())
+        .collect();
+    anyhow::ensure!(!files.is_empty(), "No update check is scheduled");
+
+    // The timer may already be gone from systemd; the files go either way
+    let _ = run(
+        "systemctl",
+        &[
+            "--user",
+            "disable",
+            "--now",
+            &format!("{}.timer", TASK_NAME),
+        ],
+    );
+    for file in &files {
+        std::fs::remove_file(file)?;
+    }
+    run("systemctl", &["--user", "daemon-reload"])?;
+    Ok(files)
+}
+
+#[cfg(not(any(windows, all(unix, not(target_os = "macos")))))]
+fn register(_command: &[String], _interval: Duration) -> Result<Vec<PathBuf>> {
+    anyhow::bail!("Scheduled update checks need Task Scheduler (Windows) or systemd (Linux)")
+}
+
+#[cfg(not(any(windows, all(unix, not(target_os = "macos")))))]
+fn unregister() -> Result<Vec<PathBuf>> {
+    anyhow::bail!("Scheduled update checks need Task Scheduler (Windows) or systemd (Linux)")
+}
+
+/// Task Scheduler definition running `command` every `interval` while the
+/// user is logged on, catching up on missed runs
+pub fn task_xml(command: &[String], interval: Duration) -> String {
+    let (program, args) = command.split_first().expect("empty command");
+    let args: Vec<String> = args.iter().map(|arg| windows_quote(arg)).collect();
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-16"?>
+<Task version="1.2" xmlns="http://schemas.microsoft.com/windows/2004/02/mit/task">
+  <RegistrationInfo>
+    <Description>Patches new Yandex Music releases (yandex-music-mod)</Description>
+  </RegistrationInfo>
+  <Triggers>
+    <TimeTrigger>
+      <StartBoundary>2020-01-01T00:00:00</StartBoundary>
+      <Repetition>
+        <Interval>{}</Interval>
+        <StopAtDurationEnd>false</StopAtDurationEnd>
+      </Repetition>
+      <Enabled>true</Enabled>
+    </TimeTrigger>
+  </Triggers>
+  <Settings>
+    <MultipleInstancesPolicy>IgnoreNew</MultipleInstancesPolicy>
+    <DisallowStartIfOnBatteries>false</DisallowStartIfOnBatteries>
+    <StopIfGoingOnBatteries>false</StopIfGoingOnBatteries>
+    <StartWhenAvailable>true</StartWhenAvailable>
+    <RunOnlyIfNetworkAvailable>true</RunOnlyIfNetworkAvailable>
+    <ExecutionTimeLimit>PT2H</ExecutionTimeLimit>
+    <Hidden>true</Hidden>
+  </Settings>
+  <Actions Context="Author">
+    <Exec>
+      <Command>{}</Command>
+      <Arguments>{}</Arguments>
+    </Exec>
+  </Actions>
+</Task>
+"#,
+        iso_duration(interval),
+        xml_escape(program),
+        xml_escape(&args.join(" "))
+    )
+}
+
+/// systemd service running `command` once
+pub fn service_unit(command: &[String]) -> String {
+    let command: Vec<String> = command.iter().map(|arg| systemd_quote(arg)).collect();
+    format!(
+        "[Unit]\n\
+         Description=Patch new Yandex Music releases (yandex-music-mod)\n\
+         Wants=network-online.target\n\
+         After=network-online.target\n\
+         \n\
+         [Service]\n\
+         Type=oneshot\n\
+         ExecStart={}\n",
+        command.join(" ")
+    )
+}
+
+/// systemd timer starting the service every `interval`, first shortly after login
+pub fn timer_unit(interval: Duration) -> String {
+    format!(
+        "[Unit]\n\
+         Description=Check for Yandex Music releases every {}\n\
+         \n\
+         [Timer]\n\
+         OnStartupSec=5min\n\
+         OnUnitActiveSec={}s\n\
+         \n\
+         [Install]\n\
+         WantedBy=timers.target\n",
+        humantime::format_duration(interval),
+        interval.as_secs()
+    )
+}
+
+/// ISO 8601 duration in whole minutes, as Task Scheduler takes intervals
+fn iso_duration(interval: Duration) -> String {
+    let minutes = interval.as_secs() / 60;
+    let (days, hours, minutes) = (minutes / 1440, minutes / 60 % 24, minutes % 60);
+    let mut iso = "P".to_string();
+    if days > 0 {
+        iso.push_str(&format!("{}D", days));
+    }
+    if hours > 0 || minutes > 0 {
+        iso.push('T');
+        if hours > 0 {
+            iso.push_str(&format!("{}H", hours));
+        }
+        if minutes > 0 {
+            iso.push_str(&format!("{}M", minutes));
+        }
+    }
+    iso
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Quote an argument for a Windows command line (`CommandLineToArgvW` rules)
+pub(crate) fn windows_quote(arg: &str) -> String {
+    if !arg.is_empty() && !arg.contains([' ', '\t', '"']) {
+        return arg.to_string();
+    }
+    let mut quoted = "\"".to_string();
+    let mut backslashes = 0;
+    for c in arg.chars() {
+        match c {
+            '\\' => backslashes += 1,
+            '"' => {
+                quoted.push_str(&"\\".repeat(backslashes * 2 + 1));
+                quoted.push(c);
+                backslashes = 0;
+            }
+            _ => {
+                quoted.push_str(&"\\".repeat(backslashes));
+                quoted.push(c);
+                backslashes = 0;
+            }
+        }
+    }
+    // Backslashes before the closing quote are doubled
+    quoted.push_str(&"\\".repeat(backslashes * 2));
+    quoted.push('"');
+    quoted
+}
+
+/// Quote an argument for `ExecStart`, where `%` starts a specifier
+fn systemd_quote(arg: &str) -> String {
+    format!(
+        "\"{}\"",
+        arg.replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('%', "%%")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schedule_definitions() {
+        let command: Vec<String> = [
+            r"C:\Program Files\ymm\yandex-music-mod.exe",
+            "check-update",
+            "--output",
+            r"D:\Music builds\",
+            "say \"hi\"",
+        ]
+        .map(String::from)
+        .to_vec();
+
+        let xml = task_xml(&command, Duration::from_secs(6 * 3600));
+        assert!(xml.contains("<Interval>PT6H</Interval>"));
+        assert!(xml.contains(r"<Command>C:\Program Files\ymm\yandex-music-mod.exe</Command>"));
+        assert!(xml.contains(
+            r#"<Arguments>check-update --output &quot;D:\Music builds\\&quot; &quot;say \&quot;hi\&quot;&quot;</Arguments>"#
+        ));
+        assert_eq!(iso_duration(Duration::from_secs(90_000)), "P1DT1H");
+        assert_eq!(iso_duration(Duration::from_secs(1800)), "PT30M");
+
+        let service = service_unit(&command[..2]);
+        assert!(service.contains(
+            r#"ExecStart="C:\\Program Files\\ymm\\yandex-music-mod.exe" "check-update""#
+        ));
+        assert_eq!(systemd_quote("50%"), "\"50%%\"");
+        assert!(timer_unit(Duration::from_secs(3600)).contains("OnUnitActiveSec=3600s"));
+
+        assert!(install(command, Duration::from_secs(10)).is_err());
+    }
+}
@@ -0,0 +1,146 @@
+//! Self-update module - replaces the patcher with the latest GitHub release
+//!
+//! Release binaries carry the tag they were published under (`YMM_RELEASE_TAG`,
+//! set by CI); the release's `SHA256SUMS` is used to verify the download.
+
+use crate::api;
+use crate::i18n::tr;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::info;
+
+/// GitHub API endpoint of this project's latest release
+const LATEST_RELEASE_URL: &str =
+    "https://api.github.com/repos/Jhon-Crow/YandexMusicBetaModeFastLP/releases/latest";
+
+/// Name of the checksum file attached to every release
+const CHECKSUMS_ASSET: &str = "SHA256SUMS";
+
+/// Release tag this binary was built for; `None` for local builds
+pub const CURRENT_TAG: Option<&str> = option_env!("YMM_RELEASE_TAG");
+
+/// Release asset holding the binary for this platform
+const PLATFORM_ASSET: &str = if cfg!(all(target_os = "windows", feature = "windows-gui")) {
+    "yandex-music-mod-windows-x64-gui.exe"
+} else if cfg!(target_os = "windows") {
+    "yandex-music-mod-windows-x64.exe"
+} else if cfg!(target_os = "macos") {
+    "yandex-music-mod-macos-x64"
+} else {
+    "yandex-music-mod-linux-x64"
+};
+
+#[derive(Debug, Deserialize)]
+struct Release {
+    tag_name: String,
+    assets: Vec<ReleaseAsset>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReleaseAsset {
+    name: String,
+    browser_download_url: String,
+}
+
+impl Release {
+    fn asset_url(&self, name: &str) -> Result<&str> {
+        self.assets
+            .iter()
+            .find(|a| a.name == name)
+            .map(|a| a.browser_download_url.as_str())
+            .with_context(|| format!("Release {} has no {} asset", self.tag_name, name))
+    }
+}
+
+/// Outcome of `self-update`
+#[derive(Debug, Serialize)]
+pub struct UpdateStatus {
+    pub current: Option<String>,
+    pub latest: String,
+    pub updated: bool,
+}
+
+/// Check the latest release and, unless `check_only`, install it.
+///
+/// Local builds (without a release tag) are only replaced with `force`.
+pub async fn self_update(check_only: bool, force: bool) -> Result<UpdateStatus> {
+    let release: Release = serde_json::from_str(&api::fetch_text(LATEST_RELEASE_URL).await?)
+        .context("Failed to parse GitHub release")?;
+    let mut status = UpdateStatus {
+        current: CURRENT_TAG.map(str::to_string),
+        latest: release.tag_name.clone(),
+        updated: false,
+    };
+
+    match CURRENT_TAG {
+        Some(tag) if tag == release.tag_name && !force => {
+            info!("Already up to date ({})", tag);
+            return Ok(status);
+        }
+        None if !force && !check_only => {
+            anyhow::bail!(tr!(
+                "This is a local build without a release tag; use --force to replace it with {}",
+                "Это локальная сборка без тега релиза; используйте --force, чтобы заменить её на {}",
+                release.tag_name
+            ));
+        }
+        _ => {}
+    }
+    if check_only {
+        info!("Update available: {}", release.tag_name);
+        return Ok(status);
+    }
+
+    info!("Downloading {} from {}", PLATFORM_ASSET, release.tag_name);
+    let binary = api::fetch_bytes(release.asset_url(PLATFORM_ASSET)?).await?;
+    let checksums = api::fetch_text(release.asset_url(CHECKSUMS_ASSET)?).await?;
+    verify_checksum(&binary, &checksums, PLATFORM_ASSET)?;
+
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join(PLATFORM_ASSET);
+    std::fs::write(&path, &binary)?;
+    self_replace::self_replace(&path).context("Failed to replace the running executable")?;
+
+    info!("Updated to {}", release.tag_name);
+    status.updated = true;
+    Ok(status)
+}
+
+/// Check `data` against the entry for `name` in a `sha256sum`-style listing
+fn verify_checksum(data: &[u8], checksums: &str, name: &str) -> Result<()> {
+    let expected = checksums
+        .lines()
+        .filter_map(|line| line.split_once(char::is_whitespace))
+        .find(|(_, file)| file.trim_start().trim_start_matches('*') == name)
+        .map(|(hash, _)| hash.to_ascii_lowercase())
+        .with_context(|| format!("No checksum for {} in {}", name, CHECKSUMS_ASSET))?;
+
+    let actual = hex::encode(Sha256::digest(data));
+    if actual != expected {
+        anyhow::bail!(tr!(
+            "Checksum mismatch for {}: expected {}, got {}",
+            "Контрольная сумма {} не совпадает: ожидалась {}, получена {}",
+            name,
+            expected,
+            actual
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify_checksum() {
+        let data = b"binary";
+        let hash = hex::encode(Sha256::digest(data));
+        let checksums = format!("{}  other\n{} *{}\n", "0".repeat(64), hash, PLATFORM_ASSET);
+
+        assert!(verify_checksum(data, &checksums, PLATFORM_ASSET).is_ok());
+        assert!(verify_checksum(b"tampered", &checksums, PLATFORM_ASSET).is_err());
+        assert!(verify_checksum(data, &checksums, "missing").is_err());
+    }
+}
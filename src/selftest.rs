@@ -0,0 +1,188 @@
+//! Selftest module - runs the patches against bundled fixtures
+//!
+//! Trimmed copies of the files the patcher targets (`fixtures/selftest`) are
+//! compiled into the binary. `selftest` patches them through the same stages as
+//! a real run with the built-in profiles and checks the result, so users can
+//! confirm a patcher build works before touching a real install.
+
+use crate::patcher::{self, PatchOptions};
+use crate::rules::ProfileSet;
+use anyhow::Result;
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+
+/// App version the fixtures were trimmed from
+pub const FIXTURE_VERSION: &str = "5.40.1";
+
+/// Fixture files by path relative to the app root
+const FIXTURES: [(&str, &str); 8] = [
+    (
+        "package.json",
+        include_str!("../fixtures/selftest/package.json"),
+    ),
+    (
+        "main/config.js",
+        include_str!("../fixtures/selftest/main/config.js"),
+    ),
+    (
+        "main/index.js",
+        include_str!("../fixtures/selftest/main/index.js"),
+    ),
+    (
+        "main/lib/preload.js",
+        include_str!("../fixtures/selftest/main/lib/preload.js"),
+    ),
+    (
+        "main/lib/createWindow.js",
+        include_str!("../fixtures/selftest/main/lib/createWindow.js"),
+    ),
+    (
+        "main/lib/systemMenu.js",
+        include_str!("../fixtures/selftest/main/lib/systemMenu.js"),
+    ),
+    (
+        "app/index.html",
+        include_str!("../fixtures/selftest/app/index.html"),
+    ),
+    (
+        "app/main.js",
+        include_str!("../fixtures/selftest/app/main.js"),
+    ),
+];
+
+/// Expected output: file, text it must (`true`) or must not (`false`) contain
+const EXPECTED: [(&str, &str, bool); 16] = [
+    ("package.json", r#""name": "YandexMusicMod""#, true),
+    ("package.json", "@yandex-chats/signer", false),
+    ("main/config.js", "enableDevTools: true", true),
+    ("main/config.js", "enableAutoUpdate: true", false),
+    ("main/lib/systemMenu.js", "if (enableSystemToolbar)", true),
+    (
+        "main/lib/createWindow.js",
+        "titleBarStyle: !enableSystemToolbar && 'hidden'",
+        true,
+    ),
+    ("main/lib/createWindow.js", "show: true", true),
+    ("main/lib/createWindow.js", "minWidth: 768", false),
+    ("main/lib/createWindow.js", "devTools: true", true),
+    ("main/index.js", "onBeforeRequest", true),
+    ("main/index.js", "// YandexMusicMod main.js", true),
+    ("main/lib/preload.js", "// YandexMusicMod preload.js", true),
+    ("app/index.html", "/yandexMusicMod/renderer.js", true),
+    ("app/main.js", "hasPlus:!1", false),
+    ("app/yandexMusicMod/renderer.js", "", true),
+    ("app/yandexMusicMod/renderer.css", "", true),
+];
+
+/// Result of a single selftest check
+#[derive(Debug, Clone, Serialize)]
+pub struct Check {
+    pub name: String,
+    pub ok: bool,
+    pub detail: String,
+}
+
+/// Outcome of `selftest`
+#[derive(Debug, Clone, Serialize)]
+pub struct SelftestReport {
+    pub profile: String,
+    pub checks: Vec<Check>,
+}
+
+impl SelftestReport {
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(|c| c.ok)
+    }
+}
+
+/// Patch the fixtures in a temporary directory and check the output
+pub fn run() -> Result<SelftestReport> {
+    let temp = tempfile::tempdir()?;
+    let app_dir = temp.path();
+    for (path, content) in FIXTURES {
+        let path = app_dir.join(path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, content)?;
+    }
+
+    let profiles = ProfileSet::builtin()?;
+    let profile = profiles.select(FIXTURE_VERSION, None)?;
+    let options = PatchOptions::default();
+    let (anchors, rules, html) =
+        patcher::patch_app(app_dir, profile, FIXTURE_VERSION, &options, None)?;
+
+    let mut checks = Vec::new();
+    for (kind, outcomes) in [("anchor", anchors), ("rule", rules)] {
+        for outcome in outcomes {
+            checks.push(Check {
+                name: format!("{} {}", kind, outcome.rule),
+                ok: outcome.matches > 0,
+                detail: format!("{} match(es)", outcome.matches),
+            });
+        }
+    }
+    for outcome in html {
+        checks.push(Check {
+            name: format!("html {}", outcome.path),
+            ok: outcome.injected,
+            detail: if outcome.injected {
+                "injected"
+            } else {
+                "no <head>"
+            }
+            .to_string(),
+        });
+    }
+    for (file, text, present) in EXPECTED {
+        checks.push(expect(app_dir, file, text, present));
+    }
+
+    Ok(SelftestReport {
+        profile: profile.name.clone(),
+        checks,
+    })
+}
+
+fn expect(app_dir: &Path, file: &str, text: &str, present: bool) -> Check {
+    let (ok, detail) = match fs::read_to_string(app_dir.join(file)) {
+        Err(e) => (false, format!("cannot read: {}", e)),
+        Ok(_) if text.is_empty() => (true, "exists".to_string()),
+        Ok(content) => {
+            let ok = content.contains(text) == present;
+            let verb = if present { "contains" } else { "lacks" };
+            (ok, format!("{} {:?}", verb, text))
+        }
+    };
+    Check {
+        name: file.to_string(),
+        ok,
+        detail,
+    }
+}
+
+/// Print the checks as a table
+pub fn print_report(report: &SelftestReport) {
+    for check in &report.checks {
+        let status = if check.ok { "OK  " } else { "FAIL" };
+        println!("[{}] {}: {}", status, check.name, check.detail);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_selftest_passes() {
+        let report = run().unwrap();
+        let failed: Vec<_> = report.checks.iter().filter(|c| !c.ok).collect();
+        assert!(failed.is_empty(), "failed checks: {:?}", failed);
+        assert!(report
+            .checks
+            .iter()
+            .any(|c| c.name == "rule account-has-plus"));
+    }
+}
@@ -0,0 +1,356 @@
+//! Serve module - a small REST API to trigger and monitor patch runs
+//!
+//! | Endpoint      | Description                                          |
+//! |---------------|------------------------------------------------------|
+//! | `GET /latest` | Latest builds of the channel                         |
+//! | `POST /patch` | Start patching the latest build (`409` while running) |
+//! | `GET /status` | Current or last run with stage and progress          |
+//! | `GET /builds` | Patched builds in the output directory               |
+//!
+//! `POST /patch` needs `Authorization: Bearer <token>` with the token made at
+//! startup, which is logged and written to `serve-token` in the data
+//! directory. Requests with an `Origin` header are refused, so web pages
+//! cannot start runs. Only built with the `serve` feature.
+
+use crate::api::{self, AppBuild};
+use crate::config::{self, Config};
+use crate::error;
+use crate::notify::Outcome;
+use crate::patcher;
+use crate::progress::{ProgressState, Snapshot};
+use crate::versions;
+use anyhow::{Context, Result};
+use hyper::header::{AUTHORIZATION, ORIGIN};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, StatusCode};
+use serde::Serialize;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tracing::{error, info};
+
+/// Default port of `serve`
+pub const DEFAULT_PORT: u16 = 8765;
+
+/// File in the data directory with the token of the running `serve`
+pub const TOKEN_FILE: &str = "serve-token";
+
+/// A patch run started through the API
+#[derive(Debug, Clone, Serialize)]
+pub struct Run {
+    /// `None` until the latest build is known
+    pub version: Option<String>,
+    /// `None` while the run is in progress
+    pub result: Option<Outcome>,
+    /// Run time in seconds, once finished
+    pub duration: Option<f64>,
+    pub error: Option<String>,
+    /// [`error::code`] of a failed run
+    pub code: Option<u8>,
+    #[serde(skip)]
+    started: Instant,
+}
+
+/// Body of `GET /status`
+#[derive(Debug, Serialize)]
+struct Status {
+    running: bool,
+    run: Option<Run>,
+    progress: Snapshot,
+    message: String,
+}
+
+struct Server {
+    config: Config,
+    channel: String,
+    output: PathBuf,
+    progress: ProgressState,
+    run: Mutex<Option<Run>>,
+    /// Bearer token required to start runs
+    token: String,
+}
+
+/// Serve the API on `addr` until interrupted; runs patch `channel` into `output`
+pub async fn serve(
+    config: Config,
+    channel: String,
+    output: PathBuf,
+    addr: SocketAddr,
+) -> Result<()> {
+    let token = generate_token()?;
+    match config::data_dir() {
+        Some(dir) => {
+            let path = write_token(&dir, &token)?;
+            info!(
+                "Access token for POST /patch (also in {:?}): {}",
+                path, token
+            );
+        }
+        None => info!("Access token for POST /patch: {}", token),
+    }
+    let server = Arc::new(Server {
+        config,
+        channel,
+        output,
+        progress: ProgressState::default(),
+        run: Mutex::new(None),
+        token,
+    });
+
+    let make_service = make_service_fn(move |_| {
+        let server = Arc::clone(&server);
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                let server = Arc::clone(&server);
+                async move { Ok::<_, Infallible>(handle(server, request).await) }
+            }))
+        }
+    });
+
+    let listener =
+        hyper::Server::try_bind(&addr).with_context(|| format!("Failed to listen on {}", addr))?;
+    info!("Serving the API on http://{}", addr);
+    listener.serve(make_service).await?;
+    Ok(())
+}
+
+async fn handle(server: Arc<Server>, request: Request<Body>) -> Response<Body> {
+    let result = match (request.method(), request.uri().path()) {
+        (&Method::GET, "/latest") => api::get_latest_build(&server.channel)
+            .await
+            .map(|builds| json(StatusCode::OK, &builds)),
+        (&Method::POST, "/patch") => Ok(server
+            .authorize(&request)
+            .unwrap_or_else(|| start_patch(&server))),
+        (&Method::GET, "/status") => Ok(json(StatusCode::OK, &server.status())),
+        (&Method::GET, "/builds") => {
+            versions::scan(&server.output).map(|builds| json(StatusCode::OK, &builds))
+        }
+        (_, "/latest" | "/patch" | "/status" | "/builds") => Ok(error_response(
+            StatusCode::METHOD_NOT_ALLOWED,
+            "Method not allowed",
+        )),
+        _ => Ok(error_response(StatusCode::NOT_FOUND, "Not found")),
+    };
+    result.unwrap_or_else(|e| {
+        error!("{} {} failed: {:#}", request.method(), request.uri(), e);
+        json(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &serde_json::json!({ "error": format!("{:#}", e), "code": error::code(&e) }),
+        )
+    })
+}
+
+/// A random token of 32 bytes in hex
+fn generate_token() -> Result<String> {
+    let mut bytes = [0u8; 32];
+    getrandom::getrandom(&mut bytes).context("Failed to generate the access token")?;
+    Ok(hex::encode(bytes))
+}
+
+/// Write `token` to [`TOKEN_FILE`] in `dir`, readable only by the user
+fn write_token(dir: &Path, token: &str) -> Result<PathBuf> {
+    let path = dir.join(TOKEN_FILE);
+    std::fs::create_dir_all(dir)?;
+    // Temporary files are created with owner-only permissions
+    let mut file = tempfile::NamedTempFile::new_in(dir)?;
+    std::io::Write::write_all(&mut file, token.as_bytes())?;
+    file.persist(&path)
+        .with_context(|| format!("Failed to write {:?}", path))?;
+    Ok(path)
+}
+
+/// Start a patch run in the background unless one is in progress
+fn start_patch(server: &Arc<Server>) -> Response<Body> {
+    let mut run = server.run.lock().unwrap();
+    if run.as_ref().is_some_and(|run| run.result.is_none()) {
+        return error_response(StatusCode::CONFLICT, "A patch run is already in progress");
+    }
+
+    server.progress.reset();
+    *run = Some(Run {
+        version: None,
+        result: None,
+        duration: None,
+        error: None,
+        code: None,
+        started: Instant::now(),
+    });
+
+    let task = Arc::clone(server);
+    tokio::spawn(async move {
+        let result = task.patch_latest().await;
+        if let Err(e) = &result {
+            error!("Patch run failed: {:#}", e);
+        }
+        if let Some(run) = task.run.lock().unwrap().as_mut() {
+            run.duration = Some(run.started.elapsed().as_secs_f64());
+            run.result = Some(match result {
+                Ok(()) => Outcome::Success,
+                Err(_) => Outcome::Failure,
+            });
+            run.code = result.as_ref().err().map(error::code);
+            run.error = result.err().map(|e| format!("{:#}", e));
+        }
+    });
+
+    json(StatusCode::ACCEPTED, &*run)
+}
+
+impl Server {
+    /// The error response for a request that may not start a run, or `None`
+    /// if it carries the token and does not come from a web page
+    fn authorize(&self, request: &Request<Body>) -> Option<Response<Body>> {
+        if request.headers().contains_key(ORIGIN) {
+            return Some(error_response(
+                StatusCode::FORBIDDEN,
+                "Cross-origin requests are not allowed",
+            ));
+        }
+        let expected = format!("Bearer {}", self.token);
+        match request.headers().get(AUTHORIZATION) {
+            Some(value) if value.as_bytes() == expected.as_bytes() => None,
+            _ => Some(error_response(
+                StatusCode::UNAUTHORIZED,
+                "Missing or wrong access token",
+            )),
+        }
+    }
+
+    async fn patch_latest(&self) -> Result<()> {
+        let build: AppBuild = api::get_latest_build(&self.channel)
+            .await?
+            .into_iter()
+            .next()
+            .context("No builds found")?;
+        if let Some(run) = self.run.lock().unwrap().as_mut() {
+            run.version = Some(build.version.clone());
+        }
+
+        info!("Patching Yandex Music {} for an API request", build.version);
+        let options = self.config.patch_options()?;
+        patcher::process_build(&build, &self.output, &options, Some(&self.progress)).await?;
+        Ok(())
+    }
+
+    fn status(&self) -> Status {
+        let run = self.run.lock().unwrap().clone();
+        let progress = self.progress.snapshot();
+        Status {
+            running: run.as_ref().is_some_and(|run| run.result.is_none()),
+            run,
+            message: progress.message(),
+            progress,
+        }
+    }
+}
+
+fn json<T: Serialize + ?Sized>(status: StatusCode, body: &T) -> Response<Body> {
+    match serde_json::to_vec(body) {
+        Ok(body) => Response::builder()
+            .status(status)
+            .header("Content-Type", "application/json")
+            .body(Body::from(body))
+            .unwrap(),
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()),
+    }
+}
+
+fn error_response(status: StatusCode, message: &str) -> Response<Body> {
+    json(status, &serde_json::json!({ "error": message }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_serve_routes() {
+        let dir = tempfile::tempdir().unwrap();
+        let server = Arc::new(Server {
+            config: Config::default(),
+            channel: api::DEFAULT_CHANNEL.to_string(),
+            output: dir.path().to_path_buf(),
+            progress: ProgressState::default(),
+            run: Mutex::new(None),
+            token: "secret".to_string(),
+        });
+        let request = |method: Method, path: &str| {
+            Request::builder()
+                .method(method)
+                .uri(path)
+                .body(Body::empty())
+                .unwrap()
+        };
+        let patch = |headers: &[(&str, &str)]| {
+            let mut builder = Request::builder().method(Method::POST).uri("/patch");
+            for (name, value) in headers {
+                builder = builder.header(*name, *value);
+            }
+            builder.body(Body::empty()).unwrap()
+        };
+
+        let response = handle(Arc::clone(&server), request(Method::GET, "/status")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let status: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(status["running"], false);
+        assert_eq!(status["progress"]["percent"], 0);
+
+        let response = handle(Arc::clone(&server), request(Method::GET, "/builds")).await;
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"[]");
+
+        let response = handle(Arc::clone(&server), patch(&[])).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let wrong = patch(&[("Authorization", "Bearer guess")]);
+        let response = handle(Arc::clone(&server), wrong).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        // A page on another site posting to the API, even with the token
+        let cross_origin = patch(&[
+            ("Origin", "https://example.com"),
+            ("Authorization", "Bearer secret"),
+        ]);
+        let response = handle(Arc::clone(&server), cross_origin).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert!(server.run.lock().unwrap().is_none());
+
+        *server.run.lock().unwrap() = Some(Run {
+            version: Some("5.0.0".to_string()),
+            result: None,
+            duration: None,
+            error: None,
+            code: None,
+            started: Instant::now(),
+        });
+        let response = handle(
+            Arc::clone(&server),
+            patch(&[("Authorization", "Bearer secret")]),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        let response = handle(Arc::clone(&server), request(Method::DELETE, "/builds")).await;
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+        let response = handle(server, request(Method::GET, "/nope")).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn test_write_token() {
+        let dir = tempfile::tempdir().unwrap();
+        let token = generate_token().unwrap();
+        assert_eq!(token.len(), 64);
+        assert_ne!(token, generate_token().unwrap());
+        let path = write_token(&dir.path().join("data"), &token).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), token);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o077, 0);
+        }
+    }
+}
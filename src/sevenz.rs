@@ -0,0 +1,147 @@
+//! Sevenz module - 7z extraction without 7-Zip
+//!
+//! The Windows installer is an NSIS executable made by electron-builder, which
+//! stores the app as a 7z archive (`app-64.7z`) without recompressing it. The
+//! archive is therefore found in the installer by its signature and read in
+//! place, so neither the NSIS format nor an external 7-Zip is needed. Plain
+//! `.7z` files, such as nested archives, are read the same way. Only built with
+//! the `native-extract` feature.
+
+use anyhow::{Context, Result};
+use sevenz_rust2::{Archive, ArchiveReader, Password};
+use std::fs;
+use std::io::Cursor;
+use std::path::{Component, Path, PathBuf};
+use tracing::{debug, info};
+
+/// Magic bytes at the start of a 7z archive
+const SIGNATURE: &[u8] = b"7z\xBC\xAF\x27\x1C";
+
+/// Signature header: signature, version and the start header
+const SIGNATURE_HEADER_SIZE: usize = 32;
+
+/// Extract every 7z archive in `path` (a `.7z` file or an installer embedding
+/// them) into `output_dir`, the first directly and further ones into
+/// `payload-<n>`. Returns the number of archives extracted.
+pub fn extract(path: &Path, output_dir: &Path) -> Result<usize> {
+    let data = fs::read(path).with_context(|| format!("Failed to read {:?}", path))?;
+    let archives = find_archives(&data);
+    if archives.is_empty() {
+        anyhow::bail!("No 7z archive found in {:?}", path);
+    }
+
+    for (index, &offset) in archives.iter().enumerate() {
+        let dir = if index == 0 {
+            output_dir.to_path_buf()
+        } else {
+            output_dir.join(format!("payload-{}", index))
+        };
+        debug!(
+            "Extracting the 7z archive at offset {} of {:?}",
+            offset, path
+        );
+        extract_archive(&data[offset..], &dir)
+            .with_context(|| format!("Failed to extract the 7z archive in {:?}", path))?;
+    }
+    info!(
+        "Extracted {} 7z archive(s) from {:?} natively",
+        archives.len(),
+        path
+    );
+    Ok(archives.len())
+}
+
+/// Offsets of the valid archives in `data`; a signature only counts if the
+/// archive headers behind it parse, which rules out stray matches
+fn find_archives(data: &[u8]) -> Vec<usize> {
+    let mut archives = Vec::new();
+    let mut start = 0;
+    while let Some(found) = find(&data[start..], SIGNATURE) {
+        let offset = start + found;
+        let mut reader = Cursor::new(&data[offset..]);
+        match Archive::read(&mut reader, &Password::empty()) {
+            Ok(archive) => {
+                archives.push(offset);
+                // Skip the packed streams; they may contain anything
+                let packed: u64 = archive.pack_sizes().iter().sum();
+                start = offset + SIGNATURE_HEADER_SIZE + (archive.pack_pos() + packed) as usize;
+            }
+            Err(_) => start = offset + 1,
+        }
+    }
+    archives
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Extract the archive at the start of `data` into `output_dir`
+fn extract_archive(data: &[u8], output_dir: &Path) -> Result<()> {
+    let mut reader = ArchiveReader::new(Cursor::new(data), Password::empty())?;
+    fs::create_dir_all(output_dir)?;
+    reader.for_each_entries(|entry, content| {
+        let Some(path) = entry_path(output_dir, entry.name()) else {
+            tracing::warn!("Skipping 7z entry {:?} outside the archive", entry.name());
+            return Ok(true);
+        };
+        if entry.is_directory() {
+            fs::create_dir_all(&path)?;
+            return Ok(true);
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = fs::File::create(&path)?;
+        std::io::copy(content, &mut file)?;
+        Ok(true)
+    })?;
+    Ok(())
+}
+
+/// `name` below `output_dir`, or `None` if it would leave it
+fn entry_path(output_dir: &Path, name: &str) -> Option<PathBuf> {
+    // 7z stores Windows separators on some writers
+    let relative = PathBuf::from(name.replace('\\', "/"));
+    relative
+        .components()
+        .all(|c| matches!(c, Component::Normal(_)))
+        .then(|| output_dir.join(relative))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_embedded() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("app");
+        fs::create_dir_all(source.join("resources")).unwrap();
+        fs::write(source.join("resources").join("app.asar"), b"asar").unwrap();
+        fs::write(source.join("Yandex Music.exe"), b"exe").unwrap();
+        let archive = dir.path().join("app-64.7z");
+        sevenz_rust2::compress_to_path(&source, &archive).unwrap();
+
+        // An installer stub around the stored archive, with a stray signature
+        let mut installer = b"MZ stub 7z\xBC\xAF\x27\x1C not an archive".to_vec();
+        installer.extend(fs::read(&archive).unwrap());
+        installer.extend(b"NSIS trailer");
+        let installer_path = dir.path().join("setup.exe");
+        fs::write(&installer_path, installer).unwrap();
+
+        let out = dir.path().join("out");
+        assert_eq!(extract(&installer_path, &out).unwrap(), 1);
+        assert_eq!(
+            fs::read(out.join("resources").join("app.asar")).unwrap(),
+            b"asar"
+        );
+        assert!(out.join("Yandex Music.exe").exists());
+
+        assert!(extract(&source.join("Yandex Music.exe"), &out).is_err());
+        assert!(entry_path(&out, "../evil").is_none());
+        assert!(entry_path(&out, r"resources\app.asar").is_some());
+    }
+}
@@ -0,0 +1,212 @@
+//! Sign module - Authenticode signing of packaged output
+//!
+//! Users with their own code signing certificate can sign what the packaging
+//! stage produces, so SmartScreen trusts the builds on their machines: the
+//! launcher executable shipped in the portable archive and the MSIX (whose
+//! `Publisher` then has to be the certificate's subject), while
+//! electron-builder gets the certificate through its `CSC_LINK` environment.
+//! Files are signed with `signtool` from the Windows SDK, or `osslsigncode`
+//! where it is missing (Linux and macOS).
+//!
+//! signtool only takes the certificate password on its command line (`/p`),
+//! where other processes of the machine can read it while it runs; import the
+//! certificate into the user's store or use a certificate without a password to
+//! avoid that. osslsigncode reads it from a private temporary file instead.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::ffi::OsString;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tracing::info;
+
+/// Timestamp server used unless another is configured
+pub const DEFAULT_TIMESTAMP_URL: &str = "http://timestamp.digicert.com";
+
+/// Environment variable the certificate password is read from
+pub const PASSWORD_ENV: &str = "YMM_SIGN_PASSWORD";
+
+/// Certificate and settings for signing packages
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SignOptions {
+    /// PKCS#12 certificate (`.pfx`/`.p12`) with its private key
+    pub cert: PathBuf,
+    /// Password of the certificate; never written to plans or reports
+    #[serde(skip_serializing)]
+    pub password: Option<String>,
+    /// RFC 3161 timestamp server, so signatures outlive the certificate
+    pub timestamp_url: Option<String>,
+    /// Subject of the certificate (`CN=...`), used as the MSIX `Publisher`
+    pub publisher: Option<String>,
+}
+
+impl SignOptions {
+    /// Sign `file` in place
+    pub fn sign(&self, file: &Path) -> Result<()> {
+        if !self.cert.is_file() {
+            anyhow::bail!("Signing certificate {:?} not found", self.cert);
+        }
+        let password = self
+            .password
+            .clone()
+            .or_else(|| std::env::var(PASSWORD_ENV).ok());
+        let timestamp_url = self
+            .timestamp_url
+            .as_deref()
+            .unwrap_or(DEFAULT_TIMESTAMP_URL);
+
+        let signtool = signtool_args(&self.cert, password.as_deref(), timestamp_url, file);
+        let output = match Command::new("signtool").args(&signtool).output() {
+            Ok(output) => output,
+            Err(_) => {
+                // osslsigncode writes a new file instead of signing in place
+                let signed = file.with_extension("signed");
+                // Kept off the command line; the file is only readable by the user
+                let password_file = password
+                    .as_deref()
+                    .map(|password| -> Result<_> {
+                        let mut password_file = tempfile::NamedTempFile::new()?;
+                        password_file.write_all(password.as_bytes())?;
+                        Ok(password_file)
+                    })
+                    .transpose()?;
+                let args = osslsigncode_args(
+                    &self.cert,
+                    password_file.as_ref().map(|f| f.path()),
+                    timestamp_url,
+                    file,
+                    &signed,
+                );
+                let output = Command::new("osslsigncode")
+                    .args(&args)
+                    .output()
+                    .context("Neither signtool nor osslsigncode found in PATH")?;
+                if output.status.success() {
+                    fs::rename(&signed, file)?;
+                } else {
+                    let _ = fs::remove_file(&signed);
+                }
+                output
+            }
+        };
+        if !output.status.success() {
+            let log = [output.stdout, output.stderr].concat();
+            anyhow::bail!(
+                "Failed to sign {:?}: {}",
+                file,
+                String::from_utf8_lossy(&log).trim()
+            );
+        }
+        info!("Signed {:?}", file);
+        Ok(())
+    }
+
+    /// Environment that makes electron-builder sign with this certificate
+    pub fn electron_builder_env(&self) -> Vec<(&'static str, OsString)> {
+        let mut env = vec![("CSC_LINK", self.cert.clone().into_os_string())];
+        if let Some(password) = self
+            .password
+            .clone()
+            .or_else(|| std::env::var(PASSWORD_ENV).ok())
+        {
+            env.push(("CSC_KEY_PASSWORD", password.into()));
+        }
+        env
+    }
+}
+
+fn signtool_args(
+    cert: &Path,
+    password: Option<&str>,
+    timestamp_url: &str,
+    file: &Path,
+) -> Vec<OsString> {
+    let mut args: Vec<OsString> = vec!["sign".into(), "/fd".into(), "SHA256".into()];
+    args.extend(["/f".into(), cert.into()]);
+    if let Some(password) = password {
+        args.extend(["/p".into(), password.into()]);
+    }
+    args.extend([
+        "/tr".into(),
+        timestamp_url.into(),
+        "/td".into(),
+        "SHA256".into(),
+        file.into(),
+    ]);
+    args
+}
+
+fn osslsigncode_args(
+    cert: &Path,
+    password_file: Option<&Path>,
+    timestamp_url: &str,
+    file: &Path,
+    signed: &Path,
+) -> Vec<OsString> {
+    let mut args: Vec<OsString> = vec!["sign".into(), "-h".into(), "sha256".into()];
+    args.extend(["-pkcs12".into(), cert.into()]);
+    if let Some(password_file) = password_file {
+        args.extend(["-readpass".into(), password_file.into()]);
+    }
+    args.extend([
+        "-ts".into(),
+        timestamp_url.into(),
+        "-in".into(),
+        file.into(),
+        "-out".into(),
+        signed.into(),
+    ]);
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sign_args() {
+        let args = signtool_args(
+            Path::new("me.pfx"),
+            Some("secret"),
+            DEFAULT_TIMESTAMP_URL,
+            Path::new("app.exe"),
+        );
+        assert_eq!(
+            args.join(" ".as_ref()),
+            "sign /fd SHA256 /f me.pfx /p secret /tr http://timestamp.digicert.com /td SHA256 app.exe"
+        );
+        let args = osslsigncode_args(
+            Path::new("me.pfx"),
+            None,
+            "http://ts",
+            Path::new("a.msix"),
+            Path::new("a.signed"),
+        );
+        assert_eq!(
+            args.join(" ".as_ref()),
+            "sign -h sha256 -pkcs12 me.pfx -ts http://ts -in a.msix -out a.signed"
+        );
+        let args = osslsigncode_args(
+            Path::new("me.pfx"),
+            Some(Path::new("pass.txt")),
+            "http://ts",
+            Path::new("a.msix"),
+            Path::new("a.signed"),
+        );
+        assert_eq!(
+            args.join(" ".as_ref()),
+            "sign -h sha256 -pkcs12 me.pfx -readpass pass.txt -ts http://ts -in a.msix -out a.signed"
+        );
+
+        let options = SignOptions {
+            cert: "me.pfx".into(),
+            password: Some("secret".to_string()),
+            ..Default::default()
+        };
+        assert!(!serde_json::to_string(&options).unwrap().contains("secret"));
+        assert!(options.sign(Path::new("app.exe")).is_err());
+    }
+}
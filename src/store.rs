@@ -0,0 +1,241 @@
+//! Store module - Yandex Music installed from the Microsoft Store
+//!
+//! The Store installs the app as an MSIX package under
+//! `%ProgramFiles%\WindowsApps`, which users can read but not change, so it
+//! cannot be patched where it is. `sideload` copies the Electron app out of the
+//! package into a folder of the user and writes the patched `app.asar` into the
+//! copy, as `patch-asar` would. The copy runs as a regular desktop app: its
+//! data goes to `%APPDATA%` instead of the package's private folder, so the
+//! account has to be signed in again. Rerun `sideload` after the Store updates
+//! the app.
+
+use crate::asar::AsarOptions;
+use crate::generic::{self, GenericReport};
+use crate::patcher::PatchOptions;
+use crate::paths;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::info;
+use walkdir::WalkDir;
+
+/// Folder of the sideloaded copy in `%LOCALAPPDATA%\Programs`
+pub const SIDELOAD_DIR_NAME: &str = "YandexMusicMod";
+
+/// Files of the MSIX package itself, left out of the copy
+const PACKAGE_FILES: &[&str] = &[
+    "AppxManifest.xml",
+    "AppxBlockMap.xml",
+    "AppxSignature.p7x",
+    "[Content_Types].xml",
+    "AppxMetadata",
+];
+
+/// How deep below the install location `resources/app.asar` is looked for
+const APP_SEARCH_DEPTH: usize = 4;
+
+/// A Yandex Music package installed from the Store, as `Get-AppxPackage` lists it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct StorePackage {
+    pub name: String,
+    pub package_full_name: String,
+    #[serde(default)]
+    pub version: Option<String>,
+    pub install_location: PathBuf,
+}
+
+impl StorePackage {
+    /// Root of the Electron app in the package: the folder holding
+    /// `resources/app.asar`
+    pub fn app_root(&self) -> Option<PathBuf> {
+        WalkDir::new(&self.install_location)
+            .max_depth(APP_SEARCH_DEPTH)
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .find(|e| e.file_name() == "app.asar")
+            .and_then(|e| e.path().parent()?.parent().map(Path::to_path_buf))
+    }
+}
+
+/// Result of `sideload`
+#[derive(Debug, Clone, Serialize)]
+pub struct SideloadReport {
+    /// The app folder copied
+    pub source: PathBuf,
+    pub target: PathBuf,
+    /// Executable that starts the copy
+    pub executable: Option<PathBuf>,
+    pub patch: GenericReport,
+}
+
+/// Yandex Music packages installed from the Store for the current user
+#[cfg(windows)]
+pub fn packages() -> Vec<StorePackage> {
+    let script = "Get-AppxPackage | Where-Object { $_.Name -match 'Yandex.*Music' } | \
+                  Select-Object Name, PackageFullName, InstallLocation, \
+                  @{ Name = 'Version'; Expression = { $_.Version.ToString() } } | \
+                  ConvertTo-Json";
+    match std::process::Command::new("powershell")
+        .args(["-NoProfile", "-NonInteractive", "-Command", script])
+        .output()
+    {
+        Ok(output) => parse_packages(&String::from_utf8_lossy(&output.stdout)),
+        Err(e) => {
+            tracing::debug!("Cannot list Store packages: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+#[cfg(not(windows))]
+pub fn packages() -> Vec<StorePackage> {
+    Vec::new()
+}
+
+/// Packages in the `ConvertTo-Json` output of `Get-AppxPackage`: one object,
+/// an array of them, or nothing
+#[cfg_attr(not(windows), allow(dead_code))]
+fn parse_packages(json: &str) -> Vec<StorePackage> {
+    let json = json.trim();
+    if json.is_empty() {
+        return Vec::new();
+    }
+    serde_json::from_str::<Vec<StorePackage>>(json)
+        .or_else(|_| serde_json::from_str::<StorePackage>(json).map(|package| vec![package]))
+        .unwrap_or_default()
+}
+
+/// Where the copy goes unless `sideload --to` says otherwise
+pub fn default_target() -> Option<PathBuf> {
+    std::env::var_os("LOCALAPPDATA")
+        .map(|dir| PathBuf::from(dir).join("Programs").join(SIDELOAD_DIR_NAME))
+}
+
+/// Copy the Electron app in `source` to `target` and patch its `app.asar` with
+/// the Yandex Music profile. A copy made earlier is replaced.
+pub fn sideload(
+    source: &Path,
+    target: &Path,
+    options: &PatchOptions,
+    asar_options: &AsarOptions,
+) -> Result<SideloadReport> {
+    let app_asar = source.join("resources").join("app.asar");
+    anyhow::ensure!(
+        app_asar.is_file(),
+        "No Yandex Music app in {:?} (resources/app.asar is missing)",
+        source
+    );
+    if target.exists() {
+        let previous_copy = target.join("resources").join("app.asar").is_file();
+        let empty = fs::read_dir(target)?.next().is_none();
+        anyhow::ensure!(
+            previous_copy || empty,
+            "{:?} is not empty and holds no earlier copy of the app; choose another folder",
+            target
+        );
+        paths::remove_dir_all(target)?;
+    }
+
+    info!("Copying the Store app from {:?} to {:?}", source, target);
+    copy_app(source, target)?;
+    let patch = generic::patch_asar(
+        &app_asar,
+        &target.join("resources").join("app.asar"),
+        None,
+        options,
+        asar_options,
+    )?;
+
+    Ok(SideloadReport {
+        source: source.to_path_buf(),
+        target: target.to_path_buf(),
+        executable: executable(target),
+        patch,
+    })
+}
+
+/// Copy everything but the package files and the `app.asar` that is patched
+/// into the copy
+fn copy_app(source: &Path, target: &Path) -> Result<()> {
+    let skipped_asar = Path::new("resources").join("app.asar");
+    let walker = WalkDir::new(source).into_iter().filter_entry(|e| {
+        let relative = e.path().strip_prefix(source).unwrap_or(e.path());
+        relative != skipped_asar
+            && !(e.depth() == 1 && PACKAGE_FILES.iter().any(|name| e.file_name() == *name))
+    });
+    for entry in walker {
+        let entry = entry?;
+        let relative = entry.path().strip_prefix(source)?;
+        let destination = target.join(relative);
+        if entry.file_type().is_dir() {
+            fs::create_dir_all(&destination)
+                .with_context(|| format!("Failed to create {:?}", destination))?;
+        } else {
+            paths::copy_file(entry.path(), &destination)?;
+        }
+    }
+    Ok(())
+}
+
+/// The app's executable in the copy
+fn executable(dir: &Path) -> Option<PathBuf> {
+    fs::read_dir(dir)
+        .ok()?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("exe"))
+        })
+        .find(|path| {
+            let name = path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_lowercase();
+            !name.starts_with("uninstall") && !name.starts_with("elevate")
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_store_package() {
+        let json = r#"{
+            "Name": "Yandex.Music",
+            "PackageFullName": "Yandex.Music_5.40.0.0_x64__abcdefgh",
+            "InstallLocation": "C:\\Program Files\\WindowsApps\\Yandex.Music_5.40.0.0_x64__abcdefgh",
+            "Version": "5.40.0.0"
+        }"#;
+        let packages = parse_packages(json);
+        assert_eq!(packages.len(), 1);
+        assert_eq!(packages[0].version.as_deref(), Some("5.40.0.0"));
+        assert_eq!(parse_packages(&format!("[{}]", json)).len(), 1);
+        assert!(parse_packages("").is_empty());
+
+        let dir = tempfile::tempdir().unwrap();
+        let install = dir.path().join("package");
+        let app = install.join("app");
+        fs::create_dir_all(app.join("resources").join("app.asar.unpacked")).unwrap();
+        fs::write(app.join("resources").join("app.asar"), "asar").unwrap();
+        fs::write(app.join("Яндекс Музыка.exe"), "").unwrap();
+        fs::write(app.join("Uninstall.exe"), "").unwrap();
+        fs::write(app.join("AppxManifest.xml"), "<Package />").unwrap();
+        let package = StorePackage {
+            install_location: install.clone(),
+            ..packages[0].clone()
+        };
+        assert_eq!(package.app_root(), Some(app.clone()));
+
+        let target = dir.path().join("copy");
+        copy_app(&app, &target).unwrap();
+        assert!(!target.join("AppxManifest.xml").exists());
+        assert!(!target.join("resources/app.asar").exists());
+        assert!(target.join("resources/app.asar.unpacked").is_dir());
+        assert_eq!(executable(&target), Some(target.join("Яндекс Музыка.exe")));
+    }
+}
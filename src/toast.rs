@@ -0,0 +1,138 @@
+//! Toast module - desktop notifications about patch runs and new releases
+//!
+//! Started by double-click, from `watch` or by a scheduled `check-update`, the
+//! patcher has no console anyone looks at. With the `toast` feature it shows a
+//! native notification (a toast on Windows, D-Bus on Linux) when a new Yandex
+//! Music version is found and when a build is patched, the latter with "Open
+//! folder" and "Run" buttons. `notifications = false` in `config.toml` turns
+//! them off.
+
+use crate::i18n::tr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::JoinHandle;
+use tracing::warn;
+
+/// Name the notifications are shown under
+const APP_NAME: &str = "Yandex Music Mod";
+
+/// Action of the "Open folder" button
+const OPEN_ACTION: &str = "open";
+
+/// Action of the "Run" button
+const RUN_ACTION: &str = "run";
+
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Show or suppress the notifications of [`new_version`] and [`patched`]
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+fn notification(summary: &str, body: &str) -> notify_rust::Notification {
+    let mut notification = notify_rust::Notification::new();
+    notification.appname(APP_NAME).summary(summary).body(body);
+    notification
+}
+
+/// Show a notification; failures are only logged
+pub fn show(summary: &str, body: &str) {
+    if let Err(e) = notification(summary, body).show() {
+        warn!("Failed to show a notification: {}", e);
+    }
+}
+
+/// Tell the user that `version` of Yandex Music was released and whether it
+/// is being patched
+pub fn new_version(version: &str, patching: bool) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    let body = if patching {
+        tr!("Patching the new version", "Патчинг новой версии")
+    } else {
+        tr!(
+            "Run \"yandex-music-mod patch\" to patch it",
+            "Запустите «yandex-music-mod patch», чтобы пропатчить её"
+        )
+    };
+    show(
+        &tr!(
+            "Yandex Music {} is available",
+            "Доступна Яндекс Музыка {}",
+            version
+        ),
+        &body,
+    );
+}
+
+/// Tell the user that `version` was patched into `build_dir`, offering to open
+/// the folder or run the app.
+///
+/// The buttons are served by a thread that waits until the notification is
+/// clicked or dismissed; join it before exiting to keep them working.
+pub fn patched(version: &str, build_dir: &Path) -> Option<JoinHandle<()>> {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return None;
+    }
+    let mut notification = patched_notification(version, build_dir);
+    notification
+        .action(OPEN_ACTION, &tr!("Open folder", "Открыть папку"))
+        .action(RUN_ACTION, &tr!("Run", "Запустить"));
+    let build_dir = build_dir.to_path_buf();
+    Some(std::thread::spawn(move || {
+        wait_for_action(notification, build_dir)
+    }))
+}
+
+/// Tell the user that `version` was patched into `build_dir`, without
+/// buttons, for runs that exit right away and nobody waits on (scheduled
+/// checks)
+pub fn patched_notice(version: &str, build_dir: &Path) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    if let Err(e) = patched_notification(version, build_dir).show() {
+        warn!("Failed to show a notification: {}", e);
+    }
+}
+
+fn patched_notification(version: &str, build_dir: &Path) -> notify_rust::Notification {
+    notification(
+        &tr!(
+            "Yandex Music {} is patched",
+            "Яндекс Музыка {} пропатчена",
+            version
+        ),
+        &build_dir.display().to_string(),
+    )
+}
+
+#[cfg(any(windows, all(unix, not(target_os = "macos"))))]
+fn wait_for_action(notification: notify_rust::Notification, build_dir: PathBuf) {
+    let handle = match notification.show() {
+        Ok(handle) => handle,
+        Err(e) => {
+            warn!("Failed to show a notification: {}", e);
+            return;
+        }
+    };
+    handle.wait_for_action(|action| {
+        let result = match action {
+            OPEN_ACTION => crate::paths::open_folder(&build_dir),
+            RUN_ACTION => crate::patcher::launch_app(&build_dir.join("mod")),
+            _ => Ok(()),
+        };
+        if let Err(e) = result {
+            warn!("{:#}", e);
+        }
+    });
+}
+
+/// Notification Center has no buttons for this app
+#[cfg(not(any(windows, all(unix, not(target_os = "macos")))))]
+fn wait_for_action(notification: notify_rust::Notification, _build_dir: PathBuf) {
+    if let Err(e) = notification.show() {
+        warn!("Failed to show a notification: {}", e);
+    }
+}
@@ -0,0 +1,310 @@
+//! Tray module - a tray icon that offers to patch new Yandex Music releases
+//!
+//! Unlike `watch`, which patches every release on its own, `tray` only polls
+//! the update manifest and tells the user: a desktop notification and a menu
+//! entry appear when a version newer than the patched builds in the output
+//! directory ships. "Patch and install" patches it with the `[patch]` options
+//! of `config.toml` and starts it. Only built with the `tray` feature.
+
+use crate::api::{self, AppBuild};
+use crate::config::Config;
+use crate::i18n::tr;
+use crate::patcher;
+use crate::toast;
+use crate::versions;
+use anyhow::Result;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tao::event::{Event, StartCause};
+use tao::event_loop::{ControlFlow, EventLoopBuilder, EventLoopProxy};
+use tokio::runtime::Handle;
+use tracing::{info, warn};
+use tray_icon::menu::{Menu, MenuEvent, MenuItem, PredefinedMenuItem};
+use tray_icon::{Icon, TrayIcon, TrayIconBuilder};
+
+/// Side of the generated tray icon in pixels
+const ICON_SIZE: u32 = 32;
+
+/// Result of an update check
+#[derive(Debug)]
+enum Update {
+    Available(AppBuild),
+    /// The newest release is patched already
+    UpToDate(String),
+}
+
+#[derive(Debug)]
+enum UserEvent {
+    Menu(MenuEvent),
+    Checked(Result<Update, String>),
+    /// A "Patch and install" run finished with the patched version
+    Patched(Result<String, String>),
+}
+
+/// What the tray shows and can do
+struct Tray {
+    config: Arc<Config>,
+    channel: String,
+    output: PathBuf,
+    runtime: Handle,
+    proxy: EventLoopProxy<UserEvent>,
+    status: MenuItem,
+    check: MenuItem,
+    patch: MenuItem,
+    quit: MenuItem,
+    icon: Option<TrayIcon>,
+    available: Option<AppBuild>,
+    /// Last version a notification was shown for
+    notified: Option<String>,
+    patching: bool,
+}
+
+/// Show the tray icon and poll `channel` every `interval` until "Quit"
+pub fn run(config: Config, channel: String, output: PathBuf, interval: Duration) -> Result<()> {
+    let event_loop = EventLoopBuilder::<UserEvent>::with_user_event().build();
+    let proxy = event_loop.create_proxy();
+    let menu_proxy = proxy.clone();
+    MenuEvent::set_event_handler(Some(move |event| {
+        let _ = menu_proxy.send_event(UserEvent::Menu(event));
+    }));
+
+    let mut tray = Tray {
+        config: Arc::new(config),
+        channel,
+        output,
+        runtime: Handle::current(),
+        proxy,
+        status: MenuItem::new(
+            tr!("Checking for updates...", "Проверка обновлений..."),
+            false,
+            None,
+        ),
+        check: MenuItem::new(tr!("Check now", "Проверить сейчас"), true, None),
+        patch: MenuItem::new(
+            tr!("Patch and install", "Пропатчить и установить"),
+            false,
+            None,
+        ),
+        quit: MenuItem::new(tr!("Quit", "Выход"), true, None),
+        icon: None,
+        available: None,
+        notified: None,
+        patching: false,
+    };
+
+    let poll_proxy = tray.proxy.clone();
+    let (channel, output) = (tray.channel.clone(), tray.output.clone());
+    tray.runtime.spawn(async move {
+        loop {
+            let update = check(&channel, &output)
+                .await
+                .map_err(|e| format!("{:#}", e));
+            if poll_proxy.send_event(UserEvent::Checked(update)).is_err() {
+                break;
+            }
+            tokio::time::sleep(interval).await;
+        }
+    });
+
+    event_loop.run(move |event, _, control_flow| {
+        *control_flow = ControlFlow::Wait;
+        match event {
+            // Tray icons must be created once the loop runs (macOS)
+            Event::NewEvents(StartCause::Init) => match tray.build_icon() {
+                Ok(icon) => tray.icon = Some(icon),
+                Err(e) => {
+                    warn!("Failed to create the tray icon: {:#}", e);
+                    *control_flow = ControlFlow::Exit;
+                }
+            },
+            Event::UserEvent(UserEvent::Menu(event)) => {
+                if event.id == *tray.quit.id() {
+                    *control_flow = ControlFlow::Exit;
+                } else if event.id == *tray.check.id() {
+                    tray.check_now();
+                } else if event.id == *tray.patch.id() {
+                    tray.patch_available();
+                }
+            }
+            Event::UserEvent(UserEvent::Checked(update)) => tray.on_checked(update),
+            Event::UserEvent(UserEvent::Patched(result)) => tray.on_patched(result),
+            _ => {}
+        }
+    })
+}
+
+impl Tray {
+    fn build_icon(&self) -> Result<TrayIcon> {
+        let menu = Menu::new();
+        menu.append_items(&[
+            &self.status,
+            &PredefinedMenuItem::separator(),
+            &self.check,
+            &self.patch,
+            &PredefinedMenuItem::separator(),
+            &self.quit,
+        ])?;
+        Ok(TrayIconBuilder::new()
+            .with_menu(Box::new(menu))
+            .with_tooltip("Yandex Music Mod")
+            .with_icon(icon()?)
+            .build()?)
+    }
+
+    fn check_now(&self) {
+        self.status
+            .set_text(tr!("Checking for updates...", "Проверка обновлений..."));
+        let proxy = self.proxy.clone();
+        let (channel, output) = (self.channel.clone(), self.output.clone());
+        self.runtime.spawn(async move {
+            let update = check(&channel, &output)
+                .await
+                .map_err(|e| format!("{:#}", e));
+            let _ = proxy.send_event(UserEvent::Checked(update));
+        });
+    }
+
+    fn on_checked(&mut self, update: Result<Update, String>) {
+        if self.patching {
+            return;
+        }
+        match update {
+            Ok(Update::Available(build)) => {
+                let message = tr!(
+                    "Yandex Music {} is available",
+                    "Доступна Яндекс Музыка {}",
+                    build.version
+                );
+                self.status.set_text(&message);
+                self.set_tooltip(&message);
+                self.patch.set_enabled(true);
+                if self.notified.as_deref() != Some(build.version.as_str()) {
+                    toast::show(
+                        &message,
+                        &tr!(
+                            "Choose \"Patch and install\" in the tray menu",
+                            "Выберите «Пропатчить и установить» в меню в трее"
+                        ),
+                    );
+                    self.notified = Some(build.version.clone());
+                }
+                self.available = Some(build);
+            }
+            Ok(Update::UpToDate(version)) => {
+                let message = tr!(
+                    "Yandex Music {} is patched",
+                    "Яндекс Музыка {} пропатчена",
+                    version
+                );
+                self.status.set_text(&message);
+                self.set_tooltip(&message);
+                self.patch.set_enabled(false);
+                self.available = None;
+            }
+            Err(e) => {
+                warn!("Update check failed: {}", e);
+                self.status.set_text(tr!(
+                    "Update check failed",
+                    "Не удалось проверить обновления"
+                ));
+            }
+        }
+    }
+
+    fn patch_available(&mut self) {
+        let Some(build) = self.available.clone() else {
+            return;
+        };
+        self.patching = true;
+        self.patch.set_enabled(false);
+        self.check.set_enabled(false);
+        self.status.set_text(tr!(
+            "Patching Yandex Music {}...",
+            "Патчинг Яндекс Музыки {}...",
+            build.version
+        ));
+
+        let proxy = self.proxy.clone();
+        let config = self.config.clone();
+        let output = self.output.clone();
+        self.runtime.spawn(async move {
+            let result = patch_and_launch(&config, &build, &output)
+                .await
+                .map(|()| build.version.clone())
+                .map_err(|e| format!("{:#}", e));
+            let _ = proxy.send_event(UserEvent::Patched(result));
+        });
+    }
+
+    fn on_patched(&mut self, result: Result<String, String>) {
+        self.patching = false;
+        self.check.set_enabled(true);
+        match result {
+            Ok(version) => {
+                info!("Successfully patched Yandex Music v{}", version);
+                toast::show(
+                    &tr!(
+                        "Yandex Music {} is patched",
+                        "Яндекс Музыка {} пропатчена",
+                        version
+                    ),
+                    &tr!("Starting the app", "Запуск приложения"),
+                );
+                self.on_checked(Ok(Update::UpToDate(version)));
+            }
+            Err(e) => {
+                warn!("Patching failed: {}", e);
+                toast::show(&tr!("Patching failed", "Ошибка патчинга"), &e);
+                self.status
+                    .set_text(tr!("Patching failed", "Ошибка патчинга"));
+                self.patch.set_enabled(self.available.is_some());
+            }
+        }
+    }
+
+    fn set_tooltip(&self, text: &str) {
+        if let Some(icon) = &self.icon {
+            let _ = icon.set_tooltip(Some(format!("Yandex Music Mod\n{}", text)));
+        }
+    }
+}
+
+/// The latest build of `channel`, unless it is patched in `output` already
+async fn check(channel: &str, output: &std::path::Path) -> Result<Update> {
+    let builds = api::get_latest_build(channel).await?;
+    let build = builds
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow::anyhow!(tr!("No builds found", "Сборки не найдены")))?;
+    Ok(if versions::is_patched(output, &build.version)? {
+        Update::UpToDate(build.version)
+    } else {
+        Update::Available(build)
+    })
+}
+
+async fn patch_and_launch(
+    config: &Config,
+    build: &AppBuild,
+    output: &std::path::Path,
+) -> Result<()> {
+    let options = config.patch_options()?;
+    patcher::process_build(build, output, &options, None).await?;
+    patcher::launch_app(&patcher::build_dir(output, &build.version, false).join("mod"))
+}
+
+/// A yellow disc, drawn instead of shipping an image
+fn icon() -> Result<Icon> {
+    let center = (ICON_SIZE as f32 - 1.0) / 2.0;
+    let radius = ICON_SIZE as f32 / 2.0 - 1.0;
+    let mut rgba = Vec::with_capacity((ICON_SIZE * ICON_SIZE * 4) as usize);
+    for y in 0..ICON_SIZE {
+        for x in 0..ICON_SIZE {
+            let distance = ((x as f32 - center).powi(2) + (y as f32 - center).powi(2)).sqrt();
+            let alpha = ((radius - distance + 0.5).clamp(0.0, 1.0) * 255.0) as u8;
+            rgba.extend_from_slice(&[0xff, 0xcc, 0x00, alpha]);
+        }
+    }
+    Ok(Icon::from_rgba(rgba, ICON_SIZE, ICON_SIZE)?)
+}
@@ -0,0 +1,278 @@
+//! Versions module - lists the builds patched into the output directory
+//!
+//! Build directories of the original TypeScript mod, which keeps them in
+//! `.versions` too, are listed alongside: a version directory without a patch
+//! report that holds an Electron app (`package.json`) counts as such a build.
+
+use crate::i18n::tr;
+use crate::journal::JOURNAL_FILE;
+use crate::patcher::PATCH_REPORT_FILE;
+use anyhow::{Context, Result};
+use semver::Version;
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use walkdir::WalkDir;
+
+/// A build directory found in the output directory
+#[derive(Debug, Clone, Serialize)]
+pub struct LocalBuild {
+    pub version: String,
+    pub path: PathBuf,
+    /// Total size of the build directory in bytes
+    pub size: u64,
+    /// When the patch report was written
+    #[serde(serialize_with = "serialize_time")]
+    pub patched_at: Option<SystemTime>,
+    /// All stages finished: report written, mod tree present, no temp files or run journal
+    pub complete: bool,
+    /// Left behind by `patch --dry-run`
+    pub dry_run: bool,
+    /// Highest complete version in the directory
+    pub newest: bool,
+    /// Made by the original TypeScript mod
+    pub legacy: bool,
+    /// The patched app to launch, if there is one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub app_dir: Option<PathBuf>,
+}
+
+/// Directories of a build of the original mod that may hold the patched app,
+/// searched before the rest of the build
+const LEGACY_APP_DIRS: &[&str] = &["mod", "modded", "app", "resources/app"];
+
+/// How deep the rest of a build of the original mod is searched for the app
+const LEGACY_SEARCH_DEPTH: usize = 4;
+
+/// Scan `output` for build directories, sorted by version
+pub fn scan(output: &Path) -> Result<Vec<LocalBuild>> {
+    if !output.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut builds = Vec::new();
+    for entry in fs::read_dir(output).with_context(|| format!("Failed to read {:?}", output))? {
+        let path = entry?.path();
+        if !path.is_dir() {
+            continue;
+        }
+        // Build directories are named after versions, which are always Unicode
+        let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        let (version, dry_run) = match name.strip_suffix("-dry-run") {
+            Some(version) => (version.to_string(), true),
+            None => (name.to_string(), false),
+        };
+        if Version::parse(&version).is_err() {
+            continue;
+        }
+
+        let report = path.join(PATCH_REPORT_FILE);
+        let patched_at = fs::metadata(&report).and_then(|m| m.modified()).ok();
+        let ours = patched_at.is_some()
+            || path.join("temp").exists()
+            || path.join(JOURNAL_FILE).exists()
+            || dry_run;
+        let (legacy, app_dir, complete, patched_at) = if ours {
+            let mod_dir = path.join("mod");
+            let complete = patched_at.is_some()
+                && !path.join("temp").exists()
+                && !path.join(JOURNAL_FILE).exists()
+                && (dry_run || mod_dir.is_dir());
+            (
+                false,
+                mod_dir.is_dir().then_some(mod_dir),
+                complete,
+                patched_at,
+            )
+        } else {
+            let app_dir = find_legacy_app(&path);
+            let modified = fs::metadata(&path).and_then(|m| m.modified()).ok();
+            (true, app_dir.clone(), app_dir.is_some(), modified)
+        };
+
+        builds.push(LocalBuild {
+            version,
+            size: dir_size(&path),
+            path,
+            patched_at,
+            complete,
+            dry_run,
+            newest: false,
+            legacy,
+            app_dir,
+        });
+    }
+
+    builds.sort_by_cached_key(|b| (Version::parse(&b.version).ok(), b.dry_run));
+    if let Some(newest) = builds.iter_mut().rev().find(|b| b.complete && !b.dry_run) {
+        newest.newest = true;
+    }
+    Ok(builds)
+}
+
+/// The app directory of a build of the original mod: a directory with a
+/// `package.json`, preferring the usual names
+fn find_legacy_app(build_dir: &Path) -> Option<PathBuf> {
+    let is_app = |dir: &Path| dir.join("package.json").is_file();
+    if let Some(dir) = LEGACY_APP_DIRS
+        .iter()
+        .map(|name| build_dir.join(name))
+        .find(|dir| is_app(dir))
+    {
+        return Some(dir);
+    }
+    WalkDir::new(build_dir)
+        .max_depth(LEGACY_SEARCH_DEPTH)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|e| e.file_name() != "node_modules")
+        .filter_map(|e| e.ok())
+        .find(|e| e.file_type().is_dir() && is_app(e.path()))
+        .map(|e| e.into_path())
+}
+
+/// Whether `version` is patched completely in `output` (not as a dry run)
+pub fn is_patched(output: &Path, version: &str) -> Result<bool> {
+    Ok(scan(output)?
+        .iter()
+        .any(|b| b.version == version && b.complete && !b.dry_run))
+}
+
+/// Print the builds as a table
+pub fn print_table(output: &Path, builds: &[LocalBuild]) {
+    if builds.is_empty() {
+        println!(
+            "{}",
+            tr!(
+                "No patched builds in {:?}",
+                "Нет пропатченных сборок в {:?}",
+                output
+            )
+        );
+        return;
+    }
+
+    println!(
+        "{}",
+        tr!(
+            "\nPatched builds in {:?}:",
+            "\nПропатченные сборки в {:?}:",
+            output
+        )
+    );
+    println!("{}", "=".repeat(72));
+    println!(
+        "{:<20} {:>10}  {:<20} {}",
+        tr!("Version", "Версия"),
+        tr!("Size", "Размер"),
+        tr!("Patched", "Пропатчена"),
+        tr!("Status", "Статус")
+    );
+    println!("{}", "-".repeat(72));
+    for build in builds {
+        let status = if build.legacy && build.complete {
+            tr!("original mod", "оригинальный мод")
+        } else if !build.complete {
+            tr!("incomplete", "не завершена")
+        } else if build.dry_run {
+            tr!("dry run", "пробный запуск")
+        } else {
+            tr!("complete", "готова")
+        };
+        let patched = build
+            .patched_at
+            .map(|t| humantime::format_rfc3339_seconds(t).to_string())
+            .unwrap_or_else(|| "-".to_string());
+        println!(
+            "{:<20} {:>10}  {:<20} {}{}",
+            build.version,
+            format_size(build.size),
+            patched,
+            status,
+            if build.newest {
+                tr!(" (newest)", " (новейшая)")
+            } else {
+                String::new()
+            }
+        );
+    }
+}
+
+fn serialize_time<S: serde::Serializer>(
+    time: &Option<SystemTime>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match time {
+        Some(time) => serializer.collect_str(&humantime::format_rfc3339_seconds(*time)),
+        None => serializer.serialize_none(),
+    }
+}
+
+pub(crate) fn dir_size(path: &Path) -> u64 {
+    WalkDir::new(path)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter_map(|e| e.metadata().ok())
+        .filter(|m| m.is_file())
+        .map(|m| m.len())
+        .sum()
+}
+
+fn format_size(bytes: u64) -> String {
+    format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scan_builds() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = &dir.path().join("Пользователь").join("Музыка");
+
+        let complete = |version: &str| {
+            let path = output.join(version);
+            fs::create_dir_all(path.join("mod")).unwrap();
+            fs::write(path.join(PATCH_REPORT_FILE), "{}").unwrap();
+        };
+        complete("5.9.0");
+        complete("5.10.1");
+        fs::create_dir_all(output.join("5.11.0").join("temp")).unwrap();
+        fs::create_dir_all(output.join("5.12.0-dry-run")).unwrap();
+        fs::write(output.join("5.12.0-dry-run").join(PATCH_REPORT_FILE), "{}").unwrap();
+        fs::create_dir_all(output.join("not-a-version")).unwrap();
+
+        let builds = scan(output).unwrap();
+        let versions: Vec<_> = builds.iter().map(|b| b.version.as_str()).collect();
+        assert_eq!(versions, ["5.9.0", "5.10.1", "5.11.0", "5.12.0"]);
+
+        assert!(builds[0].complete && !builds[0].newest);
+        assert!(builds[1].complete && builds[1].newest);
+        assert!(!builds[2].complete);
+        assert!(builds[3].complete && builds[3].dry_run && !builds[3].newest);
+
+        assert!(builds.iter().all(|b| !b.legacy));
+        assert_eq!(builds[0].app_dir, Some(output.join("5.9.0").join("mod")));
+
+        // A build of the original mod, with the app below the build directory
+        let legacy = output
+            .join("5.13.0")
+            .join("modded")
+            .join("resources")
+            .join("app");
+        fs::create_dir_all(&legacy).unwrap();
+        fs::write(legacy.join("package.json"), "{}").unwrap();
+        fs::create_dir_all(output.join("5.14.0").join("empty")).unwrap();
+        let builds = scan(output).unwrap();
+        assert!(builds[4].legacy && builds[4].complete && builds[4].newest);
+        assert_eq!(builds[4].app_dir.as_deref(), Some(legacy.as_path()));
+        assert!(builds[5].legacy && !builds[5].complete);
+        assert!(is_patched(output, "5.13.0").unwrap());
+
+        assert!(scan(&output.join("missing")).unwrap().is_empty());
+    }
+}
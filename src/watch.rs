@@ -0,0 +1,150 @@
+//! Watch module - polls the update server and patches every new release
+//!
+//! `watch` keeps polling itself; `check-update` does a single check, for
+//! scheduled runs (see the schedule module).
+
+use crate::api;
+use crate::config::Config;
+use crate::notify::{self, Notification};
+use crate::patcher;
+use crate::versions;
+use anyhow::Result;
+use serde::Serialize;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+/// Poll `channel` every `interval` and patch versions missing from `output`.
+///
+/// Runs until interrupted; failed attempts are retried on the next poll.
+/// Every patch run is reported to `notify_url`, if given.
+pub async fn watch(
+    config: &Config,
+    channel: &str,
+    output: &Path,
+    interval: Duration,
+    run: bool,
+    notify_url: Option<&str>,
+) -> Result<()> {
+    info!(
+        "Watching the {} channel every {}",
+        channel,
+        humantime::format_duration(interval)
+    );
+
+    loop {
+        match patch_new_release(config, channel, output, notify_url).await {
+            Ok(Some(version)) => {
+                let build_dir = patcher::build_dir(output, &version, false);
+                #[cfg(feature = "toast")]
+                crate::toast::patched(&version, &build_dir);
+                if run {
+                    if let Err(e) = patcher::launch_app(&build_dir.join("mod")) {
+                        warn!("{:#}", e);
+                    }
+                }
+            }
+            Ok(None) => {}
+            Err(e) => warn!("Update check failed: {:#}", e),
+        }
+
+        tokio::time::sleep(interval).await;
+    }
+}
+
+/// Result of a single update check
+#[derive(Debug, Clone, Serialize)]
+pub struct UpdateCheck {
+    pub channel: String,
+    /// Latest published version, if any
+    pub version: Option<String>,
+    /// The latest version was not patched in the output directory yet
+    pub update_available: bool,
+    /// The latest version was patched by this check
+    pub patched: bool,
+}
+
+/// Check whether the latest build of `channel` is patched in `output`, and with
+/// `patch` patch it if not. Patch runs are reported to `notify_url`, if given.
+pub async fn check_update(
+    config: &Config,
+    channel: &str,
+    output: &Path,
+    patch: bool,
+    notify_url: Option<&str>,
+) -> Result<UpdateCheck> {
+    let builds = api::get_latest_build(channel).await?;
+    let version = builds.first().map(|build| build.version.clone());
+    let update_available = match &version {
+        Some(version) => !versions::is_patched(output, version)?,
+        None => false,
+    };
+    let patched = if update_available && patch {
+        patch_new_release(config, channel, output, notify_url).await?
+    } else {
+        None
+    };
+
+    #[cfg(feature = "toast")]
+    match (&patched, &version) {
+        (Some(version), _) => {
+            let build_dir = patcher::build_dir(output, version, false);
+            if crate::prompt::is_interactive() {
+                // The check exits right after; the buttons only work while it runs
+                if let Some(buttons) = crate::toast::patched(version, &build_dir) {
+                    let _ = tokio::task::spawn_blocking(move || buttons.join()).await;
+                }
+            } else {
+                // Scheduled runs must not hang until the notification is dismissed
+                crate::toast::patched_notice(version, &build_dir);
+            }
+        }
+        (None, Some(version)) if update_available && !patch => {
+            crate::toast::new_version(version, false)
+        }
+        _ => {}
+    }
+    Ok(UpdateCheck {
+        channel: channel.to_string(),
+        version,
+        update_available,
+        patched: patched.is_some(),
+    })
+}
+
+/// Patch the latest build unless it is already patched; returns its version
+async fn patch_new_release(
+    config: &Config,
+    channel: &str,
+    output: &Path,
+    notify_url: Option<&str>,
+) -> Result<Option<String>> {
+    let builds = api::get_latest_build(channel).await?;
+    let Some(build) = builds.first() else {
+        debug!("No builds published");
+        return Ok(None);
+    };
+
+    if versions::is_patched(output, &build.version)? {
+        debug!("Yandex Music {} is already patched", build.version);
+        return Ok(None);
+    }
+
+    info!("New Yandex Music version {}, patching", build.version);
+    #[cfg(feature = "toast")]
+    crate::toast::new_version(&build.version, true);
+    let started = Instant::now();
+    let build_dir = patcher::build_dir(output, &build.version, false);
+    let result = match config.patch_options() {
+        Ok(options) => patcher::process_build(build, output, &options, None).await,
+        Err(e) => Err(e),
+    };
+    if let Some(url) = notify_url {
+        let notification =
+            Notification::new(&build.version, build_dir.clone(), false, started, &result);
+        notify::send(url, &notification).await;
+    }
+    result?;
+    info!("Successfully patched Yandex Music v{}", build.version);
+    Ok(Some(build.version.clone()))
+}
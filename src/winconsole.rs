@@ -0,0 +1,39 @@
+//! Console handling for the Windows GUI-subsystem build (`windows-gui` feature)
+//!
+//! That build gets no console window of its own. Started from a terminal it
+//! attaches to the parent console so CLI output still appears; double-clicked,
+//! it opens the GUI and reports results with message boxes.
+
+use std::sync::OnceLock;
+use windows_sys::Win32::System::Console::{AttachConsole, ATTACH_PARENT_PROCESS};
+use windows_sys::Win32::UI::WindowsAndMessaging::{
+    MessageBoxW, MB_ICONERROR, MB_ICONINFORMATION, MB_OK,
+};
+
+static HAS_CONSOLE: OnceLock<bool> = OnceLock::new();
+
+/// Attach to the console of the parent process, once; false when there is none
+pub fn has_console() -> bool {
+    // SAFETY: AttachConsole has no preconditions; failure just leaves us without a console
+    *HAS_CONSOLE.get_or_init(|| unsafe { AttachConsole(ATTACH_PARENT_PROCESS) != 0 })
+}
+
+/// Show a modal message box
+pub fn message_box(title: &str, text: &str, error: bool) {
+    let wide = |s: &str| s.encode_utf16().chain(Some(0)).collect::<Vec<u16>>();
+    let (title, text) = (wide(title), wide(text));
+    let icon = if error {
+        MB_ICONERROR
+    } else {
+        MB_ICONINFORMATION
+    };
+    // SAFETY: both strings are NUL-terminated UTF-16 and outlive the call
+    unsafe {
+        MessageBoxW(
+            std::ptr::null_mut(),
+            text.as_ptr(),
+            title.as_ptr(),
+            MB_OK | icon,
+        );
+    }
+}
@@ -0,0 +1,327 @@
+//! Wrapper module - the web version of Yandex Music with the mod, without Electron
+//!
+//! `app` opens music.yandex.ru in the system webview (WebView2, WebKitGTK,
+//! WKWebView) through wry instead of patching Yandex's Electron build. The
+//! mod renderer code and styles are injected as they are into the desktop app;
+//! a small bridge stands in for the Electron preload (`window.yandexMusicMod`)
+//! and keeps the mod settings in `app/mod_settings.json` of the data directory.
+//! Analytics requests are blocked in the page, as there is no `webRequest`
+//! API to cancel them from outside. Only built with the `wrapper` feature.
+
+use crate::config::{self, Config};
+use crate::patches;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tao::event::{Event, WindowEvent};
+use tao::event_loop::{ControlFlow, EventLoopBuilder};
+use tao::window::WindowBuilder;
+use tracing::{info, warn};
+use wry::{ProxyConfig, ProxyEndpoint, WebContext, WebViewBuilder};
+
+/// Page opened in the window
+pub const APP_URL: &str = "https://music.yandex.ru/";
+
+/// Mod settings of the wrapper, in its directory
+const SETTINGS_FILE: &str = "mod_settings.json";
+
+/// Settings the page may change: the entries of the base mod panel
+const SETTINGS_KEYS: &[&str] = &[
+    "window/alwaysOnTop",
+    "devtools/systemToolbar",
+    "network/proxy",
+    "network/proxyScope",
+    "mod/theme",
+    "mod/language",
+];
+
+/// Stands in for the Electron preload and blocks analytics; `__SETTINGS__`,
+/// `__BLOCKED__` and `__HEADERS__` are replaced before injection
+const BRIDGE_JS: &str = r#"
+(function () {
+  if (window.yandexMusicMod) return;
+  const settings = __SETTINGS__;
+  const listeners = [];
+  const post = (message) => window.ipc.postMessage(JSON.stringify(message));
+
+  window.yandexMusicMod = {
+    getStorageValue: (key) => Promise.resolve(settings[key]),
+    setStorageValue: (key, value) => {
+      settings[key] = value;
+      post({ type: "set", key, value });
+      listeners.forEach((callback) => callback(key, value));
+    },
+    onStorageValueUpdated: (callback) => listeners.push(callback),
+    // No native dialogs or downloads in the webview
+    selectDownloadFolder: () => Promise.resolve(null),
+    openFolder: () => Promise.resolve(false),
+    openDownloadDirectory: () => {},
+  };
+
+  const blocked = __BLOCKED__.map((pattern) => new RegExp("^" + pattern + "$"));
+  const bannedHeaders = __HEADERS__;
+  const resolve = (url) => {
+    try {
+      return new URL(String(url), location.href).href;
+    } catch (e) {
+      return String(url);
+    }
+  };
+  const isBlocked = (url) => blocked.some((pattern) => pattern.test(resolve(url)));
+  const isMusicApi = (url) => resolve(url).startsWith("https://api.music.yandex.net/");
+
+  const fetch = window.fetch;
+  window.fetch = function (input, init) {
+    const url = typeof input === "string" || input instanceof URL ? input : input.url;
+    if (isBlocked(url)) return Promise.reject(new TypeError("Blocked by YandexMusicMod"));
+    if (isMusicApi(url) && init && init.headers) {
+      const headers = new Headers(init.headers);
+      bannedHeaders.forEach((name) => headers.delete(name));
+      init = Object.assign({}, init, { headers });
+    }
+    return fetch.call(this, input, init);
+  };
+
+  const open = XMLHttpRequest.prototype.open;
+  const send = XMLHttpRequest.prototype.send;
+  const setRequestHeader = XMLHttpRequest.prototype.setRequestHeader;
+  XMLHttpRequest.prototype.open = function (method, url) {
+    this.__yandexMusicModUrl = url;
+    return open.apply(this, arguments);
+  };
+  XMLHttpRequest.prototype.setRequestHeader = function (name, value) {
+    if (isMusicApi(this.__yandexMusicModUrl) && bannedHeaders.includes(name.toLowerCase())) return;
+    return setRequestHeader.call(this, name, value);
+  };
+  XMLHttpRequest.prototype.send = function () {
+    if (isBlocked(this.__yandexMusicModUrl)) return this.abort();
+    return send.apply(this, arguments);
+  };
+
+  if (navigator.sendBeacon) {
+    const sendBeacon = navigator.sendBeacon.bind(navigator);
+    navigator.sendBeacon = (url, data) => (isBlocked(url) ? true : sendBeacon(url, data));
+  }
+
+  document.addEventListener("DOMContentLoaded", () => {
+    const style = document.createElement("style");
+    style.id = "yandex-music-mod-css";
+    style.textContent = __CSS__;
+    document.head.appendChild(style);
+    __RENDERER__
+  });
+})();
+"#;
+
+/// Message posted by the bridge
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+enum Message {
+    Set { key: String, value: Value },
+}
+
+/// Settings applied to the window
+#[derive(Debug)]
+enum UserEvent {
+    AlwaysOnTop(bool),
+}
+
+/// Open the wrapper window and block until it is closed
+pub fn run(config: &Config) -> Result<()> {
+    let dir = app_dir().context("Failed to determine the data directory")?;
+    fs::create_dir_all(&dir).with_context(|| format!("Failed to create {:?}", dir))?;
+    let settings_path = dir.join(SETTINGS_FILE);
+    let settings = load_settings(&settings_path, config.default_settings()?)?;
+
+    let event_loop = EventLoopBuilder::<UserEvent>::with_user_event().build();
+    let window = WindowBuilder::new()
+        .with_title("Yandex Music Mod")
+        .with_min_inner_size(tao::dpi::LogicalSize::new(
+            patches::DEFAULT_MIN_WIDTH,
+            patches::DEFAULT_MIN_HEIGHT,
+        ))
+        .with_always_on_top(settings.get("window/alwaysOnTop") == Some(&Value::Bool(true)))
+        .build(&event_loop)?;
+
+    // Cookies and storage persist next to the settings
+    let mut context = WebContext::new(Some(dir.join("webview")));
+    let proxy = event_loop.create_proxy();
+    // The handler runs on the event loop thread only
+    let store = std::cell::RefCell::new(settings.clone());
+    let mut builder = WebViewBuilder::new_with_web_context(&mut context)
+        .with_url(APP_URL)
+        .with_initialization_script(bridge_js(&settings)?)
+        .with_ipc_handler(move |request| {
+            let origin = request.uri().to_string();
+            if !is_app_origin(&origin) {
+                return warn!("Ignoring message from {}", origin);
+            }
+            let message = match serde_json::from_str::<Message>(request.body()) {
+                Ok(message) => message,
+                Err(e) => return warn!("Ignoring message from the page: {}", e),
+            };
+            let Message::Set { key, value } = message;
+            if !SETTINGS_KEYS.contains(&key.as_str()) {
+                return warn!("Ignoring unknown setting {:?} from the page", key);
+            }
+            if key == "window/alwaysOnTop" {
+                let _ = proxy.send_event(UserEvent::AlwaysOnTop(value == Value::Bool(true)));
+            }
+            let mut store = store.borrow_mut();
+            store.insert(key, value);
+            if let Err(e) = save_settings(&settings_path, &store) {
+                warn!("{:#}", e);
+            }
+        });
+    if let Some(proxy) = settings.get("network/proxy").and_then(|p| p.as_str()) {
+        match proxy_config(proxy) {
+            Some(config) => builder = builder.with_proxy_config(config),
+            None => warn!("Ignoring unsupported proxy {}", proxy),
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    let _webview = builder.build(&window)?;
+    #[cfg(target_os = "linux")]
+    let _webview = {
+        use tao::platform::unix::WindowExtUnix;
+        use wry::WebViewBuilderExtUnix;
+        builder.build_gtk(window.gtk_window())?
+    };
+    info!("Opened {} in the wrapper window", APP_URL);
+
+    event_loop.run(move |event, _, control_flow| {
+        *control_flow = ControlFlow::Wait;
+        match event {
+            Event::WindowEvent {
+                event: WindowEvent::CloseRequested,
+                ..
+            } => *control_flow = ControlFlow::Exit,
+            Event::UserEvent(UserEvent::AlwaysOnTop(on_top)) => window.set_always_on_top(on_top),
+            _ => {}
+        }
+    })
+}
+
+/// Saved settings over `defaults`, as the Electron mod merges them
+fn load_settings(
+    path: &Path,
+    defaults: BTreeMap<String, Value>,
+) -> Result<BTreeMap<String, Value>> {
+    let mut settings = defaults;
+    match fs::read_to_string(path) {
+        Ok(content) => {
+            let saved: BTreeMap<String, Value> = serde_json::from_str(&content)
+                .with_context(|| format!("Failed to parse {:?}", path))?;
+            settings.extend(saved);
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {:?}", path)),
+    }
+    Ok(settings)
+}
+
+fn save_settings(path: &Path, settings: &BTreeMap<String, Value>) -> Result<()> {
+    fs::write(path, serde_json::to_string_pretty(settings)? + "\n")
+        .with_context(|| format!("Failed to write {:?}", path))
+}
+
+/// The bridge with the settings, block list and mod code filled in
+fn bridge_js(settings: &BTreeMap<String, Value>) -> Result<String> {
+    let blocked: Vec<String> = patches::BLOCKED_ANALYTICS_URLS
+        .iter()
+        .map(|pattern| regex::escape(pattern).replace(r"\*", ".*"))
+        .collect();
+    let headers: Vec<String> = patches::BANNED_HEADERS
+        .iter()
+        .map(|h| h.to_lowercase())
+        .collect();
+    let css = patches::generate_renderer_css(&[]);
+    Ok(BRIDGE_JS
+        .replace("__SETTINGS__", &serde_json::to_string(settings)?)
+        .replace("__BLOCKED__", &serde_json::to_string(&blocked)?)
+        .replace("__HEADERS__", &serde_json::to_string(&headers)?)
+        .replace("__CSS__", &serde_json::to_string(&css)?)
+        .replace("__RENDERER__", &patches::generate_renderer_js(&[])))
+}
+
+/// Whether a message was posted by a page of [`APP_URL`]'s origin
+fn is_app_origin(uri: &str) -> bool {
+    let (Ok(uri), Ok(app)) = (url::Url::parse(uri), url::Url::parse(APP_URL)) else {
+        return false;
+    };
+    uri.origin() == app.origin()
+}
+
+/// `network/proxy` as a webview proxy: `http://` and `socks5://` with a port
+fn proxy_config(proxy: &str) -> Option<ProxyConfig> {
+    let url = url::Url::parse(proxy).ok()?;
+    let endpoint = ProxyEndpoint {
+        host: url.host_str()?.to_string(),
+        port: url.port()?.to_string(),
+    };
+    match url.scheme() {
+        "http" => Some(ProxyConfig::Http(endpoint)),
+        "socks5" | "socks5h" => Some(ProxyConfig::Socks5(endpoint)),
+        _ => None,
+    }
+}
+
+/// Directory of the wrapper's settings and webview data
+pub fn app_dir() -> Option<PathBuf> {
+    config::data_dir().map(|dir| dir.join("app"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bridge_js() {
+        let mut settings = BTreeMap::new();
+        settings.insert("mod/theme".to_string(), Value::from("light"));
+        let js = bridge_js(&settings).unwrap();
+        assert!(js.contains(r#"{"mod/theme":"light"}"#));
+        assert!(js.contains(r#"https://mc\\.yandex\\.ru/.*"#));
+        assert!(js.contains("YandexMusicMod renderer.js loaded!"));
+        assert!(!js.contains("__CSS__"));
+
+        assert!(matches!(
+            proxy_config("socks5://127.0.0.1:1080"),
+            Some(ProxyConfig::Socks5(ProxyEndpoint { ref port, .. })) if port == "1080"
+        ));
+        assert!(proxy_config("https://proxy.local").is_none());
+    }
+
+    #[test]
+    fn test_is_app_origin() {
+        assert!(is_app_origin("https://music.yandex.ru/"));
+        assert!(is_app_origin("https://music.yandex.ru/album/1?x=2"));
+        assert!(!is_app_origin("https://music.yandex.ru.example.com/"));
+        assert!(!is_app_origin("http://music.yandex.ru/"));
+        assert!(!is_app_origin("https://ads.yandex.ru/frame"));
+        assert!(!is_app_origin("about:blank"));
+        assert!(SETTINGS_KEYS.contains(&"window/alwaysOnTop"));
+    }
+
+    #[test]
+    fn test_load_settings() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(SETTINGS_FILE);
+        let mut defaults = BTreeMap::new();
+        defaults.insert("mod/theme".to_string(), Value::from("dark"));
+        defaults.insert("mod/language".to_string(), Value::from("ru"));
+        save_settings(
+            &path,
+            &BTreeMap::from([("mod/theme".to_string(), Value::from("light"))]),
+        )
+        .unwrap();
+
+        let settings = load_settings(&path, defaults).unwrap();
+        assert_eq!(settings["mod/theme"], "light");
+        assert_eq!(settings["mod/language"], "ru");
+    }
+}